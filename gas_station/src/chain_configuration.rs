@@ -1,12 +1,20 @@
 use std::cmp::Ordering;
 
 use ethers_core::types::U256;
-use lib::{foreign_address::ForeignAddress, pyth};
+use lib::{
+    foreign_address::ForeignAddress,
+    pyth,
+    transaction::{
+        canonicalize_access_list, Eip1559Transaction, Eip2930Transaction, ForeignTransaction,
+        LegacyTransaction, EIP1559_TRANSACTION_TYPE, EIP2930_TRANSACTION_TYPE,
+        LEGACY_TRANSACTION_TYPE,
+    },
+};
 use near_sdk::{json_types::U128, near};
 
 use crate::{
     error::{
-        ConfidenceIntervalTooLargeError, ExponentTooLargeError, NegativePriceError,
+        ConfidenceIntervalTooLargeError, ContractError, ExponentTooLargeError, NegativePriceError,
         NoPaymasterConfigurationForChainError, PaymasterInsufficientFundsError, PriceDataError,
         RequestNonceError,
     },
@@ -51,6 +59,72 @@ pub struct ViewPaymasterConfiguration {
     pub minimum_available_balance: U128,
 }
 
+/// Optional settings when adding a foreign chain configuration.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[near(serializers = [json])]
+pub struct ForeignChainOptions {
+    /// Defaults to the chain ID.
+    pub display_name: Option<String>,
+    /// Defaults to `true`.
+    pub is_eip1559: Option<bool>,
+    /// Defaults to zero.
+    pub min_priority_fee: Option<U128>,
+    /// Defaults to the contract-wide markup.
+    pub fee_markup_bps: Option<u32>,
+    /// Defaults to [`DEFAULT_MIN_GAS_LIMIT`].
+    pub min_gas_limit: Option<U128>,
+    /// Defaults to [`DEFAULT_MAX_GAS_LIMIT`].
+    pub max_gas_limit: Option<U128>,
+    /// Defaults to `None`, i.e. not a rollup.
+    pub rollup: Option<RollupConfiguration>,
+    /// Defaults to `false`, i.e. the same addresses as on other chains.
+    pub namespaced_derivation: Option<bool>,
+}
+
+/// How a rollup (e.g. an OP-stack chain or Arbitrum) charges for publishing
+/// a transaction to its L1. The L1 data fee of a transaction is
+/// `(serialized_len * l1_gas_per_byte + l1_gas_overhead) * l1_gas_price`,
+/// in the smallest unit of the rollup's gas token, on top of its L2 gas.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[near(serializers = [borsh, json])]
+pub struct RollupConfiguration {
+    /// L1 gas charged per byte of the serialized signed transaction, e.g.
+    /// 16 for uncompressed calldata, where every byte counts as nonzero.
+    pub l1_gas_per_byte: u64,
+    /// L1 gas charged per transaction regardless of its size.
+    pub l1_gas_overhead: u64,
+}
+
+impl RollupConfiguration {
+    /// The L1 data fee of a transaction that is `serialized_tx_len` bytes
+    /// long once signed, at `l1_gas_price`.
+    ///
+    /// # Errors
+    ///
+    /// - If the calculation overflows.
+    pub fn l1_data_fee(
+        &self,
+        serialized_tx_len: u64,
+        l1_gas_price: u128,
+    ) -> Result<u128, ExpressionOverflowError> {
+        let l1_gas = u128::from(serialized_tx_len)
+            .checked_mul(self.l1_gas_per_byte.into())
+            .and_then(|x| x.checked_add(self.l1_gas_overhead.into()))
+            .ok_or(ExpressionOverflowError)?;
+
+        l1_gas
+            .checked_mul(l1_gas_price)
+            .ok_or(ExpressionOverflowError)
+    }
+}
+
+/// The lowest gas limit that sign requests may have on chains that do not
+/// configure one: enough for a transfer.
+pub const DEFAULT_MIN_GAS_LIMIT: u128 = 21_000;
+/// The highest gas limit that sign requests may have on chains that do not
+/// configure one: the block gas limit of Ethereum mainnet.
+pub const DEFAULT_MAX_GAS_LIMIT: u128 = 30_000_000;
+
 #[derive(Debug)]
 #[near]
 pub struct ForeignChainConfiguration {
@@ -61,6 +135,27 @@ pub struct ForeignChainConfiguration {
     pub fee_rate: (u128, u128),
     pub oracle_asset_id: [u8; 32],
     pub decimals: u8,
+    pub display_name: String,
+    /// Whether the chain accepts EIP-1559 (type 2) transactions. Otherwise,
+    /// legacy EIP-155 transactions are built.
+    pub is_eip1559: bool,
+    /// Lower bound for the max priority fee per gas of EIP-1559 transactions.
+    pub min_priority_fee: u128,
+    /// Markup applied to fees for this chain, in basis points. `None` uses
+    /// the contract-wide markup.
+    pub fee_markup_bps: Option<u32>,
+    /// `None` uses [`DEFAULT_MIN_GAS_LIMIT`].
+    pub min_gas_limit: Option<u128>,
+    /// `None` uses [`DEFAULT_MAX_GAS_LIMIT`].
+    pub max_gas_limit: Option<u128>,
+    /// `Some` if the chain is a rollup that charges an L1 data fee.
+    pub rollup: Option<RollupConfiguration>,
+    /// Whether keys for this chain are derived with its chain ID appended to
+    /// the derivation path, as with [`lib::kdf::derive_epsilon_for_chain`],
+    /// so that a path signs for a different address on this chain than on
+    /// any other. Fixed once the chain is added, since changing it would
+    /// change every address on the chain.
+    pub namespaced_derivation: bool,
 }
 
 impl ForeignChainConfiguration {
@@ -68,6 +163,103 @@ impl ForeignChainConfiguration {
         U256(self.transfer_gas)
     }
 
+    pub fn min_gas_limit(&self) -> u128 {
+        self.min_gas_limit.unwrap_or(DEFAULT_MIN_GAS_LIMIT)
+    }
+
+    pub fn max_gas_limit(&self) -> u128 {
+        self.max_gas_limit.unwrap_or(DEFAULT_MAX_GAS_LIMIT)
+    }
+
+    /// Checks that sign requests for this chain may have `gas_limit`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `gas_limit` is outside of the configured bounds.
+    pub fn check_gas_limit(&self, gas_limit: u128) -> Result<(), ContractError> {
+        let (min, max) = (self.min_gas_limit(), self.max_gas_limit());
+
+        if (min..=max).contains(&gas_limit) {
+            Ok(())
+        } else {
+            Err(ContractError::GasLimitOutOfRange {
+                gas_limit,
+                min,
+                max,
+            })
+        }
+    }
+
+    /// The EIP-2718 type of the transactions that [`Self::build_transaction`]
+    /// builds for this chain: EIP-1559 if the chain supports it, or legacy
+    /// otherwise. Transactions with an access list are built as EIP-2930
+    /// transactions on chains without EIP-1559 instead.
+    pub fn transaction_type(&self) -> u8 {
+        if self.is_eip1559 {
+            EIP1559_TRANSACTION_TYPE
+        } else {
+            LEGACY_TRANSACTION_TYPE
+        }
+    }
+
+    /// Whether transactions of `transaction_type` can be built for this chain
+    /// (see [`Self::transaction_type`]).
+    pub fn supports_transaction_type(&self, transaction_type: u8) -> bool {
+        if self.is_eip1559 {
+            transaction_type == EIP1559_TRANSACTION_TYPE
+        } else {
+            transaction_type == LEGACY_TRANSACTION_TYPE
+                || transaction_type == EIP2930_TRANSACTION_TYPE
+        }
+    }
+
+    /// Builds a transaction in the format that this chain supports from the
+    /// fields of an EIP-1559 transaction. The chain ID is always taken from
+    /// this configuration.
+    ///
+    /// EIP-1559 transactions have their priority fee raised to at least the
+    /// configured minimum (but never above the max fee per gas). Chains
+    /// without EIP-1559 use the max fee per gas as the gas price, in an
+    /// EIP-2930 transaction if there is an access list to carry, or a legacy
+    /// transaction otherwise.
+    ///
+    /// The access list is canonicalized (see [`canonicalize_access_list`]),
+    /// so the signing hash does not depend on the order in which its entries
+    /// were submitted.
+    pub fn build_transaction(&self, mut transaction: Eip1559Transaction) -> ForeignTransaction {
+        transaction.chain_id = self.chain_id;
+        transaction.access_list = canonicalize_access_list(transaction.access_list);
+
+        if self.is_eip1559 {
+            transaction.max_priority_fee_per_gas = transaction
+                .max_priority_fee_per_gas
+                .max(self.min_priority_fee)
+                .min(transaction.max_fee_per_gas);
+            ForeignTransaction::Eip1559(transaction)
+        } else if !transaction.access_list.is_empty() {
+            ForeignTransaction::Eip2930(Eip2930Transaction {
+                chain_id: transaction.chain_id,
+                nonce: transaction.nonce,
+                gas_price: transaction.max_fee_per_gas,
+                gas_limit: transaction.gas_limit,
+                to: transaction.to,
+                value: transaction.value,
+                data: transaction.data,
+                access_list: transaction.access_list,
+            })
+        } else {
+            ForeignTransaction::Legacy(LegacyTransaction {
+                nonce: transaction.nonce,
+                gas_price: transaction.max_fee_per_gas,
+                gas_limit: transaction.gas_limit,
+                to: transaction.to,
+                value: transaction.value,
+                data: transaction.data,
+                chain_id: transaction.chain_id,
+            })
+        }
+    }
+
     fn next_paymaster_key(&self) -> Option<String> {
         self.paymasters
             .ceil_key(&self.next_paymaster)
@@ -226,3 +418,172 @@ impl ForeignChainConfiguration {
         .as_u128())
     }
 }
+
+#[cfg(test)]
+fn chain_configuration(is_eip1559: bool) -> ForeignChainConfiguration {
+    ForeignChainConfiguration {
+        chain_id: 97,
+        paymasters: near_sdk::collections::TreeMap::new(b"p"),
+        next_paymaster: String::new(),
+        transfer_gas: U256::from(21_000).0,
+        fee_rate: (120, 100),
+        oracle_asset_id: [0; 32],
+        decimals: 18,
+        display_name: "BSC Testnet".to_string(),
+        is_eip1559,
+        min_priority_fee: 1_000_000_000,
+        fee_markup_bps: None,
+        min_gas_limit: None,
+        max_gas_limit: None,
+        rollup: None,
+        namespaced_derivation: false,
+    }
+}
+
+#[cfg(test)]
+fn eip1559_transaction(max_priority_fee_per_gas: u128) -> Eip1559Transaction {
+    Eip1559Transaction {
+        chain_id: 1,
+        nonce: 3,
+        max_priority_fee_per_gas,
+        max_fee_per_gas: 5_000_000_000,
+        gas_limit: 21_000,
+        to: Some(ForeignAddress([1; 20])),
+        value: U256::from(100),
+        data: vec![],
+        access_list: vec![],
+    }
+}
+
+#[test]
+fn test_build_eip1559_transaction() {
+    let config = chain_configuration(true);
+
+    let ForeignTransaction::Eip1559(transaction) =
+        config.build_transaction(eip1559_transaction(100))
+    else {
+        panic!("Expected an EIP-1559 transaction");
+    };
+    assert_eq!(transaction.chain_id, 97);
+    assert_eq!(transaction.max_priority_fee_per_gas, 1_000_000_000);
+
+    let ForeignTransaction::Eip1559(transaction) =
+        config.build_transaction(eip1559_transaction(2_000_000_000))
+    else {
+        panic!("Expected an EIP-1559 transaction");
+    };
+    assert_eq!(transaction.max_priority_fee_per_gas, 2_000_000_000);
+
+    let mut low_max_fee = eip1559_transaction(0);
+    low_max_fee.max_fee_per_gas = 500_000_000;
+    let ForeignTransaction::Eip1559(transaction) = config.build_transaction(low_max_fee) else {
+        panic!("Expected an EIP-1559 transaction");
+    };
+    assert_eq!(transaction.max_priority_fee_per_gas, 500_000_000);
+}
+
+#[test]
+fn test_build_legacy_transaction() {
+    let config = chain_configuration(false);
+
+    assert_eq!(
+        config.build_transaction(eip1559_transaction(100)),
+        ForeignTransaction::Legacy(LegacyTransaction {
+            nonce: 3,
+            gas_price: 5_000_000_000,
+            gas_limit: 21_000,
+            to: Some(ForeignAddress([1; 20])),
+            value: U256::from(100),
+            data: vec![],
+            chain_id: 97,
+        }),
+    );
+}
+
+#[test]
+fn test_build_eip2930_transaction() {
+    let config = chain_configuration(false);
+    let access_list = vec![(ForeignAddress([2; 20]), vec![[3; 32]])];
+
+    let mut transaction = eip1559_transaction(100);
+    transaction.access_list.clone_from(&access_list);
+
+    assert_eq!(
+        config.build_transaction(transaction),
+        ForeignTransaction::Eip2930(Eip2930Transaction {
+            chain_id: 97,
+            nonce: 3,
+            gas_price: 5_000_000_000,
+            gas_limit: 21_000,
+            to: Some(ForeignAddress([1; 20])),
+            value: U256::from(100),
+            data: vec![],
+            access_list,
+        }),
+    );
+}
+
+#[test]
+fn test_build_transaction_canonical_access_list() {
+    let access_list = vec![
+        (ForeignAddress([2; 20]), vec![[4; 32], [3; 32]]),
+        (ForeignAddress([1; 20]), vec![[5; 32]]),
+    ];
+    let shuffled = vec![
+        (ForeignAddress([1; 20]), vec![[5; 32]]),
+        (ForeignAddress([2; 20]), vec![[3; 32]]),
+        (ForeignAddress([2; 20]), vec![[4; 32], [3; 32]]),
+    ];
+
+    for is_eip1559 in [true, false] {
+        let config = chain_configuration(is_eip1559);
+        let build = |access_list| {
+            let mut transaction = eip1559_transaction(100);
+            transaction.access_list = access_list;
+            config.build_transaction(transaction)
+        };
+
+        assert_eq!(
+            build(access_list.clone()).signing_hash(),
+            build(shuffled.clone()).signing_hash(),
+        );
+    }
+}
+
+#[test]
+fn test_check_gas_limit() {
+    let mut config = chain_configuration(true);
+
+    config.check_gas_limit(DEFAULT_MIN_GAS_LIMIT).unwrap();
+    config.check_gas_limit(DEFAULT_MAX_GAS_LIMIT).unwrap();
+    assert!(matches!(
+        config.check_gas_limit(20_999),
+        Err(ContractError::GasLimitOutOfRange {
+            gas_limit: 20_999,
+            min: DEFAULT_MIN_GAS_LIMIT,
+            max: DEFAULT_MAX_GAS_LIMIT,
+        }),
+    ));
+    assert!(config.check_gas_limit(DEFAULT_MAX_GAS_LIMIT + 1).is_err());
+
+    config.min_gas_limit = Some(50_000);
+    config.max_gas_limit = Some(100_000);
+    config.check_gas_limit(75_000).unwrap();
+    assert!(config.check_gas_limit(21_000).is_err());
+    assert!(config.check_gas_limit(100_001).is_err());
+}
+
+#[test]
+fn test_l1_data_fee() {
+    let rollup = RollupConfiguration {
+        l1_gas_per_byte: 16,
+        l1_gas_overhead: 188,
+    };
+
+    assert_eq!(rollup.l1_data_fee(0, 10).unwrap(), 1_880);
+    assert_eq!(
+        rollup.l1_data_fee(100, 30_000_000_000).unwrap(),
+        (100 * 16 + 188) * 30_000_000_000,
+    );
+    assert!(rollup.l1_data_fee(u64::MAX, u128::MAX).is_err());
+}
@@ -1,7 +1,13 @@
-use near_sdk::{json_types::U64, near, AccountId};
+use lib::foreign_address::ForeignAddress;
+use near_sdk::{
+    json_types::{U128, U64},
+    near,
+    serde_json::Value,
+    AccountId,
+};
 use near_sdk_contract_tools::event;
 
-use crate::PendingTransactionSequence;
+use crate::{fee::FeeSettlement, PendingTransactionSequence};
 
 /// A successful request will emit two events, one for the request and one for
 /// the finalized transaction, in that order. The `id` field will be the same
@@ -13,6 +19,16 @@ use crate::PendingTransactionSequence;
 pub enum ContractEvent {
     TransactionSequenceCreated(TransactionSequenceCreated),
     TransactionSequenceSigned(TransactionSequenceSigned),
+    SignRequested(SignRequested),
+    SignCompleted(SignCompleted),
+    SignFailed(SignFailed),
+    SignCancelled(SignCancelled),
+    FeeSettled(FeeSettled),
+    NonceReconciled(NonceReconciled),
+    NonceGapDetected(NonceGapDetected),
+    GasPriceUpdated(GasPriceUpdated),
+    SimulationRequested(SimulationRequested),
+    SimulationFulfilled(SimulationFulfilled),
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -31,3 +47,108 @@ pub struct TransactionSequenceSigned {
     pub created_by_account_id: AccountId,
     pub signed_transactions: Vec<String>,
 }
+
+/// Emitted by `sign` once the transaction has been assigned a nonce and sent
+/// to the MPC contract. Followed by either [`SignCompleted`] or [`SignFailed`]
+/// with the same `request_id`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [json])]
+pub struct SignRequested {
+    /// Hex-encoded [`RequestId`](crate::request_status::RequestId).
+    pub request_id: String,
+    pub caller: AccountId,
+    pub chain_id: U64,
+    pub foreign_address: ForeignAddress,
+    pub nonce: U64,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [json])]
+pub struct SignCompleted {
+    pub request_id: String,
+    /// Hash of the raw signed transaction, i.e. its hash on the foreign chain.
+    pub raw_tx_hash: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [json])]
+pub struct SignFailed {
+    pub request_id: String,
+    pub reason: String,
+}
+
+/// Emitted by `cancel_request`, instead of [`SignCompleted`] or
+/// [`SignFailed`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [json])]
+pub struct SignCancelled {
+    pub request_id: String,
+    /// Whether the nonce of the request will be reused.
+    pub nonce_released: bool,
+}
+
+/// Emitted by `settle_fee`. A nonzero `shortfall` means that the prepaid fee
+/// did not cover the actual cost of the transaction.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [json])]
+pub struct FeeSettled {
+    pub request_id: String,
+    pub payer: AccountId,
+    #[serde(flatten)]
+    pub settlement: FeeSettlement,
+}
+
+/// Emitted by `report_chain_nonce` and `sweep_expired_reservations` when they
+/// move the next nonce of a foreign address.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [json])]
+pub struct NonceReconciled {
+    pub chain_id: U64,
+    pub foreign_address: ForeignAddress,
+    pub previous_nonce: U64,
+    pub nonce: U64,
+}
+
+/// Emitted by `report_chain_nonce` when the foreign chain is behind the next
+/// nonce of a foreign address. If the report is repeated without
+/// `chain_nonce` advancing, the transaction with `chain_nonce` was likely
+/// never broadcast, and every later one is stuck behind it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [json])]
+pub struct NonceGapDetected {
+    pub chain_id: U64,
+    pub foreign_address: ForeignAddress,
+    pub chain_nonce: U64,
+    pub next_nonce: U64,
+}
+
+/// Emitted by `update_gas_price`, so that the gas prices that fees were
+/// charged at can be audited.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [json])]
+pub struct GasPriceUpdated {
+    pub chain_id: U64,
+    pub max_fee_per_gas: U128,
+    pub max_priority_fee_per_gas: U128,
+    pub updated_by: AccountId,
+    pub block_height: U64,
+}
+
+/// Emitted by `simulate_call`, for a relayer to send `rpc_request` to an RPC
+/// node of the chain and report the result with `fulfill_simulation`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [json])]
+pub struct SimulationRequested {
+    pub request_id: U64,
+    pub caller: AccountId,
+    pub chain_id: U64,
+    /// The JSON-RPC `eth_call` request to send.
+    pub rpc_request: Value,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [json])]
+pub struct SimulationFulfilled {
+    pub request_id: U64,
+    pub success: bool,
+}
@@ -1,5 +1,6 @@
 use ethers_core::types::U256;
-use near_sdk::AccountId;
+use lib::foreign_address::ForeignAddress;
+use near_sdk::{AccountId, FunctionError, Gas};
 use thiserror::Error;
 
 #[derive(Debug, Error, Clone)]
@@ -83,6 +84,177 @@ pub enum RequestNonceError {
     NonceOverflow(#[from] NonceOverflowError),
 }
 
+#[derive(Debug, Error, Clone)]
+pub enum UnreasonableFeeError {
+    #[error("Max fee per gas {max_fee_per_gas} is outside of the allowed range of {min} to {max}")]
+    MaxFeeOutOfBand {
+        max_fee_per_gas: u128,
+        min: u128,
+        max: u128,
+    },
+    #[error("Max priority fee per gas {max_priority_fee_per_gas} exceeds max fee per gas {max_fee_per_gas}")]
+    PriorityFeeExceedsMaxFee {
+        max_priority_fee_per_gas: u128,
+        max_fee_per_gas: u128,
+    },
+}
+
+#[derive(Debug, Error, Clone)]
+#[error("Rate limit exceeded: at most {max_requests} sign requests per {window_seconds} seconds")]
+pub struct RateLimitExceededError {
+    pub max_requests: u32,
+    pub window_seconds: u64,
+}
+
+/// Returned by the main entry points of the contract, so that callers can
+/// tell failures apart. Each variant panics with its message when returned
+/// from a contract method.
+#[derive(Debug, Error, FunctionError)]
+pub enum ContractError {
+    #[error("Can only be called by {required}, not {account_id}")]
+    Unauthorized {
+        account_id: AccountId,
+        required: &'static str,
+    },
+    #[error("Contract is paused")]
+    Paused,
+    #[error("Configuration for chain ID {chain_id} does not exist")]
+    ChainNotConfigured { chain_id: u64 },
+    #[error("No current gas price for chain ID {chain_id}")]
+    GasPriceUnavailable { chain_id: u64 },
+    #[error(
+        "Gas price for chain ID {chain_id} is stale: last observed at block {observed_at_block}"
+    )]
+    GasPriceStale {
+        chain_id: u64,
+        observed_at_block: u64,
+    },
+    #[error("No current gas token price for chain ID {chain_id}")]
+    TokenPriceUnavailable { chain_id: u64 },
+    #[error("Gas token price for chain ID {chain_id} is stale: last observed at block {observed_at_block}")]
+    TokenPriceStale {
+        chain_id: u64,
+        observed_at_block: u64,
+    },
+    #[error("No current L1 gas price for rollup chain ID {chain_id}")]
+    L1GasPriceUnavailable { chain_id: u64 },
+    #[error(
+        "L1 gas price for rollup chain ID {chain_id} is stale: last observed at block {observed_at_block}"
+    )]
+    L1GasPriceStale {
+        chain_id: u64,
+        observed_at_block: u64,
+    },
+    #[error("Chain ID {chain_id} does not support transactions of type {transaction_type:#04x}")]
+    UnsupportedTransactionType { chain_id: u64, transaction_type: u8 },
+    #[error("Gas limit {gas_limit} is outside of the allowed range of {min} to {max}")]
+    GasLimitOutOfRange {
+        gas_limit: u128,
+        min: u128,
+        max: u128,
+    },
+    #[error(transparent)]
+    UnreasonableFee(#[from] UnreasonableFeeError),
+    #[error("Attached deposit is {shortfall} yoctoNEAR short of the fee of {fee} yoctoNEAR")]
+    InsufficientDeposit { fee: u128, shortfall: u128 },
+    #[error(
+        "Attached deposit is {shortfall} yoctoNEAR short of the storage deposit of \
+         {storage_deposit} yoctoNEAR, after any fee"
+    )]
+    InsufficientStorageDeposit {
+        storage_deposit: u128,
+        shortfall: u128,
+    },
+    #[error("Not enough gas prepaid: {required} required, {available} available")]
+    InsufficientGas { required: Gas, available: Gas },
+    #[error("Expected nonce {expected}, got {actual}")]
+    NonceUnavailable { expected: u64, actual: u64 },
+    #[error("Sign request is already pending")]
+    RequestPending,
+    #[error("Sign request has not completed")]
+    RequestNotCompleted,
+    #[error("Sign request does not exist")]
+    RequestNotFound,
+    #[error("Sign request is not pending")]
+    RequestNotPending,
+    #[error("Sign request cannot be cancelled until {timeout_seconds} seconds after it was made")]
+    RequestNotTimedOut { timeout_seconds: u64 },
+    #[error("MPC contract is not configured")]
+    MpcNotConfigured,
+    #[error("MPC signature request failed")]
+    MpcUnavailable,
+    #[error("Invalid signature from MPC contract: {0}")]
+    InvalidSignature(String),
+    #[error("Signature from MPC contract recovers to {recovered}, not to the derived address {expected}")]
+    SenderMismatch {
+        expected: ForeignAddress,
+        recovered: ForeignAddress,
+    },
+    #[error("Recipient {recipient} is not on the recipient allowlist of the caller")]
+    RecipientNotAllowed { recipient: ForeignAddress },
+    #[error("Contract creation is not allowed for callers with a recipient allowlist")]
+    ContractCreationNotAllowed,
+    #[error("Derived address {derived} does not match {registered}, the address registered for this path on chain ID {chain_id}")]
+    AddressMismatch {
+        chain_id: u64,
+        registered: ForeignAddress,
+        derived: ForeignAddress,
+    },
+    #[error("MPC public key is not configured")]
+    MpcKeyNotConfigured,
+    #[error("MPC public key version {key_version} is not configured (available versions: {available:?})")]
+    UnknownKeyVersion {
+        key_version: u32,
+        available: Vec<u32>,
+    },
+    #[error("MPC public key version {key_version} is already configured")]
+    KeyVersionAlreadyConfigured { key_version: u32 },
+    #[error("Derivation prefix version {prefix_version} is not configured (available versions: {available:?})")]
+    UnknownPrefixVersion {
+        prefix_version: u32,
+        available: Vec<u32>,
+    },
+    #[error("Derivation prefix version {prefix_version} is already configured")]
+    PrefixVersionAlreadyConfigured { prefix_version: u32 },
+    #[error("Invalid MPC public key: {0}")]
+    InvalidMpcKey(#[from] lib::kdf::PublicKeyConversionError),
+    #[error("{0}")]
+    InvalidInput(String),
+    #[error("Memo cannot be longer than {max_len} bytes, got {len}")]
+    MemoTooLong { len: usize, max_len: usize },
+    #[error("Path must be between 1 and {max_len} bytes long, got {len}")]
+    InvalidPath { len: usize, max_len: u32 },
+    #[error(transparent)]
+    RateLimitExceeded(#[from] RateLimitExceededError),
+    #[error("No fee was prepaid for sign request")]
+    NoPrepaidFee,
+    #[error("Fee has already been settled")]
+    FeeAlreadySettled,
+    #[error("Simulation does not exist")]
+    SimulationNotFound,
+    #[error("Simulation has already been fulfilled")]
+    SimulationAlreadyFulfilled,
+    #[error(transparent)]
+    ExpressionOverflow(#[from] ExpressionOverflowError),
+}
+
+impl From<ChainConfigurationDoesNotExistError> for ContractError {
+    fn from(e: ChainConfigurationDoesNotExistError) -> Self {
+        Self::ChainNotConfigured {
+            chain_id: e.chain_id,
+        }
+    }
+}
+
+impl From<ComputeFeeError> for ContractError {
+    fn from(e: ComputeFeeError) -> Self {
+        match e {
+            ComputeFeeError::ChainConfigurationDoesNotExist(e) => e.into(),
+            ComputeFeeError::ExpressionOverflow(e) => e.into(),
+        }
+    }
+}
+
 #[derive(Debug, Error, Clone)]
 #[error("Oracle query failed")]
 pub struct OracleQueryFailureError;
@@ -111,3 +283,11 @@ pub enum TryCreateTransactionCallbackError {
     #[error(transparent)]
     ExpressionOverflow(#[from] ExpressionOverflowError),
 }
+
+#[derive(Debug, Error, Clone)]
+pub enum ComputeFeeError {
+    #[error(transparent)]
+    ChainConfigurationDoesNotExist(#[from] ChainConfigurationDoesNotExistError),
+    #[error(transparent)]
+    ExpressionOverflow(#[from] ExpressionOverflowError),
+}
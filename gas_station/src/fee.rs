@@ -0,0 +1,384 @@
+use ethers_core::types::U256;
+use lib::foreign_address::ForeignAddress;
+use near_sdk::{
+    json_types::{U128, U64},
+    near, AccountId, NearToken,
+};
+
+use crate::error::ExpressionOverflowError;
+
+/// Basis points in 100%.
+pub const BPS_DENOMINATOR: u32 = 10_000;
+
+/// Calculate the NEAR fee charged to cover the foreign gas cost of a
+/// transaction, with a markup of `markup_bps` basis points applied on top.
+///
+/// `foreign_token_price_in_near` is the price of one whole foreign gas token
+/// (`10^foreign_token_decimals` of its smallest unit, e.g. `10^18` wei per
+/// ETH) in yoctoNEAR, so the fee needs no scaling by NEAR's decimals.
+///
+/// Rounds up, so that the fee never undercharges.
+///
+/// # Errors
+///
+/// - If the calculation overflows, or the resulting fee does not fit in
+///   128 bits.
+pub fn compute_fee(
+    gas_limit: u128,
+    gas_price: u128,
+    foreign_token_price_in_near: u128,
+    foreign_token_decimals: u8,
+    markup_bps: u32,
+) -> Result<NearToken, ExpressionOverflowError> {
+    compute_fee_with_l1_data_fee(
+        gas_limit,
+        gas_price,
+        0,
+        foreign_token_price_in_near,
+        foreign_token_decimals,
+        markup_bps,
+    )
+}
+
+/// The most of a deposit that is kept as the prepaid fee of a sign request
+/// with fee `fee`: the fee plus a buffer of `slippage_bps` basis points of
+/// it, which is refunded when the fee is settled. Saturates.
+#[must_use]
+pub fn max_prepaid_fee(fee: u128, slippage_bps: u32) -> u128 {
+    let buffer = U256::from(fee) * U256::from(slippage_bps) / U256::from(BPS_DENOMINATOR);
+    fee.saturating_add(u128::try_from(buffer).unwrap_or(u128::MAX))
+}
+
+/// The range of max fees per gas that sign requests may set, relative to
+/// the current gas price reported to the oracle: from `min_bps` to `max_bps`
+/// basis points of its max fee per gas. `min_bps` should leave room for the
+/// base fee to fall, but not for transactions that would never be included;
+/// `max_bps` bounds the fee that callers can be charged by mistake.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[near(serializers = [borsh, json])]
+pub struct FeeBand {
+    pub min_bps: u32,
+    pub max_bps: u32,
+}
+
+impl FeeBand {
+    /// `(min, max)` for the oracle's `max_fee_per_gas`. Saturates.
+    #[must_use]
+    pub fn bounds(&self, max_fee_per_gas: u128) -> (u128, u128) {
+        let scale = |bps: u32| {
+            let scaled =
+                U256::from(max_fee_per_gas) * U256::from(bps) / U256::from(BPS_DENOMINATOR);
+            u128::try_from(scaled).unwrap_or(u128::MAX)
+        };
+        (scale(self.min_bps), scale(self.max_bps))
+    }
+}
+
+/// Like [`compute_fee`], for a transaction on a rollup that also costs
+/// `l1_data_fee` (in the smallest unit of the foreign gas token) to publish
+/// to its L1.
+///
+/// # Errors
+///
+/// - If the calculation overflows, or the resulting fee does not fit in
+///   128 bits.
+pub fn compute_fee_with_l1_data_fee(
+    gas_limit: u128,
+    gas_price: u128,
+    l1_data_fee: u128,
+    foreign_token_price_in_near: u128,
+    foreign_token_decimals: u8,
+    markup_bps: u32,
+) -> Result<NearToken, ExpressionOverflowError> {
+    let numerator = U256::from(gas_limit)
+        .checked_mul(U256::from(gas_price))
+        .and_then(|x| x.checked_add(U256::from(l1_data_fee)))
+        .and_then(|x| x.checked_mul(U256::from(foreign_token_price_in_near)))
+        .and_then(|x| x.checked_mul(U256::from(BPS_DENOMINATOR.checked_add(markup_bps)?)))
+        .ok_or(ExpressionOverflowError)?;
+    let denominator = U256::from(10)
+        .checked_pow(foreign_token_decimals.into())
+        .and_then(|x| x.checked_mul(BPS_DENOMINATOR.into()))
+        .ok_or(ExpressionOverflowError)?;
+    let (b, rem) = numerator.div_mod(denominator);
+
+    let fee = if rem.is_zero() {
+        b
+    } else {
+        b.checked_add(U256::one()).ok_or(ExpressionOverflowError)?
+    };
+
+    u128::try_from(fee)
+        .map(NearToken::from_yoctonear)
+        .map_err(|_| ExpressionOverflowError)
+}
+
+/// Everything needed to make a sign request, as of the current block.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [json])]
+pub struct Quote {
+    pub foreign_address: ForeignAddress,
+    pub next_nonce: U64,
+    pub max_fee_per_gas: U128,
+    pub max_priority_fee_per_gas: U128,
+    /// The L1 data fee of the transaction, in the smallest unit of the
+    /// foreign gas token. Zero unless the chain is a rollup.
+    pub l1_data_fee: U128,
+    /// In yoctoNEAR, for the gas limit at `max_fee_per_gas`, plus the L1
+    /// data fee.
+    pub near_fee: U128,
+}
+
+/// A fee paid up front for a sign request, along with the pricing inputs it
+/// was computed from, so that it can later be settled against the actual
+/// cost of the transaction.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [borsh, json])]
+pub struct PrepaidFee {
+    pub payer: AccountId,
+    pub chain_id: U64,
+    /// In yoctoNEAR.
+    pub amount: U128,
+    pub foreign_token_price_in_near: U128,
+    /// Of the foreign gas token, which `foreign_token_price_in_near` is the
+    /// price of a whole unit of.
+    pub foreign_token_decimals: u8,
+    pub markup_bps: u32,
+    /// The L1 data fee that `amount` includes, in the smallest unit of the
+    /// foreign gas token. Charged as quoted, since relayers only report the
+    /// L2 gas used.
+    pub l1_data_fee: U128,
+    pub settlement: Option<FeeSettlement>,
+}
+
+impl PrepaidFee {
+    /// Settle the fee against the actual gas cost, repricing it with the
+    /// same token price and markup that the prepaid amount was based on.
+    ///
+    /// # Errors
+    ///
+    /// - If the calculation overflows.
+    pub fn settle(
+        &self,
+        actual_gas_used: u128,
+        actual_gas_price: u128,
+    ) -> Result<FeeSettlement, ExpressionOverflowError> {
+        let actual_fee = compute_fee_with_l1_data_fee(
+            actual_gas_used,
+            actual_gas_price,
+            self.l1_data_fee.0,
+            self.foreign_token_price_in_near.0,
+            self.foreign_token_decimals,
+            self.markup_bps,
+        )?
+        .as_yoctonear();
+
+        Ok(FeeSettlement {
+            actual_fee: actual_fee.into(),
+            refund: self.amount.0.saturating_sub(actual_fee).into(),
+            shortfall: actual_fee.saturating_sub(self.amount.0).into(),
+        })
+    }
+}
+
+/// All amounts are in yoctoNEAR. At most one of `refund` and `shortfall` is
+/// nonzero.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [borsh, json])]
+pub struct FeeSettlement {
+    pub actual_fee: U128,
+    /// Overpayment returned to the payer.
+    pub refund: U128,
+    /// Cost not covered by the prepaid amount, which is absorbed by the
+    /// contract.
+    pub shortfall: U128,
+}
+
+#[test]
+fn test_fee_band_bounds() {
+    let band = FeeBand {
+        min_bps: 5_000,
+        max_bps: 30_000,
+    };
+    assert_eq!(
+        band.bounds(20_000_000_000),
+        (10_000_000_000, 60_000_000_000)
+    );
+    assert_eq!(band.bounds(u128::MAX), (u128::MAX / 2, u128::MAX));
+}
+
+#[test]
+fn test_compute_fee() {
+    // 21000 gas at 20 gwei, 1 ETH = 1000 NEAR
+    let fee = compute_fee(
+        21_000,
+        20_000_000_000,
+        NearToken::from_near(1000).as_yoctonear(),
+        18,
+        0,
+    )
+    .unwrap();
+
+    // 0.00042 ETH = 0.42 NEAR
+    assert_eq!(fee, NearToken::from_millinear(420));
+}
+
+#[test]
+fn test_compute_fee_with_markup() {
+    let fee = compute_fee(
+        21_000,
+        20_000_000_000,
+        NearToken::from_near(1000).as_yoctonear(),
+        18,
+        500,
+    )
+    .unwrap();
+
+    // 0.42 NEAR + 5%
+    assert_eq!(fee, NearToken::from_millinear(441));
+}
+
+#[test]
+fn test_compute_fee_rounds_up() {
+    // 1 wei at 1 yoctoNEAR per ETH is a tiny fraction of a yoctoNEAR.
+    assert_eq!(
+        compute_fee(1, 1, 1, 18, 0).unwrap(),
+        NearToken::from_yoctonear(1),
+    );
+    assert_eq!(
+        compute_fee(0, 1, 1, 18, 0).unwrap(),
+        NearToken::from_yoctonear(0)
+    );
+}
+
+#[test]
+fn test_compute_fee_overflow() {
+    assert!(compute_fee(u128::MAX, u128::MAX, u128::MAX, 18, 0).is_err());
+    assert!(compute_fee(u128::MAX, u128::MAX, 1, 18, 0).is_err());
+    assert!(compute_fee(1, 1, 1, 18, u32::MAX).is_err());
+}
+
+#[test]
+fn test_compute_fee_token_decimals() {
+    // 21000 gas at 20 gwei on an 18-decimal chain, 1 ETH = 1000 NEAR, is
+    // 0.42 NEAR, whatever the decimals of the price.
+    assert_eq!(
+        compute_fee(
+            21_000,
+            20_000_000_000,
+            NearToken::from_near(1000).as_yoctonear(),
+            18,
+            0,
+        )
+        .unwrap(),
+        NearToken::from_millinear(420),
+    );
+
+    // A 6-decimal gas token: 100,000 gas at 3 units per gas is 0.3 tokens,
+    // which at 1 token = 2 NEAR is 0.6 NEAR.
+    assert_eq!(
+        compute_fee(100_000, 3, NearToken::from_near(2).as_yoctonear(), 6, 0).unwrap(),
+        NearToken::from_millinear(600),
+    );
+    // The same cost in an 18-decimal token is a 10^12th of that.
+    assert_eq!(
+        compute_fee(100_000, 3, NearToken::from_near(2).as_yoctonear(), 18, 0).unwrap(),
+        NearToken::from_yoctonear(NearToken::from_millinear(600).as_yoctonear() / 10_u128.pow(12)),
+    );
+
+    // 10^78 does not fit in 256 bits.
+    assert!(compute_fee(1, 1, 1, 78, 0).is_err());
+    assert!(compute_fee(1, 1, 1, u8::MAX, 0).is_err());
+}
+
+#[cfg(test)]
+fn prepaid_fee(amount: NearToken) -> PrepaidFee {
+    PrepaidFee {
+        payer: "alice.near".parse().unwrap(),
+        chain_id: 97.into(),
+        amount: amount.as_yoctonear().into(),
+        foreign_token_price_in_near: NearToken::from_near(1000).as_yoctonear().into(),
+        foreign_token_decimals: 18,
+        markup_bps: 0,
+        l1_data_fee: 0.into(),
+        settlement: None,
+    }
+}
+
+#[test]
+fn test_max_prepaid_fee() {
+    assert_eq!(max_prepaid_fee(1_000, 0), 1_000);
+    assert_eq!(max_prepaid_fee(1_000, 500), 1_050);
+    // Rounds the buffer down.
+    assert_eq!(max_prepaid_fee(999, 1), 999);
+    assert_eq!(max_prepaid_fee(u128::MAX, BPS_DENOMINATOR), u128::MAX);
+}
+
+#[test]
+fn test_compute_fee_with_l1_data_fee() {
+    // 21000 gas at 20 gwei plus 0.00058 ETH, 1 ETH = 1000 NEAR is 1 NEAR.
+    let fee = compute_fee_with_l1_data_fee(
+        21_000,
+        20_000_000_000,
+        580_000_000_000_000,
+        NearToken::from_near(1000).as_yoctonear(),
+        18,
+        0,
+    )
+    .unwrap();
+    assert_eq!(fee, NearToken::from_near(1));
+
+    assert_eq!(
+        compute_fee_with_l1_data_fee(21_000, 20_000_000_000, 0, 1_000, 18, 250).unwrap(),
+        compute_fee(21_000, 20_000_000_000, 1_000, 18, 250).unwrap(),
+    );
+}
+
+#[test]
+fn test_prepaid_fee_settle_keeps_l1_data_fee() {
+    let mut prepaid_fee = prepaid_fee(NearToken::from_near(1));
+    prepaid_fee.l1_data_fee = 580_000_000_000_000.into();
+
+    // Only the L2 part of the fee changes with the actual gas used.
+    let settlement = prepaid_fee.settle(10_500, 20_000_000_000).unwrap();
+    assert_eq!(
+        settlement.actual_fee,
+        NearToken::from_millinear(790).as_yoctonear().into(),
+    );
+    assert_eq!(
+        settlement.refund,
+        NearToken::from_millinear(210).as_yoctonear().into(),
+    );
+}
+
+#[test]
+fn test_prepaid_fee_settle() {
+    // 21000 gas at 20 gwei, 1 ETH = 1000 NEAR is 0.42 NEAR.
+    let refund = prepaid_fee(NearToken::from_millinear(500))
+        .settle(21_000, 20_000_000_000)
+        .unwrap();
+    assert_eq!(
+        refund.actual_fee,
+        NearToken::from_millinear(420).as_yoctonear().into()
+    );
+    assert_eq!(
+        refund.refund,
+        NearToken::from_millinear(80).as_yoctonear().into()
+    );
+    assert_eq!(refund.shortfall, U128(0));
+
+    let exact = prepaid_fee(NearToken::from_millinear(420))
+        .settle(21_000, 20_000_000_000)
+        .unwrap();
+    assert_eq!(exact.refund, U128(0));
+    assert_eq!(exact.shortfall, U128(0));
+
+    let shortfall = prepaid_fee(NearToken::from_millinear(400))
+        .settle(21_000, 20_000_000_000)
+        .unwrap();
+    assert_eq!(shortfall.refund, U128(0));
+    assert_eq!(
+        shortfall.shortfall,
+        NearToken::from_millinear(20).as_yoctonear().into()
+    );
+}
@@ -0,0 +1,324 @@
+use near_sdk::{
+    json_types::{U128, U64},
+    near,
+    store::LookupMap,
+    IntoStorageKey,
+};
+
+use crate::error::ContractError;
+
+const WEI_PER_GWEI: u128 = 1_000_000_000;
+/// Gwei have nine decimal places of wei.
+const GWEI_DECIMALS: usize = 9;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [borsh, json])]
+pub struct GasPrice {
+    pub max_fee_per_gas: U128,
+    pub max_priority_fee_per_gas: U128,
+    pub observed_at_block: U64,
+}
+
+/// A gas price reported by a relayer, tagged with its unit so that gwei are
+/// never mistaken for wei.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [json])]
+pub enum GasPriceInput {
+    /// A decimal string with up to nine decimal places, e.g. `"1.5"`.
+    Gwei(String),
+    Wei(U128),
+}
+
+impl GasPriceInput {
+    /// # Errors
+    ///
+    /// Returns an error if a price in gwei is not a non-negative decimal
+    /// number of wei, or does not fit in a `u128` once converted.
+    pub fn to_wei(&self) -> Result<u128, ContractError> {
+        match self {
+            Self::Gwei(gwei) => parse_gwei(gwei),
+            Self::Wei(wei) => Ok(wei.0),
+        }
+    }
+}
+
+impl From<u128> for GasPriceInput {
+    fn from(wei: u128) -> Self {
+        Self::Wei(wei.into())
+    }
+}
+
+/// Converts a decimal amount of gwei, e.g. `"1.5"`, to wei, exactly.
+///
+/// # Errors
+///
+/// Returns an error if `gwei` is not made of digits with at most one
+/// decimal point, has more than nine decimal places (which would be a
+/// fraction of a wei), or overflows a `u128` once converted.
+pub fn parse_gwei(gwei: &str) -> Result<u128, ContractError> {
+    let invalid = || ContractError::InvalidInput(format!("Invalid gas price in gwei: {gwei:?}"));
+
+    let (whole, fraction) = gwei.split_once('.').unwrap_or((gwei, ""));
+    if whole.is_empty()
+        || fraction.len() > GWEI_DECIMALS
+        || !whole
+            .bytes()
+            .chain(fraction.bytes())
+            .all(|b| b.is_ascii_digit())
+    {
+        return Err(invalid());
+    }
+
+    let whole: u128 = whole.parse().map_err(|_| invalid())?;
+    let fraction: u128 = format!("{fraction:0<GWEI_DECIMALS$}")
+        .parse()
+        .map_err(|_| invalid())?;
+
+    whole
+        .checked_mul(WEI_PER_GWEI)
+        .and_then(|wei| wei.checked_add(fraction))
+        .ok_or_else(invalid)
+}
+
+/// The price of one whole gas token of a foreign chain (`10^decimals` of its
+/// smallest unit, with the decimals of the chain's configuration).
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [borsh, json])]
+pub struct TokenPrice {
+    /// In yoctoNEAR.
+    pub price_in_near: U128,
+    pub observed_at_block: U64,
+}
+
+/// The gas price on the L1 that a rollup publishes its transactions to, in
+/// the smallest unit of the rollup's gas token.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [borsh, json])]
+pub struct L1GasPrice {
+    pub gas_price: U128,
+    pub observed_at_block: U64,
+}
+
+/// Latest gas price and gas token price quotes for each foreign chain, as
+/// reported by a trusted relayer.
+#[derive(Debug)]
+#[near]
+pub struct GasPriceOracle {
+    quotes: LookupMap<u64, GasPrice>,
+    token_prices: LookupMap<u64, TokenPrice>,
+    /// By the chain ID of the rollup, not of its L1.
+    l1_gas_prices: LookupMap<u64, L1GasPrice>,
+    /// Quotes older than this many blocks are considered stale.
+    pub max_age_blocks: u64,
+}
+
+impl GasPriceOracle {
+    pub fn new(
+        gas_prices_prefix: impl IntoStorageKey,
+        token_prices_prefix: impl IntoStorageKey,
+        l1_gas_prices_prefix: impl IntoStorageKey,
+        max_age_blocks: u64,
+    ) -> Self {
+        Self {
+            quotes: LookupMap::new(gas_prices_prefix),
+            token_prices: LookupMap::new(token_prices_prefix),
+            l1_gas_prices: LookupMap::new(l1_gas_prices_prefix),
+            max_age_blocks,
+        }
+    }
+
+    pub fn update_gas_price(&mut self, chain_id: u64, gas_price: GasPrice) {
+        self.quotes.insert(chain_id, gas_price);
+    }
+
+    /// Returns the latest quote for `chain_id` regardless of its age.
+    pub fn latest_gas_price(&self, chain_id: u64) -> Option<&GasPrice> {
+        self.quotes.get(&chain_id)
+    }
+
+    /// Returns the latest quote for `chain_id`, or `None` if there is no
+    /// quote or it is stale as of `block_height`.
+    pub fn current_gas_price(&self, chain_id: u64, block_height: u64) -> Option<&GasPrice> {
+        self.latest_gas_price(chain_id)
+            .filter(|quote| !self.is_stale(quote, block_height))
+    }
+
+    pub fn is_stale(&self, gas_price: &GasPrice, block_height: u64) -> bool {
+        self.is_observation_stale(gas_price.observed_at_block.0, block_height)
+    }
+
+    pub fn update_token_price(&mut self, chain_id: u64, token_price: TokenPrice) {
+        self.token_prices.insert(chain_id, token_price);
+    }
+
+    /// Returns the latest gas token price for `chain_id` regardless of its
+    /// age.
+    pub fn latest_token_price(&self, chain_id: u64) -> Option<&TokenPrice> {
+        self.token_prices.get(&chain_id)
+    }
+
+    /// Returns the latest gas token price for `chain_id`, or `None` if there
+    /// is no quote or it is stale as of `block_height`.
+    pub fn current_token_price(&self, chain_id: u64, block_height: u64) -> Option<&TokenPrice> {
+        self.latest_token_price(chain_id)
+            .filter(|quote| !self.is_token_price_stale(quote, block_height))
+    }
+
+    pub fn is_token_price_stale(&self, token_price: &TokenPrice, block_height: u64) -> bool {
+        self.is_observation_stale(token_price.observed_at_block.0, block_height)
+    }
+
+    pub fn update_l1_gas_price(&mut self, chain_id: u64, l1_gas_price: L1GasPrice) {
+        self.l1_gas_prices.insert(chain_id, l1_gas_price);
+    }
+
+    /// Returns the latest L1 gas price for the rollup `chain_id` regardless
+    /// of its age.
+    pub fn latest_l1_gas_price(&self, chain_id: u64) -> Option<&L1GasPrice> {
+        self.l1_gas_prices.get(&chain_id)
+    }
+
+    /// Returns the latest L1 gas price for the rollup `chain_id`, or `None`
+    /// if there is no quote or it is stale as of `block_height`.
+    pub fn current_l1_gas_price(&self, chain_id: u64, block_height: u64) -> Option<&L1GasPrice> {
+        self.latest_l1_gas_price(chain_id)
+            .filter(|quote| !self.is_l1_gas_price_stale(quote, block_height))
+    }
+
+    pub fn is_l1_gas_price_stale(&self, l1_gas_price: &L1GasPrice, block_height: u64) -> bool {
+        self.is_observation_stale(l1_gas_price.observed_at_block.0, block_height)
+    }
+
+    fn is_observation_stale(&self, observed_at_block: u64, block_height: u64) -> bool {
+        block_height.saturating_sub(observed_at_block) > self.max_age_blocks
+    }
+}
+
+#[cfg(test)]
+fn gas_price(max_fee_per_gas: u128, observed_at_block: u64) -> GasPrice {
+    GasPrice {
+        max_fee_per_gas: max_fee_per_gas.into(),
+        max_priority_fee_per_gas: 1_000_000_000.into(),
+        observed_at_block: observed_at_block.into(),
+    }
+}
+
+#[test]
+fn test_update_and_retrieve_gas_price() {
+    let mut oracle = GasPriceOracle::new(b"g", b"t", b"l", 10);
+
+    assert_eq!(oracle.current_gas_price(1, 100), None);
+
+    oracle.update_gas_price(1, gas_price(30_000_000_000, 100));
+    assert_eq!(
+        oracle.current_gas_price(1, 100),
+        Some(&gas_price(30_000_000_000, 100)),
+    );
+    assert_eq!(oracle.current_gas_price(97, 100), None);
+
+    oracle.update_gas_price(1, gas_price(40_000_000_000, 105));
+    assert_eq!(
+        oracle.current_gas_price(1, 105),
+        Some(&gas_price(40_000_000_000, 105)),
+    );
+}
+
+#[test]
+fn test_stale_gas_price() {
+    let mut oracle = GasPriceOracle::new(b"g", b"t", b"l", 10);
+
+    oracle.update_gas_price(1, gas_price(30_000_000_000, 100));
+
+    assert!(oracle.current_gas_price(1, 110).is_some());
+    assert_eq!(oracle.current_gas_price(1, 111), None);
+    assert_eq!(
+        oracle.latest_gas_price(1),
+        Some(&gas_price(30_000_000_000, 100)),
+    );
+
+    oracle.max_age_blocks = 20;
+    assert!(oracle.current_gas_price(1, 111).is_some());
+}
+
+#[test]
+fn test_stale_token_price() {
+    let mut oracle = GasPriceOracle::new(b"g", b"t", b"l", 10);
+    let token_price = TokenPrice {
+        price_in_near: 1_000.into(),
+        observed_at_block: 100.into(),
+    };
+
+    oracle.update_token_price(1, token_price.clone());
+
+    assert_eq!(oracle.current_token_price(1, 110), Some(&token_price));
+    assert_eq!(oracle.current_token_price(1, 111), None);
+    assert_eq!(oracle.latest_token_price(1), Some(&token_price));
+    assert_eq!(oracle.current_token_price(97, 100), None);
+    // Independent from gas prices.
+    assert_eq!(oracle.current_gas_price(1, 100), None);
+}
+
+#[test]
+fn test_stale_l1_gas_price() {
+    let mut oracle = GasPriceOracle::new(b"g", b"t", b"l", 10);
+    let l1_gas_price = L1GasPrice {
+        gas_price: 30_000_000_000.into(),
+        observed_at_block: 100.into(),
+    };
+
+    oracle.update_l1_gas_price(10, l1_gas_price.clone());
+
+    assert_eq!(oracle.current_l1_gas_price(10, 110), Some(&l1_gas_price));
+    assert_eq!(oracle.current_l1_gas_price(10, 111), None);
+    assert_eq!(oracle.latest_l1_gas_price(10), Some(&l1_gas_price));
+    // Independent from L2 gas prices.
+    assert_eq!(oracle.current_gas_price(10, 100), None);
+}
+
+#[test]
+fn test_parse_gwei() {
+    assert_eq!(parse_gwei("1.5").unwrap(), 1_500_000_000);
+    assert_eq!(parse_gwei("30").unwrap(), 30_000_000_000);
+    assert_eq!(parse_gwei("0").unwrap(), 0);
+    assert_eq!(parse_gwei("0.000000001").unwrap(), 1);
+    assert_eq!(parse_gwei("2.").unwrap(), 2_000_000_000);
+    assert_eq!(
+        parse_gwei("340282366920938463463374607431.768211455").unwrap(),
+        u128::MAX,
+    );
+
+    for invalid in [
+        "",
+        "-1",
+        "+1",
+        ".5",
+        "1.5.0",
+        "NaN",
+        "inf",
+        "1e9",
+        " 1",
+        // A fraction of a wei.
+        "0.0000000001",
+        // Overflows.
+        "340282366920938463463374607432",
+    ] {
+        assert!(
+            matches!(parse_gwei(invalid), Err(ContractError::InvalidInput(_))),
+            "{invalid:?}",
+        );
+    }
+}
+
+#[test]
+fn test_gas_price_input_json() {
+    use near_sdk::serde_json::{from_value, json};
+
+    let gwei: GasPriceInput = from_value(json!({ "Gwei": "1.5" })).unwrap();
+    assert_eq!(gwei.to_wei().unwrap(), 1_500_000_000);
+
+    let wei: GasPriceInput = from_value(json!({ "Wei": "1500000000" })).unwrap();
+    assert_eq!(wei.to_wei().unwrap(), 1_500_000_000);
+
+    // Untagged prices are ambiguous.
+    assert!(from_value::<GasPriceInput>(json!("1500000000")).is_err());
+}
@@ -6,14 +6,11 @@ use near_sdk::{
     collections::UnorderedMap, env, near, near_bindgen, require, AccountId, Promise, PromiseError,
     PromiseOrValue, PublicKey,
 };
-use near_sdk_contract_tools::{
-    nft::{ext_nep171, Nep171Receiver, TokenId},
-    rbac::Rbac,
-};
+use near_sdk_contract_tools::nft::{ext_nep171, Nep171Receiver, TokenId};
 
 #[allow(unused_imports)]
 use crate::ContractExt;
-use crate::{ChainKeyAuthorization, ChainKeyData, Contract, Role, StorageKey};
+use crate::{ChainKeyAuthorization, ChainKeyData, Contract, StorageKey};
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 #[near(serializers = [json])]
@@ -80,8 +77,7 @@ impl Contract {
             env::panic_str("Failed to retrieve public key from signer contract");
         };
 
-        let sent_from_contract_administrator =
-            <Self as Rbac>::has_role(&account_id, &Role::Administrator);
+        let sent_from_contract_administrator = Self::is_administrator(&account_id);
 
         let marked_as_paymaster_key = || {
             near_sdk::serde_json::from_str::<ChainKeyReceiverMsg>(&msg)
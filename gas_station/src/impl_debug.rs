@@ -1,12 +1,24 @@
+use lib::{
+    hash::to_hex_prefixed,
+    kdf::{derive_epsilon, EPSILON_DERIVATION_PREFIX},
+};
 use near_sdk::{
-    collections::{UnorderedMap, UnorderedSet, Vector},
+    collections::{TreeMap, UnorderedMap, UnorderedSet, Vector},
     env,
     json_types::{Base64VecU8, U64},
-    near, AccountId,
+    near,
+    store::LookupMap,
+    AccountId,
 };
-use near_sdk_contract_tools::rbac::Rbac;
+use near_sdk_contract_tools::owner::Owner;
 
-use crate::{Contract, ContractExt, Flags, Role, StorageKey, DEFAULT_EXPIRE_SEQUENCE_AFTER_BLOCKS};
+use crate::{
+    gas_price_oracle::GasPriceOracle, nonce_manager::NonceManager, rate_limiter::RateLimiter,
+    request_status::CancelledNoncePolicy, treasury::Treasury, Contract, ContractExt, Flags,
+    StorageKey, DEFAULT_EXPIRE_NONCE_RESERVATION_AFTER_BLOCKS,
+    DEFAULT_EXPIRE_SEQUENCE_AFTER_BLOCKS, DEFAULT_GAS_PRICE_MAX_AGE_BLOCKS, DEFAULT_MAX_PATH_LEN,
+    DEFAULT_MPC_SIGN_GAS, DEFAULT_SIGN_REQUEST_TIMEOUT_SECONDS,
+};
 
 #[derive(Clone, Debug)]
 #[near(serializers = [json])]
@@ -47,14 +59,68 @@ impl Contract {
             ),
             signed_transaction_sequences: Vector::new(StorageKey::SignedTransactionSequences),
             collected_fees: UnorderedMap::new(StorageKey::CollectedFees),
+            nonces: NonceManager::new(StorageKey::Nonces, StorageKey::NonceReservations),
+            gas_price_oracle: GasPriceOracle::new(
+                StorageKey::GasPrices,
+                StorageKey::TokenPrices,
+                StorageKey::L1GasPrices,
+                DEFAULT_GAS_PRICE_MAX_AGE_BLOCKS,
+            ),
+            fee_markup_bps: 0,
+            mpc_contract_id: None,
+            mpc_keys: TreeMap::new(StorageKey::MpcKeys),
+            rate_limiter: RateLimiter::new(StorageKey::RateLimits, StorageKey::RateLimitLog),
+            sign_requests: LookupMap::new(StorageKey::SignRequests),
+            sign_request_ids: Vector::new(StorageKey::SignRequestIds),
+            prepaid_fees: LookupMap::new(StorageKey::PrepaidFees),
+            sign_batches: LookupMap::new(StorageKey::SignBatches),
+            pending_sign_requests: LookupMap::new(StorageKey::PendingSignRequests),
+            sign_request_timeout_seconds: DEFAULT_SIGN_REQUEST_TIMEOUT_SECONDS,
+            cancelled_nonce_policy: CancelledNoncePolicy::default(),
+            sign_fees: UnorderedMap::new(StorageKey::SignFees),
+            mpc_sign_gas: DEFAULT_MPC_SIGN_GAS,
+            derivation_prefixes: TreeMap::new(StorageKey::DerivationPrefixes),
+            max_path_len: DEFAULT_MAX_PATH_LEN,
+            treasury: Treasury::default(),
+            fee_slippage_bps: 0,
+            sign_request_memos: LookupMap::new(StorageKey::SignRequestMemos),
+            expire_nonce_reservation_after_blocks: DEFAULT_EXPIRE_NONCE_RESERVATION_AFTER_BLOCKS,
+            mpc_fallback_contract_ids: Vec::new(),
+            sign_request_providers: LookupMap::new(StorageKey::SignRequestProviders),
+            registered_addresses: LookupMap::new(StorageKey::RegisteredAddresses),
+            simulations: LookupMap::new(StorageKey::Simulations),
+            sign_stats: UnorderedMap::new(StorageKey::SignStats),
+            recipient_allowlists: LookupMap::new(StorageKey::RecipientAllowlists),
+            fee_band: None,
         };
 
-        Rbac::add_role(
-            &mut contract,
-            &env::predecessor_account_id(),
-            &Role::Administrator,
-        );
+        contract
+            .derivation_prefixes
+            .insert(&0, &EPSILON_DERIVATION_PREFIX.to_string());
+        Owner::init(&mut contract, &env::predecessor_account_id());
 
         contract
     }
+
+    /// Diagnostic view for auditing key derivation: the `derive_epsilon`
+    /// scalar for `account_id` and `path`, as big-endian hex. Reads nothing
+    /// from contract state, so the result can be reproduced off-chain.
+    pub fn debug_epsilon(&self, account_id: AccountId, path: String) -> String {
+        to_hex_prefixed(&derive_epsilon(&account_id, &path).to_bytes())
+    }
+}
+
+#[test]
+fn test_debug_epsilon() {
+    let contract = crate::setup_contract();
+    let alice: AccountId = "alice.near".parse().unwrap();
+
+    assert_eq!(
+        contract.debug_epsilon(alice.clone(), "ethereum-1".to_string()),
+        ethers_core::utils::hex::encode_prefixed(derive_epsilon(&alice, "ethereum-1").to_bytes()),
+    );
+    assert_ne!(
+        contract.debug_epsilon(alice.clone(), "ethereum-1".to_string()),
+        contract.debug_epsilon(alice, "ethereum-2".to_string()),
+    );
 }
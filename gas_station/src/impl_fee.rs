@@ -0,0 +1,471 @@
+use lib::hash::to_hex_prefixed;
+use near_sdk::{
+    env, json_types::U128, near_bindgen, require, AccountId, NearToken, Promise, PromiseOrValue,
+};
+use near_sdk_contract_tools::{owner::Owner, standard::nep297::Event};
+
+use crate::{
+    contract_event::{ContractEvent, FeeSettled},
+    error::ContractError,
+    fee::PrepaidFee,
+    request_status::{parse_request_id, RequestStatus},
+    treasury::{storage_reserve, TreasuryInfo},
+    Contract, ContractExt,
+};
+
+#[near_bindgen]
+impl Contract {
+    /// `None` if no fee was prepaid for the request.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `request_id` is not 32 hex-encoded bytes.
+    #[handle_result]
+    pub fn get_prepaid_fee(&self, request_id: String) -> Result<Option<PrepaidFee>, ContractError> {
+        Ok(self
+            .prepaid_fees
+            .get(&parse_request_id(&request_id)?)
+            .cloned())
+    }
+
+    /// Settle the fee prepaid for a completed sign request against the
+    /// actual cost of the transaction on the foreign chain, as observed by
+    /// the relayer. If the prepaid fee exceeds the actual cost, the
+    /// difference is refunded to the payer. If it falls short, nothing is
+    /// refunded and the shortfall is recorded in the `fee_settled` event.
+    ///
+    /// Each fee can only be settled once.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the caller is not a relayer, or if the
+    /// request has not completed, was not prepaid, or was already settled.
+    #[handle_result]
+    pub fn settle_fee(
+        &mut self,
+        request_id: String,
+        actual_gas_used: U128,
+        actual_gas_price: U128,
+    ) -> Result<PromiseOrValue<()>, ContractError> {
+        Self::check_relayer()?;
+
+        let request_id = parse_request_id(&request_id)?;
+
+        if !matches!(
+            self.sign_requests.get(&request_id),
+            Some(RequestStatus::Completed { .. }),
+        ) {
+            return Err(ContractError::RequestNotCompleted);
+        }
+
+        let prepaid_fee = self
+            .prepaid_fees
+            .get_mut(&request_id)
+            .ok_or(ContractError::NoPrepaidFee)?;
+
+        if prepaid_fee.settlement.is_some() {
+            return Err(ContractError::FeeAlreadySettled);
+        }
+
+        let settlement = prepaid_fee.settle(actual_gas_used.0, actual_gas_price.0)?;
+        prepaid_fee.settlement = Some(settlement.clone());
+        let payer = prepaid_fee.payer.clone();
+        let chain_id = prepaid_fee.chain_id.0;
+        let prepaid_fee_amount = prepaid_fee.amount.0;
+
+        let refund = settlement.refund.0;
+        self.debit_sign_fees(chain_id, refund);
+        self.treasury
+            .settle(prepaid_fee_amount.saturating_sub(refund));
+        if let Some(mut stats) = self.sign_stats.get(&chain_id) {
+            stats.fees_collected = stats.fees_collected.saturating_sub(refund);
+            self.sign_stats.insert(&chain_id, &stats);
+        }
+
+        ContractEvent::FeeSettled(FeeSettled {
+            request_id: to_hex_prefixed(&request_id),
+            payer: payer.clone(),
+            settlement,
+        })
+        .emit();
+
+        Ok(if refund > 0 {
+            PromiseOrValue::Promise(Promise::new(payer).transfer(NearToken::from_yoctonear(refund)))
+        } else {
+            PromiseOrValue::Value(())
+        })
+    }
+
+    /// NEAR fees charged for sign requests that have been settled and not
+    /// withdrawn yet. Prepaid fees are only withdrawable once settled, since
+    /// until then they may still be refunded.
+    pub fn get_withdrawable_fees(&self) -> NearToken {
+        self.treasury.withdrawable_balance()
+    }
+
+    /// How the balance of the contract splits between withdrawable fees and
+    /// the balance staked for storage.
+    pub fn get_treasury(&self) -> TreasuryInfo {
+        TreasuryInfo {
+            withdrawable: self.treasury.withdrawable_balance(),
+            unsettled: self.treasury.unsettled_balance(),
+            storage_reserved: storage_reserve(),
+            total: env::account_balance(),
+        }
+    }
+
+    /// Transfer `amount` of the withdrawable sign request fees (all of them
+    /// by default) to `to`. Can only be called by the owner. Never
+    /// withdraws into the balance that the contract needs to cover its
+    /// storage.
+    #[payable]
+    pub fn withdraw_fees(&mut self, amount: Option<NearToken>, to: AccountId) -> Promise {
+        near_sdk::assert_one_yocto();
+        <Self as Owner>::require_owner();
+
+        let withdrawable = self.get_withdrawable_fees();
+        let amount = amount.unwrap_or(withdrawable);
+        require!(amount <= withdrawable, "Not enough fees to withdraw");
+
+        require!(
+            env::account_balance()
+                .checked_sub(amount)
+                .is_some_and(|remaining| remaining >= storage_reserve()),
+            "Withdrawal would leave too little balance to cover storage",
+        );
+
+        self.treasury.debit(amount.as_yoctonear());
+
+        Promise::new(to).transfer(amount)
+    }
+}
+
+#[cfg(test)]
+fn setup_prepaid_request(contract: &mut Contract, amount: NearToken) -> String {
+    let request_id = [1; 32];
+    contract.sign_requests.insert(
+        request_id,
+        RequestStatus::Completed {
+            raw_transaction: "0x02".to_string(),
+            sender: lib::foreign_address::ForeignAddress([1; 20]),
+        },
+    );
+    contract.prepaid_fees.insert(
+        request_id,
+        PrepaidFee {
+            payer: "alice.near".parse().unwrap(),
+            chain_id: 97.into(),
+            amount: amount.as_yoctonear().into(),
+            foreign_token_price_in_near: NearToken::from_near(1000).as_yoctonear().into(),
+            foreign_token_decimals: 18,
+            markup_bps: 0,
+            l1_data_fee: 0.into(),
+            settlement: None,
+        },
+    );
+    ethers_core::utils::hex::encode(request_id)
+}
+
+#[cfg(test)]
+pub(crate) fn refund_transfers() -> Vec<(String, NearToken)> {
+    use near_sdk::{mock::MockAction, test_utils::get_created_receipts};
+
+    get_created_receipts()
+        .into_iter()
+        .flat_map(|receipt| {
+            let receiver_id = receipt.receiver_id.to_string();
+            receipt.actions.into_iter().filter_map(move |action| {
+                if let MockAction::Transfer { deposit, .. } = action {
+                    Some((receiver_id.clone(), deposit))
+                } else {
+                    None
+                }
+            })
+        })
+        .collect()
+}
+
+#[test]
+fn test_settle_fee_refund() {
+    let mut contract = crate::setup_contract();
+    let request_id = setup_prepaid_request(&mut contract, NearToken::from_millinear(500));
+
+    // 21000 gas at 20 gwei, 1 ETH = 1000 NEAR is 0.42 NEAR.
+    let Ok(PromiseOrValue::Promise(_)) =
+        contract.settle_fee(request_id.clone(), 21_000.into(), 20_000_000_000.into())
+    else {
+        panic!("Expected a refund");
+    };
+
+    assert_eq!(
+        refund_transfers(),
+        vec![("alice.near".to_string(), NearToken::from_millinear(80))],
+    );
+
+    let settlement = contract
+        .get_prepaid_fee(request_id)
+        .unwrap()
+        .unwrap()
+        .settlement
+        .unwrap();
+    assert_eq!(
+        settlement.actual_fee,
+        NearToken::from_millinear(420).as_yoctonear().into(),
+    );
+    assert_eq!(settlement.shortfall, U128(0));
+}
+
+#[test]
+fn test_settle_fee_exact() {
+    let mut contract = crate::setup_contract();
+    let request_id = setup_prepaid_request(&mut contract, NearToken::from_millinear(420));
+
+    assert!(matches!(
+        contract.settle_fee(request_id.clone(), 21_000.into(), 20_000_000_000.into()),
+        Ok(PromiseOrValue::Value(())),
+    ));
+    assert!(refund_transfers().is_empty());
+
+    let settlement = contract
+        .get_prepaid_fee(request_id)
+        .unwrap()
+        .unwrap()
+        .settlement
+        .unwrap();
+    assert_eq!(settlement.refund, U128(0));
+    assert_eq!(settlement.shortfall, U128(0));
+}
+
+#[test]
+fn test_settle_fee_shortfall() {
+    let mut contract = crate::setup_contract();
+    let request_id = setup_prepaid_request(&mut contract, NearToken::from_millinear(400));
+
+    assert!(matches!(
+        contract.settle_fee(request_id.clone(), 21_000.into(), 20_000_000_000.into()),
+        Ok(PromiseOrValue::Value(())),
+    ));
+    assert!(refund_transfers().is_empty());
+
+    assert_eq!(
+        crate::impl_mpc::event_logs().last().unwrap(),
+        &near_sdk::serde_json::json!({
+            "standard": "x-gas-station",
+            "version": "0.1.0",
+            "event": "fee_settled",
+            "data": {
+                "request_id": to_hex_prefixed(&[1; 32]),
+                "payer": "alice.near",
+                "actual_fee": NearToken::from_millinear(420).as_yoctonear().to_string(),
+                "refund": "0",
+                "shortfall": NearToken::from_millinear(20).as_yoctonear().to_string(),
+            },
+        }),
+    );
+
+    let settlement = contract
+        .get_prepaid_fee(request_id)
+        .unwrap()
+        .unwrap()
+        .settlement
+        .unwrap();
+    assert_eq!(settlement.refund, U128(0));
+    assert_eq!(
+        settlement.shortfall,
+        NearToken::from_millinear(20).as_yoctonear().into(),
+    );
+}
+
+#[test]
+fn test_settle_fee_twice() {
+    let mut contract = crate::setup_contract();
+    let request_id = setup_prepaid_request(&mut contract, NearToken::from_millinear(500));
+
+    drop(contract.settle_fee(request_id.clone(), 21_000.into(), 20_000_000_000.into()));
+    assert!(matches!(
+        contract.settle_fee(request_id, 21_000.into(), 20_000_000_000.into()),
+        Err(ContractError::FeeAlreadySettled),
+    ));
+}
+
+#[test]
+fn test_settle_fee_requires_relayer() {
+    use near_sdk::{test_utils::VMContextBuilder, testing_env};
+
+    let mut contract = crate::setup_contract();
+    let request_id = setup_prepaid_request(&mut contract, NearToken::from_millinear(500));
+
+    testing_env!(VMContextBuilder::new()
+        .current_account_id("canhazgas.testnet".parse().unwrap())
+        .predecessor_account_id("alice.near".parse().unwrap())
+        .build());
+    assert!(matches!(
+        contract.settle_fee(request_id, 21_000.into(), 20_000_000_000.into()),
+        Err(ContractError::Unauthorized { account_id, .. }) if account_id == "alice.near",
+    ));
+}
+
+#[test]
+fn test_settle_fee_requires_completed_request() {
+    let mut contract = crate::setup_contract();
+    let request_id = setup_prepaid_request(&mut contract, NearToken::from_millinear(500));
+    contract
+        .sign_requests
+        .insert([1; 32], RequestStatus::Pending);
+
+    assert!(matches!(
+        contract.settle_fee(request_id, 21_000.into(), 20_000_000_000.into()),
+        Err(ContractError::RequestNotCompleted),
+    ));
+    assert!(matches!(
+        contract.settle_fee("0x1234".to_string(), 21_000.into(), 20_000_000_000.into()),
+        Err(ContractError::InvalidInput(_)),
+    ));
+}
+
+#[cfg(test)]
+fn setup_withdrawable_fees(contract: &mut Contract, fees: NearToken) {
+    use near_sdk::{test_utils::VMContextBuilder, testing_env};
+
+    contract.credit_sign_fees(97, fees.as_yoctonear());
+    contract.treasury.settle(fees.as_yoctonear());
+    testing_env!(VMContextBuilder::new()
+        .current_account_id("canhazgas.testnet".parse().unwrap())
+        .predecessor_account_id("canhazgas.testnet".parse().unwrap())
+        .account_balance(NearToken::from_near(100))
+        .attached_deposit(NearToken::from_yoctonear(1))
+        .build());
+}
+
+#[test]
+fn test_withdraw_fees_full() {
+    let mut contract = crate::setup_contract();
+    setup_withdrawable_fees(&mut contract, NearToken::from_near(2));
+
+    drop(contract.withdraw_fees(None, "treasury.near".parse().unwrap()));
+
+    assert_eq!(contract.get_withdrawable_fees(), NearToken::from_near(0));
+    assert_eq!(
+        refund_transfers(),
+        vec![("treasury.near".to_string(), NearToken::from_near(2))],
+    );
+}
+
+#[test]
+fn test_withdraw_fees_partial() {
+    let mut contract = crate::setup_contract();
+    setup_withdrawable_fees(&mut contract, NearToken::from_near(2));
+
+    drop(contract.withdraw_fees(
+        Some(NearToken::from_millinear(500)),
+        "treasury.near".parse().unwrap(),
+    ));
+
+    assert_eq!(
+        contract.get_withdrawable_fees(),
+        NearToken::from_millinear(1500),
+    );
+    assert_eq!(
+        refund_transfers(),
+        vec![("treasury.near".to_string(), NearToken::from_millinear(500))],
+    );
+}
+
+#[test]
+#[should_panic = "Not enough fees to withdraw"]
+fn test_withdraw_fees_exceeding_balance() {
+    let mut contract = crate::setup_contract();
+    setup_withdrawable_fees(&mut contract, NearToken::from_near(2));
+
+    drop(contract.withdraw_fees(
+        Some(NearToken::from_near(3)),
+        "treasury.near".parse().unwrap(),
+    ));
+}
+
+#[test]
+#[should_panic = "Withdrawal would leave too little balance to cover storage"]
+fn test_withdraw_fees_keeps_storage_balance() {
+    use near_sdk::{test_utils::VMContextBuilder, testing_env};
+
+    let mut contract = crate::setup_contract();
+    contract.credit_sign_fees(97, NearToken::from_near(2).as_yoctonear());
+    contract
+        .treasury
+        .settle(NearToken::from_near(2).as_yoctonear());
+    // The fees have already been spent, e.g. on refunds.
+    testing_env!(VMContextBuilder::new()
+        .current_account_id("canhazgas.testnet".parse().unwrap())
+        .predecessor_account_id("canhazgas.testnet".parse().unwrap())
+        .account_balance(NearToken::from_near(1))
+        .storage_usage(100_000)
+        .attached_deposit(NearToken::from_yoctonear(1))
+        .build());
+
+    drop(contract.withdraw_fees(None, "treasury.near".parse().unwrap()));
+}
+
+#[test]
+fn test_treasury_tracks_withdrawable_fees() {
+    use near_sdk::{test_utils::VMContextBuilder, testing_env};
+
+    let mut contract = crate::setup_contract();
+    let request_id = setup_prepaid_request(&mut contract, NearToken::from_millinear(500));
+    contract.credit_sign_fees(97, NearToken::from_millinear(500).as_yoctonear());
+    // Not withdrawable until settled.
+    assert_eq!(contract.get_withdrawable_fees(), NearToken::from_near(0));
+    assert_eq!(
+        contract.get_treasury().unsettled,
+        NearToken::from_millinear(500),
+    );
+
+    // 0.08 NEAR is refunded.
+    drop(contract.settle_fee(request_id, 21_000.into(), 20_000_000_000.into()));
+    assert_eq!(
+        contract.get_withdrawable_fees(),
+        NearToken::from_millinear(420),
+    );
+
+    testing_env!(VMContextBuilder::new()
+        .current_account_id("canhazgas.testnet".parse().unwrap())
+        .predecessor_account_id("canhazgas.testnet".parse().unwrap())
+        .account_balance(NearToken::from_near(10))
+        .storage_usage(100_000)
+        .attached_deposit(NearToken::from_yoctonear(1))
+        .build());
+    drop(contract.withdraw_fees(
+        Some(NearToken::from_millinear(100)),
+        "treasury.near".parse().unwrap(),
+    ));
+
+    assert_eq!(
+        contract.get_treasury(),
+        TreasuryInfo {
+            withdrawable: NearToken::from_millinear(320),
+            unsettled: NearToken::from_near(0),
+            storage_reserved: env::storage_byte_cost().saturating_mul(100_000),
+            total: env::account_balance(),
+        },
+    );
+    // Withdrawals do not change the record of the fees charged.
+    assert_eq!(
+        contract.get_sign_fees(97.into()),
+        NearToken::from_millinear(420).as_yoctonear().into(),
+    );
+}
+
+#[test]
+#[should_panic = "Owner only"]
+fn test_withdraw_fees_requires_owner() {
+    use near_sdk::{test_utils::VMContextBuilder, testing_env};
+
+    let mut contract = crate::setup_contract();
+    setup_withdrawable_fees(&mut contract, NearToken::from_near(2));
+    testing_env!(VMContextBuilder::new()
+        .current_account_id("canhazgas.testnet".parse().unwrap())
+        .predecessor_account_id("mallory.near".parse().unwrap())
+        .attached_deposit(NearToken::from_yoctonear(1))
+        .build());
+
+    drop(contract.withdraw_fees(None, "mallory.near".parse().unwrap()));
+}
@@ -1,35 +1,47 @@
 use ethers_core::types::U256;
+use lib::signature::MAX_EIP155_CHAIN_ID;
 use near_sdk::{
     collections::TreeMap,
     env,
     json_types::{U128, U64},
     near_bindgen, require, AccountId, Promise,
 };
-use near_sdk_contract_tools::{pause::Pause, rbac::Rbac};
+use near_sdk_contract_tools::{owner::Owner, pause::Pause, rbac::Rbac, standard::nep297::Event};
 
 use crate::{
     chain_configuration::{
-        ForeignChainConfiguration, PaymasterConfiguration, ViewPaymasterConfiguration,
+        ForeignChainConfiguration, ForeignChainOptions, PaymasterConfiguration,
+        RollupConfiguration, ViewPaymasterConfiguration,
+    },
+    contract_event::{
+        ContractEvent, GasPriceUpdated, NonceGapDetected, NonceReconciled,
+        TransactionSequenceSigned,
     },
-    contract_event::TransactionSequenceSigned,
     decode_transaction_request,
+    error::ContractError,
+    fee::{FeeBand, BPS_DENOMINATOR},
+    gas_price_oracle::{GasPrice, GasPriceInput, L1GasPrice, TokenPrice},
+    nonce_manager::NonceReconciliation,
+    rate_limiter::RateLimit,
     valid_transaction_request::ValidTransactionRequest,
-    Contract, ContractExt, Flags, GetForeignChain, LocalAssetConfiguration,
-    PendingTransactionSequence, Role, StorageKey,
+    ChainMetadata, Contract, ContractExt, ContractFeatures, ContractMetadata, Flags,
+    GetForeignChain, LocalAssetConfiguration, PendingTransactionSequence, Role, StorageKey,
+    MAX_REPORTED_NONCE_ADVANCE,
 };
 use lib::{
-    asset::AssetId, foreign_address::ForeignAddress, oracle::decode_pyth_price_id, pyth, Rejectable,
+    address::AddressType, asset::AssetId, foreign_address::ForeignAddress,
+    oracle::decode_pyth_price_id, pyth, Rejectable,
 };
 
 #[near_bindgen]
 impl Contract {
     pub fn add_administrator(&mut self, account_id: AccountId) {
-        <Self as Rbac>::require_role(&Role::Administrator);
+        <Self as Owner>::require_owner();
         self.add_role(&account_id, &Role::Administrator);
     }
 
     pub fn remove_administrator(&mut self, account_id: AccountId) {
-        <Self as Rbac>::require_role(&Role::Administrator);
+        <Self as Owner>::require_owner();
         self.remove_role(&account_id, &Role::Administrator);
     }
 
@@ -38,12 +50,12 @@ impl Contract {
     }
 
     pub fn add_market_maker(&mut self, account_id: AccountId) {
-        <Self as Rbac>::require_role(&Role::Administrator);
+        Self::require_administrator();
         self.add_role(&account_id, &Role::MarketMaker);
     }
 
     pub fn remove_market_maker(&mut self, account_id: AccountId) {
-        <Self as Rbac>::require_role(&Role::Administrator);
+        Self::require_administrator();
         self.remove_role(&account_id, &Role::MarketMaker);
     }
 
@@ -51,13 +63,56 @@ impl Contract {
         <Self as Rbac>::iter_members_of(&Role::MarketMaker).collect()
     }
 
+    pub fn add_relayer(&mut self, account_id: AccountId) {
+        <Self as Owner>::require_owner();
+        self.add_role(&account_id, &Role::Relayer);
+    }
+
+    pub fn remove_relayer(&mut self, account_id: AccountId) {
+        <Self as Owner>::require_owner();
+        self.remove_role(&account_id, &Role::Relayer);
+    }
+
+    pub fn is_relayer(&self, account_id: AccountId) -> bool {
+        <Self as Rbac>::has_role(&account_id, &Role::Relayer)
+    }
+
+    pub fn get_relayers(&self) -> Vec<AccountId> {
+        <Self as Rbac>::iter_members_of(&Role::Relayer).collect()
+    }
+
+    /// Deprecated alias of [`Self::add_relayer`], kept for one release. Like
+    /// it, and unlike before, it may only be called by the owner.
+    pub fn add_gas_price_relayer(&mut self, account_id: AccountId) {
+        self.add_relayer(account_id);
+    }
+
+    /// Deprecated alias of [`Self::remove_relayer`], kept for one release.
+    /// Like it, and unlike before, it may only be called by the owner.
+    pub fn remove_gas_price_relayer(&mut self, account_id: AccountId) {
+        self.remove_relayer(account_id);
+    }
+
+    /// Deprecated alias of [`Self::get_relayers`], kept for one release.
+    pub fn get_gas_price_relayers(&self) -> Vec<AccountId> {
+        self.get_relayers()
+    }
+
+    pub fn is_paused(&self) -> bool {
+        <Self as Pause>::is_paused()
+    }
+
+    /// Halt signing, e.g. while the MPC contract is suspected to be
+    /// compromised. Views, cancellations, and the callbacks of requests that
+    /// are already pending remain available, so callers can recover their
+    /// fees.
     pub fn pause(&mut self) {
-        <Self as Rbac>::require_role(&Role::Administrator);
+        Self::require_administrator();
         <Self as Pause>::pause(self);
     }
 
     pub fn unpause(&mut self) {
-        <Self as Rbac>::require_role(&Role::Administrator);
+        Self::require_administrator();
         <Self as Pause>::unpause(self);
     }
 
@@ -66,7 +121,7 @@ impl Contract {
     }
 
     pub fn set_expire_sequence_after_blocks(&mut self, expire_sequence_after_blocks: U64) {
-        <Self as Rbac>::require_role(&Role::Administrator);
+        Self::require_administrator();
         self.expire_sequence_after_blocks = expire_sequence_after_blocks.into();
     }
 
@@ -75,7 +130,7 @@ impl Contract {
     }
 
     pub fn set_signer_contract_id(&mut self, account_id: AccountId) {
-        <Self as Rbac>::require_role(&Role::Administrator);
+        Self::require_administrator();
         self.signer_contract_id = account_id;
     }
 
@@ -84,7 +139,7 @@ impl Contract {
     }
 
     pub fn set_flags(&mut self, flags: Flags) {
-        <Self as Rbac>::require_role(&Role::Administrator);
+        Self::require_administrator();
         self.flags = flags;
     }
 
@@ -93,21 +148,21 @@ impl Contract {
     }
 
     pub fn add_to_receiver_whitelist(&mut self, addresses: Vec<ForeignAddress>) {
-        <Self as Rbac>::require_role(&Role::Administrator);
+        Self::require_administrator();
         for address in addresses {
             self.receiver_whitelist.insert(&address);
         }
     }
 
     pub fn remove_from_receiver_whitelist(&mut self, addresses: Vec<ForeignAddress>) {
-        <Self as Rbac>::require_role(&Role::Administrator);
+        Self::require_administrator();
         for address in addresses {
             self.receiver_whitelist.remove(&address);
         }
     }
 
     pub fn clear_receiver_whitelist(&mut self) {
-        <Self as Rbac>::require_role(&Role::Administrator);
+        Self::require_administrator();
         self.receiver_whitelist.clear();
     }
 
@@ -116,21 +171,21 @@ impl Contract {
     }
 
     pub fn add_to_sender_whitelist(&mut self, addresses: Vec<AccountId>) {
-        <Self as Rbac>::require_role(&Role::Administrator);
+        Self::require_administrator();
         for address in addresses {
             self.sender_whitelist.insert(&address);
         }
     }
 
     pub fn remove_from_sender_whitelist(&mut self, addresses: Vec<AccountId>) {
-        <Self as Rbac>::require_role(&Role::Administrator);
+        Self::require_administrator();
         for address in addresses {
             self.sender_whitelist.remove(&address);
         }
     }
 
     pub fn clear_sender_whitelist(&mut self) {
-        <Self as Rbac>::require_role(&Role::Administrator);
+        Self::require_administrator();
         self.sender_whitelist.clear();
     }
 
@@ -140,7 +195,7 @@ impl Contract {
         oracle_asset_id: String,
         decimals: u8,
     ) {
-        <Self as Rbac>::require_role(&Role::Administrator);
+        Self::require_administrator();
         self.accepted_local_assets.insert(
             &asset_id,
             &LocalAssetConfiguration {
@@ -151,7 +206,7 @@ impl Contract {
     }
 
     pub fn remove_accepted_local_asset(&mut self, asset_id: AssetId) {
-        <Self as Rbac>::require_role(&Role::Administrator);
+        Self::require_administrator();
         self.accepted_local_assets
             .remove(&asset_id)
             .expect_or_reject("Asset not found");
@@ -170,25 +225,116 @@ impl Contract {
         transfer_gas: U128,
         fee_rate: (U128, U128),
         decimals: u8,
+        options: Option<ForeignChainOptions>,
     ) {
-        <Self as Rbac>::require_role(&Role::Administrator);
+        <Self as Owner>::require_owner();
 
-        self.foreign_chains.insert(
-            &chain_id.0,
-            &ForeignChainConfiguration {
-                chain_id: chain_id.0,
-                next_paymaster: String::new(),
-                oracle_asset_id: decode_pyth_price_id(&oracle_asset_id),
-                transfer_gas: U256::from(transfer_gas.0).0,
-                fee_rate: (fee_rate.0.into(), fee_rate.1.into()),
-                paymasters: TreeMap::new(StorageKey::Paymasters(chain_id.0)),
-                decimals,
-            },
+        require!(
+            self.foreign_chains.get(&chain_id.0).is_none(),
+            "Foreign chain is already configured",
+        );
+        // Legacy transactions could not be signed for larger chain IDs.
+        require!(
+            chain_id.0 <= MAX_EIP155_CHAIN_ID,
+            "Chain ID is too large for EIP-155 signatures",
         );
+
+        let options = options.unwrap_or_default();
+        if let Some(fee_markup_bps) = options.fee_markup_bps {
+            require_valid_fee_markup_bps(fee_markup_bps);
+        }
+
+        let config = ForeignChainConfiguration {
+            chain_id: chain_id.0,
+            next_paymaster: String::new(),
+            oracle_asset_id: decode_pyth_price_id(&oracle_asset_id),
+            transfer_gas: U256::from(transfer_gas.0).0,
+            fee_rate: (fee_rate.0.into(), fee_rate.1.into()),
+            paymasters: TreeMap::new(StorageKey::Paymasters(chain_id.0)),
+            decimals,
+            display_name: options
+                .display_name
+                .unwrap_or_else(|| chain_id.0.to_string()),
+            is_eip1559: options.is_eip1559.unwrap_or(true),
+            min_priority_fee: options.min_priority_fee.map_or(0, |v| v.0),
+            fee_markup_bps: options.fee_markup_bps,
+            min_gas_limit: options.min_gas_limit.map(|v| v.0),
+            max_gas_limit: options.max_gas_limit.map(|v| v.0),
+            rollup: options.rollup,
+            namespaced_derivation: options.namespaced_derivation.unwrap_or(false),
+        };
+        require_valid_gas_limit_bounds(&config);
+
+        self.foreign_chains.insert(&chain_id.0, &config);
+    }
+
+    pub fn set_foreign_chain_display_name(&mut self, chain_id: U64, display_name: String) {
+        <Self as Owner>::require_owner();
+
+        self.with_mut_chain(chain_id.0, |config| {
+            config.display_name = display_name;
+        });
+    }
+
+    pub fn set_foreign_chain_is_eip1559(&mut self, chain_id: U64, is_eip1559: bool) {
+        <Self as Owner>::require_owner();
+
+        self.with_mut_chain(chain_id.0, |config| {
+            config.is_eip1559 = is_eip1559;
+        });
+    }
+
+    pub fn set_foreign_chain_min_priority_fee(&mut self, chain_id: U64, min_priority_fee: U128) {
+        <Self as Owner>::require_owner();
+
+        self.with_mut_chain(chain_id.0, |config| {
+            config.min_priority_fee = min_priority_fee.0;
+        });
+    }
+
+    /// Override the fee markup for a chain. `None` reverts to the
+    /// contract-wide markup.
+    pub fn set_foreign_chain_fee_markup_bps(&mut self, chain_id: U64, fee_markup_bps: Option<u32>) {
+        <Self as Owner>::require_owner();
+        if let Some(fee_markup_bps) = fee_markup_bps {
+            require_valid_fee_markup_bps(fee_markup_bps);
+        }
+
+        self.with_mut_chain(chain_id.0, |config| {
+            config.fee_markup_bps = fee_markup_bps;
+        });
+    }
+
+    /// Set the bounds on the gas limit of sign requests for a chain. `None`
+    /// reverts to the default bound.
+    pub fn set_foreign_chain_gas_limit_bounds(
+        &mut self,
+        chain_id: U64,
+        min_gas_limit: Option<U128>,
+        max_gas_limit: Option<U128>,
+    ) {
+        <Self as Owner>::require_owner();
+
+        self.with_mut_chain(chain_id.0, |config| {
+            config.min_gas_limit = min_gas_limit.map(|v| v.0);
+            config.max_gas_limit = max_gas_limit.map(|v| v.0);
+            require_valid_gas_limit_bounds(config);
+        });
+    }
+
+    /// Mark a chain as a rollup that charges an L1 data fee, which is then
+    /// included in the fees of sign requests for it. `None` marks it as not
+    /// a rollup.
+    pub fn set_foreign_chain_rollup(&mut self, chain_id: U64, rollup: Option<RollupConfiguration>) {
+        <Self as Owner>::require_owner();
+
+        self.with_mut_chain(chain_id.0, |config| {
+            config.rollup = rollup;
+        });
     }
 
     pub fn set_foreign_chain_oracle_asset_id(&mut self, chain_id: U64, oracle_asset_id: String) {
-        <Self as Rbac>::require_role(&Role::Administrator);
+        <Self as Owner>::require_owner();
 
         self.with_mut_chain(chain_id.0, |config| {
             config.oracle_asset_id = decode_pyth_price_id(&oracle_asset_id);
@@ -196,7 +342,7 @@ impl Contract {
     }
 
     pub fn set_foreign_chain_transfer_gas(&mut self, chain_id: U64, transfer_gas: U128) {
-        <Self as Rbac>::require_role(&Role::Administrator);
+        <Self as Owner>::require_owner();
 
         self.with_mut_chain(chain_id.0, |config| {
             config.transfer_gas = U256::from(transfer_gas.0).0;
@@ -204,7 +350,7 @@ impl Contract {
     }
 
     pub fn remove_foreign_chain(&mut self, chain_id: U64) {
-        <Self as Rbac>::require_role(&Role::Administrator);
+        <Self as Owner>::require_owner();
         if let Some(mut config) = self.foreign_chains.remove(&chain_id.0) {
             config.paymasters.clear();
         }
@@ -213,11 +359,55 @@ impl Contract {
     pub fn get_foreign_chains(&self) -> Vec<GetForeignChain> {
         self.foreign_chains
             .iter()
-            .map(|(chain_id, config)| GetForeignChain {
+            .map(|(_, config)| GetForeignChain::from(&config))
+            .collect()
+    }
+
+    /// The version of the contract, and which chains, address types and
+    /// features it supports.
+    pub fn contract_metadata(&self) -> ContractMetadata {
+        let mut chains = self
+            .foreign_chains
+            .iter()
+            .map(|(chain_id, config)| ChainMetadata {
                 chain_id: chain_id.into(),
-                oracle_asset_id: near_sdk::bs58::encode(&config.oracle_asset_id).into_string(),
+                transaction_type: config.transaction_type(),
             })
-            .collect()
+            .collect::<Vec<_>>();
+        chains.sort_by_key(|chain| chain.chain_id.0);
+
+        ContractMetadata {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            chains,
+            address_types: vec![AddressType::Evm],
+            features: ContractFeatures {
+                debug: cfg!(feature = "debug"),
+                paused: <Self as Pause>::is_paused(),
+                flags: self.flags.clone(),
+                is_rate_limited: self.rate_limiter.limit.is_some(),
+                is_fee_band_enabled: self.fee_band.is_some(),
+            },
+        }
+    }
+
+    pub fn get_foreign_chain(&self, chain_id: U64) -> Option<GetForeignChain> {
+        self.foreign_chains
+            .get(&chain_id.0)
+            .map(|config| GetForeignChain::from(&config))
+    }
+
+    /// The EIP-2718 type of the transactions that `sign` builds for
+    /// `chain_id`: 2 for EIP-1559 chains, or 0 (legacy) otherwise, so that
+    /// clients know which kind of transaction the signing hash is for.
+    /// Transactions with an access list are EIP-2930 (type 1) transactions
+    /// on chains without EIP-1559.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the chain is not configured.
+    #[handle_result]
+    pub fn tx_type_for_chain(&self, chain_id: U64) -> Result<u8, ContractError> {
+        Ok(self.get_chain(chain_id.0)?.transaction_type())
     }
 
     pub fn add_paymaster(
@@ -227,7 +417,7 @@ impl Contract {
         token_id: String,
         balance: Option<near_sdk::json_types::U128>,
     ) {
-        <Self as Rbac>::require_role(&Role::Administrator);
+        Self::require_administrator();
 
         require!(
             self.paymaster_keys.get(&token_id).is_some(),
@@ -251,7 +441,7 @@ impl Contract {
         let predecessor = env::predecessor_account_id();
         require!(
             <Self as Rbac>::has_role(&predecessor, &Role::MarketMaker)
-                || <Self as Rbac>::has_role(&predecessor, &Role::Administrator),
+                || Self::is_administrator(&predecessor),
             "Can only be called by administrator or market maker",
         );
     }
@@ -296,7 +486,7 @@ impl Contract {
     /// the paymaster getting removed, this method will not prevent those
     /// payloads from getting signed.
     pub fn remove_paymaster(&mut self, chain_id: U64, token_id: String) {
-        <Self as Rbac>::require_role(&Role::Administrator);
+        Self::require_administrator();
 
         self.with_mut_chain(chain_id.0, |chain_config| {
             chain_config.paymasters.remove(&token_id).unwrap_or_reject();
@@ -322,6 +512,340 @@ impl Contract {
             .collect()
     }
 
+    /// Report the current gas prices of `chain_id`, each tagged with its
+    /// unit, e.g. `{ "Gwei": "1.5" }` or `{ "Wei": "1500000000" }`.
+    pub fn update_gas_price(
+        &mut self,
+        chain_id: U64,
+        max_fee_per_gas: GasPriceInput,
+        max_priority_fee_per_gas: GasPriceInput,
+    ) {
+        Self::require_relayer();
+        let max_fee_per_gas = U128(max_fee_per_gas.to_wei().unwrap_or_reject());
+        let max_priority_fee_per_gas = U128(max_priority_fee_per_gas.to_wei().unwrap_or_reject());
+        require!(
+            max_priority_fee_per_gas.0 <= max_fee_per_gas.0,
+            "Max priority fee per gas cannot exceed max fee per gas",
+        );
+
+        self.gas_price_oracle.update_gas_price(
+            chain_id.0,
+            GasPrice {
+                max_fee_per_gas,
+                max_priority_fee_per_gas,
+                observed_at_block: env::block_height().into(),
+            },
+        );
+
+        ContractEvent::GasPriceUpdated(GasPriceUpdated {
+            chain_id,
+            max_fee_per_gas,
+            max_priority_fee_per_gas,
+            updated_by: env::predecessor_account_id(),
+            block_height: env::block_height().into(),
+        })
+        .emit();
+    }
+
+    /// Returns `None` if there is no gas price quote for the chain or if the
+    /// latest quote is stale.
+    pub fn current_gas_price(&self, chain_id: U64) -> Option<GasPrice> {
+        self.gas_price_oracle
+            .current_gas_price(chain_id.0, env::block_height())
+            .cloned()
+    }
+
+    /// Report the price of one whole gas token of a foreign chain in
+    /// yoctoNEAR, by which sign requests are charged.
+    pub fn update_foreign_token_price(&mut self, chain_id: U64, price_in_near: U128) {
+        Self::require_relayer();
+
+        self.gas_price_oracle.update_token_price(
+            chain_id.0,
+            TokenPrice {
+                price_in_near,
+                observed_at_block: env::block_height().into(),
+            },
+        );
+    }
+
+    /// Report the gas price on the L1 of a rollup chain, in the smallest
+    /// unit of the rollup's gas token, by which its L1 data fees are
+    /// charged.
+    pub fn update_l1_gas_price(&mut self, chain_id: U64, l1_gas_price: U128) {
+        Self::require_relayer();
+
+        self.gas_price_oracle.update_l1_gas_price(
+            chain_id.0,
+            L1GasPrice {
+                gas_price: l1_gas_price,
+                observed_at_block: env::block_height().into(),
+            },
+        );
+    }
+
+    /// Returns `None` if there is no L1 gas price quote for the rollup chain
+    /// or if the latest quote is stale. Shares its maximum age with gas
+    /// price quotes.
+    pub fn current_l1_gas_price(&self, chain_id: U64) -> Option<L1GasPrice> {
+        self.gas_price_oracle
+            .current_l1_gas_price(chain_id.0, env::block_height())
+            .cloned()
+    }
+
+    /// Returns `None` if there is no gas token price quote for the chain or
+    /// if the latest quote is stale. Shares its maximum age with gas price
+    /// quotes.
+    pub fn current_foreign_token_price(&self, chain_id: U64) -> Option<TokenPrice> {
+        self.gas_price_oracle
+            .current_token_price(chain_id.0, env::block_height())
+            .cloned()
+    }
+
+    /// NEAR fees (in yoctoNEAR) charged for sign requests on a chain, less
+    /// refunds.
+    pub fn get_sign_fees(&self, chain_id: U64) -> U128 {
+        self.sign_fees.get(&chain_id.0).unwrap_or(U128(0))
+    }
+
+    /// `(chain_id, signed, fees_collected)` for each chain that a sign
+    /// request has completed on: how many sign requests resolved to a signed
+    /// transaction, and the NEAR fees (in yoctoNEAR) prepaid for them, less
+    /// refunds on settlement.
+    pub fn stats(&self) -> Vec<(U64, U64, U128)> {
+        self.sign_stats
+            .iter()
+            .map(|(chain_id, stats)| {
+                (
+                    chain_id.into(),
+                    stats.signed.into(),
+                    stats.fees_collected.into(),
+                )
+            })
+            .collect()
+    }
+
+    pub fn get_gas_price_max_age_blocks(&self) -> U64 {
+        self.gas_price_oracle.max_age_blocks.into()
+    }
+
+    pub fn set_gas_price_max_age_blocks(&mut self, max_age_blocks: U64) {
+        <Self as Owner>::require_owner();
+        self.gas_price_oracle.max_age_blocks = max_age_blocks.0;
+    }
+
+    pub fn get_fee_markup_bps(&self) -> u32 {
+        self.fee_markup_bps
+    }
+
+    /// The markup for chains that do not override it.
+    pub fn set_fee_markup_bps(&mut self, fee_markup_bps: u32) {
+        Self::require_administrator();
+        require_valid_fee_markup_bps(fee_markup_bps);
+        self.fee_markup_bps = fee_markup_bps;
+    }
+
+    pub fn get_fee_slippage_bps(&self) -> u32 {
+        self.fee_slippage_bps
+    }
+
+    /// Let sign requests keep up to `fee_slippage_bps` basis points of their
+    /// fee in excess of it out of their deposit, so that callers can attach
+    /// a margin over a quote without it being refunded immediately.
+    /// Whatever the transaction did not cost is refunded when the fee is
+    /// settled, and none of the fee is withdrawable before then.
+    pub fn set_fee_slippage_bps(&mut self, fee_slippage_bps: u32) {
+        Self::require_administrator();
+        require!(
+            fee_slippage_bps <= BPS_DENOMINATOR,
+            format!("Fee slippage cannot exceed {BPS_DENOMINATOR} basis points"),
+        );
+        self.fee_slippage_bps = fee_slippage_bps;
+    }
+
+    pub fn get_fee_band(&self) -> Option<FeeBand> {
+        self.fee_band
+    }
+
+    /// Reject sign requests with a max fee per gas outside of `fee_band`
+    /// around the current gas price, or lift the restriction if `None`.
+    pub fn set_fee_band(&mut self, fee_band: Option<FeeBand>) {
+        Self::require_administrator();
+        if let Some(fee_band) = fee_band {
+            require!(
+                fee_band.min_bps <= fee_band.max_bps,
+                "Fee band minimum cannot exceed its maximum",
+            );
+        }
+        self.fee_band = fee_band;
+    }
+
+    /// Calculate the NEAR fee (in yoctoNEAR) for `gas_limit` gas at
+    /// `gas_price` on a foreign chain, given the price of one whole foreign
+    /// gas token in yoctoNEAR.
+    pub fn get_fee(
+        &self,
+        chain_id: U64,
+        gas_limit: U128,
+        gas_price: U128,
+        foreign_token_price_in_near: U128,
+    ) -> U128 {
+        self.compute_fee(
+            chain_id.0,
+            gas_limit.0,
+            gas_price.0,
+            foreign_token_price_in_near.0,
+        )
+        .unwrap_or_reject()
+        .as_yoctonear()
+        .into()
+    }
+
+    pub fn get_rate_limit(&self) -> Option<RateLimit> {
+        self.rate_limiter.limit
+    }
+
+    /// Limit each account to `max_requests` sign requests within any window
+    /// of `window_seconds` seconds.
+    pub fn set_rate_limit(&mut self, max_requests: u32, window_seconds: U64) {
+        Self::require_administrator();
+        self.rate_limiter.limit = Some(RateLimit {
+            max_requests,
+            window_seconds,
+        });
+    }
+
+    pub fn remove_rate_limit(&mut self) {
+        Self::require_administrator();
+        self.rate_limiter.limit = None;
+    }
+
+    pub fn get_nonce(&self, chain_id: U64, address: ForeignAddress) -> U64 {
+        self.nonces.peek_nonce(chain_id.0, address).into()
+    }
+
+    /// Resynchronize the next nonce for a foreign address, e.g. after a
+    /// reorg on the foreign chain invalidated previously-signed transactions.
+    pub fn set_nonce(&mut self, chain_id: U64, address: ForeignAddress, nonce: U64) {
+        Self::require_administrator();
+        self.nonces.set_nonce(chain_id.0, address, nonce.0);
+    }
+
+    /// Report `observed_nonce`, the nonce that `chain_id` expects next from
+    /// `address`. If it is ahead of the next nonce, the next nonce is
+    /// advanced to it, by at most 1000 unless the owner sets `force`. If it
+    /// is behind, a `NonceGapDetected` event is emitted and the next nonce
+    /// is left unchanged, unless the owner sets `force` to reset it, e.g.
+    /// once the requests that were assigned the missing nonces are known to
+    /// have been abandoned.
+    pub fn report_chain_nonce(
+        &mut self,
+        chain_id: U64,
+        address: ForeignAddress,
+        observed_nonce: U64,
+        force: Option<bool>,
+    ) -> NonceReconciliation {
+        let force = force.unwrap_or(false);
+        if force {
+            <Self as Owner>::require_owner();
+        } else {
+            Self::require_relayer();
+            require!(
+                observed_nonce.0
+                    <= self
+                        .nonces
+                        .peek_nonce(chain_id.0, address)
+                        .saturating_add(MAX_REPORTED_NONCE_ADVANCE),
+                "Reported nonce is too far ahead of the next nonce",
+            );
+        }
+
+        let reconciliation =
+            self.nonces
+                .reconcile_nonce(chain_id.0, address, observed_nonce.0, force);
+
+        match reconciliation {
+            NonceReconciliation::InSync => {}
+            NonceReconciliation::Advanced { previous_nonce }
+            | NonceReconciliation::Reset { previous_nonce } => {
+                ContractEvent::NonceReconciled(NonceReconciled {
+                    chain_id,
+                    foreign_address: address,
+                    previous_nonce,
+                    nonce: observed_nonce,
+                })
+                .emit();
+            }
+            NonceReconciliation::Gap {
+                chain_nonce,
+                next_nonce,
+            } => {
+                ContractEvent::NonceGapDetected(NonceGapDetected {
+                    chain_id,
+                    foreign_address: address,
+                    chain_nonce,
+                    next_nonce,
+                })
+                .emit();
+            }
+        }
+
+        reconciliation
+    }
+
+    pub fn get_expire_nonce_reservation_after_blocks(&self) -> U64 {
+        self.expire_nonce_reservation_after_blocks.into()
+    }
+
+    /// Set how many blocks after it was made a nonce reservation may be
+    /// reclaimed by `sweep_expired_reservations`, which must be more than
+    /// the sign request timeout (see `set_sign_request_timeout_seconds`),
+    /// even at the fastest block rate. Reservations of pending sign requests
+    /// are never reclaimed, however old.
+    pub fn set_expire_nonce_reservation_after_blocks(&mut self, blocks: U64) {
+        Self::require_administrator();
+        require!(
+            blocks.0 > self.sign_request_timeout_blocks(),
+            "Nonce reservations must expire after the sign request timeout",
+        );
+        self.expire_nonce_reservation_after_blocks = blocks.0;
+    }
+
+    /// Reclaim up to `limit` of the latest nonce reservations of `address`
+    /// on `chain_id` that have expired without being used or released, and
+    /// whose sign requests are no longer pending, so that the next nonce
+    /// does not run ahead of the foreign chain. Returns the number of nonces
+    /// reclaimed.
+    pub fn sweep_expired_reservations(
+        &mut self,
+        chain_id: U64,
+        address: ForeignAddress,
+        limit: u32,
+    ) -> u32 {
+        Self::require_relayer();
+
+        let previous_nonce = self.nonces.peek_nonce(chain_id.0, address);
+        let reclaimed = self.nonces.sweep_expired_reservations(
+            chain_id.0,
+            address,
+            self.expire_nonce_reservation_after_blocks,
+            limit,
+            |request_id| self.pending_sign_requests.contains_key(request_id),
+        );
+
+        if reclaimed > 0 {
+            ContractEvent::NonceReconciled(NonceReconciled {
+                chain_id,
+                foreign_address: address,
+                previous_nonce: previous_nonce.into(),
+                nonce: self.nonces.peek_nonce(chain_id.0, address).into(),
+            })
+            .emit();
+        }
+
+        reclaimed
+    }
+
     pub fn list_pending_transaction_sequences(
         &self,
         account_id: Option<AccountId>,
@@ -440,3 +964,583 @@ impl Contract {
         purchase_price_for_gas_tokens.into()
     }
 }
+
+fn require_valid_fee_markup_bps(fee_markup_bps: u32) {
+    require!(
+        fee_markup_bps <= BPS_DENOMINATOR,
+        format!("Fee markup cannot exceed {BPS_DENOMINATOR} basis points"),
+    );
+}
+
+fn require_valid_gas_limit_bounds(config: &ForeignChainConfiguration) {
+    require!(
+        config.min_gas_limit() <= config.max_gas_limit(),
+        "Minimum gas limit cannot exceed maximum gas limit",
+    );
+}
+
+#[cfg(test)]
+fn add_test_foreign_chain(contract: &mut Contract, options: Option<ForeignChainOptions>) {
+    contract.add_foreign_chain(
+        97.into(),
+        "EdVCmQ9FSPcVe5YySXDPCRmc8aDQLKJ9xvYBMZPie1Vw".to_string(),
+        21000.into(),
+        (120.into(), 100.into()),
+        18,
+        options,
+    );
+}
+
+#[test]
+fn test_add_foreign_chain() {
+    let mut contract = crate::setup_contract();
+
+    add_test_foreign_chain(
+        &mut contract,
+        Some(ForeignChainOptions {
+            display_name: Some("BSC Testnet".to_string()),
+            is_eip1559: Some(false),
+            min_priority_fee: Some(1_000_000_000.into()),
+            fee_markup_bps: Some(250),
+            min_gas_limit: Some(21_000.into()),
+            max_gas_limit: None,
+            rollup: None,
+            namespaced_derivation: Some(true),
+        }),
+    );
+
+    let chain = contract.get_foreign_chain(97.into()).unwrap();
+    assert_eq!(
+        chain,
+        GetForeignChain {
+            chain_id: 97.into(),
+            oracle_asset_id: "EdVCmQ9FSPcVe5YySXDPCRmc8aDQLKJ9xvYBMZPie1Vw".to_string(),
+            display_name: "BSC Testnet".to_string(),
+            is_eip1559: false,
+            min_priority_fee: 1_000_000_000.into(),
+            fee_markup_bps: Some(250),
+            min_gas_limit: Some(21_000.into()),
+            max_gas_limit: None,
+            rollup: None,
+            namespaced_derivation: true,
+        },
+    );
+    assert_eq!(contract.get_foreign_chains(), vec![chain]);
+    assert_eq!(contract.get_foreign_chain(1.into()), None);
+    assert!(matches!(
+        contract.tx_type_for_chain(1.into()),
+        Err(ContractError::ChainNotConfigured { chain_id: 1 }),
+    ));
+
+    assert_eq!(contract.tx_type_for_chain(97.into()).unwrap(), 0);
+
+    contract.set_foreign_chain_is_eip1559(97.into(), true);
+    assert!(contract.get_foreign_chain(97.into()).unwrap().is_eip1559);
+    assert_eq!(contract.tx_type_for_chain(97.into()).unwrap(), 2);
+
+    contract.remove_foreign_chain(97.into());
+    assert_eq!(contract.get_foreign_chain(97.into()), None);
+}
+
+#[test]
+fn test_add_foreign_chain_defaults() {
+    let mut contract = crate::setup_contract();
+
+    add_test_foreign_chain(&mut contract, None);
+
+    let chain = contract.get_foreign_chain(97.into()).unwrap();
+    assert_eq!(chain.display_name, "97");
+    assert!(chain.is_eip1559);
+    assert_eq!(chain.min_priority_fee.0, 0);
+    assert_eq!(chain.fee_markup_bps, None);
+    assert_eq!(chain.min_gas_limit, None);
+    assert_eq!(chain.max_gas_limit, None);
+    assert!(!chain.namespaced_derivation);
+}
+
+#[test]
+fn test_contract_metadata() {
+    let mut contract = crate::setup_contract();
+
+    add_test_foreign_chain(&mut contract, None);
+    contract.add_foreign_chain(
+        1.into(),
+        "EdVCmQ9FSPcVe5YySXDPCRmc8aDQLKJ9xvYBMZPie1Vw".to_string(),
+        21000.into(),
+        (120.into(), 100.into()),
+        18,
+        Some(ForeignChainOptions {
+            is_eip1559: Some(false),
+            ..Default::default()
+        }),
+    );
+
+    let metadata = contract.contract_metadata();
+
+    let mut configured = contract
+        .get_foreign_chains()
+        .into_iter()
+        .map(|chain| chain.chain_id)
+        .collect::<Vec<_>>();
+    configured.sort_by_key(|chain_id| chain_id.0);
+    assert_eq!(
+        metadata
+            .chains
+            .iter()
+            .map(|chain| chain.chain_id)
+            .collect::<Vec<_>>(),
+        configured,
+    );
+    assert_eq!(
+        metadata.chains,
+        vec![
+            ChainMetadata {
+                chain_id: 1.into(),
+                transaction_type: 0,
+            },
+            ChainMetadata {
+                chain_id: 97.into(),
+                transaction_type: 2,
+            },
+        ],
+    );
+
+    assert_eq!(metadata.version, env!("CARGO_PKG_VERSION"));
+    assert_eq!(metadata.address_types, vec![AddressType::Evm]);
+    assert_eq!(
+        metadata.features,
+        ContractFeatures {
+            debug: cfg!(feature = "debug"),
+            paused: false,
+            flags: Flags::default(),
+            is_rate_limited: false,
+            is_fee_band_enabled: false,
+        },
+    );
+}
+
+#[test]
+#[should_panic = "Foreign chain is already configured"]
+fn test_add_foreign_chain_rejects_duplicate() {
+    let mut contract = crate::setup_contract();
+
+    add_test_foreign_chain(&mut contract, None);
+    add_test_foreign_chain(&mut contract, None);
+}
+
+#[cfg(test)]
+fn set_predecessor(account_id: &str) {
+    near_sdk::testing_env!(near_sdk::test_utils::VMContextBuilder::new()
+        .current_account_id("canhazgas.testnet".parse().unwrap())
+        .predecessor_account_id(account_id.parse().unwrap())
+        .build());
+}
+
+#[test]
+fn test_relayer_can_update_gas_price() {
+    let mut contract = crate::setup_contract();
+    let alice: AccountId = "alice.near".parse().unwrap();
+
+    assert!(!contract.is_relayer(alice.clone()));
+    contract.add_relayer(alice.clone());
+    assert!(contract.is_relayer(alice.clone()));
+    assert_eq!(contract.get_relayers(), vec![alice]);
+
+    set_predecessor("alice.near");
+    contract.update_gas_price(97.into(), 30_000_000_000.into(), 1_000_000_000.into());
+
+    let gas_price = contract.current_gas_price(97.into()).unwrap();
+    assert_eq!(gas_price.max_fee_per_gas.0, 30_000_000_000);
+    assert_eq!(gas_price.max_priority_fee_per_gas.0, 1_000_000_000);
+
+    assert_eq!(
+        crate::impl_mpc::event_logs().last().unwrap(),
+        &near_sdk::serde_json::json!({
+            "standard": "x-gas-station",
+            "version": "0.1.0",
+            "event": "gas_price_updated",
+            "data": {
+                "chain_id": "97",
+                "max_fee_per_gas": "30000000000",
+                "max_priority_fee_per_gas": "1000000000",
+                "updated_by": "alice.near",
+                "block_height": "0",
+            },
+        }),
+    );
+}
+
+#[test]
+fn test_update_gas_price_in_gwei() {
+    let mut contract = crate::setup_contract();
+    contract.add_relayer("alice.near".parse().unwrap());
+
+    set_predecessor("alice.near");
+    contract.update_gas_price(
+        97.into(),
+        GasPriceInput::Gwei("30".to_string()),
+        GasPriceInput::Gwei("1.5".to_string()),
+    );
+
+    let gas_price = contract.current_gas_price(97.into()).unwrap();
+    assert_eq!(gas_price.max_fee_per_gas.0, 30_000_000_000);
+    assert_eq!(gas_price.max_priority_fee_per_gas.0, 1_500_000_000);
+}
+
+#[test]
+#[should_panic = "Invalid gas price in gwei: \"-1\""]
+fn test_update_gas_price_rejects_negative_gwei() {
+    let mut contract = crate::setup_contract();
+    contract.add_relayer("alice.near".parse().unwrap());
+
+    set_predecessor("alice.near");
+    contract.update_gas_price(97.into(), GasPriceInput::Gwei("-1".to_string()), 0.into());
+}
+
+#[test]
+#[should_panic = "Can only be called by owner or relayer, not mallory.near"]
+fn test_non_relayer_cannot_update_gas_price() {
+    let mut contract = crate::setup_contract();
+
+    set_predecessor("mallory.near");
+    contract.update_gas_price(97.into(), 30_000_000_000.into(), 1_000_000_000.into());
+}
+
+#[test]
+#[should_panic = "Can only be called by owner or relayer, not alice.near"]
+fn test_removed_relayer_cannot_update_gas_price() {
+    let mut contract = crate::setup_contract();
+    let alice: AccountId = "alice.near".parse().unwrap();
+
+    contract.add_relayer(alice.clone());
+    contract.remove_relayer(alice.clone());
+    assert!(!contract.is_relayer(alice));
+
+    set_predecessor("alice.near");
+    contract.update_gas_price(97.into(), 30_000_000_000.into(), 1_000_000_000.into());
+}
+
+#[test]
+#[should_panic = "Owner only"]
+fn test_add_relayer_requires_owner() {
+    let mut contract = crate::setup_contract();
+    contract.add_administrator("alice.near".parse().unwrap());
+
+    // Administrators cannot appoint relayers.
+    set_predecessor("alice.near");
+    contract.add_relayer("mallory.near".parse().unwrap());
+}
+
+#[test]
+fn test_deprecated_gas_price_relayer_methods() {
+    let mut contract = crate::setup_contract();
+    let alice: AccountId = "alice.near".parse().unwrap();
+
+    contract.add_gas_price_relayer(alice.clone());
+    assert!(contract.is_relayer(alice.clone()));
+    assert_eq!(contract.get_gas_price_relayers(), vec![alice.clone()]);
+
+    contract.remove_gas_price_relayer(alice.clone());
+    assert!(!contract.is_relayer(alice));
+    assert!(contract.get_gas_price_relayers().is_empty());
+}
+
+#[test]
+#[should_panic = "Owner only"]
+fn test_deprecated_add_gas_price_relayer_requires_owner() {
+    let mut contract = crate::setup_contract();
+    contract.add_administrator("alice.near".parse().unwrap());
+
+    set_predecessor("alice.near");
+    contract.add_gas_price_relayer("mallory.near".parse().unwrap());
+}
+
+#[test]
+fn test_owner_can_update_gas_price_without_relayer_role() {
+    let mut contract = crate::setup_contract();
+
+    set_predecessor("canhazgas.testnet");
+    contract.update_gas_price(97.into(), 30_000_000_000.into(), 1_000_000_000.into());
+
+    let gas_price = contract.current_gas_price(97.into()).unwrap();
+    assert_eq!(gas_price.max_fee_per_gas.0, 30_000_000_000);
+}
+
+#[test]
+fn test_fee_markup_per_chain() {
+    let mut contract = crate::setup_contract();
+    contract.set_fee_markup_bps(100);
+    add_test_foreign_chain(&mut contract, None);
+    contract.add_foreign_chain(
+        1.into(),
+        "EdVCmQ9FSPcVe5YySXDPCRmc8aDQLKJ9xvYBMZPie1Vw".to_string(),
+        21000.into(),
+        (120.into(), 100.into()),
+        18,
+        Some(ForeignChainOptions {
+            fee_markup_bps: Some(1000),
+            ..Default::default()
+        }),
+    );
+
+    // 21000 gas at 20 gwei, 1 ETH = 1000 NEAR is 0.42 NEAR before markup.
+    let fee = |contract: &Contract, chain_id: u64| {
+        contract
+            .get_fee(
+                chain_id.into(),
+                21000.into(),
+                20_000_000_000.into(),
+                near_sdk::NearToken::from_near(1000).as_yoctonear().into(),
+            )
+            .0
+    };
+    let near = near_sdk::NearToken::from_near(1).as_yoctonear();
+
+    assert_eq!(fee(&contract, 97), near * 4242 / 10_000);
+    assert_eq!(fee(&contract, 1), near * 462 / 1000);
+
+    contract.set_foreign_chain_fee_markup_bps(97.into(), Some(1000));
+    assert_eq!(fee(&contract, 97), fee(&contract, 1));
+
+    contract.set_foreign_chain_fee_markup_bps(1.into(), None);
+    assert_eq!(fee(&contract, 1), near * 4242 / 10_000);
+}
+
+#[test]
+#[should_panic = "Fee markup cannot exceed 10000 basis points"]
+fn test_set_foreign_chain_fee_markup_bps_out_of_range() {
+    let mut contract = crate::setup_contract();
+    add_test_foreign_chain(&mut contract, None);
+
+    contract.set_foreign_chain_fee_markup_bps(97.into(), Some(10_001));
+}
+
+#[test]
+#[should_panic = "Fee markup cannot exceed 10000 basis points"]
+fn test_set_fee_markup_bps_out_of_range() {
+    let mut contract = crate::setup_contract();
+
+    contract.set_fee_markup_bps(10_001);
+}
+
+#[test]
+#[should_panic = "Fee slippage cannot exceed 10000 basis points"]
+fn test_set_fee_slippage_bps_out_of_range() {
+    let mut contract = crate::setup_contract();
+
+    contract.set_fee_slippage_bps(500);
+    assert_eq!(contract.get_fee_slippage_bps(), 500);
+    contract.set_fee_slippage_bps(10_001);
+}
+
+#[test]
+#[should_panic = "Minimum gas limit cannot exceed maximum gas limit"]
+fn test_set_foreign_chain_gas_limit_bounds_inverted() {
+    let mut contract = crate::setup_contract();
+    add_test_foreign_chain(&mut contract, None);
+
+    contract.set_foreign_chain_gas_limit_bounds(97.into(), Some(50_000_000.into()), None);
+}
+
+#[test]
+#[should_panic = "Chain ID is too large for EIP-155 signatures"]
+fn test_add_foreign_chain_chain_id_too_large() {
+    let mut contract = crate::setup_contract();
+
+    contract.add_foreign_chain(
+        (MAX_EIP155_CHAIN_ID + 1).into(),
+        "EdVCmQ9FSPcVe5YySXDPCRmc8aDQLKJ9xvYBMZPie1Vw".to_string(),
+        21000.into(),
+        (120.into(), 100.into()),
+        18,
+        None,
+    );
+}
+
+#[test]
+fn test_report_chain_nonce_advances() {
+    let mut contract = crate::setup_contract();
+    let address = ForeignAddress([1; 20]);
+    contract.add_relayer("alice.near".parse().unwrap());
+
+    set_predecessor("alice.near");
+    assert_eq!(
+        contract.report_chain_nonce(97.into(), address, 3.into(), None),
+        NonceReconciliation::Advanced {
+            previous_nonce: 0.into(),
+        },
+    );
+    assert_eq!(contract.get_nonce(97.into(), address), 3.into());
+
+    let logs = crate::impl_mpc::event_logs();
+    assert_eq!(logs.last().unwrap()["event"], "nonce_reconciled");
+    assert_eq!(logs.last().unwrap()["data"]["nonce"], "3");
+
+    assert_eq!(
+        contract.report_chain_nonce(97.into(), address, 3.into(), None),
+        NonceReconciliation::InSync,
+    );
+}
+
+#[test]
+fn test_report_chain_nonce_max_advance() {
+    let mut contract = crate::setup_contract();
+    let address = ForeignAddress([1; 20]);
+    contract.set_nonce(97.into(), address, 5.into());
+    contract.add_relayer("alice.near".parse().unwrap());
+
+    set_predecessor("alice.near");
+    contract.report_chain_nonce(
+        97.into(),
+        address,
+        (5 + MAX_REPORTED_NONCE_ADVANCE).into(),
+        None,
+    );
+    assert_eq!(
+        contract.get_nonce(97.into(), address),
+        (5 + MAX_REPORTED_NONCE_ADVANCE).into(),
+    );
+
+    // The owner may advance it further.
+    set_predecessor("canhazgas.testnet");
+    contract.report_chain_nonce(97.into(), address, u64::MAX.into(), Some(true));
+    assert_eq!(contract.get_nonce(97.into(), address), u64::MAX.into());
+}
+
+#[test]
+#[should_panic = "Reported nonce is too far ahead of the next nonce"]
+fn test_report_chain_nonce_too_far_ahead() {
+    let mut contract = crate::setup_contract();
+    let address = ForeignAddress([1; 20]);
+    contract.set_nonce(97.into(), address, 5.into());
+    contract.add_relayer("alice.near".parse().unwrap());
+
+    set_predecessor("alice.near");
+    contract.report_chain_nonce(
+        97.into(),
+        address,
+        (6 + MAX_REPORTED_NONCE_ADVANCE).into(),
+        None,
+    );
+}
+
+#[test]
+fn test_report_chain_nonce_gap() {
+    let mut contract = crate::setup_contract();
+    let address = ForeignAddress([1; 20]);
+    contract.set_nonce(97.into(), address, 5.into());
+    contract.add_relayer("alice.near".parse().unwrap());
+
+    set_predecessor("alice.near");
+    assert_eq!(
+        contract.report_chain_nonce(97.into(), address, 2.into(), None),
+        NonceReconciliation::Gap {
+            chain_nonce: 2.into(),
+            next_nonce: 5.into(),
+        },
+    );
+    // Not moved back without `force`.
+    assert_eq!(contract.get_nonce(97.into(), address), 5.into());
+
+    let logs = crate::impl_mpc::event_logs();
+    assert_eq!(logs.last().unwrap()["event"], "nonce_gap_detected");
+    assert_eq!(logs.last().unwrap()["data"]["chain_nonce"], "2");
+
+    set_predecessor("canhazgas.testnet");
+    assert_eq!(
+        contract.report_chain_nonce(97.into(), address, 2.into(), Some(true)),
+        NonceReconciliation::Reset {
+            previous_nonce: 5.into(),
+        },
+    );
+    assert_eq!(contract.get_nonce(97.into(), address), 2.into());
+}
+
+#[test]
+fn test_sweep_expired_reservations() {
+    let mut contract = crate::setup_contract();
+    let address = ForeignAddress([1; 20]);
+    contract.set_expire_nonce_reservation_after_blocks(2000.into());
+    contract.add_relayer("alice.near".parse().unwrap());
+
+    // A reservation that was never used or released.
+    assert_eq!(contract.nonces.reserve_nonce(97, address, [1; 32]), 0);
+
+    set_predecessor("alice.near");
+    assert_eq!(
+        contract.sweep_expired_reservations(97.into(), address, 10),
+        0
+    );
+    assert_eq!(contract.get_nonce(97.into(), address), 1.into());
+
+    near_sdk::testing_env!(near_sdk::test_utils::VMContextBuilder::new()
+        .current_account_id("canhazgas.testnet".parse().unwrap())
+        .predecessor_account_id("alice.near".parse().unwrap())
+        .block_height(2001)
+        .build());
+    assert_eq!(
+        contract.sweep_expired_reservations(97.into(), address, 10),
+        1
+    );
+    assert_eq!(contract.get_nonce(97.into(), address), 0.into());
+
+    let logs = crate::impl_mpc::event_logs();
+    assert_eq!(logs.last().unwrap()["event"], "nonce_reconciled");
+    assert_eq!(logs.last().unwrap()["data"]["previous_nonce"], "1");
+    assert_eq!(logs.last().unwrap()["data"]["nonce"], "0");
+}
+
+#[test]
+fn test_sweep_expired_reservations_skips_pending_requests() {
+    use crate::request_status::PendingSignRequest;
+
+    let mut contract = crate::setup_contract();
+    let address = ForeignAddress([1; 20]);
+    contract.add_relayer("alice.near".parse().unwrap());
+
+    assert_eq!(contract.nonces.reserve_nonce(97, address, [1; 32]), 0);
+    contract.pending_sign_requests.insert(
+        [1; 32],
+        PendingSignRequest {
+            caller: "alice.near".parse().unwrap(),
+            chain_id: 97,
+            foreign_address: address,
+            nonce: 0,
+            created_at_ns: 0,
+            mpc_path: "alice.near,ethereum-1".to_string(),
+            key_version: 0,
+            storage_deposit: 0,
+            storage_usage: 0,
+        },
+    );
+
+    near_sdk::testing_env!(near_sdk::test_utils::VMContextBuilder::new()
+        .current_account_id("canhazgas.testnet".parse().unwrap())
+        .predecessor_account_id("alice.near".parse().unwrap())
+        .block_height(crate::DEFAULT_EXPIRE_NONCE_RESERVATION_AFTER_BLOCKS * 10)
+        .build());
+    assert_eq!(
+        contract.sweep_expired_reservations(97.into(), address, 10),
+        0
+    );
+    assert_eq!(contract.get_nonce(97.into(), address), 1.into());
+}
+
+#[test]
+#[should_panic = "Nonce reservations must expire after the sign request timeout"]
+fn test_set_expire_nonce_reservation_after_blocks_within_sign_request_timeout() {
+    let mut contract = crate::setup_contract();
+
+    contract
+        .set_expire_nonce_reservation_after_blocks(contract.sign_request_timeout_seconds.into());
+}
+
+#[test]
+#[should_panic = "Owner only"]
+fn test_report_chain_nonce_force_requires_owner() {
+    let mut contract = crate::setup_contract();
+    contract.add_relayer("alice.near".parse().unwrap());
+
+    set_predecessor("alice.near");
+    contract.report_chain_nonce(97.into(), ForeignAddress([1; 20]), 0.into(), Some(true));
+}
@@ -0,0 +1,248 @@
+use lib::{asset::AssetId, foreign_address::ForeignAddress, kdf::EPSILON_DERIVATION_PREFIX};
+use near_sdk::{
+    collections::{TreeMap, UnorderedMap, UnorderedSet, Vector},
+    env,
+    json_types::U128,
+    near, near_bindgen,
+    store::LookupMap,
+    AccountId,
+};
+use near_sdk_contract_tools::owner::Owner;
+
+use crate::{
+    chain_configuration::{ForeignChainConfiguration, PaymasterConfiguration},
+    gas_price_oracle::GasPriceOracle,
+    nonce_manager::NonceManager,
+    rate_limiter::RateLimiter,
+    request_status::CancelledNoncePolicy,
+    treasury::Treasury,
+    ChainKeyData, Contract, ContractExt, Flags, LocalAssetConfiguration,
+    PendingTransactionSequence, StorageKey, TransactionSequenceSignedEventAt,
+    DEFAULT_EXPIRE_NONCE_RESERVATION_AFTER_BLOCKS, DEFAULT_GAS_PRICE_MAX_AGE_BLOCKS,
+    DEFAULT_MAX_PATH_LEN, DEFAULT_MPC_SIGN_GAS, DEFAULT_SIGN_REQUEST_TIMEOUT_SECONDS,
+};
+
+/// The state of the contract before sign requests were added, which
+/// deployments from then are migrated from by `migrate`.
+#[near(serializers = [borsh])]
+struct ContractV0 {
+    next_unique_id: u64,
+    signer_contract_id: AccountId,
+    oracle_id: AccountId,
+    accepted_local_assets: UnorderedMap<AssetId, LocalAssetConfiguration>,
+    flags: Flags,
+    expire_sequence_after_blocks: u64,
+    foreign_chains: UnorderedMap<u64, ForeignChainConfigurationV0>,
+    user_chain_keys: UnorderedMap<AccountId, UnorderedMap<String, ChainKeyData>>,
+    paymaster_keys: UnorderedMap<String, ChainKeyData>,
+    sender_whitelist: UnorderedSet<AccountId>,
+    receiver_whitelist: UnorderedSet<ForeignAddress>,
+    pending_transaction_sequences: UnorderedMap<u64, PendingTransactionSequence>,
+    signed_transaction_sequences: Vector<TransactionSequenceSignedEventAt>,
+    collected_fees: UnorderedMap<AssetId, U128>,
+}
+
+/// [`ForeignChainConfiguration`] as of [`ContractV0`].
+#[near(serializers = [borsh])]
+struct ForeignChainConfigurationV0 {
+    chain_id: u64,
+    paymasters: TreeMap<String, PaymasterConfiguration>,
+    next_paymaster: String,
+    transfer_gas: [u64; 4],
+    fee_rate: (u128, u128),
+    oracle_asset_id: [u8; 32],
+    decimals: u8,
+}
+
+impl From<ForeignChainConfigurationV0> for ForeignChainConfiguration {
+    /// With the defaults of `add_foreign_chain` for everything else.
+    fn from(config: ForeignChainConfigurationV0) -> Self {
+        Self {
+            chain_id: config.chain_id,
+            paymasters: config.paymasters,
+            next_paymaster: config.next_paymaster,
+            transfer_gas: config.transfer_gas,
+            fee_rate: config.fee_rate,
+            oracle_asset_id: config.oracle_asset_id,
+            decimals: config.decimals,
+            display_name: config.chain_id.to_string(),
+            is_eip1559: true,
+            min_priority_fee: 0,
+            fee_markup_bps: None,
+            min_gas_limit: None,
+            max_gas_limit: None,
+            rollup: None,
+            namespaced_derivation: false,
+        }
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Migrate the state of a deployment from before sign requests were
+    /// added, once the current code is deployed over it, which cannot be
+    /// used until then. Everything since is initialized as by `new`, and
+    /// foreign chains keep their paymasters and get the defaults of
+    /// `add_foreign_chain`. `owner_id` becomes the owner, which deployments
+    /// from then did not have; their administrators are kept.
+    #[private]
+    #[init(ignore_state)]
+    pub fn migrate(owner_id: AccountId) -> Self {
+        let mut old: ContractV0 = env::state_read().unwrap_or_else(|| {
+            env::panic_str("No state to migrate from before sign requests were added")
+        });
+
+        // Rewritten under the same prefix, since the values changed layout.
+        let old_chains = old.foreign_chains.to_vec();
+        old.foreign_chains.clear();
+        let mut foreign_chains = UnorderedMap::new(StorageKey::ForeignChains);
+        for (chain_id, config) in old_chains {
+            foreign_chains.insert(&chain_id, &ForeignChainConfiguration::from(config));
+        }
+
+        let mut contract = Self {
+            next_unique_id: old.next_unique_id,
+            signer_contract_id: old.signer_contract_id,
+            oracle_id: old.oracle_id,
+            accepted_local_assets: old.accepted_local_assets,
+            flags: old.flags,
+            expire_sequence_after_blocks: old.expire_sequence_after_blocks,
+            foreign_chains,
+            user_chain_keys: old.user_chain_keys,
+            paymaster_keys: old.paymaster_keys,
+            sender_whitelist: old.sender_whitelist,
+            receiver_whitelist: old.receiver_whitelist,
+            pending_transaction_sequences: old.pending_transaction_sequences,
+            signed_transaction_sequences: old.signed_transaction_sequences,
+            collected_fees: old.collected_fees,
+            nonces: NonceManager::new(StorageKey::Nonces, StorageKey::NonceReservations),
+            gas_price_oracle: GasPriceOracle::new(
+                StorageKey::GasPrices,
+                StorageKey::TokenPrices,
+                StorageKey::L1GasPrices,
+                DEFAULT_GAS_PRICE_MAX_AGE_BLOCKS,
+            ),
+            fee_markup_bps: 0,
+            mpc_contract_id: None,
+            mpc_keys: TreeMap::new(StorageKey::MpcKeys),
+            rate_limiter: RateLimiter::new(StorageKey::RateLimits, StorageKey::RateLimitLog),
+            sign_requests: LookupMap::new(StorageKey::SignRequests),
+            sign_request_ids: Vector::new(StorageKey::SignRequestIds),
+            prepaid_fees: LookupMap::new(StorageKey::PrepaidFees),
+            sign_batches: LookupMap::new(StorageKey::SignBatches),
+            pending_sign_requests: LookupMap::new(StorageKey::PendingSignRequests),
+            sign_request_timeout_seconds: DEFAULT_SIGN_REQUEST_TIMEOUT_SECONDS,
+            cancelled_nonce_policy: CancelledNoncePolicy::default(),
+            sign_fees: UnorderedMap::new(StorageKey::SignFees),
+            mpc_sign_gas: DEFAULT_MPC_SIGN_GAS,
+            derivation_prefixes: TreeMap::new(StorageKey::DerivationPrefixes),
+            max_path_len: DEFAULT_MAX_PATH_LEN,
+            treasury: Treasury::default(),
+            fee_slippage_bps: 0,
+            sign_request_memos: LookupMap::new(StorageKey::SignRequestMemos),
+            expire_nonce_reservation_after_blocks: DEFAULT_EXPIRE_NONCE_RESERVATION_AFTER_BLOCKS,
+            mpc_fallback_contract_ids: Vec::new(),
+            sign_request_providers: LookupMap::new(StorageKey::SignRequestProviders),
+            registered_addresses: LookupMap::new(StorageKey::RegisteredAddresses),
+            simulations: LookupMap::new(StorageKey::Simulations),
+            sign_stats: UnorderedMap::new(StorageKey::SignStats),
+            recipient_allowlists: LookupMap::new(StorageKey::RecipientAllowlists),
+            fee_band: None,
+        };
+
+        contract
+            .derivation_prefixes
+            .insert(&0, &EPSILON_DERIVATION_PREFIX.to_string());
+        Owner::init(&mut contract, &owner_id);
+
+        contract
+    }
+}
+
+#[test]
+fn test_migrate() {
+    near_sdk::testing_env!(near_sdk::test_utils::VMContextBuilder::new()
+        .current_account_id("canhazgas.testnet".parse().unwrap())
+        .predecessor_account_id("canhazgas.testnet".parse().unwrap())
+        .build());
+
+    let mut paymasters = TreeMap::new(StorageKey::Paymasters(97));
+    paymasters.insert(
+        &"paymaster.canhazgas.testnet".to_string(),
+        &PaymasterConfiguration {
+            nonce: 3,
+            token_id: "1".to_string(),
+            minimum_available_balance: [1_000, 0, 0, 0],
+        },
+    );
+    let mut old = ContractV0 {
+        next_unique_id: 7,
+        signer_contract_id: "nft.canhazgas.testnet".parse().unwrap(),
+        oracle_id: "pyth-oracle.testnet".parse().unwrap(),
+        accepted_local_assets: UnorderedMap::new(StorageKey::AcceptedLocalAssets),
+        flags: Flags {
+            is_sender_whitelist_enabled: true,
+            is_receiver_whitelist_enabled: false,
+        },
+        expire_sequence_after_blocks: 100,
+        foreign_chains: UnorderedMap::new(StorageKey::ForeignChains),
+        user_chain_keys: UnorderedMap::new(StorageKey::UserChainKeys),
+        paymaster_keys: UnorderedMap::new(StorageKey::PaymasterKeys),
+        sender_whitelist: UnorderedSet::new(StorageKey::SenderWhitelist),
+        receiver_whitelist: UnorderedSet::new(StorageKey::ReceiverWhitelist),
+        pending_transaction_sequences: UnorderedMap::new(StorageKey::PendingTransactionSequences),
+        signed_transaction_sequences: Vector::new(StorageKey::SignedTransactionSequences),
+        collected_fees: UnorderedMap::new(StorageKey::CollectedFees),
+    };
+    old.foreign_chains.insert(
+        &97,
+        &ForeignChainConfigurationV0 {
+            chain_id: 97,
+            paymasters,
+            next_paymaster: "paymaster.canhazgas.testnet".to_string(),
+            transfer_gas: [21_000, 0, 0, 0],
+            fee_rate: (120, 100),
+            oracle_asset_id: [1; 32],
+            decimals: 18,
+        },
+    );
+    old.sender_whitelist.insert(&"alice.near".parse().unwrap());
+    env::state_write(&old);
+
+    let mut contract = Contract::migrate("owner.near".parse().unwrap());
+
+    assert_eq!(contract.next_unique_id, 7);
+    assert_eq!(contract.expire_sequence_after_blocks, 100);
+    assert!(contract.flags.is_sender_whitelist_enabled);
+    assert!(contract
+        .sender_whitelist
+        .contains(&"alice.near".parse().unwrap()));
+    assert_eq!(contract.get_owner(), Some("owner.near".parse().unwrap()));
+
+    let chain = contract.get_chain(97).unwrap();
+    assert_eq!(chain.next_paymaster, "paymaster.canhazgas.testnet");
+    assert_eq!(chain.fee_rate, (120, 100));
+    assert_eq!(chain.display_name, "97");
+    assert!(chain.is_eip1559);
+    assert!(!chain.namespaced_derivation);
+    assert_eq!(
+        chain
+            .paymasters
+            .get(&"paymaster.canhazgas.testnet".to_string())
+            .unwrap()
+            .nonce,
+        3,
+    );
+    assert_eq!(contract.foreign_chains.len(), 1);
+
+    // Everything since is initialized.
+    assert_eq!(
+        contract.get_derivation_prefix(None).unwrap(),
+        EPSILON_DERIVATION_PREFIX,
+    );
+    let address: ForeignAddress = "0xb9a07c631d10fdce87d37eb6f18c11cbe75f1eeb"
+        .parse()
+        .unwrap();
+    contract.nonces.set_nonce(97, address, 5);
+    assert_eq!(contract.nonces.peek_nonce(97, address), 5);
+}
@@ -0,0 +1,5940 @@
+use ethers_core::{
+    types::Signature,
+    utils::{hex, raw_public_key_to_address},
+};
+use lib::{
+    eip712::{typed_data_hash, Eip712Domain},
+    foreign_address::ForeignAddress,
+    hash::{keccak256, personal_sign_hash, to_hex_prefixed},
+    kdf::{
+        get_mpc_address_from_affine_with_prefix, join_derivation_path, PublicKeyConversionError,
+        HIERARCHICAL_PATH_SEPARATOR,
+    },
+    signature::Signature as MpcSignature,
+    signer::{ext_signer, SignRequest, SignatureResponse},
+    transaction::{decode_unsigned, intrinsic_gas, Eip1559Transaction, ForeignTransaction},
+    Rejectable,
+};
+use near_sdk::{
+    env,
+    json_types::{U128, U64},
+    near_bindgen, require,
+    store::LookupSet,
+    AccountId, Gas, NearToken, Promise, PromiseError, PromiseOrValue, PublicKey,
+};
+use near_sdk_contract_tools::{owner::Owner, standard::nep297::Event};
+
+use crate::{
+    contract_event::{ContractEvent, SignCancelled, SignCompleted, SignFailed, SignRequested},
+    error::{ContractError, UnreasonableFeeError},
+    fee::{compute_fee_with_l1_data_fee, max_prepaid_fee, PrepaidFee, Quote},
+    gas_price_oracle::{GasPrice, L1GasPrice, TokenPrice},
+    mpc_key::{MpcKey, MpcPublicKeyInput},
+    request_status::{
+        parse_request_id, sign_request_id, BatchSignRequest, CancelledNoncePolicy,
+        PendingSignRequest, RequestId, RequestStatus, SignBatchItem, SignBatchItemStatus,
+        SignDryRun, SignRequestRecord, SignResult,
+    },
+    transaction_input::{decode_access_list, TransactionInput},
+    treasury::storage_cost,
+    Contract, ContractExt, StorageKey,
+};
+
+#[near_bindgen]
+impl Contract {
+    pub fn get_mpc_contract_id(&self) -> Option<&AccountId> {
+        self.mpc_contract_id.as_ref()
+    }
+
+    pub fn set_mpc_contract_id(&mut self, account_id: AccountId) {
+        Self::require_administrator();
+        self.mpc_contract_id = Some(account_id);
+    }
+
+    /// The MPC contracts that signatures are requested from: the MPC
+    /// contract, followed by its fallbacks in the order they are tried.
+    pub fn get_mpc_providers(&self) -> Vec<AccountId> {
+        self.mpc_contract_id
+            .iter()
+            .chain(&self.mpc_fallback_contract_ids)
+            .cloned()
+            .collect()
+    }
+
+    /// Set the MPC contracts to request the signature of a sign request
+    /// from, in order, if requesting it from the MPC contract fails. They
+    /// must derive keys from the same root keys as the MPC contract. At most
+    /// [`Contract::MAX_MPC_FALLBACKS`] fallbacks can be set.
+    ///
+    /// Failing over takes the gas for another MPC call and callback, which
+    /// is not reserved up front: it comes out of whatever gas a sign request
+    /// was attached beyond what it requires. A request that runs out of gas
+    /// to fail over with fails instead.
+    pub fn set_mpc_fallback_contract_ids(&mut self, account_ids: Vec<AccountId>) {
+        Self::require_administrator();
+        require!(
+            account_ids.len() <= Self::MAX_MPC_FALLBACKS,
+            format!(
+                "At most {} MPC fallback contracts can be set",
+                Self::MAX_MPC_FALLBACKS,
+            ),
+        );
+        self.mpc_fallback_contract_ids = account_ids;
+    }
+
+    pub fn get_latest_mpc_key_version(&self) -> Option<u32> {
+        self.mpc_keys.max()
+    }
+
+    /// The configured MPC key versions, in ascending order.
+    pub fn available_key_versions(&self) -> Vec<u32> {
+        self.mpc_keys
+            .iter()
+            .map(|(key_version, _)| key_version)
+            .collect()
+    }
+
+    /// Defaults to the latest key version.
+    pub fn get_mpc_key(&self, key_version: Option<u32>) -> Option<MpcKey> {
+        self.mpc_keys
+            .get(&key_version.or_else(|| self.mpc_keys.max())?)
+    }
+
+    /// Defaults to the latest key version.
+    pub fn get_mpc_public_key(&self, key_version: Option<u32>) -> Option<PublicKey> {
+        self.get_mpc_key(key_version).map(|key| key.public_key)
+    }
+
+    /// Set the root public key of the MPC contract for `key_version`, which
+    /// is used to derive the foreign addresses that the gas station signs
+    /// for. Keys cannot be replaced once set, so that addresses derived from
+    /// earlier key versions remain reproducible.
+    ///
+    /// SECP256K1 keys may also be given in SEC1 form, compressed or
+    /// uncompressed, as the MPC network may report them (see
+    /// [`MpcPublicKeyInput`]).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the caller is not an administrator, if
+    /// `key_version` is already configured, or if `public_key` is invalid.
+    #[handle_result]
+    pub fn set_mpc_key(
+        &mut self,
+        key_version: u32,
+        public_key: MpcPublicKeyInput,
+    ) -> Result<(), ContractError> {
+        Self::check_administrator()?;
+        if self.mpc_keys.get(&key_version).is_some() {
+            return Err(ContractError::KeyVersionAlreadyConfigured { key_version });
+        }
+        let key = MpcKey::new(public_key.0, key_version)?;
+        self.mpc_keys.insert(&key_version, &key);
+        Ok(())
+    }
+
+    /// The configured key derivation prefix versions, in ascending order.
+    /// Version 0 is the prefix of the original derivation protocol.
+    pub fn available_derivation_prefix_versions(&self) -> Vec<u32> {
+        self.derivation_prefixes
+            .iter()
+            .map(|(prefix_version, _)| prefix_version)
+            .collect()
+    }
+
+    /// Defaults to the latest prefix version.
+    pub fn get_derivation_prefix(&self, prefix_version: Option<u32>) -> Option<String> {
+        self.derivation_prefixes
+            .get(&prefix_version.or_else(|| self.derivation_prefixes.max())?)
+    }
+
+    /// Add the key derivation prefix for `prefix_version`, e.g. when the MPC
+    /// contract upgrades its derivation protocol. Sign requests derive their
+    /// foreign addresses with the latest prefix, while addresses derived
+    /// with earlier prefixes can still be looked up by version. Prefixes
+    /// cannot be replaced once set.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the caller is not an administrator, or if
+    /// `prefix_version` is already configured.
+    #[handle_result]
+    pub fn set_derivation_prefix(
+        &mut self,
+        prefix_version: u32,
+        prefix: String,
+    ) -> Result<(), ContractError> {
+        Self::check_administrator()?;
+        if self.derivation_prefixes.get(&prefix_version).is_some() {
+            return Err(ContractError::PrefixVersionAlreadyConfigured { prefix_version });
+        }
+        self.derivation_prefixes.insert(&prefix_version, &prefix);
+        Ok(())
+    }
+
+    /// The checksummed EVM address that `sign` would sign for on behalf of
+    /// `account_id` at `path` on chain `chain_id` with MPC key version
+    /// `key_version` (the latest by default), so that it can be funded in
+    /// advance.
+    ///
+    /// With `prefix_version`, the address is instead derived with that
+    /// version of the derivation prefix, e.g. to look up an address that
+    /// was issued before the prefix was rotated.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the chain, the MPC key version, or the prefix
+    /// version is not configured, or if `path` is empty, longer than the
+    /// maximum path length, or contains [`HIERARCHICAL_PATH_SEPARATOR`].
+    #[handle_result]
+    pub fn foreign_address_for(
+        &self,
+        account_id: AccountId,
+        path: String,
+        chain_id: U64,
+        key_version: Option<u32>,
+        prefix_version: Option<u32>,
+    ) -> Result<String, ContractError> {
+        let mpc_path = self.try_chain_mpc_path(&account_id, &path, chain_id.0)?;
+        let key_version = self.try_resolve_mpc_key_version(key_version)?;
+
+        Ok(self
+            .try_foreign_address_for_mpc_path_with_prefix(
+                &mpc_path,
+                key_version,
+                &self.try_derivation_prefix(prefix_version)?,
+            )?
+            .to_string())
+    }
+
+    /// The checksummed foreign addresses that `account_id` signs for at
+    /// `path` with the latest MPC key version, as `foreign_address_for`
+    /// would return them, for each configured chain by ascending chain ID.
+    /// Chains that namespace derivation each have their own address, and
+    /// the others share one. Lists at most
+    /// [`Contract::MAX_ALL_ADDRESSES_CHAINS`] chains.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the MPC key is not configured, or if `path` is
+    /// empty, longer than the maximum path length, or contains
+    /// [`HIERARCHICAL_PATH_SEPARATOR`].
+    #[handle_result]
+    pub fn all_addresses_for(
+        &self,
+        account_id: AccountId,
+        path: String,
+    ) -> Result<Vec<(U64, String)>, ContractError> {
+        let mut chain_ids = self.foreign_chains.keys().collect::<Vec<_>>();
+        chain_ids.sort_unstable();
+        chain_ids.truncate(Self::MAX_ALL_ADDRESSES_CHAINS);
+
+        let mpc_path = self.try_caller_mpc_path(&account_id, &path)?;
+        let key_version = self.try_resolve_mpc_key_version(None)?;
+
+        // The address shared by chains that do not namespace derivation is
+        // only derived once.
+        let mut shared_address = None;
+        chain_ids
+            .into_iter()
+            .map(|chain_id| {
+                let foreign_address = if self.get_chain(chain_id)?.namespaced_derivation {
+                    self.try_foreign_address_for_mpc_path(
+                        &namespaced_mpc_path(&mpc_path, chain_id),
+                        key_version,
+                    )?
+                } else if let Some(foreign_address) = shared_address {
+                    foreign_address
+                } else {
+                    *shared_address
+                        .insert(self.try_foreign_address_for_mpc_path(&mpc_path, key_version)?)
+                };
+
+                Ok((chain_id.into(), foreign_address.to_string()))
+            })
+            .collect()
+    }
+
+    /// Derive the foreign address that `sign` would sign for on behalf of
+    /// the predecessor at `path` on chain `chain_id` with the latest MPC key
+    /// version, and register it, so that later sign requests for `path` on
+    /// that chain are rejected if they derive to a different address, e.g.
+    /// because the MPC key was rotated. Registering again replaces the
+    /// registered address. Returns the checksummed address.
+    ///
+    /// The attached deposit must cover the storage of the registration, and
+    /// the rest is refunded. `unregister_address` refunds the storage.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the chain or the MPC key is not configured, if
+    /// `path` is empty, longer than the maximum path length, or contains
+    /// [`HIERARCHICAL_PATH_SEPARATOR`], or if the attached deposit does not
+    /// cover the storage deposit.
+    #[payable]
+    #[handle_result]
+    pub fn register_address(
+        &mut self,
+        chain_id: U64,
+        path: String,
+    ) -> Result<String, ContractError> {
+        let caller = env::predecessor_account_id();
+        let mpc_path = self.try_chain_mpc_path(&caller, &path, chain_id.0)?;
+        let key_version = self.try_resolve_mpc_key_version(None)?;
+        let foreign_address = self.try_foreign_address_for_mpc_path(&mpc_path, key_version)?;
+
+        let storage_usage_before = env::storage_usage();
+        self.registered_addresses
+            .insert((caller, path, chain_id.0), foreign_address);
+        self.registered_addresses.flush();
+        Self::try_charge_storage(env::storage_usage().saturating_sub(storage_usage_before))?;
+
+        Ok(foreign_address.to_string())
+    }
+
+    /// Remove the address that the predecessor registered for `path` on
+    /// chain `chain_id` with `register_address`, if any, and refund its
+    /// storage. Sign requests for `path` are then no longer checked against
+    /// it.
+    pub fn unregister_address(&mut self, chain_id: U64, path: String) {
+        let storage_usage_before = env::storage_usage();
+        self.registered_addresses
+            .remove(&(env::predecessor_account_id(), path, chain_id.0));
+        self.registered_addresses.flush();
+        Self::refund_freed_storage(storage_usage_before);
+    }
+
+    /// Add `recipient` to the recipient allowlist of the predecessor. Once a
+    /// caller has allowed a recipient, its sign requests are rejected unless
+    /// they send to a recipient on its allowlist, on any chain; callers that
+    /// never have are unrestricted.
+    ///
+    /// The attached deposit must cover the storage of the entry, and the rest
+    /// is refunded. `deny_recipient` refunds the storage.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the attached deposit does not cover the storage
+    /// deposit.
+    #[payable]
+    #[handle_result]
+    pub fn allow_recipient(&mut self, recipient: ForeignAddress) -> Result<(), ContractError> {
+        let caller = env::predecessor_account_id();
+        let storage_usage_before = env::storage_usage();
+        let allowlist = self
+            .recipient_allowlists
+            .entry(caller.clone())
+            .or_insert_with(|| LookupSet::new(StorageKey::RecipientAllowlist(caller)));
+        allowlist.insert(recipient);
+        self.recipient_allowlists.flush();
+        Self::try_charge_storage(env::storage_usage().saturating_sub(storage_usage_before))?;
+
+        Ok(())
+    }
+
+    /// Remove `recipient` from the recipient allowlist of the predecessor,
+    /// and refund the storage of its entry. The allowlist stays enabled even
+    /// once it is empty, in which case every sign request is rejected.
+    pub fn deny_recipient(&mut self, recipient: ForeignAddress) {
+        let storage_usage_before = env::storage_usage();
+        if let Some(allowlist) = self
+            .recipient_allowlists
+            .get_mut(&env::predecessor_account_id())
+        {
+            allowlist.remove(&recipient);
+        }
+        Self::refund_freed_storage(storage_usage_before);
+    }
+
+    /// Whether sign requests of `account_id` may send to `recipient`.
+    pub fn is_recipient_allowed(&self, account_id: AccountId, recipient: ForeignAddress) -> bool {
+        self.check_recipient_allowed(&account_id, Some(recipient))
+            .is_ok()
+    }
+
+    /// The checksummed address that `account_id` registered for `path` on
+    /// chain `chain_id`, if any.
+    pub fn get_registered_address(
+        &self,
+        account_id: AccountId,
+        path: String,
+        chain_id: U64,
+    ) -> Option<String> {
+        self.registered_addresses
+            .get(&(account_id, path, chain_id.0))
+            .map(ToString::to_string)
+    }
+
+    /// The checksummed EVM address that is derived for the gas station
+    /// itself at [`Contract::RELAYER_PATH`] on chain `chain_id`, with the
+    /// latest MPC key version. It holds the funds that relayers broadcast
+    /// sponsored transactions with, so operators top it up.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the chain or the MPC key is not configured.
+    #[handle_result]
+    pub fn contract_foreign_address(&self, chain_id: U64) -> Result<String, ContractError> {
+        self.foreign_address_for(
+            env::current_account_id(),
+            Self::RELAYER_PATH.to_string(),
+            chain_id,
+            None,
+            None,
+        )
+    }
+
+    /// Quote a sign request by `caller` for `path` on `chain_id`: the
+    /// foreign address it would be signed for, the nonce it would be
+    /// assigned, the current gas price, and the NEAR fee that `sign` would
+    /// charge for `gas_limit` gas at that price.
+    ///
+    /// On rollups, the fee includes the L1 data fee of a transaction that is
+    /// `serialized_tx_len` bytes long once signed, which defaults to the
+    /// length of a typical signed transfer.
+    ///
+    /// # Errors
+    ///
+    /// - If the chain is not configured.
+    /// - If there is no gas price or gas token price for the chain (or L1
+    ///   gas price, for rollups), or any of them is stale.
+    /// - If the MPC key is not configured.
+    #[handle_result]
+    pub fn get_quote(
+        &self,
+        caller: AccountId,
+        path: String,
+        chain_id: U64,
+        gas_limit: U128,
+        key_version: Option<u32>,
+        serialized_tx_len: Option<U64>,
+    ) -> Result<Quote, ContractError> {
+        let chain_id = chain_id.0;
+        let decimals = self.get_chain(chain_id)?.decimals;
+        let gas_price = self.try_current_gas_price(chain_id)?;
+        let token_price = self.try_current_token_price(chain_id)?;
+        let l1_data_fee = self.try_l1_data_fee(
+            chain_id,
+            serialized_tx_len.map_or(Self::TYPICAL_SIGNED_TRANSFER_LEN, |len| len.0),
+        )?;
+        let key_version = self.try_resolve_mpc_key_version(key_version)?;
+        let foreign_address = self.try_foreign_address_for_mpc_path(
+            &self.try_chain_mpc_path(&caller, &path, chain_id)?,
+            key_version,
+        )?;
+        let near_fee = compute_fee_with_l1_data_fee(
+            gas_limit.0,
+            gas_price.max_fee_per_gas.0,
+            l1_data_fee,
+            token_price.price_in_near.0,
+            decimals,
+            self.fee_markup_bps_for_chain(chain_id)?,
+        )?;
+
+        Ok(Quote {
+            foreign_address,
+            next_nonce: self.nonces.peek_nonce(chain_id, foreign_address).into(),
+            max_fee_per_gas: gas_price.max_fee_per_gas,
+            max_priority_fee_per_gas: gas_price.max_priority_fee_per_gas,
+            l1_data_fee: l1_data_fee.into(),
+            near_fee: near_fee.as_yoctonear().into(),
+        })
+    }
+
+    /// The `0x`-prefixed hash that the MPC contract would be asked to sign
+    /// if `account_id` called `sign` with the same arguments now, so that
+    /// clients can verify it independently. The transaction is built the
+    /// same way, in the format that the chain supports.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `sign` would reject the transaction before
+    /// requesting a signature.
+    #[handle_result]
+    pub fn preview_signing_hash(
+        &self,
+        account_id: AccountId,
+        path: String,
+        transaction: TransactionInput,
+        key_version: Option<u32>,
+    ) -> Result<String, ContractError> {
+        let key_version = self.try_resolve_mpc_key_version(key_version)?;
+        let foreign_address = self.try_foreign_address_for_mpc_path(
+            &self.try_chain_mpc_path(&account_id, &path, transaction.chain_id.0)?,
+            key_version,
+        )?;
+        let nonce = transaction.nonce.map_or_else(
+            || {
+                self.nonces
+                    .peek_nonce(transaction.chain_id.0, foreign_address)
+            },
+            |nonce| nonce.0,
+        );
+        let transaction = self.prepare_transaction(transaction, nonce)?;
+
+        Ok(to_hex_prefixed(&transaction.signing_hash()))
+    }
+
+    /// `None` if there is no such request.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `request_id` is not 32 hex-encoded bytes.
+    #[handle_result]
+    pub fn get_request_status(
+        &self,
+        request_id: String,
+    ) -> Result<Option<SignRequestRecord>, ContractError> {
+        let request_id = parse_request_id(&request_id)?;
+
+        Ok(self
+            .sign_requests
+            .get(&request_id)
+            .map(|status| SignRequestRecord {
+                status: status.clone(),
+                memo: self.sign_request_memos.get(&request_id).cloned(),
+                mpc_contract_id: self.sign_request_providers.get(&request_id).cloned(),
+            }))
+    }
+
+    /// Sign requests in the order they were first made, starting at
+    /// `from_index`. At most [`Contract::MAX_LIST_REQUESTS_LIMIT`] requests
+    /// are returned.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ContractError::RequestNotFound`] if a listed request has no
+    /// status, which would be a bug.
+    #[handle_result]
+    pub fn list_requests(
+        &self,
+        from_index: U64,
+        limit: U64,
+    ) -> Result<Vec<(String, RequestStatus)>, ContractError> {
+        let limit = limit.0.min(Self::MAX_LIST_REQUESTS_LIMIT);
+
+        (from_index.0..self.sign_request_ids.len())
+            .take(usize::try_from(limit).unwrap_or(usize::MAX))
+            .filter_map(|index| self.sign_request_ids.get(index))
+            .map(|request_id| {
+                let status = self
+                    .sign_requests
+                    .get(&request_id)
+                    .cloned()
+                    .ok_or(ContractError::RequestNotFound)?;
+
+                Ok((to_hex_prefixed(&request_id), status))
+            })
+            .collect()
+    }
+
+    /// Run a sign request by `account_id` with the same arguments as `sign`
+    /// through every check short of the attached deposit and the rate
+    /// limit, and return the nonce, signing hash, and fee that `sign` would
+    /// use. No signature is requested and nothing is modified: in
+    /// particular, the nonce is not reserved.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error that `sign` would, except for those about the
+    /// deposit and the rate limit.
+    #[handle_result]
+    pub fn dry_run_sign(
+        &self,
+        account_id: AccountId,
+        path: String,
+        transaction: TransactionInput,
+        key_version: Option<u32>,
+    ) -> Result<SignDryRun, ContractError> {
+        let prepared = self.try_prepare_sign(&account_id, path, transaction, key_version)?;
+        let (fee, raw_transaction) = match prepared.state {
+            PreparedSignState::Completed(result) => (0, Some(result.raw_tx)),
+            PreparedSignState::ToSign { prepaid_fee, .. } => (prepaid_fee.amount.0, None),
+        };
+
+        Ok(SignDryRun {
+            request_id: to_hex_prefixed(&prepared.request_id),
+            foreign_address: prepared.foreign_address,
+            nonce: prepared.nonce.into(),
+            signing_hash: to_hex_prefixed(&prepared.payload),
+            fee: fee.into(),
+            raw_transaction,
+        })
+    }
+
+    /// Build a transaction for the predecessor's foreign address at `path`,
+    /// assign it the next nonce, and request a signature from the MPC
+    /// contract with MPC key version `key_version` (the latest by default).
+    /// Resolves to the raw signed transaction, along with its hash, sender,
+    /// nonce, and chain ID.
+    ///
+    /// Requests are identified by their caller, chain ID, nonce, key
+    /// version, foreign address, and signing hash. Resubmitting a request with the nonce that it was assigned
+    /// returns the signed transaction if it has already completed, instead
+    /// of signing it again.
+    ///
+    /// Subject to the per-account rate limit, if one is configured.
+    ///
+    /// New requests are charged the NEAR fee for the gas limit of the
+    /// transaction at its max fee per gas, which must be attached. Up to the
+    /// fee slippage (see `set_fee_slippage_bps`) of the excess deposit is
+    /// kept with the fee, and the rest is refunded. The fee can later be
+    /// settled against the actual cost of the transaction with
+    /// `settle_fee`, which refunds whatever it did not cost. Until then, none
+    /// of it is withdrawable.
+    ///
+    /// `memo` is an optional reference of up to [`Contract::MAX_MEMO_LEN`]
+    /// bytes (e.g. an order ID) to store with the request, for reconciliation
+    /// with off-chain systems. It is returned by `get_request_status`.
+    ///
+    /// The storage cost of the request's records, including the signed
+    /// transaction that it will resolve to, is also kept out of the deposit,
+    /// before the fee slippage buffer. Once the request completes, fails or
+    /// is cancelled, whatever its remaining records do not take up is
+    /// refunded.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the contract is paused, if the request is invalid,
+    /// is already pending, exceeds the rate limit, if the foreign address
+    /// does not match the one registered for `path` with `register_address`,
+    /// if the recipient is not on the caller's recipient allowlist (see
+    /// `allow_recipient`), if its max fee per gas is outside of the fee band
+    /// (see `set_fee_band`), if not enough gas is prepaid for the MPC call, or
+    /// if the attached deposit does not cover the fee and the storage
+    /// deposit. Nothing is modified in that case.
+    #[payable]
+    #[handle_result]
+    pub fn sign(
+        &mut self,
+        path: String,
+        transaction: TransactionInput,
+        key_version: Option<u32>,
+        memo: Option<String>,
+    ) -> Result<PromiseOrValue<SignResult>, ContractError> {
+        Self::check_unpaused()?;
+
+        let caller = env::predecessor_account_id();
+        let deposit = env::attached_deposit().as_yoctonear();
+        let (_, charged, result) =
+            self.try_sign(&caller, path, transaction, key_version, memo, deposit)?;
+        Self::refund_deposit(caller, deposit - charged);
+
+        Ok(result)
+    }
+
+    /// Like `sign`, but for a transaction that the caller built itself:
+    /// `unsigned_rlp` is the hex-encoded unsigned EIP-2718 envelope (or, for
+    /// legacy transactions, the EIP-155 RLP) whose keccak hash the MPC
+    /// contract is asked to sign, with the latest key version. Resolves to
+    /// `unsigned_rlp` with the signature attached.
+    ///
+    /// The transaction is held to the same policies as with `sign`: its
+    /// nonce must be the next one of the foreign address, and its fees and
+    /// gas limit must be within the configured bounds. It is not otherwise
+    /// modified, e.g. to raise its priority fee to the chain's minimum.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if `unsigned_rlp` is not valid hex or not the
+    /// canonical encoding of an unsigned transaction, if the chain does not support its type (see
+    /// `contract_metadata`) or it is for another chain, or for any of the
+    /// reasons that `sign` would reject it. Nothing is modified in that case.
+    #[payable]
+    #[handle_result]
+    pub fn sign_prebuilt(
+        &mut self,
+        chain_id: U64,
+        unsigned_rlp: String,
+        path: String,
+    ) -> Result<PromiseOrValue<SignResult>, ContractError> {
+        Self::check_unpaused()?;
+
+        let unsigned_rlp = hex::decode(unsigned_rlp).map_err(|e| {
+            ContractError::InvalidInput(format!("Error decoding `unsigned_rlp` as hex: {e}"))
+        })?;
+        let caller = env::predecessor_account_id();
+        let deposit = env::attached_deposit().as_yoctonear();
+        let prepared = self.try_prepare_prebuilt_sign(&caller, chain_id.0, &unsigned_rlp, path)?;
+        let (_, charged, result) = self.try_request_sign(&caller, prepared, None, deposit)?;
+        Self::refund_deposit(caller, deposit - charged);
+
+        Ok(result)
+    }
+
+    /// Request a `personal_sign` signature over the hex-encoded `message`
+    /// (see [`personal_sign_hash`]) from the key derived for the caller at
+    /// `path`, for off-chain authentication as the address returned by
+    /// `foreign_address_for`. Resolves to the hex-encoded 65-byte signature.
+    ///
+    /// Subject to the per-account rate limit, if one is configured. The
+    /// attached deposit must cover the deposit that the MPC contract
+    /// requires, and the rest is refunded.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the contract is paused, if `message` is not valid
+    /// hex, if the MPC contract or key version is not configured, if the
+    /// request exceeds the rate limit, or if the attached deposit does not
+    /// cover the MPC deposit.
+    #[payable]
+    #[handle_result]
+    pub fn sign_message(
+        &mut self,
+        path: String,
+        message: String,
+        key_version: Option<u32>,
+    ) -> Result<Promise, ContractError> {
+        Self::check_unpaused()?;
+
+        let message = hex::decode(message).map_err(|e| {
+            ContractError::InvalidInput(format!("Error decoding `message` as hex: {e}"))
+        })?;
+
+        self.try_sign_message_hash(personal_sign_hash(&message), path, key_version)
+    }
+
+    /// Like [`Contract::sign_message`], but signs EIP-712 typed data: the
+    /// hex-encoded `struct_hash` (`hashStruct(message)`) in `domain` (see
+    /// [`typed_data_hash`]).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the contract is paused, if `struct_hash` is not 32
+    /// hex-encoded bytes, if the MPC contract or key version is not
+    /// configured, if the request exceeds the rate limit, or if the attached
+    /// deposit does not cover the MPC deposit.
+    #[payable]
+    #[handle_result]
+    pub fn sign_typed_data(
+        &mut self,
+        path: String,
+        domain: Eip712Domain,
+        struct_hash: String,
+        key_version: Option<u32>,
+    ) -> Result<Promise, ContractError> {
+        Self::check_unpaused()?;
+
+        let struct_hash: [u8; 32] = hex::decode(struct_hash)
+            .ok()
+            .and_then(|bytes| bytes.try_into().ok())
+            .ok_or_else(|| {
+                ContractError::InvalidInput("Struct hash must be 32 hex-encoded bytes".into())
+            })?;
+
+        self.try_sign_message_hash(typed_data_hash(&domain, &struct_hash), path, key_version)
+    }
+
+    /// Like [`Contract::sign`], but for up to [`Contract::MAX_SIGN_BATCH_SIZE`]
+    /// transactions at once, which are signed in parallel. Returns the ID of
+    /// the batch, by which the status of each request can be queried with
+    /// `get_sign_batch`.
+    ///
+    /// Requests are independent: a request that is rejected, or whose
+    /// signature fails, does not affect the others. The attached deposit
+    /// pays for the requests in order, so a request whose fee exceeds what
+    /// is left of it is rejected. Each request keeps its fee slippage buffer
+    /// out of what is left, as with `sign`, and the storage cost of the
+    /// batch's own record is kept out of what is left after the requests.
+    /// The remainder is refunded.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the contract is paused, if the batch is empty or
+    /// too large, or if what is left of the deposit does not cover the
+    /// storage deposit of a request or of the batch. Other errors in
+    /// individual requests are recorded in the batch instead.
+    #[payable]
+    #[handle_result]
+    pub fn sign_batch(&mut self, requests: Vec<BatchSignRequest>) -> Result<U64, ContractError> {
+        Self::check_unpaused()?;
+        if requests.is_empty() {
+            return Err(ContractError::InvalidInput("Batch is empty".into()));
+        }
+        if requests.len() > Self::MAX_SIGN_BATCH_SIZE {
+            return Err(ContractError::InvalidInput(format!(
+                "Batch cannot contain more than {} requests",
+                Self::MAX_SIGN_BATCH_SIZE,
+            )));
+        }
+
+        let caller = env::predecessor_account_id();
+        let mut deposit = env::attached_deposit().as_yoctonear();
+        let items = requests
+            .into_iter()
+            .map(|request| {
+                match self.try_sign(
+                    &caller,
+                    request.path,
+                    request.transaction,
+                    request.key_version,
+                    request.memo,
+                    deposit,
+                ) {
+                    // Completed requests resolve immediately, and pending
+                    // requests resolve through their own callbacks.
+                    Ok((request_id, charged, _)) => {
+                        deposit -= charged;
+                        Ok(SignBatchItem::Requested(request_id))
+                    }
+                    // Its records are already written, and can only be
+                    // reverted along with the whole batch.
+                    Err(e @ ContractError::InsufficientStorageDeposit { .. }) => Err(e),
+                    Err(e) => Ok(SignBatchItem::Rejected {
+                        reason: e.to_string(),
+                    }),
+                }
+            })
+            .collect::<Result<_, _>>()?;
+
+        let storage_usage_before = self.flushed_storage_usage();
+        let batch_id = self.generate_unique_id();
+        self.sign_batches.insert(batch_id, items);
+        self.sign_batches.flush();
+        let storage_deposit =
+            storage_cost(env::storage_usage().saturating_sub(storage_usage_before));
+        if deposit < storage_deposit {
+            return Err(ContractError::InsufficientStorageDeposit {
+                storage_deposit,
+                shortfall: storage_deposit - deposit,
+            });
+        }
+        Self::refund_deposit(caller, deposit - storage_deposit);
+
+        Ok(batch_id.into())
+    }
+
+    /// The status of each request in a batch, in the order they were
+    /// submitted. Rejected requests have no request ID. `None` if there is
+    /// no such batch.
+    pub fn get_sign_batch(&self, batch_id: U64) -> Option<Vec<SignBatchItemStatus>> {
+        self.sign_batches
+            .get(&batch_id.0)?
+            .iter()
+            .map(|item| match item {
+                SignBatchItem::Requested(request_id) => Some(SignBatchItemStatus {
+                    request_id: Some(to_hex_prefixed(request_id)),
+                    status: self.sign_requests.get(request_id)?.clone(),
+                }),
+                SignBatchItem::Rejected { reason } => Some(SignBatchItemStatus {
+                    request_id: None,
+                    status: RequestStatus::Failed {
+                        reason: reason.clone(),
+                    },
+                }),
+            })
+            .collect()
+    }
+
+    pub fn get_mpc_sign_gas(&self) -> Gas {
+        self.mpc_sign_gas
+    }
+
+    /// Set the gas attached to each call to the MPC contract, e.g. when a
+    /// new version of the MPC contract needs more. Sign requests must be
+    /// made with enough prepaid gas for this, their callback, and their
+    /// own execution.
+    pub fn set_mpc_sign_gas(&mut self, gas: Gas) {
+        <Self as Owner>::require_owner();
+        self.mpc_sign_gas = gas;
+    }
+
+    pub fn get_max_path_len(&self) -> u32 {
+        self.max_path_len
+    }
+
+    /// Set the longest path that callers may sign for. Longer paths would
+    /// only cost more gas to derive keys for.
+    pub fn set_max_path_len(&mut self, max_path_len: u32) {
+        Self::require_administrator();
+        self.max_path_len = max_path_len;
+    }
+
+    pub fn get_sign_request_timeout_seconds(&self) -> U64 {
+        self.sign_request_timeout_seconds.into()
+    }
+
+    /// Set how long after a sign request was made its caller may cancel
+    /// it: at least [`Contract::MIN_SIGN_REQUEST_TIMEOUT_SECONDS`], and
+    /// ending before its nonce reservation may expire (see
+    /// `set_expire_nonce_reservation_after_blocks`).
+    pub fn set_sign_request_timeout_seconds(&mut self, timeout_seconds: U64) {
+        Self::require_administrator();
+        require!(
+            timeout_seconds.0 >= Self::MIN_SIGN_REQUEST_TIMEOUT_SECONDS,
+            format!(
+                "Sign request timeout must be at least {} seconds",
+                Self::MIN_SIGN_REQUEST_TIMEOUT_SECONDS,
+            ),
+        );
+        self.sign_request_timeout_seconds = timeout_seconds.0;
+        require!(
+            self.sign_request_timeout_blocks() < self.expire_nonce_reservation_after_blocks,
+            "Sign requests must time out before their nonce reservations expire",
+        );
+    }
+
+    pub fn get_cancelled_nonce_policy(&self) -> CancelledNoncePolicy {
+        self.cancelled_nonce_policy
+    }
+
+    pub fn set_cancelled_nonce_policy(&mut self, policy: CancelledNoncePolicy) {
+        Self::require_administrator();
+        self.cancelled_nonce_policy = policy;
+    }
+
+    /// Cancel a sign request that is still waiting for its MPC signature,
+    /// e.g. because the MPC contract never responded. Can only be called by
+    /// the caller of `sign`, once the sign request timeout has elapsed since
+    /// the request was made. Any unsettled prepaid fee is refunded to its
+    /// payer, as is the storage deposit of the request beyond what its
+    /// cancelled status takes up, and the nonce is released or burned
+    /// according to the cancelled nonce policy.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request does not exist or is not pending, if
+    /// the predecessor did not make the request, or if the timeout has not
+    /// elapsed yet.
+    #[handle_result]
+    pub fn cancel_request(
+        &mut self,
+        request_id: String,
+    ) -> Result<PromiseOrValue<()>, ContractError> {
+        let request_id = parse_request_id(&request_id)?;
+
+        match self.sign_requests.get(&request_id) {
+            None => return Err(ContractError::RequestNotFound),
+            Some(RequestStatus::Pending) => {}
+            Some(_) => return Err(ContractError::RequestNotPending),
+        }
+
+        let pending = self
+            .pending_sign_requests
+            .get(&request_id)
+            .unwrap_or_reject() // inconsistent state if this fails
+            .clone();
+
+        let predecessor = env::predecessor_account_id();
+        if predecessor != pending.caller {
+            return Err(ContractError::Unauthorized {
+                account_id: predecessor,
+                required: "the account that made the sign request",
+            });
+        }
+
+        let timeout_ns = self
+            .sign_request_timeout_seconds
+            .saturating_mul(1_000_000_000);
+        if env::block_timestamp().saturating_sub(pending.created_at_ns) < timeout_ns {
+            return Err(ContractError::RequestNotTimedOut {
+                timeout_seconds: self.sign_request_timeout_seconds,
+            });
+        }
+
+        let storage_usage_before = self.flushed_storage_usage();
+        self.pending_sign_requests.remove(&request_id);
+        self.sign_request_providers.remove(&request_id);
+        self.sign_requests
+            .insert(request_id, RequestStatus::Cancelled);
+
+        let nonce_released = match self.cancelled_nonce_policy {
+            CancelledNoncePolicy::Release => {
+                self.nonces
+                    .release_nonce(pending.chain_id, pending.foreign_address, pending.nonce)
+            }
+            CancelledNoncePolicy::Burn => {
+                self.nonces
+                    .confirm_nonce(pending.chain_id, pending.foreign_address, pending.nonce);
+                false
+            }
+        };
+
+        ContractEvent::SignCancelled(SignCancelled {
+            request_id: to_hex_prefixed(&request_id),
+            nonce_released,
+        })
+        .emit();
+
+        let refund = self.refund_prepaid_fee(&request_id);
+        self.refund_storage_deposit(&pending, storage_usage_before);
+
+        Ok(match refund {
+            Some(refund) => PromiseOrValue::Promise(refund),
+            None => PromiseOrValue::Value(()),
+        })
+    }
+
+    /// # Errors
+    ///
+    /// Returns an error if the MPC contract failed to sign, or returned an
+    /// invalid signature.
+    #[private]
+    #[handle_result]
+    pub fn sign_message_callback(
+        &mut self,
+        #[callback_result] result: Result<SignatureResponse, PromiseError>,
+    ) -> Result<String, ContractError> {
+        let signature = Self::try_decode_signature_response(result)?;
+
+        Ok(to_hex_prefixed(&signature.to_rsv_bytes()))
+    }
+
+    /// If the MPC contract could not be reached, the signature is requested
+    /// from the next of its fallbacks instead, if there is one.
+    #[private]
+    pub fn sign_callback(
+        &mut self,
+        #[serializer(borsh)] request_id: RequestId,
+        #[serializer(borsh)] foreign_address: ForeignAddress,
+        #[serializer(borsh)] transaction: ForeignTransaction,
+        #[callback_result] result: Result<SignatureResponse, PromiseError>,
+    ) -> PromiseOrValue<SignResult> {
+        // The nonce of a cancelled request has already been dealt with.
+        if matches!(
+            self.sign_requests.get(&request_id),
+            Some(RequestStatus::Cancelled),
+        ) {
+            return PromiseOrValue::Promise(
+                Self::ext(env::current_account_id())
+                    .throw(ContractError::RequestNotPending.to_string()),
+            );
+        }
+        // The MPC contract may be unreachable, rather than unable to sign.
+        if result.is_err() {
+            if let Some(failover) =
+                self.try_fail_over_mpc_sign(request_id, foreign_address, &transaction)
+            {
+                return PromiseOrValue::Promise(failover);
+            }
+        }
+        let storage_usage_before = self.flushed_storage_usage();
+        let pending = self.pending_sign_requests.remove(&request_id);
+        self.sign_request_providers.remove(&request_id);
+
+        match Self::try_sign_callback(transaction.clone(), foreign_address, result) {
+            Ok(raw_transaction) => {
+                ContractEvent::SignCompleted(SignCompleted {
+                    request_id: to_hex_prefixed(&request_id),
+                    raw_tx_hash: to_hex_prefixed(&keccak256(&raw_transaction)),
+                })
+                .emit();
+
+                self.nonces.confirm_nonce(
+                    transaction.chain_id(),
+                    foreign_address,
+                    transaction.nonce(),
+                );
+                self.record_signed(&request_id, transaction.chain_id());
+                let result = SignResult::new(
+                    &raw_transaction,
+                    foreign_address,
+                    transaction.nonce(),
+                    transaction.chain_id(),
+                );
+                self.sign_requests.insert(
+                    request_id,
+                    RequestStatus::Completed {
+                        raw_transaction: result.raw_tx.clone(),
+                        sender: foreign_address,
+                    },
+                );
+                if let Some(pending) = &pending {
+                    self.refund_storage_deposit(pending, storage_usage_before);
+                }
+
+                PromiseOrValue::Value(result)
+            }
+            Err(e) => {
+                // The transaction will never be broadcast, so its nonce can be
+                // reused and its fee refunded.
+                self.nonces.release_nonce(
+                    transaction.chain_id(),
+                    foreign_address,
+                    transaction.nonce(),
+                );
+                drop(self.refund_prepaid_fee(&request_id));
+
+                ContractEvent::SignFailed(SignFailed {
+                    request_id: to_hex_prefixed(&request_id),
+                    reason: e.to_string(),
+                })
+                .emit();
+
+                self.sign_requests.insert(
+                    request_id,
+                    RequestStatus::Failed {
+                        reason: e.to_string(),
+                    },
+                );
+                if let Some(pending) = &pending {
+                    self.refund_storage_deposit(pending, storage_usage_before);
+                }
+
+                PromiseOrValue::Promise(Self::ext(env::current_account_id()).throw(e.to_string()))
+            }
+        }
+    }
+}
+
+/// `mpc_path` with `chain_id` appended, so that the MPC contract derives the
+/// same key for it as [`lib::kdf::derive_epsilon_for_chain`] does for
+/// `mpc_path`.
+fn namespaced_mpc_path(mpc_path: &str, chain_id: u64) -> String {
+    join_derivation_path(&[mpc_path, &chain_id.to_string()])
+}
+
+/// See [`Contract::try_prepare_sign`].
+struct PreparedSign {
+    request_id: RequestId,
+    key_version: u32,
+    mpc_path: String,
+    foreign_address: ForeignAddress,
+    nonce: u64,
+    transaction: ForeignTransaction,
+    payload: [u8; 32],
+    state: PreparedSignState,
+}
+
+enum PreparedSignState {
+    /// Resubmitted after completing, so not signed again.
+    Completed(SignResult),
+    ToSign {
+        /// `false` if the request failed or was cancelled before.
+        is_new_request: bool,
+        prepaid_fee: PrepaidFee,
+    },
+}
+
+impl Contract {
+    /// Each request in a batch needs its own MPC call and callback, which
+    /// with the default MPC sign gas leaves room for no more than 3 requests
+    /// in a transaction.
+    pub const MAX_SIGN_BATCH_SIZE: usize = 3;
+    /// Bounds how many times a sign request can fail over.
+    pub const MAX_MPC_FALLBACKS: usize = 3;
+    pub const MAX_LIST_REQUESTS_LIMIT: u64 = 100;
+    /// Bounds the response of `all_addresses_for`.
+    pub const MAX_ALL_ADDRESSES_CHAINS: usize = 64;
+    /// The path that the gas station derives its own foreign address at.
+    pub const RELAYER_PATH: &'static str = "relayer";
+    /// Bounds the storage that each sign request can take up with its memo.
+    pub const MAX_MEMO_LEN: usize = 256;
+    /// The MPC contract gives up on a sign request after its yield/resume
+    /// timeout of 200 blocks, a few minutes at most. A request cannot be
+    /// cancelled before then, since the MPC contract might still sign it
+    /// after its fee was refunded and its nonce reused.
+    pub const MIN_SIGN_REQUEST_TIMEOUT_SECONDS: u64 = 5 * 60;
+    /// Roughly the length of a signed EIP-1559 transfer, which `get_quote`
+    /// assumes by default.
+    const TYPICAL_SIGNED_TRANSFER_LEN: u64 = 115;
+    /// The MPC contract requires a deposit to sign, to deter spam.
+    const MPC_SIGN_DEPOSIT: NearToken = NearToken::from_yoctonear(1);
+    const SIGN_CALLBACK_GAS: Gas = Gas::from_tgas(5);
+    /// Gas used by a sign request besides the MPC call and its callback.
+    const SIGN_LOCAL_GAS: Gas = Gas::from_tgas(10);
+
+    /// Request a signature over `payload` from the MPC contract, using the
+    /// key derived for this contract at `path`.
+    pub fn mpc_sign(&self, payload: [u8; 32], path: String, key_version: u32) -> Promise {
+        self.try_mpc_sign(payload, path, key_version, Self::SIGN_CALLBACK_GAS)
+            .unwrap_or_reject()
+    }
+
+    /// Requests a signature from the MPC contract, checking that enough gas
+    /// is left for the call and a callback with `callback_gas`.
+    fn try_mpc_sign(
+        &self,
+        payload: [u8; 32],
+        path: String,
+        key_version: u32,
+        callback_gas: Gas,
+    ) -> Result<Promise, ContractError> {
+        let mpc_contract_id = self.try_mpc_sign_contract_id(callback_gas)?;
+        Ok(self.mpc_sign_call(mpc_contract_id, payload, path, key_version))
+    }
+
+    /// The checks of [`Self::try_mpc_sign`], without creating the promise,
+    /// which is scheduled even if it is dropped. Returns the ID of the MPC
+    /// contract to request the signature from.
+    fn try_mpc_sign_contract_id(&self, callback_gas: Gas) -> Result<AccountId, ContractError> {
+        let mpc_contract_id = self
+            .mpc_contract_id
+            .clone()
+            .ok_or(ContractError::MpcNotConfigured)?;
+
+        // Checked for each call, since the gas attached to earlier calls
+        // counts as used.
+        let required = self
+            .mpc_sign_gas
+            .saturating_add(callback_gas)
+            .saturating_add(Self::SIGN_LOCAL_GAS);
+        let available = env::prepaid_gas().saturating_sub(env::used_gas());
+        if available < required {
+            return Err(ContractError::InsufficientGas {
+                required,
+                available,
+            });
+        }
+
+        Ok(mpc_contract_id)
+    }
+
+    fn mpc_sign_call(
+        &self,
+        mpc_contract_id: AccountId,
+        payload: [u8; 32],
+        path: String,
+        key_version: u32,
+    ) -> Promise {
+        // Any unused gas goes to the callbacks instead, to fail over with.
+        ext_signer::ext(mpc_contract_id)
+            .with_static_gas(self.mpc_sign_gas)
+            .with_unused_gas_weight(0)
+            .with_attached_deposit(Self::MPC_SIGN_DEPOSIT)
+            .sign(SignRequest::new(payload, path, key_version))
+    }
+
+    /// Requests the signature of a pending sign request from the MPC
+    /// contract after the one that it was last requested from, if there is
+    /// one and enough gas is left. The request's timeout restarts, since the
+    /// next MPC contract might sign it until its own timeout.
+    fn try_fail_over_mpc_sign(
+        &mut self,
+        request_id: RequestId,
+        foreign_address: ForeignAddress,
+        transaction: &ForeignTransaction,
+    ) -> Option<Promise> {
+        let providers = self.get_mpc_providers();
+        let served_by = self.sign_request_providers.get(&request_id)?;
+        let next_index = providers.iter().position(|id| id == served_by)? + 1;
+        let next_provider = providers.get(next_index)?.clone();
+
+        let available = env::prepaid_gas().saturating_sub(env::used_gas());
+        if available < self.mpc_sign_gas.saturating_add(Self::SIGN_CALLBACK_GAS) {
+            return None;
+        }
+
+        let pending = self.pending_sign_requests.get_mut(&request_id)?;
+        pending.created_at_ns = env::block_timestamp();
+        let pending = pending.clone();
+        self.sign_request_providers
+            .insert(request_id, next_provider.clone());
+
+        Some(
+            self.mpc_sign_call(
+                next_provider,
+                transaction.signing_hash(),
+                pending.mpc_path,
+                pending.key_version,
+            )
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(Self::SIGN_CALLBACK_GAS)
+                    .sign_callback(request_id, foreign_address, transaction.clone()),
+            ),
+        )
+    }
+
+    /// Requests a signature over `message_hash` from the key derived for the
+    /// caller at `path`, to be encoded by `sign_message_callback`. The
+    /// attached deposit pays for the MPC deposit, and the rest is refunded.
+    fn try_sign_message_hash(
+        &mut self,
+        message_hash: [u8; 32],
+        path: String,
+        key_version: Option<u32>,
+    ) -> Result<Promise, ContractError> {
+        let caller = env::predecessor_account_id();
+        let deposit = env::attached_deposit().as_yoctonear();
+        let fee = Self::MPC_SIGN_DEPOSIT.as_yoctonear();
+        if deposit < fee {
+            return Err(ContractError::InsufficientDeposit {
+                fee,
+                shortfall: fee - deposit,
+            });
+        }
+        let key_version = self.try_resolve_mpc_key_version(key_version)?;
+        let mpc_path = self.try_caller_mpc_path(&caller, &path)?;
+        let mpc_contract_id = self.try_mpc_sign_contract_id(Self::SIGN_CALLBACK_GAS)?;
+        self.rate_limiter
+            .record_request(&caller, env::block_timestamp())?;
+        Self::refund_deposit(caller, deposit - fee);
+
+        Ok(self
+            .mpc_sign_call(mpc_contract_id, message_hash, mpc_path, key_version)
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(Self::SIGN_CALLBACK_GAS)
+                    .with_unused_gas_weight(0)
+                    .sign_message_callback(),
+            ))
+    }
+
+    /// Validates a sign request on behalf of `caller` and, unless it has
+    /// already completed, reserves its nonce, charges its fee out of
+    /// `deposit`, and requests the signature. Returns the amount charged (the
+    /// fee and the storage deposit), in yoctoNEAR. Nothing is modified if the
+    /// request is rejected, except on
+    /// [`ContractError::InsufficientStorageDeposit`], which is only detected
+    /// once the request's records are written.
+    fn try_sign(
+        &mut self,
+        caller: &AccountId,
+        path: String,
+        transaction: TransactionInput,
+        key_version: Option<u32>,
+        memo: Option<String>,
+        deposit: u128,
+    ) -> Result<(RequestId, u128, PromiseOrValue<SignResult>), ContractError> {
+        if let Some(memo) = &memo {
+            if memo.len() > Self::MAX_MEMO_LEN {
+                return Err(ContractError::MemoTooLong {
+                    len: memo.len(),
+                    max_len: Self::MAX_MEMO_LEN,
+                });
+            }
+        }
+
+        let prepared = self.try_prepare_sign(caller, path, transaction, key_version)?;
+        self.try_request_sign(caller, prepared, memo, deposit)
+    }
+
+    /// The part of [`Self::try_sign`] after the request has been prepared.
+    fn try_request_sign(
+        &mut self,
+        caller: &AccountId,
+        prepared: PreparedSign,
+        memo: Option<String>,
+        deposit: u128,
+    ) -> Result<(RequestId, u128, PromiseOrValue<SignResult>), ContractError> {
+        let PreparedSign {
+            request_id,
+            key_version,
+            mpc_path,
+            foreign_address,
+            nonce,
+            transaction,
+            payload,
+            state,
+        } = prepared;
+        let chain_id = transaction.chain_id();
+
+        let (is_new_request, prepaid_fee) = match state {
+            PreparedSignState::Completed(result) => {
+                return Ok((request_id, 0, PromiseOrValue::Value(result)));
+            }
+            PreparedSignState::ToSign {
+                is_new_request,
+                prepaid_fee,
+            } => (is_new_request, prepaid_fee),
+        };
+
+        let fee = prepaid_fee.amount.0;
+        if deposit < fee {
+            return Err(ContractError::InsufficientDeposit {
+                fee,
+                shortfall: fee - deposit,
+            });
+        }
+        // The promise is only created once nothing else can fail, since it
+        // would be scheduled even if the request were rejected.
+        let mpc_contract_id = self.try_mpc_sign_contract_id(Self::SIGN_CALLBACK_GAS)?;
+        self.rate_limiter
+            .record_request(caller, env::block_timestamp())?;
+
+        let storage_usage_before = self.flushed_storage_usage();
+        self.nonces
+            .reserve_nonce(chain_id, foreign_address, request_id);
+        self.sign_requests
+            .insert(request_id, RequestStatus::Pending);
+        // A resubmission of a failed request replaces its memo.
+        match memo {
+            Some(memo) => self.sign_request_memos.insert(request_id, memo),
+            None => self.sign_request_memos.remove(&request_id),
+        };
+        self.pending_sign_requests.insert(
+            request_id,
+            PendingSignRequest {
+                caller: caller.clone(),
+                chain_id,
+                foreign_address,
+                nonce,
+                created_at_ns: env::block_timestamp(),
+                mpc_path: mpc_path.clone(),
+                key_version,
+                storage_deposit: 0,
+                storage_usage: 0,
+            },
+        );
+        if let Some(mpc_contract_id) = &self.mpc_contract_id {
+            self.sign_request_providers
+                .insert(request_id, mpc_contract_id.clone());
+        }
+        if is_new_request {
+            self.sign_request_ids.push(&request_id);
+        }
+        self.prepaid_fees.insert(request_id, prepaid_fee);
+
+        let storage_usage = self
+            .flushed_storage_usage()
+            .saturating_sub(storage_usage_before);
+        let (fee, storage_deposit) =
+            self.try_charge_request(&request_id, &transaction, storage_usage, fee, deposit)?;
+        self.credit_sign_fees(chain_id, fee);
+
+        ContractEvent::SignRequested(SignRequested {
+            request_id: to_hex_prefixed(&request_id),
+            caller: caller.clone(),
+            chain_id: chain_id.into(),
+            foreign_address,
+            nonce: nonce.into(),
+        })
+        .emit();
+
+        Ok((
+            request_id,
+            fee + storage_deposit,
+            PromiseOrValue::Promise(
+                self.mpc_sign_call(mpc_contract_id, payload, mpc_path, key_version)
+                    .then(
+                        Self::ext(env::current_account_id())
+                            .with_static_gas(Self::SIGN_CALLBACK_GAS)
+                            .sign_callback(request_id, foreign_address, transaction),
+                    ),
+            ),
+        ))
+    }
+
+    /// Charges the just-written sign request `request_id` for `transaction`
+    /// its `fee` out of `deposit`, along with the storage cost of its
+    /// records, which took up `storage_usage` bytes, and of the signed
+    /// transaction that it will resolve to. Returns the fee kept, with its
+    /// slippage buffer, and the storage deposit.
+    fn try_charge_request(
+        &mut self,
+        request_id: &RequestId,
+        transaction: &ForeignTransaction,
+        storage_usage: u64,
+        fee: u128,
+        deposit: u128,
+    ) -> Result<(u128, u128), ContractError> {
+        let storage_deposit = storage_cost(storage_usage.saturating_add(
+            RequestStatus::completed_storage_growth(transaction.encode_unsigned().len()),
+        ));
+        // Only known once the records are written, which the caller must
+        // revert by panicking.
+        if deposit - fee < storage_deposit {
+            return Err(ContractError::InsufficientStorageDeposit {
+                storage_deposit,
+                shortfall: storage_deposit - (deposit - fee),
+            });
+        }
+
+        // Any slippage buffer is kept until the fee is settled.
+        let fee = (deposit - storage_deposit).min(max_prepaid_fee(fee, self.fee_slippage_bps));
+        if let Some(prepaid_fee) = self.prepaid_fees.get_mut(request_id) {
+            prepaid_fee.amount = fee.into();
+        }
+        if let Some(pending) = self.pending_sign_requests.get_mut(request_id) {
+            pending.storage_deposit = storage_deposit;
+            pending.storage_usage = storage_usage;
+        }
+
+        Ok((fee, storage_deposit))
+    }
+
+    /// Everything about a sign request by `caller` that can be determined
+    /// without modifying anything: the checks and derivations of `sign`, up
+    /// to where the fee is charged.
+    fn try_prepare_sign(
+        &self,
+        caller: &AccountId,
+        path: String,
+        transaction: TransactionInput,
+        key_version: Option<u32>,
+    ) -> Result<PreparedSign, ContractError> {
+        let chain_id = transaction.chain_id.0;
+        let (key_version, mpc_path, foreign_address) =
+            self.try_sign_key(caller, path, chain_id, key_version)?;
+        self.check_recipient_allowed(caller, transaction.to)?;
+        let next_nonce = self.nonces.peek_nonce(chain_id, foreign_address);
+        let nonce = transaction.nonce.map_or(next_nonce, |nonce| nonce.0);
+        let transaction = self.prepare_transaction(transaction, nonce)?;
+
+        self.try_prepare_transaction_sign(
+            caller,
+            key_version,
+            mpc_path,
+            foreign_address,
+            next_nonce,
+            transaction,
+        )
+    }
+
+    /// [`Self::try_prepare_sign`] for `sign_prebuilt`, with the checks of
+    /// `prepare_transaction` applied to the decoded transaction instead.
+    fn try_prepare_prebuilt_sign(
+        &self,
+        caller: &AccountId,
+        chain_id: u64,
+        unsigned_rlp: &[u8],
+        path: String,
+    ) -> Result<PreparedSign, ContractError> {
+        let chain = self.get_chain(chain_id)?;
+        let transaction = decode_unsigned(unsigned_rlp).map_err(|e| {
+            ContractError::InvalidInput(format!("Error decoding `unsigned_rlp`: {e}"))
+        })?;
+        let transaction_type = transaction.transaction_type();
+        if !chain.supports_transaction_type(transaction_type) {
+            return Err(ContractError::UnsupportedTransactionType {
+                chain_id,
+                transaction_type,
+            });
+        }
+        if transaction.chain_id() != chain_id {
+            return Err(ContractError::InvalidInput(format!(
+                "`unsigned_rlp` is for chain ID {}, not {chain_id}",
+                transaction.chain_id(),
+            )));
+        }
+        self.check_reasonable_fee(
+            chain_id,
+            transaction.max_fee_per_gas(),
+            transaction.max_priority_fee_per_gas(),
+        )?;
+        chain.check_gas_limit(transaction.gas_limit())?;
+
+        let (key_version, mpc_path, foreign_address) =
+            self.try_sign_key(caller, path, chain_id, None)?;
+        self.check_recipient_allowed(caller, transaction.to())?;
+        let next_nonce = self.nonces.peek_nonce(chain_id, foreign_address);
+
+        self.try_prepare_transaction_sign(
+            caller,
+            key_version,
+            mpc_path,
+            foreign_address,
+            next_nonce,
+            transaction,
+        )
+    }
+
+    /// Resolves the key version, MPC path, and foreign address that `caller`
+    /// signs with at `path`, checking the address against the one registered
+    /// for `path` on `chain_id`, if any.
+    fn try_sign_key(
+        &self,
+        caller: &AccountId,
+        path: String,
+        chain_id: u64,
+        key_version: Option<u32>,
+    ) -> Result<(u32, String, ForeignAddress), ContractError> {
+        let key_version = self.try_resolve_mpc_key_version(key_version)?;
+        let mpc_path = self.try_chain_mpc_path(caller, &path, chain_id)?;
+        let foreign_address = self.try_foreign_address_for_mpc_path(&mpc_path, key_version)?;
+        if let Some(&registered) = self
+            .registered_addresses
+            .get(&(caller.clone(), path, chain_id))
+        {
+            if registered != foreign_address {
+                return Err(ContractError::AddressMismatch {
+                    chain_id,
+                    registered,
+                    derived: foreign_address,
+                });
+            }
+        }
+
+        Ok((key_version, mpc_path, foreign_address))
+    }
+
+    /// The part of [`Self::try_prepare_sign`] once `transaction` is built:
+    /// resolves the request ID and what is to be done with the request,
+    /// which must have the next nonce of `foreign_address` unless it is a
+    /// resubmission.
+    fn try_prepare_transaction_sign(
+        &self,
+        caller: &AccountId,
+        key_version: u32,
+        mpc_path: String,
+        foreign_address: ForeignAddress,
+        next_nonce: u64,
+        transaction: ForeignTransaction,
+    ) -> Result<PreparedSign, ContractError> {
+        let chain_id = transaction.chain_id();
+        let nonce = transaction.nonce();
+        let payload = transaction.signing_hash();
+        let request_id = sign_request_id(
+            caller,
+            chain_id,
+            nonce,
+            key_version,
+            &foreign_address,
+            &payload,
+        );
+
+        let state = match self.sign_requests.get(&request_id) {
+            Some(RequestStatus::Completed {
+                raw_transaction,
+                sender,
+            }) => PreparedSignState::Completed(SignResult::new(
+                &hex::decode(raw_transaction)
+                    .unwrap_or_else(|_| unreachable!("Raw transactions are stored hex-encoded")),
+                *sender,
+                nonce,
+                chain_id,
+            )),
+            Some(RequestStatus::Pending) => return Err(ContractError::RequestPending),
+            status => {
+                if nonce != next_nonce {
+                    return Err(ContractError::NonceUnavailable {
+                        expected: next_nonce,
+                        actual: nonce,
+                    });
+                }
+
+                PreparedSignState::ToSign {
+                    is_new_request: status.is_none(),
+                    prepaid_fee: self.try_prepaid_fee(caller, &transaction)?,
+                }
+            }
+        };
+
+        Ok(PreparedSign {
+            request_id,
+            key_version,
+            mpc_path,
+            foreign_address,
+            nonce,
+            transaction,
+            payload,
+            state,
+        })
+    }
+
+    /// Checks that the priority fee does not exceed the max fee, and that
+    /// the max fee is within the fee band around the current gas price, if
+    /// one is configured.
+    fn check_reasonable_fee(
+        &self,
+        chain_id: u64,
+        max_fee_per_gas: u128,
+        max_priority_fee_per_gas: u128,
+    ) -> Result<(), ContractError> {
+        if max_priority_fee_per_gas > max_fee_per_gas {
+            return Err(UnreasonableFeeError::PriorityFeeExceedsMaxFee {
+                max_priority_fee_per_gas,
+                max_fee_per_gas,
+            }
+            .into());
+        }
+
+        if let Some(fee_band) = self.fee_band {
+            let gas_price = self.try_current_gas_price(chain_id)?;
+            let (min, max) = fee_band.bounds(gas_price.max_fee_per_gas.0);
+            if !(min..=max).contains(&max_fee_per_gas) {
+                return Err(UnreasonableFeeError::MaxFeeOutOfBand {
+                    max_fee_per_gas,
+                    min,
+                    max,
+                }
+                .into());
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Checks `recipient` against the recipient allowlist of `caller`, if
+    /// it has one. `None` (contract creation) is never on an allowlist.
+    fn check_recipient_allowed(
+        &self,
+        caller: &AccountId,
+        recipient: Option<ForeignAddress>,
+    ) -> Result<(), ContractError> {
+        let Some(allowlist) = self.recipient_allowlists.get(caller) else {
+            return Ok(());
+        };
+
+        match recipient {
+            Some(recipient) if allowlist.contains(&recipient) => Ok(()),
+            Some(recipient) => Err(ContractError::RecipientNotAllowed { recipient }),
+            None => Err(ContractError::ContractCreationNotAllowed),
+        }
+    }
+
+    /// Defaults to the latest key version.
+    fn try_resolve_mpc_key_version(&self, key_version: Option<u32>) -> Result<u32, ContractError> {
+        match key_version {
+            Some(key_version) if self.mpc_keys.get(&key_version).is_none() => {
+                Err(self.unknown_key_version(key_version))
+            }
+            Some(key_version) => Ok(key_version),
+            None => self
+                .mpc_keys
+                .max()
+                .ok_or(ContractError::MpcKeyNotConfigured),
+        }
+    }
+
+    /// Defaults to the latest prefix version.
+    fn try_derivation_prefix(&self, prefix_version: Option<u32>) -> Result<String, ContractError> {
+        let prefix_version = prefix_version
+            .or_else(|| self.derivation_prefixes.max())
+            .unwrap_or_default();
+
+        self.derivation_prefixes
+            .get(&prefix_version)
+            .ok_or_else(|| ContractError::UnknownPrefixVersion {
+                prefix_version,
+                available: self.available_derivation_prefix_versions(),
+            })
+    }
+
+    fn unknown_key_version(&self, key_version: u32) -> ContractError {
+        ContractError::UnknownKeyVersion {
+            key_version,
+            available: self.available_key_versions(),
+        }
+    }
+
+    fn try_current_token_price(&self, chain_id: u64) -> Result<TokenPrice, ContractError> {
+        let token_price = self
+            .gas_price_oracle
+            .latest_token_price(chain_id)
+            .ok_or(ContractError::TokenPriceUnavailable { chain_id })?;
+
+        if self
+            .gas_price_oracle
+            .is_token_price_stale(token_price, env::block_height())
+        {
+            return Err(ContractError::TokenPriceStale {
+                chain_id,
+                observed_at_block: token_price.observed_at_block.0,
+            });
+        }
+
+        Ok(token_price.clone())
+    }
+
+    /// The L1 data fee of a transaction on `chain_id` that is
+    /// `serialized_tx_len` bytes long once signed, at the current L1 gas
+    /// price. Zero if the chain is not a rollup.
+    fn try_l1_data_fee(
+        &self,
+        chain_id: u64,
+        serialized_tx_len: u64,
+    ) -> Result<u128, ContractError> {
+        if self.get_chain(chain_id)?.rollup.is_none() {
+            return Ok(0);
+        }
+
+        let l1_gas_price = self.try_current_l1_gas_price(chain_id)?;
+        Ok(self.l1_data_fee(chain_id, serialized_tx_len, l1_gas_price.gas_price.0)?)
+    }
+
+    fn try_current_l1_gas_price(&self, chain_id: u64) -> Result<L1GasPrice, ContractError> {
+        let l1_gas_price = self
+            .gas_price_oracle
+            .latest_l1_gas_price(chain_id)
+            .ok_or(ContractError::L1GasPriceUnavailable { chain_id })?;
+
+        if self
+            .gas_price_oracle
+            .is_l1_gas_price_stale(l1_gas_price, env::block_height())
+        {
+            return Err(ContractError::L1GasPriceStale {
+                chain_id,
+                observed_at_block: l1_gas_price.observed_at_block.0,
+            });
+        }
+
+        Ok(l1_gas_price.clone())
+    }
+
+    /// The fee for signing `transaction`: its gas limit at its max fee per
+    /// gas plus, on rollups, its L1 data fee, priced at the current gas
+    /// token price with the chain's markup.
+    fn try_prepaid_fee(
+        &self,
+        payer: &AccountId,
+        transaction: &ForeignTransaction,
+    ) -> Result<PrepaidFee, ContractError> {
+        let chain_id = transaction.chain_id();
+        let token_price = self.try_current_token_price(chain_id)?;
+        let foreign_token_decimals = self.get_chain(chain_id)?.decimals;
+        let markup_bps = self.fee_markup_bps_for_chain(chain_id)?;
+        let l1_data_fee = self.try_l1_data_fee(
+            chain_id,
+            u64::try_from(transaction.signed_len()).unwrap_or(u64::MAX),
+        )?;
+        let amount = compute_fee_with_l1_data_fee(
+            transaction.gas_limit(),
+            transaction.max_fee_per_gas(),
+            l1_data_fee,
+            token_price.price_in_near.0,
+            foreign_token_decimals,
+            markup_bps,
+        )?;
+
+        Ok(PrepaidFee {
+            payer: payer.clone(),
+            chain_id: chain_id.into(),
+            amount: amount.as_yoctonear().into(),
+            foreign_token_price_in_near: token_price.price_in_near,
+            foreign_token_decimals,
+            markup_bps,
+            l1_data_fee: l1_data_fee.into(),
+            settlement: None,
+        })
+    }
+
+    /// Refunds the prepaid fee of a sign request that will never be
+    /// broadcast, unless it has already been settled.
+    fn refund_prepaid_fee(&mut self, request_id: &RequestId) -> Option<Promise> {
+        let prepaid_fee = self.prepaid_fees.remove(request_id)?;
+        if prepaid_fee.settlement.is_some() || prepaid_fee.amount.0 == 0 {
+            return None;
+        }
+
+        self.debit_sign_fees(prepaid_fee.chain_id.0, prepaid_fee.amount.0);
+        Some(
+            Promise::new(prepaid_fee.payer)
+                .transfer(NearToken::from_yoctonear(prepaid_fee.amount.0)),
+        )
+    }
+
+    pub(crate) fn credit_sign_fees(&mut self, chain_id: u64, amount: u128) {
+        let fees = self.sign_fees.get(&chain_id).unwrap_or(U128(0));
+        self.sign_fees
+            .insert(&chain_id, &fees.0.saturating_add(amount).into());
+        self.treasury.prepay(amount);
+    }
+
+    /// For refunds of prepaid fees. Withdrawals are only debited from the
+    /// treasury, since `sign_fees` remains the record of the fees charged on
+    /// each chain.
+    pub(crate) fn debit_sign_fees(&mut self, chain_id: u64, amount: u128) {
+        let fees = self.sign_fees.get(&chain_id).unwrap_or(U128(0));
+        self.sign_fees
+            .insert(&chain_id, &fees.0.saturating_sub(amount).into());
+        self.treasury.refund(amount);
+    }
+
+    /// Counts a completed sign request, with its prepaid fee, in the stats
+    /// of `chain_id`.
+    fn record_signed(&mut self, request_id: &RequestId, chain_id: u64) {
+        let fee = self
+            .prepaid_fees
+            .get(request_id)
+            .map_or(0, |prepaid_fee| prepaid_fee.amount.0);
+
+        let mut stats = self.sign_stats.get(&chain_id).unwrap_or_default();
+        stats.signed = stats.signed.saturating_add(1);
+        stats.fees_collected = stats.fees_collected.saturating_add(fee);
+        self.sign_stats.insert(&chain_id, &stats);
+    }
+
+    /// The storage usage of the contract, with the pending writes of the
+    /// sign request records included.
+    fn flushed_storage_usage(&mut self) -> u64 {
+        self.nonces.flush();
+        self.sign_requests.flush();
+        self.sign_request_memos.flush();
+        self.pending_sign_requests.flush();
+        self.sign_request_providers.flush();
+        self.prepaid_fees.flush();
+        env::storage_usage()
+    }
+
+    /// Refunds the part of the storage deposit of the resolved sign request
+    /// `pending` that its remaining records do not take up, given the storage
+    /// usage from before its records were cleaned up.
+    fn refund_storage_deposit(&mut self, pending: &PendingSignRequest, storage_usage_before: u64) {
+        let storage_usage = (pending
+            .storage_usage
+            .saturating_add(self.flushed_storage_usage()))
+        .saturating_sub(storage_usage_before);
+        Self::refund_deposit(
+            pending.caller.clone(),
+            pending
+                .storage_deposit
+                .saturating_sub(storage_cost(storage_usage)),
+        );
+    }
+
+    fn try_current_gas_price(&self, chain_id: u64) -> Result<GasPrice, ContractError> {
+        let gas_price = self
+            .gas_price_oracle
+            .latest_gas_price(chain_id)
+            .ok_or(ContractError::GasPriceUnavailable { chain_id })?;
+
+        if self
+            .gas_price_oracle
+            .is_stale(gas_price, env::block_height())
+        {
+            return Err(ContractError::GasPriceStale {
+                chain_id,
+                observed_at_block: gas_price.observed_at_block.0,
+            });
+        }
+
+        Ok(gas_price.clone())
+    }
+
+    /// The MPC path of `path` for `account_id`, once `path` is checked to be
+    /// non-empty, no longer than the maximum path length, and flat, so that
+    /// it cannot collide with a hierarchical path.
+    fn try_caller_mpc_path(
+        &self,
+        account_id: &AccountId,
+        path: &str,
+    ) -> Result<String, ContractError> {
+        if path.is_empty() || path.len() > self.max_path_len as usize {
+            return Err(ContractError::InvalidPath {
+                len: path.len(),
+                max_len: self.max_path_len,
+            });
+        }
+        if path.contains(HIERARCHICAL_PATH_SEPARATOR) {
+            return Err(ContractError::InvalidInput(
+                "`path` cannot contain the hierarchical path separator (0x1f)".to_string(),
+            ));
+        }
+
+        Ok(join_derivation_path(&[account_id.as_str(), path]))
+    }
+
+    /// [`Self::try_caller_mpc_path`] for signing on `chain_id`, namespaced by
+    /// the chain if it is configured to be.
+    fn try_chain_mpc_path(
+        &self,
+        account_id: &AccountId,
+        path: &str,
+        chain_id: u64,
+    ) -> Result<String, ContractError> {
+        let chain = self.get_chain(chain_id)?;
+        let mpc_path = self.try_caller_mpc_path(account_id, path)?;
+
+        Ok(if chain.namespaced_derivation {
+            namespaced_mpc_path(&mpc_path, chain_id)
+        } else {
+            mpc_path
+        })
+    }
+
+    /// Derives with the latest prefix, like the MPC contract.
+    fn try_foreign_address_for_mpc_path(
+        &self,
+        mpc_path: &str,
+        key_version: u32,
+    ) -> Result<ForeignAddress, ContractError> {
+        self.try_foreign_address_for_mpc_path_with_prefix(
+            mpc_path,
+            key_version,
+            &self.try_derivation_prefix(None)?,
+        )
+    }
+
+    fn try_foreign_address_for_mpc_path_with_prefix(
+        &self,
+        mpc_path: &str,
+        key_version: u32,
+        prefix: &str,
+    ) -> Result<ForeignAddress, ContractError> {
+        let mpc_key = self
+            .mpc_keys
+            .get(&key_version)
+            .ok_or_else(|| self.unknown_key_version(key_version))?;
+
+        let affine = mpc_key
+            .affine()
+            .ok_or(PublicKeyConversionError::WrongCurveType(mpc_key.curve))?;
+
+        Ok(get_mpc_address_from_affine_with_prefix(
+            affine,
+            prefix,
+            &env::current_account_id(),
+            mpc_path,
+        )?)
+    }
+
+    /// Resolves defaults and builds the transaction in the format that the
+    /// foreign chain supports.
+    fn prepare_transaction(
+        &self,
+        input: TransactionInput,
+        nonce: u64,
+    ) -> Result<ForeignTransaction, ContractError> {
+        let chain_id = input.chain_id.0;
+        let chain = self.get_chain(chain_id)?;
+
+        let (max_fee_per_gas, max_priority_fee_per_gas) =
+            match (input.max_fee_per_gas, input.max_priority_fee_per_gas) {
+                (Some(max_fee_per_gas), Some(max_priority_fee_per_gas)) => {
+                    (max_fee_per_gas.0, max_priority_fee_per_gas.0)
+                }
+                (max_fee_per_gas, max_priority_fee_per_gas) => {
+                    let gas_price = self.try_current_gas_price(chain_id)?;
+                    (
+                        max_fee_per_gas.unwrap_or(gas_price.max_fee_per_gas).0,
+                        max_priority_fee_per_gas
+                            .unwrap_or(gas_price.max_priority_fee_per_gas)
+                            .0,
+                    )
+                }
+            };
+        self.check_reasonable_fee(chain_id, max_fee_per_gas, max_priority_fee_per_gas)?;
+
+        let data = input
+            .data
+            .map(hex::decode)
+            .transpose()
+            .map_err(|e| ContractError::InvalidInput(format!("Error decoding `data` as hex: {e}")))?
+            .unwrap_or_default();
+        let access_list = decode_access_list(input.access_list.unwrap_or_default())?;
+        let gas_limit = input.gas_limit.map_or_else(
+            || u128::from(intrinsic_gas(&data, &access_list, input.to.is_none())),
+            |gas_limit| gas_limit.0,
+        );
+        chain.check_gas_limit(gas_limit)?;
+
+        Ok(chain.build_transaction(Eip1559Transaction {
+            chain_id,
+            nonce,
+            max_priority_fee_per_gas,
+            max_fee_per_gas,
+            gas_limit,
+            to: input.to,
+            value: input.value.unwrap_or_default().0,
+            data,
+            access_list,
+        }))
+    }
+
+    /// Signs `transaction`, after checking that the signature recovers to
+    /// `foreign_address`, which would otherwise indicate a derivation bug.
+    ///
+    /// Some MPC implementations do not return the canonical recovery ID, so
+    /// if the signature does not recover to `foreign_address` with the
+    /// recovery ID returned, it is tried with the other y-parity too.
+    fn try_sign_callback(
+        transaction: ForeignTransaction,
+        foreign_address: ForeignAddress,
+        result: Result<SignatureResponse, PromiseError>,
+    ) -> Result<Vec<u8>, ContractError> {
+        let signature = Self::try_decode_signature_response(result)?;
+        let signing_hash = transaction.signing_hash();
+
+        // Reported if neither recovery ID matches.
+        let mut recovered = None;
+        for recovery_id in [signature.y_parity(), signature.y_parity() ^ 1] {
+            let candidate = MpcSignature::from_rsv(signature.r(), signature.s(), recovery_id)
+                .unwrap_or_else(|_| unreachable!("0 and 1 are valid recovery IDs"));
+            // Recover through the host function, which is far cheaper than
+            // doing the curve arithmetic in wasm. It requires the low-S form
+            // that the signed transaction uses.
+            let address =
+                env::ecrecover(&signing_hash, &candidate.to_rs_bytes(), recovery_id, true)
+                    .map(|public_key| ForeignAddress::from(raw_public_key_to_address(public_key)));
+            if address == Some(foreign_address) {
+                return Ok(transaction.into_signed(candidate));
+            }
+            recovered = recovered.or(address);
+        }
+
+        match recovered {
+            Some(recovered) => Err(ContractError::SenderMismatch {
+                expected: foreign_address,
+                recovered,
+            }),
+            None => Err(ContractError::InvalidSignature(
+                "Failed to recover signer".into(),
+            )),
+        }
+    }
+
+    /// The signature returned by the MPC contract, in low-S form.
+    fn try_decode_signature_response(
+        result: Result<SignatureResponse, PromiseError>,
+    ) -> Result<MpcSignature, ContractError> {
+        let response = result.map_err(|_| ContractError::MpcUnavailable)?;
+        let recovery_id = response.recovery_id;
+        let signature = Signature::try_from(response)
+            .map_err(|e| ContractError::InvalidSignature(e.to_string()))?;
+
+        let mut r = [0u8; 32];
+        signature.r.to_big_endian(&mut r);
+        let mut s = [0u8; 32];
+        signature.s.to_big_endian(&mut s);
+
+        MpcSignature::from_rsv(r, s, recovery_id)
+            .map_err(|e| ContractError::InvalidSignature(e.to_string()))
+    }
+}
+
+#[test]
+fn test_mpc_sign() {
+    use near_sdk::{mock::MockAction, test_utils::get_created_receipts};
+
+    let mut contract = crate::setup_contract();
+    contract.set_mpc_contract_id("v1.signer-prod.testnet".parse().unwrap());
+
+    drop(contract.mpc_sign([7; 32], "alice.near,".to_string(), 0));
+
+    let receipts = get_created_receipts();
+    assert_eq!(receipts.len(), 1);
+    assert_eq!(receipts[0].receiver_id.as_str(), "v1.signer-prod.testnet");
+
+    let [MockAction::FunctionCallWeight {
+        method_name,
+        args,
+        attached_deposit,
+        prepaid_gas,
+        ..
+    }] = &receipts[0].actions[..]
+    else {
+        panic!("Expected a single function call: {:?}", receipts[0].actions);
+    };
+
+    assert_eq!(method_name, b"sign");
+    assert_eq!(
+        near_sdk::serde_json::from_slice::<near_sdk::serde_json::Value>(args).unwrap(),
+        near_sdk::serde_json::json!({
+            "request": {
+                "payload": ([7_u8; 32]),
+                "path": "alice.near,",
+                "key_version": 0,
+            },
+        }),
+    );
+    assert_eq!(*attached_deposit, Contract::MPC_SIGN_DEPOSIT);
+    assert_eq!(*prepaid_gas, contract.mpc_sign_gas);
+}
+
+#[test]
+fn test_set_mpc_sign_gas() {
+    use near_sdk::{mock::MockAction, test_utils::get_created_receipts};
+
+    let mut contract = crate::setup_contract();
+    contract.set_mpc_contract_id("v1.signer-prod.testnet".parse().unwrap());
+    assert_eq!(contract.get_mpc_sign_gas(), Gas::from_tgas(50));
+
+    contract.set_mpc_sign_gas(Gas::from_tgas(100));
+    assert_eq!(contract.get_mpc_sign_gas(), Gas::from_tgas(100));
+
+    drop(contract.mpc_sign([7; 32], "alice.near,".to_string(), 0));
+
+    let receipts = get_created_receipts();
+    let [MockAction::FunctionCallWeight { prepaid_gas, .. }] = &receipts[0].actions[..] else {
+        panic!("Expected a single function call: {:?}", receipts[0].actions);
+    };
+    assert_eq!(*prepaid_gas, Gas::from_tgas(100));
+}
+
+#[test]
+#[should_panic = "Owner only"]
+fn test_set_mpc_sign_gas_requires_owner() {
+    let mut contract = setup_mpc_contract();
+
+    contract.set_mpc_sign_gas(Gas::from_tgas(1));
+}
+
+#[test]
+fn test_sign_insufficient_prepaid_gas() {
+    let mut contract = setup_mpc_contract();
+    near_sdk::testing_env!(near_sdk::test_utils::VMContextBuilder::new()
+        .current_account_id("canhazgas.testnet".parse().unwrap())
+        .predecessor_account_id("alice.near".parse().unwrap())
+        .prepaid_gas(Gas::from_tgas(60))
+        .build());
+
+    assert!(matches!(
+        contract.sign("ethereum-1".to_string(), sample_transaction_input(), None, None),
+        Err(ContractError::InsufficientGas { required, .. }) if required == Gas::from_tgas(65),
+    ));
+
+    // Nothing is reserved.
+    let foreign_address = contract
+        .foreign_address_for(
+            "alice.near".parse().unwrap(),
+            "ethereum-1".to_string(),
+            97.into(),
+            None,
+            None,
+        )
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert_eq!(contract.nonces.peek_nonce(97, foreign_address), 0);
+}
+
+#[test]
+fn test_sign_path_length() {
+    let mut contract = setup_mpc_contract();
+    set_attached_deposit(NearToken::from_near(1));
+
+    assert!(matches!(
+        contract.sign(String::new(), sample_transaction_input(), None, None),
+        Err(ContractError::InvalidPath {
+            len: 0,
+            max_len: 256,
+        }),
+    ));
+    assert!(matches!(
+        contract.sign("a".repeat(257), sample_transaction_input(), None, None),
+        Err(ContractError::InvalidPath {
+            len: 257,
+            max_len: 256,
+        }),
+    ));
+    assert!(matches!(
+        contract.foreign_address_for(
+            "alice.near".parse().unwrap(),
+            "a".repeat(257),
+            97.into(),
+            None,
+            None,
+        ),
+        Err(ContractError::InvalidPath { len: 257, .. }),
+    ));
+
+    contract
+        .sign("a".repeat(256), sample_transaction_input(), None, None)
+        .unwrap();
+}
+
+#[test]
+fn test_set_max_path_len() {
+    let mut contract = setup_mpc_contract();
+    near_sdk::testing_env!(near_sdk::test_utils::VMContextBuilder::new()
+        .current_account_id("canhazgas.testnet".parse().unwrap())
+        .predecessor_account_id("canhazgas.testnet".parse().unwrap())
+        .build());
+    contract.set_max_path_len(8);
+    assert_eq!(contract.get_max_path_len(), 8);
+
+    let alice: AccountId = "alice.near".parse().unwrap();
+    contract
+        .foreign_address_for(alice.clone(), "a".repeat(8), 97.into(), None, None)
+        .unwrap();
+    assert!(matches!(
+        contract.foreign_address_for(alice, "a".repeat(9), 97.into(), None, None),
+        Err(ContractError::InvalidPath { len: 9, max_len: 8 }),
+    ));
+}
+
+#[test]
+fn test_path_cannot_collide_with_hierarchical_path() {
+    use lib::kdf::hierarchical_derivation_path;
+
+    let mut contract = setup_mpc_contract();
+    let alice: AccountId = "alice.near".parse().unwrap();
+
+    for path in [
+        hierarchical_derivation_path(&["ethereum", "1"]),
+        hierarchical_derivation_path(&[]),
+        format!("ethereum{HIERARCHICAL_PATH_SEPARATOR}1"),
+    ] {
+        assert!(matches!(
+            contract.foreign_address_for(alice.clone(), path.clone(), 97.into(), None, None),
+            Err(ContractError::InvalidInput(_)),
+        ));
+        assert!(matches!(
+            contract.register_address(97.into(), path.clone()),
+            Err(ContractError::InvalidInput(_)),
+        ));
+        assert!(matches!(
+            contract.sign(path, sample_transaction_input(), None, None),
+            Err(ContractError::InvalidInput(_)),
+        ));
+    }
+    assert!(contract.sign_request_ids.is_empty());
+}
+
+#[test]
+#[should_panic = "MPC contract is not configured"]
+fn test_mpc_sign_requires_mpc_contract() {
+    let contract = crate::setup_contract();
+
+    drop(contract.mpc_sign([7; 32], "alice.near,".to_string(), 0));
+}
+
+/// The root secret key of the MPC network in tests, the same as that of
+/// `lib`'s tests.
+#[cfg(test)]
+pub(crate) const TEST_MPC_ROOT_SECRET_KEY: [u8; 32] = [0x17; 32];
+
+/// Covers the storage deposit of a sample sign request, the rest of which is
+/// refunded.
+#[cfg(test)]
+const SIGN_STORAGE_DEPOSIT: NearToken = NearToken::from_millinear(20);
+
+#[cfg(test)]
+fn setup_mpc_contract() -> Contract {
+    use ethers_core::k256::ecdsa::SigningKey;
+
+    let mut contract = crate::setup_contract();
+    contract.set_mpc_contract_id("v1.signer-prod.testnet".parse().unwrap());
+
+    let root_key = SigningKey::from_bytes(&TEST_MPC_ROOT_SECRET_KEY.into()).unwrap();
+    contract
+        .set_mpc_key(
+            0,
+            PublicKey::from_parts(
+                near_sdk::CurveType::SECP256K1,
+                root_key.verifying_key().to_encoded_point(false).as_bytes()[1..].to_vec(),
+            )
+            .unwrap()
+            .into(),
+        )
+        .unwrap();
+
+    contract.add_foreign_chain(
+        97.into(),
+        "EdVCmQ9FSPcVe5YySXDPCRmc8aDQLKJ9xvYBMZPie1Vw".to_string(),
+        21000.into(),
+        (120.into(), 100.into()),
+        18,
+        None,
+    );
+    // Free, so that tests do not have to attach deposits unless they test fees.
+    contract.gas_price_oracle.update_token_price(
+        97,
+        TokenPrice {
+            price_in_near: 0.into(),
+            observed_at_block: 0.into(),
+        },
+    );
+
+    near_sdk::testing_env!(near_sdk::test_utils::VMContextBuilder::new()
+        .current_account_id("canhazgas.testnet".parse().unwrap())
+        .predecessor_account_id("alice.near".parse().unwrap())
+        .attached_deposit(SIGN_STORAGE_DEPOSIT)
+        .build());
+
+    contract
+}
+
+#[cfg(test)]
+fn sample_transaction_input() -> TransactionInput {
+    TransactionInput {
+        chain_id: 97.into(),
+        nonce: None,
+        to: Some(
+            "0xb9a07c631d10fdce87d37eb6f18c11cbe75f1eeb"
+                .parse()
+                .unwrap(),
+        ),
+        value: Some(100.into()),
+        data: None,
+        gas_limit: Some(21000.into()),
+        max_fee_per_gas: Some(20_000_000_000.into()),
+        max_priority_fee_per_gas: Some(1_000_000_000.into()),
+        access_list: None,
+    }
+}
+
+/// Returns the arguments of the most recent `sign_callback` receipt.
+#[cfg(test)]
+fn last_sign_callback_args() -> (RequestId, ForeignAddress, ForeignTransaction) {
+    use near_sdk::{borsh::BorshDeserialize, mock::MockAction, test_utils::get_created_receipts};
+
+    get_created_receipts()
+        .iter()
+        .flat_map(|receipt| &receipt.actions)
+        .rev()
+        .find_map(|action| match action {
+            MockAction::FunctionCallWeight {
+                method_name, args, ..
+            } if method_name == b"sign_callback" => Some(
+                <(RequestId, ForeignAddress, ForeignTransaction)>::try_from_slice(args).unwrap(),
+            ),
+            _ => None,
+        })
+        .unwrap()
+}
+
+/// Calls `sign` and returns the arguments that it passes to `sign_callback`.
+#[cfg(test)]
+fn sign_and_get_callback_args(
+    contract: &mut Contract,
+) -> (RequestId, ForeignAddress, ForeignTransaction) {
+    drop(
+        contract
+            .sign(
+                "ethereum-1".to_string(),
+                sample_transaction_input(),
+                None,
+                None,
+            )
+            .unwrap(),
+    );
+
+    last_sign_callback_args()
+}
+
+#[cfg(test)]
+pub(crate) fn event_logs() -> Vec<near_sdk::serde_json::Value> {
+    near_sdk::test_utils::get_logs()
+        .iter()
+        .filter_map(|log| log.strip_prefix("EVENT_JSON:"))
+        .map(|json| near_sdk::serde_json::from_str(json).unwrap())
+        .collect()
+}
+
+/// Signs `transaction` like the MPC network would.
+#[cfg(test)]
+fn mpc_signature_response(transaction: &ForeignTransaction) -> SignatureResponse {
+    mpc_signature_response_for_payload(&transaction.signing_hash())
+}
+
+/// Signs `payload` with the key derived for `alice.near` at `ethereum-1`,
+/// like the MPC network would.
+#[cfg(test)]
+fn mpc_signature_response_for_payload(payload: &[u8; 32]) -> SignatureResponse {
+    use ethers_core::k256::ecdsa::SigningKey;
+    use lib::{
+        kdf::derive_epsilon,
+        signer::{AffnPnt, Sclr, SignResult},
+    };
+
+    let secret = *SigningKey::from_bytes(&TEST_MPC_ROOT_SECRET_KEY.into())
+        .unwrap()
+        .as_nonzero_scalar()
+        .as_ref()
+        + derive_epsilon(
+            &"canhazgas.testnet".parse().unwrap(),
+            "alice.near,ethereum-1",
+        );
+    let (signature, recovery_id) = SigningKey::from_bytes(&secret.to_bytes())
+        .unwrap()
+        .sign_prehash_recoverable(payload)
+        .unwrap();
+    let sign_result = SignResult::from_ecdsa_signature(signature, recovery_id).unwrap();
+
+    SignatureResponse {
+        big_r: AffnPnt {
+            affine_point: sign_result.big_r_hex,
+        },
+        s: Sclr {
+            scalar: sign_result.s_hex,
+        },
+        recovery_id: recovery_id.to_byte(),
+    }
+}
+
+#[test]
+fn test_sign_callback_success() {
+    use ethers_core::{types::transaction::eip2718::TypedTransaction, utils::rlp::Rlp};
+
+    let mut contract = setup_mpc_contract();
+    let (request_id, foreign_address, transaction) = sign_and_get_callback_args(&mut contract);
+
+    assert_eq!(
+        foreign_address,
+        lib::kdf::get_mpc_address(
+            contract.get_mpc_public_key(None).unwrap(),
+            &"canhazgas.testnet".parse().unwrap(),
+            "alice.near,ethereum-1",
+        )
+        .unwrap(),
+    );
+    assert_eq!(transaction.chain_id(), 97);
+    assert_eq!(transaction.nonce(), 0);
+    assert_eq!(contract.nonces.peek_nonce(97, foreign_address), 1);
+
+    let response = mpc_signature_response(&transaction);
+
+    let PromiseOrValue::Value(result) =
+        contract.sign_callback(request_id, foreign_address, transaction, Ok(response))
+    else {
+        panic!("Expected the signed transaction");
+    };
+
+    let raw_transaction = hex::decode(&result.raw_tx).unwrap();
+    assert_eq!(
+        result.tx_hash,
+        hex::encode_prefixed(keccak256(&raw_transaction)),
+    );
+    assert_eq!(result.sender, foreign_address);
+    assert_eq!(result.nonce, 0.into());
+    assert_eq!(result.chain_id, 97.into());
+    let (decoded, decoded_signature) =
+        TypedTransaction::decode_signed(&Rlp::new(&raw_transaction)).unwrap();
+    assert_eq!(decoded.chain_id(), Some(97.into()));
+    assert_eq!(decoded.nonce(), Some(&0.into()));
+    assert_eq!(
+        ForeignAddress::from(decoded_signature.recover(decoded.sighash()).unwrap()),
+        foreign_address,
+    );
+
+    // Nonce stays reserved.
+    assert_eq!(contract.nonces.peek_nonce(97, foreign_address), 1);
+
+    assert_eq!(
+        event_logs().last().unwrap(),
+        &near_sdk::serde_json::json!({
+            "standard": "x-gas-station",
+            "version": "0.1.0",
+            "event": "sign_completed",
+            "data": {
+                "request_id": hex::encode_prefixed(request_id),
+                "raw_tx_hash": hex::encode_prefixed(keccak256(&raw_transaction)),
+            },
+        }),
+    );
+}
+
+#[cfg(test)]
+fn sample_prebuilt_transaction() -> Eip1559Transaction {
+    Eip1559Transaction {
+        chain_id: 97,
+        nonce: 0,
+        max_priority_fee_per_gas: 1_000_000_000,
+        max_fee_per_gas: 20_000_000_000,
+        gas_limit: 21000,
+        to: Some(
+            "0xb9a07c631d10fdce87d37eb6f18c11cbe75f1eeb"
+                .parse()
+                .unwrap(),
+        ),
+        value: 100.into(),
+        data: vec![],
+        access_list: vec![],
+    }
+}
+
+#[test]
+fn test_sign_prebuilt() {
+    use lib::transaction::decode_eip1559;
+
+    let mut contract = setup_mpc_contract();
+    let unsigned_rlp = sample_prebuilt_transaction().encode_unsigned();
+
+    drop(
+        contract
+            .sign_prebuilt(
+                97.into(),
+                hex::encode(&unsigned_rlp),
+                "ethereum-1".to_string(),
+            )
+            .unwrap(),
+    );
+    let (request_id, foreign_address, transaction) = last_sign_callback_args();
+    assert_eq!(
+        transaction,
+        ForeignTransaction::Eip1559(sample_prebuilt_transaction()),
+    );
+    assert_eq!(transaction.signing_hash(), keccak256(&unsigned_rlp));
+    assert_eq!(contract.nonces.peek_nonce(97, foreign_address), 1);
+
+    let response = mpc_signature_response(&transaction);
+    let PromiseOrValue::Value(result) =
+        contract.sign_callback(request_id, foreign_address, transaction, Ok(response))
+    else {
+        panic!("Expected the signed transaction");
+    };
+
+    assert_eq!(result.sender, foreign_address);
+    assert_eq!(result.nonce, 0.into());
+    let (decoded, _) = decode_eip1559(&hex::decode(&result.raw_tx).unwrap()).unwrap();
+    assert_eq!(decoded.encode_unsigned(), unsigned_rlp);
+
+    // The nonce is parsed from the payload, and must be the next one.
+    let mut transaction = sample_prebuilt_transaction();
+    transaction.nonce = 5;
+    assert!(matches!(
+        contract.sign_prebuilt(
+            97.into(),
+            hex::encode(transaction.encode_unsigned()),
+            "ethereum-1".to_string(),
+        ),
+        Err(ContractError::NonceUnavailable {
+            expected: 1,
+            actual: 5,
+        }),
+    ));
+}
+
+#[test]
+fn test_sign_prebuilt_rejects_invalid_payloads() {
+    use lib::transaction::LegacyTransaction;
+
+    let mut contract = setup_mpc_contract();
+    let transaction = sample_prebuilt_transaction();
+
+    // Chain 97 is configured for EIP-1559 transactions.
+    let legacy = LegacyTransaction {
+        nonce: transaction.nonce,
+        gas_price: transaction.max_fee_per_gas,
+        gas_limit: transaction.gas_limit,
+        to: transaction.to,
+        value: transaction.value,
+        data: vec![],
+        chain_id: 97,
+    };
+    assert!(matches!(
+        contract.sign_prebuilt(
+            97.into(),
+            hex::encode(legacy.encode_unsigned()),
+            "ethereum-1".to_string(),
+        ),
+        Err(ContractError::UnsupportedTransactionType {
+            chain_id: 97,
+            transaction_type: 0,
+        }),
+    ));
+
+    let mut other_chain = transaction.clone();
+    other_chain.chain_id = 1;
+    assert!(matches!(
+        contract.sign_prebuilt(
+            97.into(),
+            hex::encode(other_chain.encode_unsigned()),
+            "ethereum-1".to_string(),
+        ),
+        Err(ContractError::InvalidInput(_)),
+    ));
+
+    let mut truncated = transaction.encode_unsigned();
+    truncated.pop();
+    assert!(matches!(
+        contract.sign_prebuilt(97.into(), hex::encode(truncated), "ethereum-1".to_string()),
+        Err(ContractError::InvalidInput(_)),
+    ));
+
+    assert!(matches!(
+        contract.sign_prebuilt(97.into(), "0xzz".to_string(), "ethereum-1".to_string()),
+        Err(ContractError::InvalidInput(message))
+            if message.starts_with("Error decoding `unsigned_rlp` as hex: "),
+    ));
+
+    let mut priority_fee_too_high = transaction;
+    priority_fee_too_high.max_priority_fee_per_gas = priority_fee_too_high.max_fee_per_gas + 1;
+    assert!(matches!(
+        contract.sign_prebuilt(
+            97.into(),
+            hex::encode(priority_fee_too_high.encode_unsigned()),
+            "ethereum-1".to_string(),
+        ),
+        Err(ContractError::UnreasonableFee(
+            UnreasonableFeeError::PriorityFeeExceedsMaxFee { .. }
+        )),
+    ));
+
+    assert!(contract.sign_request_ids.is_empty());
+}
+
+#[test]
+fn test_sign_callback_tries_both_recovery_ids() {
+    use lib::transaction::decode_eip1559;
+
+    let mut contract = setup_mpc_contract();
+
+    // Sign until the correct recovery ID has been each of 0 and 1, each time
+    // returning the other one from the MPC contract.
+    let mut correct_recovery_ids = vec![];
+    for nonce in 0..16 {
+        if correct_recovery_ids.len() == 2 {
+            break;
+        }
+
+        // Each request is a transaction of its own, with its own prepaid gas.
+        set_block_timestamp_seconds(nonce);
+        let (request_id, foreign_address, transaction) = sign_and_get_callback_args(&mut contract);
+        let mut response = mpc_signature_response(&transaction);
+        let recovery_id = response.recovery_id;
+        if correct_recovery_ids.contains(&recovery_id) {
+            continue;
+        }
+        correct_recovery_ids.push(recovery_id);
+        response.recovery_id ^= 1;
+
+        let PromiseOrValue::Value(result) =
+            contract.sign_callback(request_id, foreign_address, transaction, Ok(response))
+        else {
+            panic!("Expected the signed transaction with recovery ID {recovery_id}");
+        };
+
+        assert_eq!(result.sender, foreign_address);
+        assert_eq!(result.nonce, nonce.into());
+        let (_, signature) = decode_eip1559(&hex::decode(&result.raw_tx).unwrap()).unwrap();
+        assert_eq!(signature.y_parity(), recovery_id);
+    }
+
+    correct_recovery_ids.sort_unstable();
+    assert_eq!(correct_recovery_ids, [0, 1]);
+}
+
+#[test]
+fn test_sign_callback_returns_prefixed_hex() {
+    use ethers_core::{types::transaction::eip2718::TypedTransaction, utils::rlp::Rlp};
+
+    let mut contract = setup_mpc_contract();
+
+    for is_eip1559 in [true, false] {
+        near_sdk::testing_env!(near_sdk::test_utils::VMContextBuilder::new()
+            .current_account_id("canhazgas.testnet".parse().unwrap())
+            .predecessor_account_id("canhazgas.testnet".parse().unwrap())
+            .build());
+        contract.set_foreign_chain_is_eip1559(97.into(), is_eip1559);
+        near_sdk::testing_env!(near_sdk::test_utils::VMContextBuilder::new()
+            .current_account_id("canhazgas.testnet".parse().unwrap())
+            .predecessor_account_id("alice.near".parse().unwrap())
+            .attached_deposit(SIGN_STORAGE_DEPOSIT)
+            .build());
+
+        let (request_id, foreign_address, transaction) = sign_and_get_callback_args(&mut contract);
+        let response = mpc_signature_response(&transaction);
+        let PromiseOrValue::Value(SignResult {
+            raw_tx: raw_transaction,
+            ..
+        }) = contract.sign_callback(request_id, foreign_address, transaction, Ok(response))
+        else {
+            panic!("Expected the signed transaction");
+        };
+
+        // EIP-1559 transactions are typed with `0x02`, while legacy
+        // transactions are bare RLP lists, which start at `0xc0`.
+        let expected_prefix = if is_eip1559 { "0x02" } else { "0xf8" };
+        assert!(
+            raw_transaction.starts_with(expected_prefix),
+            "{raw_transaction}",
+        );
+
+        let bytes = hex::decode(&raw_transaction).unwrap();
+        assert_eq!(to_hex_prefixed(&bytes), raw_transaction);
+        assert!(TypedTransaction::decode_signed(&Rlp::new(&bytes)).is_ok());
+    }
+}
+
+#[test]
+fn test_sign_emits_sign_requested() {
+    let mut contract = setup_mpc_contract();
+    let (request_id, foreign_address, _) = sign_and_get_callback_args(&mut contract);
+
+    assert_eq!(
+        event_logs(),
+        vec![near_sdk::serde_json::json!({
+            "standard": "x-gas-station",
+            "version": "0.1.0",
+            "event": "sign_requested",
+            "data": {
+                "request_id": hex::encode_prefixed(request_id),
+                "caller": "alice.near",
+                "chain_id": "97",
+                "foreign_address": foreign_address,
+                "nonce": "0",
+            },
+        })],
+    );
+}
+
+#[test]
+fn test_sign_callback_failure_releases_nonce() {
+    let mut contract = setup_mpc_contract();
+    let (request_id, foreign_address, transaction) = sign_and_get_callback_args(&mut contract);
+
+    assert_eq!(contract.nonces.peek_nonce(97, foreign_address), 1);
+
+    let PromiseOrValue::Promise(_) = contract.sign_callback(
+        request_id,
+        foreign_address,
+        transaction,
+        Err(PromiseError::Failed),
+    ) else {
+        panic!("Expected the callback to fail");
+    };
+
+    assert_eq!(contract.nonces.peek_nonce(97, foreign_address), 0);
+
+    assert_eq!(
+        event_logs().last().unwrap(),
+        &near_sdk::serde_json::json!({
+            "standard": "x-gas-station",
+            "version": "0.1.0",
+            "event": "sign_failed",
+            "data": {
+                "request_id": hex::encode_prefixed(request_id),
+                "reason": "MPC signature request failed",
+            },
+        }),
+    );
+
+    // The released nonce is used by the next request.
+    let (_, _, transaction) = sign_and_get_callback_args(&mut contract);
+    assert_eq!(transaction.nonce(), 0);
+}
+
+#[test]
+fn test_sign_fails_over_to_next_mpc_provider() {
+    use near_sdk::{mock::MockAction, test_utils::get_created_receipts};
+
+    let mut contract = setup_mpc_contract();
+    contract.mpc_fallback_contract_ids = vec!["v1.signer-backup.testnet".parse().unwrap()];
+    assert_eq!(
+        contract.get_mpc_providers(),
+        [
+            "v1.signer-prod.testnet".parse::<AccountId>().unwrap(),
+            "v1.signer-backup.testnet".parse().unwrap(),
+        ],
+    );
+
+    let (request_id, foreign_address, transaction) = sign_and_get_callback_args(&mut contract);
+    let mpc_contract_id = |contract: &Contract| {
+        contract
+            .get_request_status(hex::encode(request_id))
+            .unwrap()
+            .unwrap()
+            .mpc_contract_id
+            .unwrap()
+    };
+    assert_eq!(mpc_contract_id(&contract), "v1.signer-prod.testnet");
+
+    set_block_timestamp_seconds(1_000);
+    let PromiseOrValue::Promise(_) = contract.sign_callback(
+        request_id,
+        foreign_address,
+        transaction.clone(),
+        Err(PromiseError::Failed),
+    ) else {
+        panic!("Expected the signature to be requested again");
+    };
+
+    // Requested again from the fallback, with the same nonce.
+    let retry = get_created_receipts()
+        .into_iter()
+        .rev()
+        .find(|receipt| {
+            receipt.actions.iter().any(|action| {
+                matches!(action, MockAction::FunctionCallWeight { method_name, .. } if method_name == b"sign")
+            })
+        })
+        .unwrap();
+    assert_eq!(retry.receiver_id.as_str(), "v1.signer-backup.testnet");
+    assert_eq!(mpc_contract_id(&contract), "v1.signer-backup.testnet");
+    assert_eq!(
+        contract
+            .get_request_status(hex::encode(request_id))
+            .unwrap()
+            .unwrap()
+            .status,
+        RequestStatus::Pending,
+    );
+    assert_eq!(contract.nonces.peek_nonce(97, foreign_address), 1);
+    // The timeout restarts from the failover.
+    assert_eq!(
+        contract.pending_sign_requests[&request_id].created_at_ns,
+        1_000_000_000_000,
+    );
+
+    let (_, _, retried_transaction) = last_sign_callback_args();
+    assert_eq!(retried_transaction, transaction);
+
+    // No providers are left to fail over to.
+    drop(contract.sign_callback(
+        request_id,
+        foreign_address,
+        transaction,
+        Err(PromiseError::Failed),
+    ));
+    assert!(matches!(
+        contract
+            .get_request_status(hex::encode(request_id))
+            .unwrap()
+            .unwrap()
+            .status,
+        RequestStatus::Failed { .. },
+    ));
+    assert_eq!(contract.nonces.peek_nonce(97, foreign_address), 0);
+    assert_eq!(
+        contract
+            .get_request_status(hex::encode(request_id))
+            .unwrap()
+            .unwrap()
+            .mpc_contract_id,
+        None,
+    );
+}
+
+#[test]
+fn test_sign_batch_fits_with_max_mpc_fallbacks() {
+    use near_sdk::{mock::MockAction, test_utils::get_created_receipts};
+
+    let mut contract = setup_mpc_contract();
+    contract.mpc_fallback_contract_ids = (0..Contract::MAX_MPC_FALLBACKS)
+        .map(|i| format!("v1.signer-backup-{i}.testnet").parse().unwrap())
+        .collect();
+    // With the gas limit of a transaction, which the mocked blockchain
+    // defaults to.
+    set_attached_deposit(NearToken::from_millinear(100));
+
+    let batch_id = contract
+        .sign_batch(
+            (0..Contract::MAX_SIGN_BATCH_SIZE)
+                .map(|i| BatchSignRequest {
+                    path: format!("ethereum-{i}"),
+                    transaction: sample_transaction_input(),
+                    key_version: None,
+                    memo: None,
+                })
+                .collect(),
+        )
+        .unwrap();
+    assert!(contract
+        .get_sign_batch(batch_id)
+        .unwrap()
+        .iter()
+        .all(|item| item.status == RequestStatus::Pending));
+
+    // Only the MPC calls and their callbacks are reserved gas for, not
+    // failing over.
+    let reserved_gas = get_created_receipts()
+        .iter()
+        .flat_map(|receipt| &receipt.actions)
+        .map(|action| match action {
+            MockAction::FunctionCallWeight { prepaid_gas, .. } => prepaid_gas.as_gas(),
+            _ => 0,
+        })
+        .sum::<u64>();
+    assert_eq!(
+        reserved_gas,
+        (contract.mpc_sign_gas.as_gas() + Contract::SIGN_CALLBACK_GAS.as_gas())
+            * Contract::MAX_SIGN_BATCH_SIZE as u64,
+    );
+}
+
+#[test]
+#[should_panic = "At most 3 MPC fallback contracts can be set"]
+fn test_set_too_many_mpc_fallbacks() {
+    let mut contract = setup_mpc_contract();
+    near_sdk::testing_env!(near_sdk::test_utils::VMContextBuilder::new()
+        .current_account_id("canhazgas.testnet".parse().unwrap())
+        .predecessor_account_id("canhazgas.testnet".parse().unwrap())
+        .build());
+
+    contract.set_mpc_fallback_contract_ids(
+        (0..=Contract::MAX_MPC_FALLBACKS)
+            .map(|i| format!("v1.signer-backup-{i}.testnet").parse().unwrap())
+            .collect(),
+    );
+}
+
+#[cfg(test)]
+fn set_block_timestamp_seconds(seconds: u64) {
+    near_sdk::testing_env!(near_sdk::test_utils::VMContextBuilder::new()
+        .current_account_id("canhazgas.testnet".parse().unwrap())
+        .predecessor_account_id("alice.near".parse().unwrap())
+        .block_timestamp(seconds * 1_000_000_000)
+        .attached_deposit(SIGN_STORAGE_DEPOSIT)
+        .build());
+}
+
+#[test]
+fn test_sign_rate_limit_exceeded() {
+    let mut contract = setup_mpc_contract();
+    contract.rate_limiter.limit = Some(crate::rate_limiter::RateLimit {
+        max_requests: 2,
+        window_seconds: 60.into(),
+    });
+
+    set_block_timestamp_seconds(1_000);
+    sign_and_get_callback_args(&mut contract);
+    set_block_timestamp_seconds(1_030);
+    sign_and_get_callback_args(&mut contract);
+    set_block_timestamp_seconds(1_059);
+    assert!(matches!(
+        contract.sign(
+            "ethereum-1".to_string(),
+            sample_transaction_input(),
+            None,
+            None
+        ),
+        Err(ContractError::RateLimitExceeded(
+            crate::error::RateLimitExceededError {
+                max_requests: 2,
+                window_seconds: 60,
+            }
+        )),
+    ));
+}
+
+#[test]
+fn test_sign_rate_limit_window_elapses() {
+    let mut contract = setup_mpc_contract();
+    contract.rate_limiter.limit = Some(crate::rate_limiter::RateLimit {
+        max_requests: 2,
+        window_seconds: 60.into(),
+    });
+
+    set_block_timestamp_seconds(1_000);
+    sign_and_get_callback_args(&mut contract);
+    sign_and_get_callback_args(&mut contract);
+
+    // Both earlier requests have left the window.
+    set_block_timestamp_seconds(1_060);
+    let (_, _, transaction) = sign_and_get_callback_args(&mut contract);
+    assert_eq!(transaction.nonce(), 2);
+    set_block_timestamp_seconds(1_061);
+    let (_, _, transaction) = sign_and_get_callback_args(&mut contract);
+    assert_eq!(transaction.nonce(), 3);
+}
+
+#[test]
+fn test_foreign_address_for_matches_sign() {
+    let mut contract = setup_mpc_contract();
+
+    let expected = contract
+        .foreign_address_for(
+            "alice.near".parse().unwrap(),
+            "ethereum-1".to_string(),
+            97.into(),
+            None,
+            None,
+        )
+        .unwrap();
+    let (_, foreign_address, _) = sign_and_get_callback_args(&mut contract);
+
+    assert_eq!(expected, foreign_address.to_string());
+}
+
+#[test]
+fn test_all_addresses_for() {
+    let mut contract = setup_mpc_contract();
+    near_sdk::testing_env!(near_sdk::test_utils::VMContextBuilder::new()
+        .current_account_id("canhazgas.testnet".parse().unwrap())
+        .predecessor_account_id("canhazgas.testnet".parse().unwrap())
+        .build());
+    for chain_id in [1, 10] {
+        contract.add_foreign_chain(
+            chain_id.into(),
+            "EdVCmQ9FSPcVe5YySXDPCRmc8aDQLKJ9xvYBMZPie1Vw".to_string(),
+            21000.into(),
+            (120.into(), 100.into()),
+            18,
+            None,
+        );
+    }
+
+    let addresses = contract
+        .all_addresses_for("alice.near".parse().unwrap(), "ethereum-1".to_string())
+        .unwrap();
+
+    let expected = [1, 10, 97]
+        .map(|chain_id| {
+            (
+                chain_id.into(),
+                contract
+                    .foreign_address_for(
+                        "alice.near".parse().unwrap(),
+                        "ethereum-1".to_string(),
+                        chain_id.into(),
+                        None,
+                        None,
+                    )
+                    .unwrap(),
+            )
+        })
+        .to_vec();
+    assert_eq!(addresses, expected);
+    // Without namespacing, EVM chains share one address.
+    assert!(addresses
+        .iter()
+        .all(|(_, address)| *address == addresses[0].1));
+
+    assert!(matches!(
+        contract.all_addresses_for("alice.near".parse().unwrap(), String::new()),
+        Err(ContractError::InvalidPath { len: 0, .. }),
+    ));
+}
+
+#[test]
+fn test_all_addresses_for_namespaced_chains() {
+    use ethers_core::k256::elliptic_curve::sec1::ToEncodedPoint;
+
+    use crate::chain_configuration::ForeignChainOptions;
+
+    let mut contract = setup_mpc_contract();
+    near_sdk::testing_env!(near_sdk::test_utils::VMContextBuilder::new()
+        .current_account_id("canhazgas.testnet".parse().unwrap())
+        .predecessor_account_id("canhazgas.testnet".parse().unwrap())
+        .build());
+    for chain_id in [1, 10, 8453] {
+        contract.add_foreign_chain(
+            chain_id.into(),
+            "EdVCmQ9FSPcVe5YySXDPCRmc8aDQLKJ9xvYBMZPie1Vw".to_string(),
+            21000.into(),
+            (120.into(), 100.into()),
+            18,
+            Some(ForeignChainOptions {
+                namespaced_derivation: Some(true),
+                ..Default::default()
+            }),
+        );
+    }
+
+    let addresses = contract
+        .all_addresses_for("alice.near".parse().unwrap(), "ethereum-1".to_string())
+        .unwrap();
+    assert_eq!(
+        addresses
+            .iter()
+            .map(|(chain_id, _)| chain_id.0)
+            .collect::<Vec<_>>(),
+        [1, 10, 97, 8453],
+    );
+
+    for (chain_id, address) in &addresses {
+        assert_eq!(
+            *address,
+            contract
+                .foreign_address_for(
+                    "alice.near".parse().unwrap(),
+                    "ethereum-1".to_string(),
+                    *chain_id,
+                    None,
+                    None,
+                )
+                .unwrap(),
+        );
+    }
+
+    // The namespaced chains each have their own address, which also differs
+    // from the address on chain 97, which is not namespaced.
+    let namespaced = addresses
+        .iter()
+        .filter(|(chain_id, _)| chain_id.0 != 97)
+        .map(|(_, address)| address)
+        .collect::<std::collections::HashSet<_>>();
+    assert_eq!(namespaced.len(), 3);
+    assert!(!namespaced.contains(&addresses[2].1));
+
+    // The MPC contract derives the same key as `derive_epsilon_for_chain`.
+    let mpc_key = contract.mpc_keys.get(&0).unwrap().affine().unwrap();
+    let epsilon =
+        lib::kdf::derive_epsilon_for_chain(&env::current_account_id(), "alice.near,ethereum-1", 10);
+    assert_eq!(
+        addresses[1].1,
+        ForeignAddress::from_raw_public_key(
+            lib::kdf::derive_key(mpc_key, epsilon)
+                .to_encoded_point(false)
+                .as_bytes(),
+        )
+        .to_string(),
+    );
+}
+
+#[test]
+fn test_contract_foreign_address() {
+    let contract = setup_mpc_contract();
+
+    let contract_address = contract.contract_foreign_address(97.into()).unwrap();
+    assert_eq!(
+        contract_address,
+        contract
+            .foreign_address_for(
+                "canhazgas.testnet".parse().unwrap(),
+                Contract::RELAYER_PATH.to_string(),
+                97.into(),
+                None,
+                None,
+            )
+            .unwrap(),
+    );
+    // Users cannot sign for it, even at the same path.
+    assert_ne!(
+        contract_address,
+        contract
+            .foreign_address_for(
+                "alice.near".parse().unwrap(),
+                Contract::RELAYER_PATH.to_string(),
+                97.into(),
+                None,
+                None,
+            )
+            .unwrap(),
+    );
+
+    assert!(matches!(
+        contract.contract_foreign_address(999.into()),
+        Err(ContractError::ChainNotConfigured { chain_id: 999 }),
+    ));
+}
+
+#[test]
+fn test_foreign_address_for_requires_mpc_public_key() {
+    let mut contract = setup_mpc_contract();
+    contract.mpc_keys.clear();
+
+    assert!(matches!(
+        contract.foreign_address_for(
+            "alice.near".parse().unwrap(),
+            "ethereum-1".to_string(),
+            97.into(),
+            None,
+            None,
+        ),
+        Err(ContractError::MpcKeyNotConfigured),
+    ));
+}
+
+#[test]
+fn test_foreign_address_for_unconfigured_chain() {
+    let contract = setup_mpc_contract();
+
+    assert!(matches!(
+        contract.foreign_address_for(
+            "alice.near".parse().unwrap(),
+            "ethereum-1".to_string(),
+            999.into(),
+            None,
+            None,
+        ),
+        Err(ContractError::ChainNotConfigured { chain_id: 999 }),
+    ));
+}
+
+#[cfg(test)]
+fn set_block_height(block_height: u64) {
+    near_sdk::testing_env!(near_sdk::test_utils::VMContextBuilder::new()
+        .current_account_id("canhazgas.testnet".parse().unwrap())
+        .predecessor_account_id("alice.near".parse().unwrap())
+        .block_height(block_height)
+        .attached_deposit(SIGN_STORAGE_DEPOSIT)
+        .build());
+}
+
+#[test]
+fn test_get_quote_matches_views() {
+    let mut contract = setup_mpc_contract();
+    let alice: AccountId = "alice.near".parse().unwrap();
+
+    set_block_height(100);
+    contract.gas_price_oracle.update_gas_price(
+        97,
+        GasPrice {
+            max_fee_per_gas: 20_000_000_000.into(),
+            max_priority_fee_per_gas: 1_000_000_000.into(),
+            observed_at_block: 100.into(),
+        },
+    );
+    set_token_price(&mut contract, 0, 100);
+    contract
+        .sign(
+            "ethereum-1".to_string(),
+            sample_transaction_input(),
+            None,
+            None,
+        )
+        .unwrap();
+    set_token_price(&mut contract, 1000, 100);
+
+    let quote = contract
+        .get_quote(
+            alice.clone(),
+            "ethereum-1".to_string(),
+            97.into(),
+            21000.into(),
+            None,
+            None,
+        )
+        .unwrap();
+    let gas_price = contract.current_gas_price(97.into()).unwrap();
+
+    assert_eq!(
+        quote.foreign_address.to_string(),
+        contract
+            .foreign_address_for(alice, "ethereum-1".to_string(), 97.into(), None, None)
+            .unwrap(),
+    );
+    assert_eq!(quote.next_nonce, 1.into());
+    assert_eq!(
+        quote.next_nonce,
+        contract.get_nonce(97.into(), quote.foreign_address),
+    );
+    assert_eq!(quote.max_fee_per_gas, gas_price.max_fee_per_gas);
+    assert_eq!(
+        quote.max_priority_fee_per_gas,
+        gas_price.max_priority_fee_per_gas
+    );
+    assert_eq!(
+        quote.near_fee,
+        contract.get_fee(
+            97.into(),
+            21000.into(),
+            gas_price.max_fee_per_gas,
+            NearToken::from_near(1000).as_yoctonear().into(),
+        ),
+    );
+}
+
+#[test]
+fn test_rollup_fee_includes_l1_data_fee() {
+    use crate::chain_configuration::RollupConfiguration;
+
+    let mut contract = setup_mpc_contract();
+    let alice: AccountId = "alice.near".parse().unwrap();
+    let rollup = RollupConfiguration {
+        l1_gas_per_byte: 16,
+        l1_gas_overhead: 188,
+    };
+
+    set_block_height(100);
+    contract.gas_price_oracle.update_gas_price(
+        97,
+        GasPrice {
+            max_fee_per_gas: 20_000_000_000.into(),
+            max_priority_fee_per_gas: 1_000_000_000.into(),
+            observed_at_block: 100.into(),
+        },
+    );
+    set_token_price(&mut contract, 1000, 100);
+    let quote = |contract: &Contract| {
+        contract.get_quote(
+            alice.clone(),
+            "ethereum-1".to_string(),
+            97.into(),
+            21000.into(),
+            None,
+            None,
+        )
+    };
+    let dry_run = |contract: &Contract| {
+        contract.dry_run_sign(
+            alice.clone(),
+            "ethereum-1".to_string(),
+            sample_transaction_input(),
+            None,
+        )
+    };
+
+    let l1_quote = quote(&contract).unwrap();
+    let l1_dry_run = dry_run(&contract).unwrap();
+    assert_eq!(l1_quote.l1_data_fee, 0.into());
+
+    near_sdk::testing_env!(near_sdk::test_utils::VMContextBuilder::new()
+        .current_account_id("canhazgas.testnet".parse().unwrap())
+        .predecessor_account_id("canhazgas.testnet".parse().unwrap())
+        .block_height(100)
+        .build());
+    contract.set_foreign_chain_rollup(97.into(), Some(rollup));
+    assert_eq!(
+        contract.get_foreign_chain(97.into()).unwrap().rollup,
+        Some(rollup),
+    );
+    assert!(matches!(
+        quote(&contract),
+        Err(ContractError::L1GasPriceUnavailable { chain_id: 97 }),
+    ));
+
+    contract.add_relayer(alice.clone());
+    set_block_height(100);
+    contract.update_l1_gas_price(97.into(), 30_000_000_000.into());
+
+    let rollup_quote = quote(&contract).unwrap();
+    assert_eq!(
+        rollup_quote.l1_data_fee.0,
+        rollup
+            .l1_data_fee(Contract::TYPICAL_SIGNED_TRANSFER_LEN, 30_000_000_000)
+            .unwrap(),
+    );
+    assert!(rollup_quote.near_fee.0 > l1_quote.near_fee.0);
+
+    // Sign requests are charged for the L1 data fee of the actual
+    // transaction.
+    let rollup_dry_run = dry_run(&contract).unwrap();
+    set_attached_deposit(NearToken::from_near(10));
+    let (_, _, transaction) = sign_and_get_callback_args(&mut contract);
+    let l1_data_fee = rollup
+        .l1_data_fee(transaction.signed_len() as u64, 30_000_000_000)
+        .unwrap();
+    assert_eq!(
+        rollup_dry_run.fee.0,
+        compute_fee_with_l1_data_fee(
+            21000,
+            20_000_000_000,
+            l1_data_fee,
+            NearToken::from_near(1000).as_yoctonear(),
+            18,
+            0,
+        )
+        .unwrap()
+        .as_yoctonear(),
+    );
+    assert!(rollup_dry_run.fee.0 > l1_dry_run.fee.0);
+}
+
+#[test]
+fn test_get_quote_errors() {
+    let mut contract = setup_mpc_contract();
+    let quote = |contract: &Contract, chain_id: u64| {
+        contract.get_quote(
+            "alice.near".parse().unwrap(),
+            "ethereum-1".to_string(),
+            chain_id.into(),
+            21000.into(),
+            None,
+            None,
+        )
+    };
+
+    assert!(matches!(
+        quote(&contract, 999),
+        Err(ContractError::ChainNotConfigured { chain_id: 999 }),
+    ));
+    assert!(matches!(
+        quote(&contract, 97),
+        Err(ContractError::GasPriceUnavailable { chain_id: 97 }),
+    ));
+
+    contract.gas_price_oracle.update_gas_price(
+        97,
+        GasPrice {
+            max_fee_per_gas: 20_000_000_000.into(),
+            max_priority_fee_per_gas: 1_000_000_000.into(),
+            observed_at_block: 0.into(),
+        },
+    );
+    quote(&contract, 97).unwrap();
+
+    let block_height = contract.gas_price_oracle.max_age_blocks + 1;
+    set_block_height(block_height);
+    assert!(matches!(
+        quote(&contract, 97),
+        Err(ContractError::GasPriceStale {
+            chain_id: 97,
+            observed_at_block: 0,
+        }),
+    ));
+
+    contract.gas_price_oracle.update_gas_price(
+        97,
+        GasPrice {
+            max_fee_per_gas: 20_000_000_000.into(),
+            max_priority_fee_per_gas: 1_000_000_000.into(),
+            observed_at_block: block_height.into(),
+        },
+    );
+    assert!(matches!(
+        quote(&contract, 97),
+        Err(ContractError::TokenPriceStale {
+            chain_id: 97,
+            observed_at_block: 0,
+        }),
+    ));
+}
+
+#[cfg(test)]
+fn set_token_price(contract: &mut Contract, near_per_token: u128, observed_at_block: u64) {
+    contract.gas_price_oracle.update_token_price(
+        97,
+        TokenPrice {
+            price_in_near: NearToken::from_near(near_per_token).as_yoctonear().into(),
+            observed_at_block: observed_at_block.into(),
+        },
+    );
+}
+
+#[cfg(test)]
+fn set_attached_deposit(deposit: NearToken) {
+    near_sdk::testing_env!(near_sdk::test_utils::VMContextBuilder::new()
+        .current_account_id("canhazgas.testnet".parse().unwrap())
+        .predecessor_account_id("alice.near".parse().unwrap())
+        .attached_deposit(deposit)
+        .build());
+}
+
+#[test]
+fn test_sign_insufficient_deposit() {
+    let mut contract = setup_mpc_contract();
+    // 21000 gas at 20 gwei, 1 ETH = 1000 NEAR is 0.42 NEAR.
+    set_token_price(&mut contract, 1000, 0);
+    set_attached_deposit(NearToken::from_millinear(400));
+
+    let result = contract.sign(
+        "ethereum-1".to_string(),
+        sample_transaction_input(),
+        None,
+        None,
+    );
+
+    assert!(matches!(
+        result,
+        Err(ContractError::InsufficientDeposit { fee, shortfall })
+            if fee == NearToken::from_millinear(420).as_yoctonear()
+                && shortfall == NearToken::from_millinear(20).as_yoctonear(),
+    ));
+    assert_eq!(contract.get_sign_fees(97.into()), 0.into());
+    assert!(contract.sign_request_ids.is_empty());
+}
+
+/// The storage deposit of a request to sign the sample transaction, found
+/// by signing it for a path of the same length as that of
+/// [`sign_and_get_callback_args`].
+#[cfg(test)]
+fn sample_storage_deposit(contract: &mut Contract) -> NearToken {
+    drop(
+        contract
+            .sign(
+                "ethereum-2".to_string(),
+                sample_transaction_input(),
+                None,
+                None,
+            )
+            .unwrap(),
+    );
+
+    storage_deposit_of(contract, &last_sign_callback_args().0)
+}
+
+#[test]
+fn test_sign_exact_deposit() {
+    let mut contract = setup_mpc_contract();
+    let storage_deposit = sample_storage_deposit(&mut contract);
+    set_token_price(&mut contract, 1000, 0);
+    set_attached_deposit(NearToken::from_millinear(420).saturating_add(storage_deposit));
+
+    let (request_id, _, _) = sign_and_get_callback_args(&mut contract);
+
+    let fee = NearToken::from_millinear(420).as_yoctonear();
+    assert_eq!(contract.get_sign_fees(97.into()), fee.into());
+    assert_eq!(
+        contract.prepaid_fees.get(&request_id).unwrap().amount,
+        fee.into()
+    );
+    assert_eq!(crate::impl_fee::refund_transfers(), vec![]);
+}
+
+#[test]
+fn test_sign_excess_deposit_refunded() {
+    let mut contract = setup_mpc_contract();
+    set_token_price(&mut contract, 1000, 0);
+    set_attached_deposit(NearToken::from_near(1));
+
+    let (request_id, ..) = sign_and_get_callback_args(&mut contract);
+
+    assert_eq!(
+        contract.get_sign_fees(97.into()),
+        NearToken::from_millinear(420).as_yoctonear().into(),
+    );
+    assert_eq!(
+        crate::impl_fee::refund_transfers(),
+        vec![(
+            "alice.near".to_string(),
+            NearToken::from_millinear(580)
+                .saturating_sub(storage_deposit_of(&contract, &request_id)),
+        )],
+    );
+}
+
+#[test]
+fn test_stats_count_only_completed_sign_requests() {
+    let mut contract = setup_mpc_contract();
+    set_token_price(&mut contract, 1000, 0);
+    set_attached_deposit(NearToken::from_millinear(420).saturating_add(SIGN_STORAGE_DEPOSIT));
+
+    let (request_id, foreign_address, transaction) = sign_and_get_callback_args(&mut contract);
+    assert_eq!(contract.stats(), vec![]);
+
+    drop(contract.sign_callback(
+        request_id,
+        foreign_address,
+        transaction,
+        Err(PromiseError::Failed),
+    ));
+    assert_eq!(contract.stats(), vec![]);
+
+    let (request_id, foreign_address, transaction) = sign_and_get_callback_args(&mut contract);
+    assert_eq!(contract.stats(), vec![]);
+
+    let response = mpc_signature_response(&transaction);
+    drop(contract.sign_callback(request_id, foreign_address, transaction, Ok(response)));
+    assert_eq!(
+        contract.stats(),
+        vec![(
+            97.into(),
+            1.into(),
+            NearToken::from_millinear(420).as_yoctonear().into(),
+        )],
+    );
+}
+
+#[test]
+fn test_sign_deposit_within_fee_slippage() {
+    let mut contract = setup_mpc_contract();
+    let storage_deposit = sample_storage_deposit(&mut contract);
+    set_token_price(&mut contract, 1000, 0);
+    contract.fee_slippage_bps = 500;
+    set_attached_deposit(NearToken::from_millinear(430).saturating_add(storage_deposit));
+
+    let (request_id, foreign_address, transaction) = sign_and_get_callback_args(&mut contract);
+
+    // The whole deposit is kept, and the excess is refunded on settlement.
+    assert_eq!(
+        contract.prepaid_fees.get(&request_id).unwrap().amount,
+        NearToken::from_millinear(430).as_yoctonear().into(),
+    );
+    assert!(crate::impl_fee::refund_transfers().is_empty());
+    assert_eq!(
+        contract.get_treasury().unsettled,
+        NearToken::from_millinear(430),
+    );
+    assert_eq!(contract.get_withdrawable_fees(), NearToken::from_near(0));
+
+    let response = mpc_signature_response(&transaction);
+    drop(contract.sign_callback(request_id, foreign_address, transaction, Ok(response)));
+    near_sdk::testing_env!(near_sdk::test_utils::VMContextBuilder::new()
+        .current_account_id("canhazgas.testnet".parse().unwrap())
+        .predecessor_account_id("canhazgas.testnet".parse().unwrap())
+        .build());
+    drop(
+        contract
+            .settle_fee(
+                ethers_core::utils::hex::encode(request_id),
+                21_000.into(),
+                20_000_000_000.into(),
+            )
+            .unwrap(),
+    );
+
+    // Only the actual cost becomes withdrawable, and the buffer is refunded.
+    assert_eq!(
+        contract.get_withdrawable_fees(),
+        NearToken::from_millinear(420),
+    );
+    assert_eq!(contract.get_treasury().unsettled, NearToken::from_near(0));
+    assert_eq!(
+        crate::impl_fee::refund_transfers(),
+        vec![("alice.near".to_string(), NearToken::from_millinear(10))],
+    );
+}
+
+#[test]
+fn test_sign_deposit_beyond_fee_slippage() {
+    let mut contract = setup_mpc_contract();
+    set_token_price(&mut contract, 1000, 0);
+    contract.fee_slippage_bps = 500;
+    set_attached_deposit(NearToken::from_near(1));
+
+    let (request_id, ..) = sign_and_get_callback_args(&mut contract);
+
+    // 0.42 NEAR plus 5%.
+    assert_eq!(
+        contract.prepaid_fees.get(&request_id).unwrap().amount,
+        NearToken::from_millinear(441).as_yoctonear().into(),
+    );
+    assert_eq!(
+        crate::impl_fee::refund_transfers(),
+        vec![(
+            "alice.near".to_string(),
+            NearToken::from_millinear(559)
+                .saturating_sub(storage_deposit_of(&contract, &request_id)),
+        )],
+    );
+}
+
+#[test]
+fn test_sign_failure_refunds_fee() {
+    let mut contract = setup_mpc_contract();
+    set_token_price(&mut contract, 1000, 0);
+    set_attached_deposit(NearToken::from_millinear(420).saturating_add(SIGN_STORAGE_DEPOSIT));
+    let (request_id, foreign_address, transaction) = sign_and_get_callback_args(&mut contract);
+
+    set_attached_deposit(NearToken::from_yoctonear(0));
+    let storage_refund = expected_storage_refund(&mut contract, &request_id, |contract| {
+        drop(contract.sign_callback(
+            request_id,
+            foreign_address,
+            transaction,
+            Err(PromiseError::Failed),
+        ));
+    });
+
+    assert_eq!(contract.get_sign_fees(97.into()), 0.into());
+    assert_eq!(contract.prepaid_fees.get(&request_id), None);
+    assert_eq!(
+        crate::impl_fee::refund_transfers(),
+        vec![
+            ("alice.near".to_string(), NearToken::from_millinear(420)),
+            ("alice.near".to_string(), storage_refund),
+        ],
+    );
+}
+
+#[test]
+fn test_sign_batch_deposit_pays_requests_in_order() {
+    let mut contract = setup_mpc_contract();
+    set_token_price(&mut contract, 1000, 0);
+    set_attached_deposit(NearToken::from_millinear(500).saturating_add(SIGN_STORAGE_DEPOSIT));
+
+    let request = BatchSignRequest {
+        path: "ethereum-1".to_string(),
+        transaction: sample_transaction_input(),
+        key_version: None,
+        memo: None,
+    };
+    let batch_id = contract.sign_batch(vec![request.clone(), request]).unwrap();
+
+    let items = contract.get_sign_batch(batch_id).unwrap();
+    assert_eq!(items[0].status, RequestStatus::Pending);
+    assert!(matches!(
+        &items[1].status,
+        RequestStatus::Failed { reason } if reason.starts_with("Attached deposit is"),
+    ));
+    // The batch's own record is charged for too.
+    assert_eq!(
+        crate::impl_fee::refund_transfers(),
+        vec![(
+            "alice.near".to_string(),
+            NearToken::from_millinear(100)
+                .saturating_sub(storage_deposit_of(&contract, &last_sign_callback_args().0))
+                .saturating_sub(batch_storage_deposit_of(&contract, batch_id)),
+        )],
+    );
+}
+
+#[cfg(test)]
+fn storage_deposit_of(contract: &Contract, request_id: &RequestId) -> NearToken {
+    NearToken::from_yoctonear(
+        contract
+            .pending_sign_requests
+            .get(request_id)
+            .unwrap()
+            .storage_deposit,
+    )
+}
+
+/// The storage deposit of the record of the batch `batch_id`: its key, with
+/// the one-byte prefix of `sign_batches`, its value, and the overhead that
+/// NEAR charges for each record.
+#[cfg(test)]
+fn batch_storage_deposit_of(contract: &Contract, batch_id: U64) -> NearToken {
+    const STORAGE_RECORD_OVERHEAD: u64 = 40;
+
+    let value = near_sdk::borsh::to_vec(contract.sign_batches.get(&batch_id.0).unwrap()).unwrap();
+    NearToken::from_yoctonear(storage_cost(
+        1 + 8 + value.len() as u64 + STORAGE_RECORD_OVERHEAD,
+    ))
+}
+
+/// Resolves the pending sign request `request_id` with `resolve`, and
+/// returns the part of its storage deposit that should be refunded: all but
+/// the cost of the records that it leaves behind, which the deposit must
+/// cover.
+#[cfg(test)]
+fn expected_storage_refund(
+    contract: &mut Contract,
+    request_id: &RequestId,
+    resolve: impl FnOnce(&mut Contract),
+) -> NearToken {
+    let pending = contract
+        .pending_sign_requests
+        .get(request_id)
+        .unwrap()
+        .clone();
+    let storage_usage_before = contract.flushed_storage_usage();
+    resolve(contract);
+    let storage_usage = (pending.storage_usage + contract.flushed_storage_usage())
+        .saturating_sub(storage_usage_before);
+
+    assert!(storage_cost(storage_usage) <= pending.storage_deposit);
+    NearToken::from_yoctonear(pending.storage_deposit - storage_cost(storage_usage))
+}
+
+/// Calls `sign` with a fee, and returns the arguments that it passes to
+/// `sign_callback`, the storage usage after the request and its storage
+/// deposit.
+#[cfg(test)]
+fn sign_with_storage_charged(
+    contract: &mut Contract,
+) -> ((RequestId, ForeignAddress, ForeignTransaction), u64, u128) {
+    set_token_price(contract, 1000, 0);
+    set_attached_deposit(NearToken::from_near(1));
+
+    let storage_usage_before = contract.flushed_storage_usage();
+    let callback_args = sign_and_get_callback_args(contract);
+    let storage_usage_after = contract.flushed_storage_usage();
+
+    let pending = contract
+        .pending_sign_requests
+        .get(&callback_args.0)
+        .unwrap()
+        .clone();
+    // Only the records of the request are charged for, not e.g. the sign fee
+    // statistics, along with the signed transaction that it will resolve to.
+    assert!(pending.storage_usage > 0);
+    assert!(pending.storage_usage <= storage_usage_after - storage_usage_before);
+    assert!(pending.storage_deposit > storage_cost(pending.storage_usage));
+
+    assert_eq!(
+        crate::impl_fee::refund_transfers(),
+        vec![(
+            "alice.near".to_string(),
+            NearToken::from_yoctonear(
+                NearToken::from_millinear(580).as_yoctonear() - pending.storage_deposit
+            ),
+        )],
+    );
+    // The storage deposit is not a fee, and the fee is only withdrawable once
+    // settled.
+    assert_eq!(
+        contract.get_treasury().unsettled,
+        NearToken::from_millinear(420),
+    );
+    assert_eq!(contract.get_withdrawable_fees(), NearToken::from_near(0));
+
+    (callback_args, storage_usage_after, pending.storage_deposit)
+}
+
+#[test]
+fn test_sign_storage_deposit_refunded_on_completion() {
+    let mut contract = setup_mpc_contract();
+    let ((request_id, foreign_address, transaction), _, storage_deposit) =
+        sign_with_storage_charged(&mut contract);
+
+    set_attached_deposit(NearToken::from_yoctonear(0));
+    let storage_refund = expected_storage_refund(&mut contract, &request_id, |contract| {
+        let response = mpc_signature_response(&transaction);
+        drop(contract.sign_callback(request_id, foreign_address, transaction, Ok(response)));
+    });
+
+    // The completed request, with its signed transaction, is kept.
+    assert_eq!(contract.pending_sign_requests.get(&request_id), None);
+    assert!(storage_refund.as_yoctonear() < storage_deposit);
+    assert_eq!(
+        crate::impl_fee::refund_transfers(),
+        vec![("alice.near".to_string(), storage_refund)],
+    );
+}
+
+#[test]
+fn test_sign_storage_deposit_refunded_on_failure() {
+    let mut contract = setup_mpc_contract();
+    let ((request_id, foreign_address, transaction), ..) = sign_with_storage_charged(&mut contract);
+
+    set_attached_deposit(NearToken::from_yoctonear(0));
+    let storage_refund = expected_storage_refund(&mut contract, &request_id, |contract| {
+        drop(contract.sign_callback(
+            request_id,
+            foreign_address,
+            transaction,
+            Err(PromiseError::Failed),
+        ));
+    });
+
+    assert_eq!(
+        crate::impl_fee::refund_transfers(),
+        vec![
+            ("alice.near".to_string(), NearToken::from_millinear(420)),
+            ("alice.near".to_string(), storage_refund),
+        ],
+    );
+}
+
+#[test]
+fn test_sign_storage_deposit_refunded_on_cancel() {
+    let mut contract = setup_mpc_contract();
+    let ((request_id, ..), storage_usage_after_sign, _) = sign_with_storage_charged(&mut contract);
+
+    set_block_timestamp_seconds(1_000 + contract.sign_request_timeout_seconds);
+    let storage_refund = expected_storage_refund(&mut contract, &request_id, |contract| {
+        contract.cancel_request(hex::encode(request_id)).unwrap();
+    });
+
+    // The pending request and its prepaid fee are removed.
+    assert!(contract.flushed_storage_usage() < storage_usage_after_sign);
+    assert_eq!(
+        crate::impl_fee::refund_transfers(),
+        vec![
+            ("alice.near".to_string(), storage_refund),
+            ("alice.near".to_string(), NearToken::from_millinear(420)),
+        ],
+    );
+}
+
+#[test]
+fn test_sign_insufficient_storage_deposit() {
+    let mut contract = setup_mpc_contract();
+    set_token_price(&mut contract, 1000, 0);
+    set_attached_deposit(NearToken::from_millinear(420));
+
+    assert!(matches!(
+        contract.sign(
+            "ethereum-1".to_string(),
+            sample_transaction_input(),
+            None,
+            None,
+        ),
+        Err(ContractError::InsufficientStorageDeposit { storage_deposit, shortfall })
+            if storage_deposit > 0 && shortfall == storage_deposit,
+    ));
+}
+
+#[test]
+fn test_sign_batch_insufficient_storage_deposit() {
+    let mut contract = setup_mpc_contract();
+    set_token_price(&mut contract, 1000, 0);
+    set_attached_deposit(NearToken::from_millinear(420));
+
+    assert!(matches!(
+        contract.sign_batch(vec![BatchSignRequest {
+            path: "ethereum-1".to_string(),
+            transaction: sample_transaction_input(),
+            key_version: None,
+            memo: None,
+        }]),
+        // Not recorded as rejected, since the whole batch must be reverted.
+        Err(ContractError::InsufficientStorageDeposit { .. }),
+    ));
+}
+
+#[test]
+fn test_preview_signing_hash() {
+    let mut contract = setup_mpc_contract();
+    let alice: AccountId = "alice.near".parse().unwrap();
+
+    for is_eip1559 in [true, false] {
+        near_sdk::testing_env!(near_sdk::test_utils::VMContextBuilder::new()
+            .current_account_id("canhazgas.testnet".parse().unwrap())
+            .predecessor_account_id("canhazgas.testnet".parse().unwrap())
+            .build());
+        contract.set_foreign_chain_is_eip1559(97.into(), is_eip1559);
+        near_sdk::testing_env!(near_sdk::test_utils::VMContextBuilder::new()
+            .current_account_id("canhazgas.testnet".parse().unwrap())
+            .predecessor_account_id(alice.clone())
+            .attached_deposit(SIGN_STORAGE_DEPOSIT)
+            .build());
+
+        let preview = contract
+            .preview_signing_hash(
+                alice.clone(),
+                "ethereum-1".to_string(),
+                sample_transaction_input(),
+                None,
+            )
+            .unwrap();
+        let (_, _, transaction) = sign_and_get_callback_args(&mut contract);
+
+        assert_eq!(
+            matches!(transaction, ForeignTransaction::Eip1559(_)),
+            is_eip1559,
+        );
+        assert_eq!(preview, hex::encode_prefixed(transaction.signing_hash()));
+    }
+}
+
+#[test]
+fn test_dry_run_sign() {
+    let mut contract = setup_mpc_contract();
+    let alice: AccountId = "alice.near".parse().unwrap();
+
+    let dry_run = contract
+        .dry_run_sign(
+            alice.clone(),
+            "ethereum-1".to_string(),
+            sample_transaction_input(),
+            None,
+        )
+        .unwrap();
+    assert_eq!(dry_run.nonce, 0.into());
+    assert_eq!(dry_run.raw_transaction, None);
+
+    // Nothing is reserved, so the dry run can be repeated.
+    assert_eq!(contract.nonces.peek_nonce(97, dry_run.foreign_address), 0);
+    assert_eq!(
+        contract
+            .get_request_status(dry_run.request_id.clone())
+            .unwrap()
+            .map(|record| record.status),
+        None,
+    );
+    assert_eq!(
+        contract
+            .dry_run_sign(
+                alice.clone(),
+                "ethereum-1".to_string(),
+                sample_transaction_input(),
+                None,
+            )
+            .unwrap(),
+        dry_run,
+    );
+
+    let (request_id, foreign_address, transaction) = sign_and_get_callback_args(&mut contract);
+    assert_eq!(dry_run.request_id, hex::encode_prefixed(request_id));
+    assert_eq!(dry_run.foreign_address, foreign_address);
+    assert_eq!(
+        dry_run.signing_hash,
+        hex::encode_prefixed(transaction.signing_hash()),
+    );
+    assert_eq!(
+        contract.prepaid_fees.get(&request_id).unwrap().amount,
+        dry_run.fee
+    );
+
+    let mut resubmission = sample_transaction_input();
+    resubmission.nonce = Some(0.into());
+    assert!(matches!(
+        contract.dry_run_sign(
+            alice.clone(),
+            "ethereum-1".to_string(),
+            resubmission.clone(),
+            None,
+        ),
+        Err(ContractError::RequestPending),
+    ));
+
+    let response = mpc_signature_response(&transaction);
+    let PromiseOrValue::Value(SignResult {
+        raw_tx: raw_transaction,
+        ..
+    }) = contract.sign_callback(request_id, foreign_address, transaction, Ok(response))
+    else {
+        panic!("Expected the signed transaction");
+    };
+
+    // A resubmission of a completed request would be free.
+    let completed = contract
+        .dry_run_sign(alice, "ethereum-1".to_string(), resubmission, None)
+        .unwrap();
+    assert_eq!(completed.fee, 0.into());
+    assert_eq!(completed.raw_transaction, Some(raw_transaction));
+}
+
+#[test]
+fn test_mpc_key_versions() {
+    use ethers_core::k256::ecdsa::SigningKey;
+
+    let mut contract = setup_mpc_contract();
+    let alice: AccountId = "alice.near".parse().unwrap();
+
+    let version_0 = contract
+        .foreign_address_for(
+            alice.clone(),
+            "ethereum-1".to_string(),
+            97.into(),
+            None,
+            None,
+        )
+        .unwrap();
+
+    near_sdk::testing_env!(near_sdk::test_utils::VMContextBuilder::new()
+        .current_account_id("canhazgas.testnet".parse().unwrap())
+        .predecessor_account_id("canhazgas.testnet".parse().unwrap())
+        .build());
+    let rotated_key = SigningKey::from_bytes(&[0x18; 32].into()).unwrap();
+    contract
+        .set_mpc_key(
+            1,
+            PublicKey::from_parts(
+                near_sdk::CurveType::SECP256K1,
+                rotated_key
+                    .verifying_key()
+                    .to_encoded_point(false)
+                    .as_bytes()[1..]
+                    .to_vec(),
+            )
+            .unwrap()
+            .into(),
+        )
+        .unwrap();
+    assert_eq!(contract.get_latest_mpc_key_version(), Some(1));
+
+    let version_1 = contract
+        .foreign_address_for(
+            alice.clone(),
+            "ethereum-1".to_string(),
+            97.into(),
+            Some(1),
+            None,
+        )
+        .unwrap();
+    assert_ne!(version_0, version_1);
+    // Older versions remain reproducible.
+    assert_eq!(
+        contract
+            .foreign_address_for(
+                alice.clone(),
+                "ethereum-1".to_string(),
+                97.into(),
+                Some(0),
+                None
+            )
+            .unwrap(),
+        version_0,
+    );
+    // The latest version is the default.
+    assert_eq!(
+        contract
+            .foreign_address_for(alice, "ethereum-1".to_string(), 97.into(), None, None)
+            .unwrap(),
+        version_1,
+    );
+
+    set_attached_deposit(SIGN_STORAGE_DEPOSIT);
+    let (_, foreign_address, _) = sign_and_get_callback_args(&mut contract);
+    assert_eq!(foreign_address.to_string(), version_1);
+}
+
+#[test]
+fn test_register_address() {
+    let mut contract = setup_mpc_contract();
+    let alice: AccountId = "alice.near".parse().unwrap();
+
+    assert_eq!(
+        contract.get_registered_address(alice.clone(), "ethereum-1".to_string(), 97.into()),
+        None,
+    );
+    let registered = contract
+        .register_address(97.into(), "ethereum-1".to_string())
+        .unwrap();
+    assert_eq!(
+        registered,
+        contract
+            .foreign_address_for(
+                alice.clone(),
+                "ethereum-1".to_string(),
+                97.into(),
+                None,
+                None,
+            )
+            .unwrap(),
+    );
+    assert_eq!(
+        contract.get_registered_address(alice.clone(), "ethereum-1".to_string(), 97.into()),
+        Some(registered.clone()),
+    );
+    // Registrations are per chain and per path.
+    assert_eq!(
+        contract.get_registered_address(alice, "ethereum-2".to_string(), 97.into()),
+        None,
+    );
+
+    let (_, foreign_address, _) = sign_and_get_callback_args(&mut contract);
+    assert_eq!(foreign_address.to_string(), registered);
+
+    assert!(matches!(
+        contract.register_address(999.into(), "ethereum-1".to_string()),
+        Err(ContractError::ChainNotConfigured { chain_id: 999 }),
+    ));
+}
+
+#[test]
+fn test_register_address_storage_deposit() {
+    let mut contract = setup_mpc_contract();
+    let alice: AccountId = "alice.near".parse().unwrap();
+
+    set_attached_deposit(NearToken::from_millinear(10));
+    let storage_usage_before = env::storage_usage();
+    contract
+        .register_address(97.into(), "ethereum-1".to_string())
+        .unwrap();
+    let storage_deposit = storage_cost(env::storage_usage() - storage_usage_before);
+    assert!(storage_deposit > 0);
+
+    // Nothing is refunded once unregistered.
+    contract.unregister_address(97.into(), "ethereum-1".to_string());
+    contract.unregister_address(97.into(), "ethereum-1".to_string());
+    assert_eq!(
+        contract.get_registered_address(alice, "ethereum-1".to_string(), 97.into()),
+        None,
+    );
+    assert_eq!(env::storage_usage(), storage_usage_before);
+    assert_eq!(
+        crate::impl_fee::refund_transfers(),
+        vec![
+            (
+                "alice.near".to_string(),
+                NearToken::from_yoctonear(
+                    NearToken::from_millinear(10).as_yoctonear() - storage_deposit
+                ),
+            ),
+            (
+                "alice.near".to_string(),
+                NearToken::from_yoctonear(storage_deposit),
+            ),
+        ],
+    );
+
+    set_attached_deposit(NearToken::from_yoctonear(0));
+    assert!(matches!(
+        contract.register_address(97.into(), "ethereum-1".to_string()),
+        Err(ContractError::InsufficientStorageDeposit { storage_deposit: deposit, shortfall })
+            if deposit == storage_deposit && shortfall == storage_deposit,
+    ));
+}
+
+#[test]
+fn test_sign_rejects_unreasonable_fees() {
+    use crate::{error::UnreasonableFeeError, fee::FeeBand};
+
+    let mut contract = setup_mpc_contract();
+    contract.gas_price_oracle.update_gas_price(
+        97,
+        GasPrice {
+            max_fee_per_gas: 20_000_000_000.into(),
+            max_priority_fee_per_gas: 1_000_000_000.into(),
+            observed_at_block: 0.into(),
+        },
+    );
+    contract.fee_band = Some(FeeBand {
+        min_bps: 5_000,
+        max_bps: 30_000,
+    });
+    let sign = |contract: &mut Contract, max_fee_per_gas: u128, max_priority_fee_per_gas: u128| {
+        let mut transaction = sample_transaction_input();
+        transaction.max_fee_per_gas = Some(max_fee_per_gas.into());
+        transaction.max_priority_fee_per_gas = Some(max_priority_fee_per_gas.into());
+        contract.sign("ethereum-1".to_string(), transaction, None, None)
+    };
+
+    // Below the base fee.
+    assert!(matches!(
+        sign(&mut contract, 9_999_999_999, 1_000_000_000),
+        Err(ContractError::UnreasonableFee(
+            UnreasonableFeeError::MaxFeeOutOfBand {
+                max_fee_per_gas: 9_999_999_999,
+                min: 10_000_000_000,
+                max: 60_000_000_000,
+            }
+        )),
+    ));
+    // Above the cap.
+    assert!(matches!(
+        sign(&mut contract, 60_000_000_001, 1_000_000_000),
+        Err(ContractError::UnreasonableFee(
+            UnreasonableFeeError::MaxFeeOutOfBand {
+                max_fee_per_gas: 60_000_000_001,
+                ..
+            }
+        )),
+    ));
+    // The priority fee exceeds the max fee, whether or not a band is
+    // configured.
+    for fee_band in [contract.fee_band, None] {
+        contract.fee_band = fee_band;
+        assert!(matches!(
+            sign(&mut contract, 20_000_000_000, 20_000_000_001),
+            Err(ContractError::UnreasonableFee(
+                UnreasonableFeeError::PriorityFeeExceedsMaxFee {
+                    max_priority_fee_per_gas: 20_000_000_001,
+                    max_fee_per_gas: 20_000_000_000,
+                }
+            )),
+        ));
+    }
+    assert!(contract.sign_request_ids.is_empty());
+
+    // The edges of the band are allowed.
+    contract.fee_band = Some(FeeBand {
+        min_bps: 5_000,
+        max_bps: 30_000,
+    });
+    assert!(sign(&mut contract, 10_000_000_000, 1_000_000_000).is_ok());
+    let mut transaction = sample_transaction_input();
+    transaction.nonce = Some(1.into());
+    transaction.max_fee_per_gas = Some(60_000_000_000.into());
+    assert!(contract
+        .sign("ethereum-1".to_string(), transaction, None, None)
+        .is_ok());
+}
+
+#[test]
+fn test_sign_recipient_allowlist() {
+    let mut contract = setup_mpc_contract();
+    let allowed = sample_transaction_input().to.unwrap();
+    let other: ForeignAddress = "0x0505050505050505050505050505050505050505"
+        .parse()
+        .unwrap();
+    let alice: AccountId = "alice.near".parse().unwrap();
+
+    // Unrestricted until a recipient is allowed.
+    assert!(contract.is_recipient_allowed(alice.clone(), other));
+    contract.allow_recipient(allowed).unwrap();
+    assert!(contract.is_recipient_allowed(alice.clone(), allowed));
+    assert!(!contract.is_recipient_allowed(alice.clone(), other));
+    // Other callers are unaffected.
+    assert!(contract.is_recipient_allowed("bob.near".parse().unwrap(), other));
+
+    assert!(contract
+        .sign(
+            "ethereum-1".to_string(),
+            sample_transaction_input(),
+            None,
+            None,
+        )
+        .is_ok());
+
+    let mut transaction = sample_transaction_input();
+    transaction.to = Some(other);
+    assert!(matches!(
+        contract.sign("ethereum-1".to_string(), transaction, None, None),
+        Err(ContractError::RecipientNotAllowed { recipient }) if recipient == other,
+    ));
+
+    let mut transaction = sample_transaction_input();
+    transaction.to = None;
+    assert!(matches!(
+        contract.sign("ethereum-1".to_string(), transaction, None, None),
+        Err(ContractError::ContractCreationNotAllowed),
+    ));
+
+    // An emptied allowlist allows nothing.
+    contract.deny_recipient(allowed);
+    assert!(!contract.is_recipient_allowed(alice, allowed));
+    let mut transaction = sample_transaction_input();
+    transaction.nonce = Some(1.into());
+    assert!(matches!(
+        contract.sign("ethereum-1".to_string(), transaction, None, None),
+        Err(ContractError::RecipientNotAllowed { .. }),
+    ));
+}
+
+#[test]
+fn test_recipient_allowlist_storage_deposit() {
+    let mut contract = setup_mpc_contract();
+    let recipient = sample_transaction_input().to.unwrap();
+
+    set_attached_deposit(NearToken::from_millinear(10));
+    let storage_usage_before = env::storage_usage();
+    contract.allow_recipient(recipient).unwrap();
+    let storage_usage_after_allow = env::storage_usage();
+    assert!(storage_usage_after_allow > storage_usage_before);
+
+    // Allowing again takes up no more storage.
+    contract.allow_recipient(recipient).unwrap();
+    assert_eq!(env::storage_usage(), storage_usage_after_allow);
+
+    // The entry is refunded, but the allowlist itself is kept.
+    contract.deny_recipient(recipient);
+    let storage_usage_after_deny = env::storage_usage();
+    assert!(storage_usage_after_deny < storage_usage_after_allow);
+
+    let storage_deposit = storage_cost(storage_usage_after_allow - storage_usage_before);
+    assert_eq!(
+        crate::impl_fee::refund_transfers(),
+        vec![
+            (
+                "alice.near".to_string(),
+                NearToken::from_yoctonear(
+                    NearToken::from_millinear(10).as_yoctonear() - storage_deposit
+                ),
+            ),
+            ("alice.near".to_string(), NearToken::from_millinear(10)),
+            (
+                "alice.near".to_string(),
+                NearToken::from_yoctonear(storage_cost(
+                    storage_usage_after_allow - storage_usage_after_deny
+                )),
+            ),
+        ],
+    );
+
+    set_attached_deposit(NearToken::from_yoctonear(0));
+    assert!(matches!(
+        contract.allow_recipient(recipient),
+        Err(ContractError::InsufficientStorageDeposit { storage_deposit, shortfall })
+            if storage_deposit > 0 && shortfall == storage_deposit,
+    ));
+}
+
+#[test]
+fn test_sign_rejects_address_mismatch_after_key_rotation() {
+    use ethers_core::k256::ecdsa::SigningKey;
+
+    let mut contract = setup_mpc_contract();
+    let registered = contract
+        .register_address(97.into(), "ethereum-1".to_string())
+        .unwrap();
+
+    near_sdk::testing_env!(near_sdk::test_utils::VMContextBuilder::new()
+        .current_account_id("canhazgas.testnet".parse().unwrap())
+        .predecessor_account_id("canhazgas.testnet".parse().unwrap())
+        .build());
+    let rotated_key = SigningKey::from_bytes(&[0x18; 32].into()).unwrap();
+    contract
+        .set_mpc_key(
+            1,
+            PublicKey::from_parts(
+                near_sdk::CurveType::SECP256K1,
+                rotated_key
+                    .verifying_key()
+                    .to_encoded_point(false)
+                    .as_bytes()[1..]
+                    .to_vec(),
+            )
+            .unwrap()
+            .into(),
+        )
+        .unwrap();
+    set_attached_deposit(NearToken::from_near(1));
+
+    let Err(ContractError::AddressMismatch {
+        chain_id: 97,
+        registered: registered_address,
+        derived,
+    }) = contract.sign(
+        "ethereum-1".to_string(),
+        sample_transaction_input(),
+        None,
+        None,
+    )
+    else {
+        panic!("Expected the derived address not to match");
+    };
+    assert_eq!(registered_address.to_string(), registered);
+    assert_ne!(derived, registered_address);
+
+    // The registered key version still derives the registered address.
+    drop(
+        contract
+            .sign(
+                "ethereum-1".to_string(),
+                sample_transaction_input(),
+                Some(0),
+                None,
+            )
+            .unwrap(),
+    );
+
+    // Registering again acknowledges the new address.
+    assert_eq!(
+        contract
+            .register_address(97.into(), "ethereum-1".to_string())
+            .unwrap(),
+        derived.to_string(),
+    );
+    let mut next = sample_transaction_input();
+    next.value = Some(200.into());
+    drop(
+        contract
+            .sign("ethereum-1".to_string(), next, None, None)
+            .unwrap(),
+    );
+}
+
+#[test]
+fn test_foreign_address_for_unknown_key_version() {
+    let contract = setup_mpc_contract();
+
+    let error = contract
+        .foreign_address_for(
+            "alice.near".parse().unwrap(),
+            "ethereum-1".to_string(),
+            97.into(),
+            Some(5),
+            None,
+        )
+        .unwrap_err();
+    assert!(matches!(
+        &error,
+        ContractError::UnknownKeyVersion {
+            key_version: 5,
+            available,
+        } if available == &[0],
+    ));
+    assert_eq!(
+        error.to_string(),
+        "MPC public key version 5 is not configured (available versions: [0])",
+    );
+}
+
+#[test]
+fn test_derivation_prefix_rotation() {
+    let mut contract = setup_mpc_contract();
+    let alice: AccountId = "alice.near".parse().unwrap();
+
+    assert_eq!(contract.available_derivation_prefix_versions(), [0]);
+    assert_eq!(
+        contract.get_derivation_prefix(None).as_deref(),
+        Some(lib::kdf::EPSILON_DERIVATION_PREFIX),
+    );
+    let original = contract
+        .foreign_address_for(
+            alice.clone(),
+            "ethereum-1".to_string(),
+            97.into(),
+            None,
+            None,
+        )
+        .unwrap();
+
+    near_sdk::testing_env!(near_sdk::test_utils::VMContextBuilder::new()
+        .current_account_id("canhazgas.testnet".parse().unwrap())
+        .predecessor_account_id("canhazgas.testnet".parse().unwrap())
+        .build());
+    contract
+        .set_derivation_prefix(
+            1,
+            "near-mpc-recovery v0.2.0 epsilon derivation:".to_string(),
+        )
+        .unwrap();
+    assert_eq!(contract.available_derivation_prefix_versions(), [0, 1]);
+
+    // The latest prefix is the default.
+    let rotated = contract
+        .foreign_address_for(
+            alice.clone(),
+            "ethereum-1".to_string(),
+            97.into(),
+            None,
+            None,
+        )
+        .unwrap();
+    assert_ne!(rotated, original);
+    assert_eq!(
+        contract
+            .foreign_address_for(
+                alice.clone(),
+                "ethereum-1".to_string(),
+                97.into(),
+                None,
+                Some(1)
+            )
+            .unwrap(),
+        rotated,
+    );
+    // Addresses issued under the old prefix can still be looked up.
+    assert_eq!(
+        contract
+            .foreign_address_for(
+                alice.clone(),
+                "ethereum-1".to_string(),
+                97.into(),
+                None,
+                Some(0)
+            )
+            .unwrap(),
+        original,
+    );
+
+    assert!(matches!(
+        contract.foreign_address_for(alice, "ethereum-1".to_string(), 97.into(), None, Some(2)),
+        Err(ContractError::UnknownPrefixVersion {
+            prefix_version: 2,
+            available,
+        }) if available == [0, 1],
+    ));
+    // Prefixes cannot be replaced.
+    assert!(matches!(
+        contract.set_derivation_prefix(0, "replaced".to_string()),
+        Err(ContractError::PrefixVersionAlreadyConfigured { prefix_version: 0 }),
+    ));
+    assert_eq!(
+        contract.get_derivation_prefix(Some(0)).as_deref(),
+        Some(lib::kdf::EPSILON_DERIVATION_PREFIX),
+    );
+}
+
+#[test]
+fn test_available_key_versions() {
+    let mut contract = crate::setup_contract();
+    let public_key: PublicKey = "secp256k1:47xve2ymatpG4x4Gp7pmYwuLJk7eeRegrFuS4VoW5VV4i3GsBiBY87vkH6UZiiY18NeZnkBzcZzipDbJJ5pmjTcc"
+        .parse()
+        .unwrap();
+
+    assert_eq!(contract.available_key_versions(), Vec::<u32>::new());
+
+    contract.set_mpc_key(3, public_key.clone().into()).unwrap();
+    contract.set_mpc_key(1, public_key.into()).unwrap();
+    assert_eq!(contract.available_key_versions(), vec![1, 3]);
+    assert_eq!(contract.get_latest_mpc_key_version(), Some(3));
+}
+
+#[test]
+fn test_set_mpc_key_cannot_replace() {
+    let mut contract = crate::setup_contract();
+    let public_key: PublicKey = "secp256k1:47xve2ymatpG4x4Gp7pmYwuLJk7eeRegrFuS4VoW5VV4i3GsBiBY87vkH6UZiiY18NeZnkBzcZzipDbJJ5pmjTcc"
+        .parse()
+        .unwrap();
+
+    contract.set_mpc_key(0, public_key.clone().into()).unwrap();
+    assert!(matches!(
+        contract.set_mpc_key(0, public_key.into()),
+        Err(ContractError::KeyVersionAlreadyConfigured { key_version: 0 }),
+    ));
+}
+
+#[test]
+fn test_set_mpc_key_requires_administrator() {
+    let mut contract = setup_mpc_contract();
+
+    assert!(matches!(
+        contract.set_mpc_key(1, contract.get_mpc_public_key(Some(0)).unwrap().into()),
+        Err(ContractError::Unauthorized { account_id, .. }) if account_id == "alice.near",
+    ));
+}
+
+#[test]
+fn test_sign_resubmission_returns_completed_request() {
+    let mut contract = setup_mpc_contract();
+    let (request_id, foreign_address, transaction) = sign_and_get_callback_args(&mut contract);
+
+    assert_eq!(
+        contract
+            .get_request_status(hex::encode(request_id))
+            .unwrap()
+            .map(|record| record.status),
+        Some(RequestStatus::Pending),
+    );
+
+    let response = mpc_signature_response(&transaction);
+    let PromiseOrValue::Value(result) =
+        contract.sign_callback(request_id, foreign_address, transaction, Ok(response))
+    else {
+        panic!("Expected the signed transaction");
+    };
+
+    assert_eq!(
+        contract
+            .get_request_status(hex::encode_prefixed(request_id))
+            .unwrap()
+            .map(|record| record.status),
+        Some(RequestStatus::Completed {
+            raw_transaction: result.raw_tx.clone(),
+            sender: foreign_address,
+        }),
+    );
+
+    // An identical resubmission is not signed again.
+    set_attached_deposit(NearToken::from_yoctonear(0));
+    let receipts_before = near_sdk::test_utils::get_created_receipts().len();
+    let mut resubmission = sample_transaction_input();
+    resubmission.nonce = Some(0.into());
+    let Ok(PromiseOrValue::Value(resubmitted)) =
+        contract.sign("ethereum-1".to_string(), resubmission, None, None)
+    else {
+        panic!("Expected the completed request to be returned");
+    };
+
+    assert_eq!(resubmitted, result);
+    assert_eq!(
+        near_sdk::test_utils::get_created_receipts().len(),
+        receipts_before,
+    );
+    assert_eq!(contract.nonces.peek_nonce(97, foreign_address), 1);
+}
+
+#[test]
+fn test_sign_resubmission_of_pending_request() {
+    let mut contract = setup_mpc_contract();
+    sign_and_get_callback_args(&mut contract);
+
+    let mut resubmission = sample_transaction_input();
+    resubmission.nonce = Some(0.into());
+    assert!(matches!(
+        contract.sign("ethereum-1".to_string(), resubmission, None, None),
+        Err(ContractError::RequestPending),
+    ));
+}
+
+#[test]
+fn test_sign_same_transaction_from_two_paths() {
+    let mut contract = setup_mpc_contract();
+    let (request_id, foreign_address, transaction) = sign_and_get_callback_args(&mut contract);
+
+    // Both foreign addresses are at nonce 0, so the transactions are the same.
+    drop(
+        contract
+            .sign(
+                "ethereum-2".to_string(),
+                sample_transaction_input(),
+                None,
+                None,
+            )
+            .unwrap(),
+    );
+    let (other_request_id, other_foreign_address, other_transaction) = last_sign_callback_args();
+
+    assert_ne!(other_foreign_address, foreign_address);
+    assert_eq!(other_transaction, transaction);
+    assert_ne!(other_request_id, request_id);
+    assert_eq!(
+        contract
+            .get_request_status(hex::encode(other_request_id))
+            .unwrap()
+            .map(|record| record.status),
+        Some(RequestStatus::Pending),
+    );
+}
+
+#[test]
+fn test_sign_different_payload_with_used_nonce() {
+    let mut contract = setup_mpc_contract();
+    sign_and_get_callback_args(&mut contract);
+
+    let mut different = sample_transaction_input();
+    different.nonce = Some(0.into());
+    different.value = Some(200.into());
+
+    // Different parameters are a different request, for which nonce 0 is no
+    // longer available.
+    assert!(matches!(
+        contract.sign("ethereum-1".to_string(), different, None, None),
+        Err(ContractError::NonceUnavailable {
+            expected: 1,
+            actual: 0,
+        }),
+    ));
+}
+
+#[test]
+fn test_sign_defaults_gas_limit_to_intrinsic_gas() {
+    let mut contract = setup_mpc_contract();
+
+    let mut input = sample_transaction_input();
+    input.gas_limit = None;
+    input.data = Some("0x00ff".to_string());
+    drop(
+        contract
+            .sign("ethereum-1".to_string(), input, None, None)
+            .unwrap(),
+    );
+
+    let (_, _, ForeignTransaction::Eip1559(transaction)) = last_sign_callback_args() else {
+        panic!("Expected an EIP-1559 transaction");
+    };
+    assert_eq!(transaction.gas_limit, 21_000 + 4 + 16);
+}
+
+#[test]
+fn test_sign_with_access_list() {
+    use crate::transaction_input::AccessListItem;
+
+    let mut contract = setup_mpc_contract();
+    let item = |address: u8, storage_keys: &[u8]| AccessListItem {
+        address: ForeignAddress([address; 20]),
+        storage_keys: storage_keys
+            .iter()
+            .map(|&storage_key| hex::encode_prefixed([storage_key; 32]))
+            .collect(),
+    };
+
+    let mut input = sample_transaction_input();
+    input.gas_limit = None;
+    input.access_list = Some(vec![item(6, &[]), item(5, &[2, 1])]);
+    drop(
+        contract
+            .sign("ethereum-1".to_string(), input.clone(), None, None)
+            .unwrap(),
+    );
+    let (_, _, transaction) = last_sign_callback_args();
+    let ForeignTransaction::Eip1559(eip1559) = &transaction else {
+        panic!("Expected an EIP-1559 transaction");
+    };
+    assert_eq!(
+        eip1559.access_list,
+        vec![
+            (ForeignAddress([5; 20]), vec![[1; 32], [2; 32]]),
+            (ForeignAddress([6; 20]), vec![]),
+        ],
+    );
+    // The access list is included in the default gas limit.
+    assert_eq!(eip1559.gas_limit, 21_000 + 2 * 2_400 + 2 * 1_900);
+
+    // The same entries in another order are signed with the same hash.
+    input.nonce = Some(1.into());
+    input.access_list = Some(vec![item(5, &[1, 2]), item(6, &[])]);
+    drop(
+        contract
+            .sign("ethereum-1".to_string(), input.clone(), None, None)
+            .unwrap(),
+    );
+    let (_, _, reordered) = last_sign_callback_args();
+    assert_eq!(reordered.signing_hash(), {
+        let mut transaction = transaction.clone();
+        if let ForeignTransaction::Eip1559(eip1559) = &mut transaction {
+            eip1559.nonce = 1;
+        }
+        transaction.signing_hash()
+    });
+
+    input.nonce = Some(2.into());
+    input.access_list = Some(vec![AccessListItem {
+        address: ForeignAddress([5; 20]),
+        storage_keys: vec!["0x01".to_string()],
+    }]);
+    assert!(matches!(
+        contract.sign("ethereum-1".to_string(), input, None, None),
+        Err(ContractError::InvalidInput(_)),
+    ));
+}
+
+#[test]
+fn test_sign_contract_creation() {
+    let mut contract = setup_mpc_contract();
+
+    let mut input = sample_transaction_input();
+    input.to = None;
+    input.gas_limit = None;
+    input.data = Some("0x00ff".to_string());
+    drop(
+        contract
+            .sign("ethereum-1".to_string(), input, None, None)
+            .unwrap(),
+    );
+
+    let (_, _, ForeignTransaction::Eip1559(transaction)) = last_sign_callback_args() else {
+        panic!("Expected an EIP-1559 transaction");
+    };
+    assert_eq!(transaction.to, None);
+    // Including the contract creation surcharge for a single word of initcode.
+    assert_eq!(transaction.gas_limit, 21_000 + 32_000 + 4 + 16 + 2);
+}
+
+#[test]
+fn test_sign_escapes_path() {
+    use near_sdk::{mock::MockAction, test_utils::get_created_receipts};
+
+    let mut contract = setup_mpc_contract();
+    drop(
+        contract
+            .sign(
+                "ethereum-1,97".to_string(),
+                sample_transaction_input(),
+                None,
+                None,
+            )
+            .unwrap(),
+    );
+
+    let mpc_path = get_created_receipts()
+        .iter()
+        .flat_map(|receipt| &receipt.actions)
+        .find_map(|action| match action {
+            MockAction::FunctionCallWeight {
+                method_name, args, ..
+            } if method_name == b"sign" => {
+                let args: near_sdk::serde_json::Value =
+                    near_sdk::serde_json::from_slice(args).unwrap();
+                Some(args["request"]["path"].as_str().unwrap().to_string())
+            }
+            _ => None,
+        })
+        .unwrap();
+
+    // Cannot be confused with the path `97` of an account `alice.near,ethereum-1`.
+    assert_eq!(mpc_path, "alice.near,ethereum-1\\,97");
+}
+
+#[test]
+fn test_sign_namespaced_chain() {
+    use near_sdk::{mock::MockAction, test_utils::get_created_receipts};
+
+    use crate::chain_configuration::ForeignChainOptions;
+
+    let mut contract = setup_mpc_contract();
+    let shared_address = contract
+        .foreign_address_for(
+            "alice.near".parse().unwrap(),
+            "ethereum-1".to_string(),
+            97.into(),
+            None,
+            None,
+        )
+        .unwrap();
+    near_sdk::testing_env!(near_sdk::test_utils::VMContextBuilder::new()
+        .current_account_id("canhazgas.testnet".parse().unwrap())
+        .predecessor_account_id("canhazgas.testnet".parse().unwrap())
+        .build());
+    contract.remove_foreign_chain(97.into());
+    contract.add_foreign_chain(
+        97.into(),
+        "EdVCmQ9FSPcVe5YySXDPCRmc8aDQLKJ9xvYBMZPie1Vw".to_string(),
+        21000.into(),
+        (120.into(), 100.into()),
+        18,
+        Some(ForeignChainOptions {
+            namespaced_derivation: Some(true),
+            ..Default::default()
+        }),
+    );
+    set_attached_deposit(SIGN_STORAGE_DEPOSIT);
+
+    let (_, foreign_address, _) = sign_and_get_callback_args(&mut contract);
+    let namespaced_address = contract
+        .foreign_address_for(
+            "alice.near".parse().unwrap(),
+            "ethereum-1".to_string(),
+            97.into(),
+            None,
+            None,
+        )
+        .unwrap();
+    assert_ne!(namespaced_address, shared_address);
+    assert_eq!(foreign_address.to_string(), namespaced_address);
+
+    // The MPC contract is asked to sign with the namespaced path, as derived
+    // by `derive_epsilon_for_chain`.
+    let mpc_path = get_created_receipts()
+        .iter()
+        .flat_map(|receipt| &receipt.actions)
+        .find_map(|action| match action {
+            MockAction::FunctionCallWeight {
+                method_name, args, ..
+            } if method_name == b"sign" => {
+                let args: near_sdk::serde_json::Value =
+                    near_sdk::serde_json::from_slice(args).unwrap();
+                Some(args["request"]["path"].as_str().unwrap().to_string())
+            }
+            _ => None,
+        })
+        .unwrap();
+    assert_eq!(mpc_path, "alice.near\\,ethereum-1,97");
+}
+
+#[test]
+fn test_sign_errors() {
+    let mut contract = setup_mpc_contract();
+
+    let mut unconfigured_chain = sample_transaction_input();
+    unconfigured_chain.chain_id = 999.into();
+    assert!(matches!(
+        contract.sign("ethereum-1".to_string(), unconfigured_chain, None, None),
+        Err(ContractError::ChainNotConfigured { chain_id: 999 }),
+    ));
+
+    let mut invalid_data = sample_transaction_input();
+    invalid_data.data = Some("not hex".to_string());
+    assert!(matches!(
+        contract.sign("ethereum-1".to_string(), invalid_data, None, None),
+        Err(ContractError::InvalidInput(_)),
+    ));
+
+    // No gas price has been reported for the chain.
+    let mut default_fees = sample_transaction_input();
+    default_fees.max_fee_per_gas = None;
+    assert!(matches!(
+        contract.sign("ethereum-1".to_string(), default_fees, None, None),
+        Err(ContractError::GasPriceUnavailable { chain_id: 97 }),
+    ));
+
+    assert!(matches!(
+        contract.sign(
+            "ethereum-1".to_string(),
+            sample_transaction_input(),
+            Some(5),
+            None,
+        ),
+        Err(ContractError::UnknownKeyVersion { key_version: 5, .. }),
+    ));
+
+    contract.mpc_contract_id = None;
+    assert!(matches!(
+        contract.sign(
+            "ethereum-1".to_string(),
+            sample_transaction_input(),
+            None,
+            None
+        ),
+        Err(ContractError::MpcNotConfigured),
+    ));
+}
+
+#[test]
+fn test_sign_resubmission_after_failure() {
+    let mut contract = setup_mpc_contract();
+    let (request_id, foreign_address, transaction) = sign_and_get_callback_args(&mut contract);
+
+    drop(contract.sign_callback(
+        request_id,
+        foreign_address,
+        transaction,
+        Err(PromiseError::Failed),
+    ));
+    assert!(matches!(
+        contract
+            .get_request_status(hex::encode(request_id))
+            .unwrap()
+            .map(|record| record.status),
+        Some(RequestStatus::Failed { .. }),
+    ));
+
+    // The nonce was released, so the same request is signed again.
+    let (resubmitted_id, _, transaction) = sign_and_get_callback_args(&mut contract);
+    assert_eq!(resubmitted_id, request_id);
+    assert_eq!(transaction.nonce(), 0);
+    assert_eq!(
+        contract
+            .get_request_status(hex::encode(request_id))
+            .unwrap()
+            .map(|record| record.status),
+        Some(RequestStatus::Pending),
+    );
+}
+
+#[test]
+fn test_sign_memo() {
+    let mut contract = setup_mpc_contract();
+
+    drop(
+        contract
+            .sign(
+                "ethereum-1".to_string(),
+                sample_transaction_input(),
+                None,
+                Some("order-42".to_string()),
+            )
+            .unwrap(),
+    );
+    let (request_id, foreign_address, transaction) = last_sign_callback_args();
+
+    assert_eq!(
+        contract
+            .get_request_status(hex::encode(request_id))
+            .unwrap(),
+        Some(SignRequestRecord {
+            status: RequestStatus::Pending,
+            memo: Some("order-42".to_string()),
+            mpc_contract_id: Some("v1.signer-prod.testnet".parse().unwrap()),
+        }),
+    );
+
+    // Kept once the request completes.
+    let response = mpc_signature_response(&transaction);
+    drop(contract.sign_callback(request_id, foreign_address, transaction, Ok(response)));
+    let record = contract
+        .get_request_status(hex::encode(request_id))
+        .unwrap()
+        .unwrap();
+    assert!(matches!(record.status, RequestStatus::Completed { .. }));
+    assert_eq!(record.memo.as_deref(), Some("order-42"));
+}
+
+#[test]
+fn test_sign_memo_storage_deposit() {
+    const STORAGE_RECORD_OVERHEAD: u64 = 40;
+
+    let mut contract = setup_mpc_contract();
+    let memo = "order-42".to_string();
+
+    drop(
+        contract
+            .sign(
+                "ethereum-1".to_string(),
+                sample_transaction_input(),
+                None,
+                None,
+            )
+            .unwrap(),
+    );
+    let (without_memo, _, _) = last_sign_callback_args();
+
+    // The first request from another path, so that its other records are
+    // the same size.
+    drop(
+        contract
+            .sign(
+                "ethereum-2".to_string(),
+                sample_transaction_input(),
+                None,
+                Some(memo.clone()),
+            )
+            .unwrap(),
+    );
+    let (with_memo, _, _) = last_sign_callback_args();
+
+    // The memo's record: its key, with the one-byte prefix of
+    // `sign_request_memos`, its length-prefixed value, and the overhead.
+    let memo_storage_usage = 1 + 32 + 4 + memo.len() as u64 + STORAGE_RECORD_OVERHEAD;
+    assert_eq!(
+        storage_deposit_of(&contract, &with_memo).as_yoctonear(),
+        storage_deposit_of(&contract, &without_memo).as_yoctonear()
+            + storage_cost(memo_storage_usage),
+    );
+}
+
+#[test]
+fn test_sign_memo_too_long() {
+    let mut contract = setup_mpc_contract();
+
+    assert!(matches!(
+        contract.sign(
+            "ethereum-1".to_string(),
+            sample_transaction_input(),
+            None,
+            Some("a".repeat(Contract::MAX_MEMO_LEN + 1)),
+        ),
+        Err(ContractError::MemoTooLong {
+            len: 257,
+            max_len: 256,
+        }),
+    ));
+
+    // The limit itself is accepted.
+    drop(
+        contract
+            .sign(
+                "ethereum-1".to_string(),
+                sample_transaction_input(),
+                None,
+                Some("a".repeat(Contract::MAX_MEMO_LEN)),
+            )
+            .unwrap(),
+    );
+}
+
+#[test]
+fn test_sign_batch_partial_failure() {
+    use near_sdk::{mock::MockAction, test_utils::get_created_receipts};
+
+    let mut contract = setup_mpc_contract();
+    set_attached_deposit(NearToken::from_millinear(100));
+
+    let mut unconfigured_chain = sample_transaction_input();
+    unconfigured_chain.chain_id = 999.into();
+    let batch_id = contract
+        .sign_batch(
+            [
+                sample_transaction_input(),
+                unconfigured_chain,
+                sample_transaction_input(),
+            ]
+            .into_iter()
+            .map(|transaction| BatchSignRequest {
+                path: "ethereum-1".to_string(),
+                transaction,
+                key_version: None,
+                memo: None,
+            })
+            .collect(),
+        )
+        .unwrap();
+
+    // Only the valid requests were sent to the MPC contract.
+    let sign_callbacks = get_created_receipts()
+        .iter()
+        .flat_map(|receipt| &receipt.actions)
+        .filter(|action| {
+            matches!(
+                action,
+                MockAction::FunctionCallWeight { method_name, .. }
+                    if method_name == b"sign_callback"
+            )
+        })
+        .count();
+    assert_eq!(sign_callbacks, 2);
+
+    let items = contract.get_sign_batch(batch_id).unwrap();
+    assert_eq!(items.len(), 3);
+    assert_eq!(items[0].status, RequestStatus::Pending);
+    assert_eq!(
+        items[1],
+        SignBatchItemStatus {
+            request_id: None,
+            status: RequestStatus::Failed {
+                reason: "Configuration for chain ID 999 does not exist".to_string(),
+            },
+        },
+    );
+    assert_eq!(items[2].status, RequestStatus::Pending);
+    assert_ne!(items[0].request_id, items[2].request_id);
+
+    // Both valid requests were assigned consecutive nonces.
+    let foreign_address = contract
+        .foreign_address_for(
+            "alice.near".parse().unwrap(),
+            "ethereum-1".to_string(),
+            97.into(),
+            None,
+            None,
+        )
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert_eq!(contract.nonces.peek_nonce(97, foreign_address), 2);
+
+    // Completing one request does not depend on the others.
+    let (request_id, foreign_address, transaction) = last_sign_callback_args();
+    let response = mpc_signature_response(&transaction);
+    drop(contract.sign_callback(request_id, foreign_address, transaction, Ok(response)));
+
+    let items = contract.get_sign_batch(batch_id).unwrap();
+    assert_eq!(items[0].status, RequestStatus::Pending);
+    assert!(matches!(items[2].status, RequestStatus::Completed { .. }));
+}
+
+#[test]
+fn test_sign_batch_rate_limited_item_is_not_sent() {
+    use near_sdk::{mock::MockAction, test_utils::get_created_receipts};
+
+    let mut contract = setup_mpc_contract();
+    contract.rate_limiter.limit = Some(crate::rate_limiter::RateLimit {
+        max_requests: 1,
+        window_seconds: 60.into(),
+    });
+    set_attached_deposit(NearToken::from_millinear(100));
+
+    let batch_id = contract
+        .sign_batch(
+            (0..2)
+                .map(|_| BatchSignRequest {
+                    path: "ethereum-1".to_string(),
+                    transaction: sample_transaction_input(),
+                    key_version: None,
+                    memo: None,
+                })
+                .collect(),
+        )
+        .unwrap();
+
+    let items = contract.get_sign_batch(batch_id).unwrap();
+    assert_eq!(items[0].status, RequestStatus::Pending);
+    assert_eq!(items[1].request_id, None);
+    assert!(matches!(
+        &items[1].status,
+        RequestStatus::Failed { reason } if reason.contains("Rate limit"),
+    ));
+
+    // The rejected request was not sent to the MPC contract.
+    let mpc_sign_calls = get_created_receipts()
+        .iter()
+        .flat_map(|receipt| &receipt.actions)
+        .filter(|action| {
+            matches!(
+                action,
+                MockAction::FunctionCallWeight { method_name, .. } if method_name == b"sign"
+            )
+        })
+        .count();
+    assert_eq!(mpc_sign_calls, 1);
+}
+
+#[test]
+fn test_sign_batch_record_storage_deposit() {
+    let mut contract = setup_mpc_contract();
+    set_attached_deposit(NearToken::from_yoctonear(0));
+
+    // Nothing is charged for the rejected request, but its record in the
+    // batch still takes up storage.
+    let mut unconfigured_chain = sample_transaction_input();
+    unconfigured_chain.chain_id = 999.into();
+    assert!(matches!(
+        contract.sign_batch(vec![BatchSignRequest {
+            path: "ethereum-1".to_string(),
+            transaction: unconfigured_chain,
+            key_version: None,
+            memo: None,
+        }]),
+        Err(ContractError::InsufficientStorageDeposit { .. }),
+    ));
+
+    assert_eq!(contract.get_sign_batch(U64(u64::MAX)), None);
+}
+
+#[test]
+fn test_sign_batch_too_large() {
+    let mut contract = setup_mpc_contract();
+
+    let result = contract.sign_batch(vec![
+        BatchSignRequest {
+            path: "ethereum-1".to_string(),
+            transaction: sample_transaction_input(),
+            key_version: None,
+            memo: None,
+        };
+        Contract::MAX_SIGN_BATCH_SIZE + 1
+    ]);
+    assert!(matches!(
+        result,
+        Err(ContractError::InvalidInput(message))
+            if message == "Batch cannot contain more than 3 requests",
+    ));
+}
+
+#[test]
+fn test_set_mpc_key() {
+    let contract = setup_mpc_contract();
+
+    let key = contract.get_mpc_key(None).unwrap();
+    assert_eq!(key.version, 0);
+    assert_eq!(key.curve, near_sdk::CurveType::SECP256K1);
+    assert_eq!(Some(key.public_key), contract.get_mpc_public_key(Some(0)));
+    assert_eq!(contract.get_mpc_key(Some(1)), None);
+}
+
+#[test]
+fn test_set_mpc_key_sec1() {
+    use ethers_core::k256::ecdsa::SigningKey;
+
+    let mut contract = crate::setup_contract();
+    let verifying_key = *SigningKey::from_bytes(&[0x18; 32].into())
+        .unwrap()
+        .verifying_key();
+
+    for (key_version, compress) in [(0, true), (1, false)] {
+        let encoded = hex::encode_prefixed(verifying_key.to_encoded_point(compress).as_bytes());
+        contract
+            .set_mpc_key(
+                key_version,
+                near_sdk::serde_json::from_value(near_sdk::serde_json::json!(encoded)).unwrap(),
+            )
+            .unwrap();
+    }
+
+    assert_eq!(
+        contract.get_mpc_public_key(Some(0)),
+        contract.get_mpc_public_key(Some(1)),
+    );
+    assert_eq!(
+        contract.get_mpc_key(Some(0)).unwrap().affine(),
+        Some(*verifying_key.as_affine()),
+    );
+}
+
+#[test]
+fn test_set_mpc_key_rejects_malformed_key() {
+    let mut contract = crate::setup_contract();
+
+    assert!(matches!(
+        contract.set_mpc_key(
+            0,
+            PublicKey::from_parts(near_sdk::CurveType::SECP256K1, vec![1; 64])
+                .unwrap()
+                .into(),
+        ),
+        Err(ContractError::InvalidMpcKey(_)),
+    ));
+    assert_eq!(contract.get_mpc_key(Some(0)), None);
+}
+
+#[test]
+fn test_request_views_reject_invalid_request_id() {
+    let contract = setup_mpc_contract();
+
+    for request_id in ["not hex", "0x1234", &hex::encode([7; 33])] {
+        assert!(matches!(
+            contract.get_request_status(request_id.to_string()),
+            Err(ContractError::InvalidInput(reason))
+                if reason == "Request ID must be 32 hex-encoded bytes",
+        ));
+        assert!(matches!(
+            contract.get_prepaid_fee(request_id.to_string()),
+            Err(ContractError::InvalidInput(reason))
+                if reason == "Request ID must be 32 hex-encoded bytes",
+        ));
+    }
+
+    // A well-formed ID of a request that does not exist is not an error.
+    assert!(contract
+        .get_request_status(hex::encode([7; 32]))
+        .unwrap()
+        .is_none());
+    assert!(contract
+        .get_prepaid_fee(hex::encode([7; 32]))
+        .unwrap()
+        .is_none());
+}
+
+#[test]
+fn test_list_requests_pagination() {
+    let mut contract = setup_mpc_contract();
+
+    let mut request_ids = vec![];
+    for _ in 0..50 {
+        // A fresh context for each call, so that prepaid gas does not run out.
+        near_sdk::testing_env!(near_sdk::test_utils::VMContextBuilder::new()
+            .current_account_id("canhazgas.testnet".parse().unwrap())
+            .predecessor_account_id("alice.near".parse().unwrap())
+            .attached_deposit(SIGN_STORAGE_DEPOSIT)
+            .build());
+        let (request_id, _, _) = sign_and_get_callback_args(&mut contract);
+        request_ids.push(hex::encode_prefixed(request_id));
+    }
+
+    let mut listed = vec![];
+    for from_index in [0, 20, 40] {
+        let page = contract
+            .list_requests(from_index.into(), 20.into())
+            .unwrap();
+        assert_eq!(page.len(), if from_index == 40 { 10 } else { 20 });
+        listed.extend(page);
+    }
+
+    assert_eq!(
+        listed.iter().map(|(id, _)| id.clone()).collect::<Vec<_>>(),
+        request_ids,
+    );
+    assert!(listed
+        .iter()
+        .all(|(_, status)| *status == RequestStatus::Pending));
+
+    assert!(contract
+        .list_requests(50.into(), 20.into())
+        .unwrap()
+        .is_empty());
+    assert!(contract
+        .list_requests(u64::MAX.into(), 20.into())
+        .unwrap()
+        .is_empty());
+    assert_eq!(
+        contract
+            .list_requests(0.into(), u64::MAX.into())
+            .unwrap()
+            .len(),
+        50
+    );
+}
+
+#[test]
+fn test_list_requests_resubmission_is_listed_once() {
+    let mut contract = setup_mpc_contract();
+    let (request_id, foreign_address, transaction) = sign_and_get_callback_args(&mut contract);
+
+    drop(contract.sign_callback(
+        request_id,
+        foreign_address,
+        transaction,
+        Err(PromiseError::Failed),
+    ));
+    sign_and_get_callback_args(&mut contract);
+
+    assert_eq!(
+        contract.list_requests(0.into(), 10.into()).unwrap(),
+        vec![(hex::encode_prefixed(request_id), RequestStatus::Pending)],
+    );
+}
+
+#[test]
+fn test_list_requests_limit_is_capped() {
+    let mut contract = setup_mpc_contract();
+
+    for i in 0..=Contract::MAX_LIST_REQUESTS_LIMIT {
+        let request_id = lib::kdf::sha256(&i.to_le_bytes()).try_into().unwrap();
+        contract
+            .sign_requests
+            .insert(request_id, RequestStatus::Pending);
+        contract.sign_request_ids.push(&request_id);
+    }
+
+    assert_eq!(
+        contract
+            .list_requests(0.into(), u64::MAX.into())
+            .unwrap()
+            .len() as u64,
+        Contract::MAX_LIST_REQUESTS_LIMIT,
+    );
+}
+
+/// Signs at 1000 seconds and moves past the default sign request timeout.
+#[cfg(test)]
+fn sign_and_time_out(contract: &mut Contract) -> (RequestId, ForeignAddress, ForeignTransaction) {
+    set_block_timestamp_seconds(1_000);
+    let callback_args = sign_and_get_callback_args(contract);
+    set_block_timestamp_seconds(1_000 + contract.sign_request_timeout_seconds);
+    callback_args
+}
+
+#[test]
+fn test_cancel_request() {
+    use crate::fee::PrepaidFee;
+
+    let mut contract = setup_mpc_contract();
+    let (request_id, foreign_address, transaction) = sign_and_time_out(&mut contract);
+    contract.prepaid_fees.insert(
+        request_id,
+        PrepaidFee {
+            payer: "alice.near".parse().unwrap(),
+            chain_id: 97.into(),
+            amount: NearToken::from_millinear(500).as_yoctonear().into(),
+            foreign_token_price_in_near: NearToken::from_near(1000).as_yoctonear().into(),
+            foreign_token_decimals: 18,
+            markup_bps: 0,
+            l1_data_fee: 0.into(),
+            settlement: None,
+        },
+    );
+
+    let storage_refund = expected_storage_refund(&mut contract, &request_id, |contract| {
+        assert!(matches!(
+            contract.cancel_request(hex::encode(request_id)),
+            Ok(PromiseOrValue::Promise(_)),
+        ));
+    });
+
+    assert_eq!(
+        contract
+            .get_request_status(hex::encode(request_id))
+            .unwrap()
+            .map(|record| record.status),
+        Some(RequestStatus::Cancelled),
+    );
+    // The prepaid fee is refunded by the returned promise.
+    assert_eq!(
+        crate::impl_fee::refund_transfers(),
+        vec![
+            ("alice.near".to_string(), storage_refund),
+            ("alice.near".to_string(), NearToken::from_millinear(500)),
+        ],
+    );
+    assert_eq!(contract.nonces.peek_nonce(97, foreign_address), 0);
+    assert_eq!(
+        event_logs().last().unwrap(),
+        &near_sdk::serde_json::json!({
+            "standard": "x-gas-station",
+            "version": "0.1.0",
+            "event": "sign_cancelled",
+            "data": {
+                "request_id": hex::encode_prefixed(request_id),
+                "nonce_released": true,
+            },
+        }),
+    );
+
+    // A late signature does not complete the request.
+    let response = mpc_signature_response(&transaction);
+    drop(contract.sign_callback(request_id, foreign_address, transaction, Ok(response)));
+    assert_eq!(
+        contract
+            .get_request_status(hex::encode(request_id))
+            .unwrap()
+            .map(|record| record.status),
+        Some(RequestStatus::Cancelled),
+    );
+
+    // The released nonce is used by the next request.
+    let (_, _, transaction) = sign_and_get_callback_args(&mut contract);
+    assert_eq!(transaction.nonce(), 0);
+}
+
+#[test]
+fn test_sign_while_paused() {
+    let mut contract = setup_mpc_contract();
+    let (request_id, _, _) = sign_and_time_out(&mut contract);
+    let as_owner = |contract: &mut Contract, f: fn(&mut Contract)| {
+        near_sdk::testing_env!(near_sdk::test_utils::VMContextBuilder::new()
+            .current_account_id("canhazgas.testnet".parse().unwrap())
+            .predecessor_account_id("canhazgas.testnet".parse().unwrap())
+            .build());
+        f(contract);
+        set_block_timestamp_seconds(1_000 + contract.sign_request_timeout_seconds);
+    };
+
+    as_owner(&mut contract, Contract::pause);
+    assert!(contract.is_paused());
+
+    let mut other = sample_transaction_input();
+    other.value = Some(200.into());
+    assert!(matches!(
+        contract.sign("ethereum-1".to_string(), other.clone(), None, None),
+        Err(ContractError::Paused),
+    ));
+    assert!(matches!(
+        contract.sign_batch(vec![BatchSignRequest {
+            path: "ethereum-1".to_string(),
+            transaction: other.clone(),
+            key_version: None,
+            memo: None,
+        }]),
+        Err(ContractError::Paused),
+    ));
+    assert!(matches!(
+        contract.sign_message("ethereum-1".to_string(), hex::encode(b"Hello"), None),
+        Err(ContractError::Paused),
+    ));
+
+    // Callers can still recover from pending requests.
+    assert!(contract
+        .get_request_status(hex::encode(request_id))
+        .unwrap()
+        .is_some());
+    contract.cancel_request(hex::encode(request_id)).unwrap();
+
+    as_owner(&mut contract, Contract::unpause);
+    assert!(!contract.is_paused());
+    drop(
+        contract
+            .sign("ethereum-1".to_string(), other, None, None)
+            .unwrap(),
+    );
+}
+
+#[test]
+fn test_set_sign_request_timeout_seconds() {
+    let mut contract = crate::setup_contract();
+
+    contract.set_sign_request_timeout_seconds(Contract::MIN_SIGN_REQUEST_TIMEOUT_SECONDS.into());
+    assert_eq!(
+        contract.get_sign_request_timeout_seconds(),
+        Contract::MIN_SIGN_REQUEST_TIMEOUT_SECONDS.into(),
+    );
+}
+
+#[test]
+#[should_panic = "Sign request timeout must be at least 300 seconds"]
+fn test_set_sign_request_timeout_seconds_within_mpc_timeout() {
+    let mut contract = crate::setup_contract();
+
+    contract
+        .set_sign_request_timeout_seconds((Contract::MIN_SIGN_REQUEST_TIMEOUT_SECONDS - 1).into());
+}
+
+#[test]
+fn test_cancel_request_before_timeout() {
+    let mut contract = setup_mpc_contract();
+    let (request_id, _, _) = sign_and_time_out(&mut contract);
+    set_block_timestamp_seconds(1_000 + contract.sign_request_timeout_seconds - 1);
+
+    assert!(matches!(
+        contract.cancel_request(hex::encode(request_id)),
+        Err(ContractError::RequestNotTimedOut {
+            timeout_seconds: 600
+        }),
+    ));
+    assert_eq!(
+        contract
+            .get_request_status(hex::encode(request_id))
+            .unwrap()
+            .map(|record| record.status),
+        Some(RequestStatus::Pending),
+    );
+}
+
+#[test]
+fn test_cancel_request_twice() {
+    let mut contract = setup_mpc_contract();
+    let (request_id, _, _) = sign_and_time_out(&mut contract);
+
+    assert!(matches!(
+        contract.cancel_request(hex::encode(request_id)),
+        Ok(PromiseOrValue::Value(())),
+    ));
+    assert!(matches!(
+        contract.cancel_request(hex::encode(request_id)),
+        Err(ContractError::RequestNotPending),
+    ));
+}
+
+#[test]
+fn test_cancel_completed_request() {
+    let mut contract = setup_mpc_contract();
+    let (request_id, foreign_address, transaction) = sign_and_time_out(&mut contract);
+    let response = mpc_signature_response(&transaction);
+    drop(contract.sign_callback(request_id, foreign_address, transaction, Ok(response)));
+
+    assert!(matches!(
+        contract.cancel_request(hex::encode(request_id)),
+        Err(ContractError::RequestNotPending),
+    ));
+    assert!(matches!(
+        contract.cancel_request(hex::encode([0; 32])),
+        Err(ContractError::RequestNotFound),
+    ));
+}
+
+#[test]
+fn test_cancel_request_by_other_account() {
+    let mut contract = setup_mpc_contract();
+    let (request_id, _, _) = sign_and_time_out(&mut contract);
+
+    near_sdk::testing_env!(near_sdk::test_utils::VMContextBuilder::new()
+        .current_account_id("canhazgas.testnet".parse().unwrap())
+        .predecessor_account_id("mallory.near".parse().unwrap())
+        .block_timestamp(2_000 * 1_000_000_000)
+        .build());
+    assert!(matches!(
+        contract.cancel_request(hex::encode(request_id)),
+        Err(ContractError::Unauthorized { account_id, .. }) if account_id == "mallory.near",
+    ));
+}
+
+#[test]
+fn test_cancel_request_burns_nonce() {
+    let mut contract = setup_mpc_contract();
+    contract.cancelled_nonce_policy = CancelledNoncePolicy::Burn;
+    let (request_id, foreign_address, _) = sign_and_time_out(&mut contract);
+
+    drop(contract.cancel_request(hex::encode(request_id)).unwrap());
+
+    assert_eq!(contract.nonces.peek_nonce(97, foreign_address), 1);
+    let (_, _, transaction) = sign_and_get_callback_args(&mut contract);
+    assert_eq!(transaction.nonce(), 1);
+}
+
+/// The payload of the last signature requested from the MPC contract,
+/// which must be followed by `sign_message_callback`.
+#[cfg(test)]
+fn last_mpc_sign_payload() -> [u8; 32] {
+    use near_sdk::{mock::MockAction, test_utils::get_created_receipts};
+
+    let receipts = get_created_receipts();
+    assert!(receipts
+        .iter()
+        .flat_map(|receipt| &receipt.actions)
+        .any(|action| matches!(
+            action,
+            MockAction::FunctionCallWeight { method_name, .. }
+                if method_name == b"sign_message_callback",
+        )));
+
+    receipts
+        .iter()
+        .flat_map(|receipt| &receipt.actions)
+        .rev()
+        .find_map(|action| match action {
+            MockAction::FunctionCallWeight {
+                method_name, args, ..
+            } if method_name == b"sign" => Some(
+                near_sdk::serde_json::from_value(
+                    near_sdk::serde_json::from_slice::<near_sdk::serde_json::Value>(args).unwrap()
+                        ["request"]["payload"]
+                        .clone(),
+                )
+                .unwrap(),
+            ),
+            _ => None,
+        })
+        .unwrap()
+}
+
+#[test]
+fn test_sign_message() {
+    let mut contract = setup_mpc_contract();
+    let message_hash = personal_sign_hash(b"Hello World");
+
+    drop(
+        contract
+            .sign_message("ethereum-1".to_string(), hex::encode(b"Hello World"), None)
+            .unwrap(),
+    );
+
+    assert_eq!(last_mpc_sign_payload(), message_hash);
+
+    let signature = contract
+        .sign_message_callback(Ok(mpc_signature_response_for_payload(&message_hash)))
+        .unwrap();
+    let signature = Signature::try_from(&hex::decode(signature).unwrap()[..]).unwrap();
+    assert!(matches!(signature.v, 27 | 28));
+
+    // Recovers to the derived address, like a MetaMask signature would.
+    assert_eq!(
+        signature
+            .recover(ethers_core::types::RecoveryMessage::Data(
+                b"Hello World".to_vec()
+            ))
+            .unwrap()
+            .0,
+        contract
+            .try_foreign_address_for_mpc_path("alice.near,ethereum-1", 0)
+            .unwrap()
+            .0,
+    );
+}
+
+#[test]
+fn test_sign_message_errors() {
+    let mut contract = setup_mpc_contract();
+
+    assert!(matches!(
+        contract.sign_message("ethereum-1".to_string(), "not hex".to_string(), None),
+        Err(ContractError::InvalidInput(_)),
+    ));
+    assert!(matches!(
+        contract.sign_message("ethereum-1".to_string(), String::new(), Some(5)),
+        Err(ContractError::UnknownKeyVersion { key_version: 5, .. }),
+    ));
+    assert!(matches!(
+        contract.sign_message_callback(Err(PromiseError::Failed)),
+        Err(ContractError::MpcUnavailable),
+    ));
+
+    // The caller pays for the MPC deposit.
+    set_attached_deposit(NearToken::from_yoctonear(0));
+    assert!(matches!(
+        contract.sign_message("ethereum-1".to_string(), String::new(), None),
+        Err(ContractError::InsufficientDeposit {
+            fee: 1,
+            shortfall: 1,
+        }),
+    ));
+    assert!(matches!(
+        contract.sign_typed_data(
+            "ethereum-1".to_string(),
+            Eip712Domain {
+                name: None,
+                version: None,
+                chain_id: None,
+                verifying_contract: None,
+                salt: None,
+            },
+            hex::encode([7; 32]),
+            None,
+        ),
+        Err(ContractError::InsufficientDeposit {
+            fee: 1,
+            shortfall: 1,
+        }),
+    ));
+}
+
+#[test]
+fn test_sign_message_refunds_excess_deposit() {
+    let mut contract = setup_mpc_contract();
+    set_attached_deposit(NearToken::from_millinear(1));
+
+    drop(
+        contract
+            .sign_message("ethereum-1".to_string(), hex::encode(b"Hello World"), None)
+            .unwrap(),
+    );
+
+    assert_eq!(
+        crate::impl_fee::refund_transfers(),
+        [(
+            "alice.near".to_string(),
+            NearToken::from_millinear(1).saturating_sub(Contract::MPC_SIGN_DEPOSIT),
+        )],
+    );
+}
+
+#[test]
+fn test_sign_typed_data() {
+    use lib::eip712::domain_separator;
+
+    let mut contract = setup_mpc_contract();
+    let domain = Eip712Domain {
+        name: Some("USD Coin".to_string()),
+        version: Some("2".to_string()),
+        chain_id: Some(1.into()),
+        verifying_contract: Some(ForeignAddress([0xa0; 20])),
+        salt: None,
+    };
+    let struct_hash = [7; 32];
+
+    drop(
+        contract
+            .sign_typed_data(
+                "ethereum-1".to_string(),
+                domain.clone(),
+                hex::encode(struct_hash),
+                None,
+            )
+            .unwrap(),
+    );
+    let digest = typed_data_hash(&domain, &struct_hash);
+    assert_eq!(last_mpc_sign_payload(), digest,);
+
+    let signature = contract
+        .sign_message_callback(Ok(mpc_signature_response_for_payload(&digest)))
+        .unwrap();
+    let signature = Signature::try_from(&hex::decode(signature).unwrap()[..]).unwrap();
+    let mut preimage = vec![0x19, 0x01];
+    preimage.extend_from_slice(&domain_separator(&domain));
+    preimage.extend_from_slice(&struct_hash);
+    assert_eq!(
+        signature
+            .recover(ethers_core::types::H256(keccak256(&preimage)))
+            .unwrap()
+            .0,
+        contract
+            .try_foreign_address_for_mpc_path("alice.near,ethereum-1", 0)
+            .unwrap()
+            .0,
+    );
+
+    assert!(matches!(
+        contract.sign_typed_data("ethereum-1".to_string(), domain, hex::encode([7; 31]), None,),
+        Err(ContractError::InvalidInput(_)),
+    ));
+}
+
+#[test]
+fn test_sign_gas_limit_bounds() {
+    let mut contract = setup_mpc_contract();
+    let with_gas_limit = |gas_limit: u128| TransactionInput {
+        gas_limit: Some(gas_limit.into()),
+        ..sample_transaction_input()
+    };
+
+    assert!(matches!(
+        contract.sign("ethereum-1".to_string(), with_gas_limit(20_999), None, None),
+        Err(ContractError::GasLimitOutOfRange {
+            gas_limit: 20_999,
+            min: 21_000,
+            max: 30_000_000,
+        }),
+    ));
+    assert!(matches!(
+        contract.sign(
+            "ethereum-1".to_string(),
+            with_gas_limit(30_000_001),
+            None,
+            None
+        ),
+        Err(ContractError::GasLimitOutOfRange {
+            gas_limit: 30_000_001,
+            ..
+        }),
+    ));
+
+    near_sdk::testing_env!(near_sdk::test_utils::VMContextBuilder::new()
+        .current_account_id("canhazgas.testnet".parse().unwrap())
+        .predecessor_account_id("canhazgas.testnet".parse().unwrap())
+        .build());
+    contract.set_foreign_chain_gas_limit_bounds(
+        97.into(),
+        Some(50_000.into()),
+        Some(100_000.into()),
+    );
+    set_attached_deposit(SIGN_STORAGE_DEPOSIT);
+
+    assert!(matches!(
+        contract.sign("ethereum-1".to_string(), with_gas_limit(21_000), None, None),
+        Err(ContractError::GasLimitOutOfRange {
+            gas_limit: 21_000,
+            min: 50_000,
+            max: 100_000,
+        }),
+    ));
+    assert!(contract
+        .sign(
+            "ethereum-1".to_string(),
+            with_gas_limit(100_000),
+            None,
+            None
+        )
+        .is_ok());
+}
+
+#[test]
+fn test_sign_callback_sender_mismatch() {
+    use ethers_core::k256::ecdsa::SigningKey;
+    use lib::signer::{AffnPnt, Sclr, SignResult};
+
+    let mut contract = setup_mpc_contract();
+    let (request_id, foreign_address, transaction) = sign_and_get_callback_args(&mut contract);
+
+    // Signed by a key other than the one derived for the request.
+    let (signature, recovery_id) = SigningKey::from_bytes(&[0x42; 32].into())
+        .unwrap()
+        .sign_prehash_recoverable(&transaction.signing_hash())
+        .unwrap();
+    let sign_result = SignResult::from_ecdsa_signature(signature, recovery_id).unwrap();
+    let response = SignatureResponse {
+        big_r: AffnPnt {
+            affine_point: sign_result.big_r_hex,
+        },
+        s: Sclr {
+            scalar: sign_result.s_hex,
+        },
+        recovery_id: recovery_id.to_byte(),
+    };
+
+    let PromiseOrValue::Promise(_) =
+        contract.sign_callback(request_id, foreign_address, transaction, Ok(response))
+    else {
+        panic!("Expected the callback to fail");
+    };
+
+    let Some(RequestStatus::Failed { reason }) = contract
+        .get_request_status(hex::encode_prefixed(request_id))
+        .unwrap()
+        .map(|record| record.status)
+    else {
+        panic!("Expected the request to fail");
+    };
+    assert!(reason.contains(&format!("not to the derived address {foreign_address}")));
+    assert_eq!(contract.nonces.peek_nonce(97, foreign_address), 0);
+}
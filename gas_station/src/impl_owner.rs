@@ -0,0 +1,230 @@
+use lib::Rejectable;
+use near_sdk::{env, near_bindgen, AccountId};
+use near_sdk_contract_tools::{
+    owner::{Owner, OwnerInternal},
+    rbac::Rbac,
+};
+
+use crate::{error::ContractError, Contract, ContractExt, Role};
+
+impl OwnerInternal for Contract {}
+
+#[near_bindgen]
+impl Contract {
+    pub fn get_owner(&self) -> Option<AccountId> {
+        Self::slot_owner().read()
+    }
+
+    pub fn get_proposed_owner(&self) -> Option<AccountId> {
+        Self::slot_proposed_owner().read()
+    }
+
+    /// Propose `new_owner` as the next owner of the contract. Ownership is
+    /// not transferred until `new_owner` calls `accept_ownership`, so a
+    /// mistyped account ID can be corrected by proposing again.
+    pub fn propose_owner(&mut self, new_owner: AccountId) {
+        Owner::propose_owner(self, Some(new_owner));
+    }
+
+    pub fn cancel_owner_proposal(&mut self) {
+        Owner::propose_owner(self, None);
+    }
+
+    /// Accept a pending ownership proposal. Can only be called by the
+    /// proposed owner.
+    pub fn accept_ownership(&mut self) {
+        Owner::accept_owner(self);
+    }
+}
+
+impl Contract {
+    /// The owner is implicitly an administrator.
+    pub(crate) fn is_administrator(account_id: &AccountId) -> bool {
+        Self::slot_owner().read().as_ref() == Some(account_id)
+            || <Self as Rbac>::has_role(account_id, &Role::Administrator)
+    }
+
+    pub(crate) fn require_administrator() {
+        Self::check_administrator().unwrap_or_reject();
+    }
+
+    pub(crate) fn check_administrator() -> Result<(), ContractError> {
+        let predecessor = env::predecessor_account_id();
+        if Self::is_administrator(&predecessor) {
+            Ok(())
+        } else {
+            Err(ContractError::Unauthorized {
+                account_id: predecessor,
+                required: "owner or administrator",
+            })
+        }
+    }
+
+    /// The owner may also act as a relayer, e.g. to update the gas price
+    /// oracle, but other administrators may not.
+    pub(crate) fn require_relayer() {
+        Self::check_relayer().unwrap_or_reject();
+    }
+
+    pub(crate) fn check_relayer() -> Result<(), ContractError> {
+        let predecessor = env::predecessor_account_id();
+        if <Self as Rbac>::has_role(&predecessor, &Role::Relayer)
+            || Self::slot_owner().read().as_ref() == Some(&predecessor)
+        {
+            Ok(())
+        } else {
+            Err(ContractError::Unauthorized {
+                account_id: predecessor,
+                required: "owner or relayer",
+            })
+        }
+    }
+}
+
+#[test]
+fn test_two_step_ownership_transfer() {
+    use near_sdk::{test_utils::VMContextBuilder, testing_env};
+
+    let mut contract = crate::setup_contract();
+    let alice: AccountId = "alice.near".parse().unwrap();
+
+    assert_eq!(
+        contract.get_owner(),
+        Some("canhazgas.testnet".parse().unwrap())
+    );
+
+    contract.propose_owner(alice.clone());
+    assert_eq!(contract.get_proposed_owner(), Some(alice.clone()));
+    // Not transferred until accepted.
+    assert_eq!(
+        contract.get_owner(),
+        Some("canhazgas.testnet".parse().unwrap())
+    );
+
+    testing_env!(VMContextBuilder::new()
+        .current_account_id("canhazgas.testnet".parse().unwrap())
+        .predecessor_account_id(alice.clone())
+        .build());
+    contract.accept_ownership();
+
+    assert_eq!(contract.get_owner(), Some(alice.clone()));
+    assert_eq!(contract.get_proposed_owner(), None);
+
+    // The new owner can administer the contract.
+    contract.set_fee_markup_bps(100);
+    assert_eq!(contract.get_fee_markup_bps(), 100);
+}
+
+#[test]
+#[should_panic = "Proposed owner only"]
+fn test_accept_ownership_by_non_proposed_account() {
+    use near_sdk::{test_utils::VMContextBuilder, testing_env};
+
+    let mut contract = crate::setup_contract();
+    contract.propose_owner("alice.near".parse().unwrap());
+
+    testing_env!(VMContextBuilder::new()
+        .current_account_id("canhazgas.testnet".parse().unwrap())
+        .predecessor_account_id("mallory.near".parse().unwrap())
+        .build());
+    contract.accept_ownership();
+}
+
+#[test]
+#[should_panic = "Owner only"]
+fn test_propose_owner_by_non_owner() {
+    use near_sdk::{test_utils::VMContextBuilder, testing_env};
+
+    let mut contract = crate::setup_contract();
+
+    testing_env!(VMContextBuilder::new()
+        .current_account_id("canhazgas.testnet".parse().unwrap())
+        .predecessor_account_id("mallory.near".parse().unwrap())
+        .build());
+    contract.propose_owner("mallory.near".parse().unwrap());
+}
+
+#[test]
+#[should_panic = "Owner only"]
+fn test_admin_method_by_non_owner() {
+    use near_sdk::{test_utils::VMContextBuilder, testing_env};
+
+    let mut contract = crate::setup_contract();
+
+    testing_env!(VMContextBuilder::new()
+        .current_account_id("canhazgas.testnet".parse().unwrap())
+        .predecessor_account_id("mallory.near".parse().unwrap())
+        .build());
+    contract.set_foreign_chain_transfer_gas(97.into(), 21000.into());
+}
+
+#[test]
+#[should_panic = "Can only be called by owner or administrator"]
+fn test_previous_owner_loses_access() {
+    use near_sdk::{test_utils::VMContextBuilder, testing_env};
+
+    let mut contract = crate::setup_contract();
+    let alice: AccountId = "alice.near".parse().unwrap();
+
+    // The deployer is the initial owner, but not otherwise an administrator.
+    contract.propose_owner(alice.clone());
+    testing_env!(VMContextBuilder::new()
+        .current_account_id("canhazgas.testnet".parse().unwrap())
+        .predecessor_account_id(alice)
+        .build());
+    contract.accept_ownership();
+
+    testing_env!(VMContextBuilder::new()
+        .current_account_id("canhazgas.testnet".parse().unwrap())
+        .predecessor_account_id("canhazgas.testnet".parse().unwrap())
+        .build());
+    contract.set_fee_markup_bps(100);
+}
+
+#[test]
+#[should_panic = "Owner only"]
+fn test_add_administrator_requires_owner() {
+    use near_sdk::{test_utils::VMContextBuilder, testing_env};
+
+    let mut contract = crate::setup_contract();
+    contract.add_administrator("alice.near".parse().unwrap());
+
+    // Administrators cannot appoint other administrators.
+    testing_env!(VMContextBuilder::new()
+        .current_account_id("canhazgas.testnet".parse().unwrap())
+        .predecessor_account_id("alice.near".parse().unwrap())
+        .build());
+    contract.add_administrator("mallory.near".parse().unwrap());
+}
+
+#[test]
+#[should_panic = "Owner only"]
+fn test_chain_config_by_administrator() {
+    use near_sdk::{test_utils::VMContextBuilder, testing_env};
+
+    let mut contract = crate::setup_contract();
+    contract.add_administrator("alice.near".parse().unwrap());
+
+    // Administrators cannot configure foreign chains.
+    testing_env!(VMContextBuilder::new()
+        .current_account_id("canhazgas.testnet".parse().unwrap())
+        .predecessor_account_id("alice.near".parse().unwrap())
+        .build());
+    contract.set_foreign_chain_transfer_gas(97.into(), 21000.into());
+}
+
+#[test]
+#[should_panic = "Can only be called by owner or relayer, not alice.near"]
+fn test_oracle_update_by_administrator() {
+    use near_sdk::{test_utils::VMContextBuilder, testing_env};
+
+    let mut contract = crate::setup_contract();
+    contract.add_administrator("alice.near".parse().unwrap());
+
+    // Nor update the gas price oracle, unless they are also relayers.
+    testing_env!(VMContextBuilder::new()
+        .current_account_id("canhazgas.testnet".parse().unwrap())
+        .predecessor_account_id("alice.near".parse().unwrap())
+        .build());
+    contract.update_foreign_token_price(97.into(), 1.into());
+}
@@ -0,0 +1,457 @@
+use ethers_core::utils::hex;
+use lib::{foreign_address::ForeignAddress, hash::to_hex_prefixed, Rejectable};
+use near_sdk::{env, json_types::U64, near_bindgen};
+use near_sdk_contract_tools::standard::nep297::Event;
+
+use crate::{
+    contract_event::{ContractEvent, SimulationFulfilled, SimulationRequested},
+    error::ContractError,
+    simulation::{Simulation, SimulationResult},
+    Contract, ContractExt,
+};
+
+#[near_bindgen]
+impl Contract {
+    /// Longest calldata or return data (in bytes) of a simulated call.
+    pub const MAX_SIMULATION_DATA_LEN: usize = 16 * 1024;
+    /// Longest error message that a relayer may report for a simulated call.
+    pub const MAX_SIMULATION_ERROR_LEN: usize = 1024;
+
+    /// Request a read-only `eth_call` of `to` with the hex-encoded `data`,
+    /// optionally as `from`, on chain `chain_id`, to check that a call would
+    /// succeed before paying to sign it. A relayer sends the request emitted
+    /// in [`SimulationRequested`] to the chain and reports the result, which
+    /// `get_simulation` then returns. Returns the request ID.
+    ///
+    /// The attached deposit must cover the storage of the simulation,
+    /// including the largest result that may be reported, and the rest is
+    /// refunded. The storage deposit is refunded by `take_simulation`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the chain is not configured, if `data` is not
+    /// valid hex or is longer than [`Contract::MAX_SIMULATION_DATA_LEN`], or
+    /// if the attached deposit does not cover the storage deposit.
+    #[payable]
+    #[handle_result]
+    pub fn simulate_call(
+        &mut self,
+        chain_id: U64,
+        to: ForeignAddress,
+        data: String,
+        from: Option<ForeignAddress>,
+    ) -> Result<U64, ContractError> {
+        self.get_chain(chain_id.0)?;
+        let data = decode_simulation_data("data", &data)?;
+
+        let request_id = self.generate_unique_id();
+        let simulation = Simulation {
+            caller: env::predecessor_account_id(),
+            chain_id,
+            from,
+            to,
+            data: to_hex_prefixed(&data),
+            requested_at_block: env::block_height().into(),
+            result: None,
+            storage_deposit: 0.into(),
+        };
+
+        ContractEvent::SimulationRequested(SimulationRequested {
+            request_id: request_id.into(),
+            caller: simulation.caller.clone(),
+            chain_id,
+            rpc_request: simulation.eth_call_request(request_id),
+        })
+        .emit();
+
+        let storage_usage_before = env::storage_usage();
+        self.simulations.insert(request_id, simulation);
+        self.simulations.flush();
+        let storage_usage = env::storage_usage() - storage_usage_before;
+        let storage_deposit = Self::try_charge_storage(storage_usage + max_result_storage_usage())?;
+        if let Some(simulation) = self.simulations.get_mut(&request_id) {
+            simulation.storage_deposit = storage_deposit.into();
+        }
+
+        Ok(request_id.into())
+    }
+
+    pub fn get_simulation(&self, request_id: U64) -> Option<&Simulation> {
+        self.simulations.get(&request_id.0)
+    }
+
+    /// Remove the simulation `request_id`, fulfilled or not, and return it.
+    /// Its storage deposit is refunded. Can only be called by the caller of
+    /// `simulate_call`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the simulation does not exist, or if the
+    /// predecessor did not request it.
+    #[handle_result]
+    pub fn take_simulation(&mut self, request_id: U64) -> Result<Simulation, ContractError> {
+        let simulation = self
+            .simulations
+            .get(&request_id.0)
+            .ok_or(ContractError::SimulationNotFound)?;
+        let predecessor = env::predecessor_account_id();
+        if predecessor != simulation.caller {
+            return Err(ContractError::Unauthorized {
+                account_id: predecessor,
+                required: "the account that requested the simulation",
+            });
+        }
+
+        let simulation = self.simulations.remove(&request_id.0).unwrap_or_reject(); // checked above
+        Self::refund_deposit(simulation.caller.clone(), simulation.storage_deposit.0);
+
+        Ok(simulation)
+    }
+
+    /// Report the result of the simulation `request_id`, as returned by the
+    /// foreign chain. Can only be called by a relayer, once per simulation.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the simulation does not exist or has already been
+    /// fulfilled, or if `result` holds invalid or overlong return data or an
+    /// overlong error message.
+    #[handle_result]
+    pub fn fulfill_simulation(
+        &mut self,
+        request_id: U64,
+        result: SimulationResult,
+    ) -> Result<(), ContractError> {
+        Self::check_relayer()?;
+
+        let simulation = self
+            .simulations
+            .get_mut(&request_id.0)
+            .ok_or(ContractError::SimulationNotFound)?;
+        if simulation.result.is_some() {
+            return Err(ContractError::SimulationAlreadyFulfilled);
+        }
+
+        let result = match result {
+            SimulationResult::Success { return_data } => SimulationResult::Success {
+                return_data: to_hex_prefixed(&decode_simulation_data("return_data", &return_data)?),
+            },
+            SimulationResult::Failure { error } => {
+                if error.len() > Self::MAX_SIMULATION_ERROR_LEN {
+                    return Err(ContractError::InvalidInput(format!(
+                        "`error` cannot be longer than {} bytes",
+                        Self::MAX_SIMULATION_ERROR_LEN,
+                    )));
+                }
+                SimulationResult::Failure { error }
+            }
+        };
+
+        ContractEvent::SimulationFulfilled(SimulationFulfilled {
+            request_id,
+            success: matches!(result, SimulationResult::Success { .. }),
+        })
+        .emit();
+
+        simulation.result = Some(result);
+
+        Ok(())
+    }
+}
+
+/// The most that reporting a result can add to the storage of a simulation:
+/// the hex-encoded return data of [`Contract::MAX_SIMULATION_DATA_LEN`]
+/// bytes, which is longer than any error message.
+fn max_result_storage_usage() -> u64 {
+    // The variant, and the length and `0x` prefix of the string.
+    (1 + 4 + 2 + 2 * Contract::MAX_SIMULATION_DATA_LEN) as u64
+}
+
+fn decode_simulation_data(name: &str, data: &str) -> Result<Vec<u8>, ContractError> {
+    let data = hex::decode(data)
+        .map_err(|e| ContractError::InvalidInput(format!("Error decoding `{name}` as hex: {e}")))?;
+    if data.len() > Contract::MAX_SIMULATION_DATA_LEN {
+        return Err(ContractError::InvalidInput(format!(
+            "`{name}` cannot be longer than {} bytes",
+            Contract::MAX_SIMULATION_DATA_LEN,
+        )));
+    }
+    Ok(data)
+}
+
+/// Covers the storage deposit of a simulation, the rest of which is refunded.
+#[cfg(test)]
+const SIMULATION_STORAGE_DEPOSIT: near_sdk::NearToken = near_sdk::NearToken::from_millinear(400);
+
+#[cfg(test)]
+fn setup_simulation_contract() -> Contract {
+    let mut contract = crate::setup_contract();
+    contract.add_foreign_chain(
+        97.into(),
+        "EdVCmQ9FSPcVe5YySXDPCRmc8aDQLKJ9xvYBMZPie1Vw".to_string(),
+        21000.into(),
+        (120.into(), 100.into()),
+        18,
+        None,
+    );
+    contract.add_relayer("relayer.near".parse().unwrap());
+
+    set_predecessor("alice.near");
+
+    contract
+}
+
+#[cfg(test)]
+fn set_predecessor(account_id: &str) {
+    near_sdk::testing_env!(near_sdk::test_utils::VMContextBuilder::new()
+        .current_account_id("canhazgas.testnet".parse().unwrap())
+        .predecessor_account_id(account_id.parse().unwrap())
+        .attached_deposit(SIMULATION_STORAGE_DEPOSIT)
+        .build());
+}
+
+#[test]
+fn test_simulate_call() {
+    let mut contract = setup_simulation_contract();
+    let to: ForeignAddress = "0x0505050505050505050505050505050505050505"
+        .parse()
+        .unwrap();
+
+    let request_id = contract
+        .simulate_call(97.into(), to, "0x70A08231".to_string(), None)
+        .unwrap();
+
+    let simulation = contract.get_simulation(request_id).unwrap();
+    assert_eq!(
+        simulation.caller,
+        "alice.near".parse::<near_sdk::AccountId>().unwrap()
+    );
+    assert_eq!(simulation.to, to);
+    assert_eq!(simulation.data, "0x70a08231");
+    assert_eq!(simulation.result, None);
+
+    assert_eq!(
+        crate::impl_mpc::event_logs().last().unwrap(),
+        &near_sdk::serde_json::json!({
+            "standard": "x-gas-station",
+            "version": "0.1.0",
+            "event": "simulation_requested",
+            "data": {
+                "request_id": request_id.0.to_string(),
+                "caller": "alice.near",
+                "chain_id": "97",
+                "rpc_request": {
+                    "jsonrpc": "2.0",
+                    "id": request_id.0,
+                    "method": "eth_call",
+                    "params": [
+                        {
+                            "to": "0x0505050505050505050505050505050505050505",
+                            "data": "0x70a08231",
+                        },
+                        "latest",
+                    ],
+                },
+            },
+        }),
+    );
+
+    set_predecessor("relayer.near");
+    contract
+        .fulfill_simulation(
+            request_id,
+            SimulationResult::Success {
+                return_data: format!("0x{}", "00".repeat(31) + "2a"),
+            },
+        )
+        .unwrap();
+
+    assert_eq!(
+        contract.get_simulation(request_id).unwrap().result,
+        Some(SimulationResult::Success {
+            return_data: format!("0x{}", "00".repeat(31) + "2a"),
+        }),
+    );
+    assert_eq!(
+        crate::impl_mpc::event_logs().last().unwrap()["data"],
+        near_sdk::serde_json::json!({
+            "request_id": request_id.0.to_string(),
+            "success": true,
+        }),
+    );
+
+    // Only fulfilled once.
+    assert!(matches!(
+        contract.fulfill_simulation(
+            request_id,
+            SimulationResult::Failure {
+                error: "execution reverted".to_string(),
+            },
+        ),
+        Err(ContractError::SimulationAlreadyFulfilled),
+    ));
+}
+
+#[test]
+fn test_simulation_storage_deposit() {
+    let mut contract = setup_simulation_contract();
+    let to: ForeignAddress = "0x0505050505050505050505050505050505050505"
+        .parse()
+        .unwrap();
+
+    let storage_usage_before = env::storage_usage();
+    let request_id = contract
+        .simulate_call(97.into(), to, "0x70a08231".to_string(), None)
+        .unwrap();
+    let storage_deposit = contract
+        .get_simulation(request_id)
+        .unwrap()
+        .storage_deposit
+        .0;
+    assert_eq!(
+        crate::impl_fee::refund_transfers(),
+        vec![(
+            "alice.near".to_string(),
+            SIMULATION_STORAGE_DEPOSIT
+                .saturating_sub(near_sdk::NearToken::from_yoctonear(storage_deposit)),
+        )],
+    );
+
+    // The deposit covers the largest result.
+    set_predecessor("relayer.near");
+    contract
+        .fulfill_simulation(
+            request_id,
+            SimulationResult::Success {
+                return_data: "ff".repeat(Contract::MAX_SIMULATION_DATA_LEN),
+            },
+        )
+        .unwrap();
+    contract.simulations.flush();
+    assert!(
+        crate::treasury::storage_cost(env::storage_usage() - storage_usage_before)
+            <= storage_deposit
+    );
+
+    // Only the caller may take the simulation, and is refunded.
+    assert!(matches!(
+        contract.take_simulation(request_id),
+        Err(ContractError::Unauthorized { .. }),
+    ));
+    set_predecessor("alice.near");
+    let simulation = contract.take_simulation(request_id).unwrap();
+    assert!(matches!(
+        simulation.result,
+        Some(SimulationResult::Success { .. }),
+    ));
+    assert_eq!(contract.get_simulation(request_id), None);
+    assert_eq!(
+        crate::impl_fee::refund_transfers(),
+        vec![(
+            "alice.near".to_string(),
+            near_sdk::NearToken::from_yoctonear(storage_deposit),
+        )],
+    );
+    assert!(matches!(
+        contract.take_simulation(request_id),
+        Err(ContractError::SimulationNotFound),
+    ));
+}
+
+#[test]
+fn test_simulate_call_insufficient_storage_deposit() {
+    let mut contract = setup_simulation_contract();
+    near_sdk::testing_env!(near_sdk::test_utils::VMContextBuilder::new()
+        .current_account_id("canhazgas.testnet".parse().unwrap())
+        .predecessor_account_id("alice.near".parse().unwrap())
+        .build());
+
+    assert!(matches!(
+        contract.simulate_call(
+            97.into(),
+            "0x0505050505050505050505050505050505050505"
+                .parse()
+                .unwrap(),
+            "0x".to_string(),
+            None,
+        ),
+        Err(ContractError::InsufficientStorageDeposit { storage_deposit, shortfall })
+            if storage_deposit > 0 && shortfall == storage_deposit,
+    ));
+}
+
+#[test]
+fn test_simulate_call_errors() {
+    let mut contract = setup_simulation_contract();
+    let to: ForeignAddress = "0x0505050505050505050505050505050505050505"
+        .parse()
+        .unwrap();
+
+    assert!(matches!(
+        contract.simulate_call(1.into(), to, "0x".to_string(), None),
+        Err(ContractError::ChainNotConfigured { chain_id: 1 }),
+    ));
+    assert!(matches!(
+        contract.simulate_call(97.into(), to, "0xzz".to_string(), None),
+        Err(ContractError::InvalidInput(_)),
+    ));
+    assert!(matches!(
+        contract.simulate_call(
+            97.into(),
+            to,
+            "00".repeat(Contract::MAX_SIMULATION_DATA_LEN + 1),
+            None,
+        ),
+        Err(ContractError::InvalidInput(_)),
+    ));
+
+    let request_id = contract
+        .simulate_call(97.into(), to, "0x".to_string(), None)
+        .unwrap();
+
+    // Only relayers may fulfill simulations.
+    assert!(matches!(
+        contract.fulfill_simulation(
+            request_id,
+            SimulationResult::Failure {
+                error: "execution reverted".to_string(),
+            },
+        ),
+        Err(ContractError::Unauthorized { .. }),
+    ));
+
+    set_predecessor("relayer.near");
+    assert!(matches!(
+        contract.fulfill_simulation(
+            (request_id.0 + 1).into(),
+            SimulationResult::Failure {
+                error: "execution reverted".to_string(),
+            },
+        ),
+        Err(ContractError::SimulationNotFound),
+    ));
+    assert!(matches!(
+        contract.fulfill_simulation(
+            request_id,
+            SimulationResult::Failure {
+                error: "a".repeat(Contract::MAX_SIMULATION_ERROR_LEN + 1),
+            },
+        ),
+        Err(ContractError::InvalidInput(_)),
+    ));
+
+    contract
+        .fulfill_simulation(
+            request_id,
+            SimulationResult::Failure {
+                error: "execution reverted".to_string(),
+            },
+        )
+        .unwrap();
+    assert_eq!(
+        contract.get_simulation(request_id).unwrap().result,
+        Some(SimulationResult::Failure {
+            error: "execution reverted".to_string(),
+        }),
+    );
+}
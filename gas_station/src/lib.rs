@@ -3,25 +3,29 @@ use ethers_core::{
     utils::hex,
 };
 use lib::{
+    address::AddressType,
     asset::{AssetBalance, AssetId},
     chain_key::ext_chain_key_token,
     foreign_address::ForeignAddress,
+    kdf::EPSILON_DERIVATION_PREFIX,
     pyth::{self, ext_pyth},
     Rejectable,
 };
 use near_sdk::{
-    collections::{UnorderedMap, UnorderedSet, Vector},
+    collections::{TreeMap, UnorderedMap, UnorderedSet, Vector},
     env,
     json_types::{U128, U64},
-    near, near_bindgen, require, AccountId, BorshStorageKey, Gas, NearToken, PanicOnDefault,
-    Promise, PromiseError, PromiseOrValue,
+    near, near_bindgen, require,
+    store::{LookupMap, LookupSet},
+    AccountId, BorshStorageKey, Gas, NearToken, PanicOnDefault, Promise, PromiseError,
+    PromiseOrValue,
 };
 #[allow(clippy::wildcard_imports)]
 use near_sdk_contract_tools::pause::*;
-use near_sdk_contract_tools::{rbac::Rbac, standard::nep297::Event, Pause, Rbac};
+use near_sdk_contract_tools::{owner::Owner, standard::nep297::Event, Pause, Rbac};
 
 pub mod chain_configuration;
-use chain_configuration::ForeignChainConfiguration;
+use chain_configuration::{ForeignChainConfiguration, RollupConfiguration};
 
 pub mod contract_event;
 use contract_event::{ContractEvent, TransactionSequenceCreated, TransactionSequenceSigned};
@@ -30,16 +34,50 @@ mod error;
 #[allow(clippy::wildcard_imports)]
 use error::*;
 
+pub mod fee;
+use fee::{FeeBand, PrepaidFee};
+
+pub mod gas_price_oracle;
+use gas_price_oracle::GasPriceOracle;
+
 mod impl_chain_key_nft;
 pub use impl_chain_key_nft::ChainKeyReceiverMsg;
 #[cfg(feature = "debug")]
 mod impl_debug;
+mod impl_fee;
 mod impl_management;
+mod impl_migration;
+mod impl_mpc;
 mod impl_nep141_receiver;
+mod impl_owner;
+mod impl_simulation;
+
+pub mod mpc_key;
+use mpc_key::MpcKey;
+
+pub mod nonce_manager;
+use nonce_manager::NonceManager;
+
+pub mod rate_limiter;
+use rate_limiter::RateLimiter;
+
+pub mod request_status;
+use request_status::{
+    CancelledNoncePolicy, ChainSignStats, PendingSignRequest, RequestId, RequestStatus,
+    SignBatchItem,
+};
 
 pub mod signature_request;
 use signature_request::{SignatureRequest, Status};
 
+pub mod simulation;
+use simulation::Simulation;
+
+pub mod transaction_input;
+
+pub mod treasury;
+use treasury::Treasury;
+
 mod utils;
 use utils::{decode_transaction_request, sighash_for_mpc_signing};
 
@@ -47,6 +85,19 @@ pub mod valid_transaction_request;
 use valid_transaction_request::ValidTransactionRequest;
 
 const DEFAULT_EXPIRE_SEQUENCE_AFTER_BLOCKS: u64 = 5 * 60; // 5ish minutes at 1s/block
+const DEFAULT_GAS_PRICE_MAX_AGE_BLOCKS: u64 = 60; // 1ish minute at 1s/block
+const DEFAULT_SIGN_REQUEST_TIMEOUT_SECONDS: u64 = 10 * 60;
+/// Enough for the `sign` method of the MPC contract as of `v1.signer`.
+const DEFAULT_MPC_SIGN_GAS: Gas = Gas::from_tgas(50);
+const DEFAULT_MAX_PATH_LEN: u32 = 256;
+const DEFAULT_EXPIRE_NONCE_RESERVATION_AFTER_BLOCKS: u64 = 24 * 60 * 60; // 1ish day at 1s/block
+/// Upper bound on the block rate, to convert durations into block counts
+/// that take at least as long to pass.
+const MAX_BLOCKS_PER_SECOND: u64 = 2;
+/// How far ahead of the next nonce a relayer may report a foreign chain to
+/// be, so that a faulty relayer cannot put an address out of use. Further
+/// advances need the owner to `force` them.
+const MAX_REPORTED_NONCE_ADVANCE: u64 = 1_000;
 
 #[derive(Clone, Debug, Default, PartialEq, Eq)]
 #[near(serializers = [borsh, json])]
@@ -55,10 +106,74 @@ pub struct Flags {
     pub is_receiver_whitelist_enabled: bool,
 }
 
+#[derive(Clone, Debug, PartialEq, Eq)]
 #[near(serializers = [json])]
 pub struct GetForeignChain {
     pub chain_id: U64,
     pub oracle_asset_id: String,
+    pub display_name: String,
+    pub is_eip1559: bool,
+    pub min_priority_fee: U128,
+    pub fee_markup_bps: Option<u32>,
+    pub min_gas_limit: Option<U128>,
+    pub max_gas_limit: Option<U128>,
+    pub rollup: Option<RollupConfiguration>,
+    pub namespaced_derivation: bool,
+}
+
+impl From<&ForeignChainConfiguration> for GetForeignChain {
+    fn from(config: &ForeignChainConfiguration) -> Self {
+        Self {
+            chain_id: config.chain_id.into(),
+            oracle_asset_id: near_sdk::bs58::encode(&config.oracle_asset_id).into_string(),
+            display_name: config.display_name.clone(),
+            is_eip1559: config.is_eip1559,
+            min_priority_fee: config.min_priority_fee.into(),
+            fee_markup_bps: config.fee_markup_bps,
+            min_gas_limit: config.min_gas_limit.map(Into::into),
+            max_gas_limit: config.max_gas_limit.map(Into::into),
+            rollup: config.rollup,
+            namespaced_derivation: config.namespaced_derivation,
+        }
+    }
+}
+
+/// What a deployment of the contract supports, as returned by
+/// `contract_metadata`, so that clients can adapt to it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [json])]
+pub struct ContractMetadata {
+    /// The version of the `gas-station` crate that the contract was built
+    /// from.
+    pub version: String,
+    /// The configured chains, by chain ID.
+    pub chains: Vec<ChainMetadata>,
+    /// The kinds of foreign addresses that sign requests are made for.
+    pub address_types: Vec<AddressType>,
+    pub features: ContractFeatures,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [json])]
+pub struct ChainMetadata {
+    pub chain_id: U64,
+    /// The EIP-2718 type of the transactions that are built for the chain.
+    pub transaction_type: u8,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [json])]
+#[allow(clippy::struct_excessive_bools)]
+pub struct ContractFeatures {
+    /// Built with the `debug` feature, which is not safe for production.
+    pub debug: bool,
+    pub paused: bool,
+    #[serde(flatten)]
+    pub flags: Flags,
+    pub is_rate_limited: bool,
+    /// Whether sign requests must set a max fee per gas within a band
+    /// around the current gas price (see `get_fee_band`).
+    pub is_fee_band_enabled: bool,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -150,6 +265,28 @@ pub enum StorageKey {
     UserChainKeys,
     UserChainKeysFor(AccountId),
     PaymasterKeys,
+    Nonces,
+    GasPrices,
+    RateLimits,
+    MpcKeys,
+    SignRequests,
+    PrepaidFees,
+    SignBatches,
+    SignRequestIds,
+    PendingSignRequests,
+    TokenPrices,
+    SignFees,
+    L1GasPrices,
+    DerivationPrefixes,
+    SignRequestMemos,
+    NonceReservations,
+    SignRequestProviders,
+    RegisteredAddresses,
+    Simulations,
+    SignStats,
+    RecipientAllowlists,
+    RecipientAllowlist(AccountId),
+    RateLimitLog,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, BorshStorageKey)]
@@ -157,6 +294,9 @@ pub enum StorageKey {
 pub enum Role {
     Administrator,
     MarketMaker,
+    /// Trusted to report data observed on foreign chains, such as gas prices
+    /// and the actual cost of signed transactions.
+    Relayer,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -185,6 +325,68 @@ pub struct Contract {
     /// TODO: Hopefully temporary measure to eliminate the need for an indexer.
     pub signed_transaction_sequences: Vector<TransactionSequenceSignedEventAt>,
     pub collected_fees: UnorderedMap<AssetId, U128>,
+    pub nonces: NonceManager,
+    pub gas_price_oracle: GasPriceOracle,
+    pub fee_markup_bps: u32,
+    pub mpc_contract_id: Option<AccountId>,
+    /// Root public keys of the MPC contract, by key version.
+    pub mpc_keys: TreeMap<u32, MpcKey>,
+    pub rate_limiter: RateLimiter,
+    pub sign_requests: LookupMap<RequestId, RequestStatus>,
+    /// Keys of `sign_requests` in insertion order, for pagination.
+    pub sign_request_ids: Vector<RequestId>,
+    /// Fees prepaid for sign requests, kept for settlement.
+    pub prepaid_fees: LookupMap<RequestId, PrepaidFee>,
+    pub sign_batches: LookupMap<u64, Vec<SignBatchItem>>,
+    /// Sign requests that are waiting for the MPC signature.
+    pub pending_sign_requests: LookupMap<RequestId, PendingSignRequest>,
+    /// How long the caller of a sign request has to wait before cancelling it.
+    pub sign_request_timeout_seconds: u64,
+    pub cancelled_nonce_policy: CancelledNoncePolicy,
+    /// NEAR fees (in yoctoNEAR) charged for sign requests on each chain,
+    /// less refunds.
+    pub sign_fees: UnorderedMap<u64, U128>,
+    /// Attached to each call to the `sign` method of the MPC contract.
+    pub mpc_sign_gas: Gas,
+    /// Key derivation prefixes, by version. The latest must be the one that
+    /// the MPC contract derives keys with.
+    pub derivation_prefixes: TreeMap<u32, String>,
+    /// Longest derivation path (in bytes) that callers may sign for.
+    pub max_path_len: u32,
+    pub treasury: Treasury,
+    /// Basis points of its fee by which the deposit for a sign request may
+    /// exceed the fee and still be kept, as a buffer against gas price
+    /// movements. Refunded when the fee is settled.
+    pub fee_slippage_bps: u32,
+    /// Memos that callers attached to their sign requests. Kept apart from
+    /// `sign_requests`, rather than on every variant of `RequestStatus`, so
+    /// that a status can be replaced without carrying the memo over, and
+    /// requests without a memo take up no storage for one. Charged for with
+    /// the rest of the request's records and, like the status, kept once the
+    /// request is resolved.
+    pub sign_request_memos: LookupMap<RequestId, String>,
+    /// How many blocks after it was made a nonce reservation that was
+    /// neither used nor released may be reclaimed.
+    pub expire_nonce_reservation_after_blocks: u64,
+    /// MPC contracts to request a signature from, in order, if the request
+    /// to `mpc_contract_id` fails.
+    pub mpc_fallback_contract_ids: Vec<AccountId>,
+    /// The MPC contract that the signature of each sign request was last
+    /// requested from.
+    pub sign_request_providers: LookupMap<RequestId, AccountId>,
+    /// Foreign addresses that callers registered for their paths on each
+    /// chain, which sign requests must derive to.
+    pub registered_addresses: LookupMap<(AccountId, String, u64), ForeignAddress>,
+    /// Read-only calls requested by `simulate_call`, by request ID.
+    pub simulations: LookupMap<u64, Simulation>,
+    /// Usage of each chain, counted as sign requests complete.
+    pub sign_stats: UnorderedMap<u64, ChainSignStats>,
+    /// Foreign addresses that callers restricted their sign requests to
+    /// send to. Callers without one are unrestricted.
+    pub recipient_allowlists: LookupMap<AccountId, LookupSet<ForeignAddress>>,
+    /// The range of max fees per gas that sign requests may set around the
+    /// current gas price. Unrestricted if `None`.
+    pub fee_band: Option<FeeBand>,
 }
 
 #[near_bindgen]
@@ -214,13 +416,45 @@ impl Contract {
             ),
             signed_transaction_sequences: Vector::new(StorageKey::SignedTransactionSequences),
             collected_fees: UnorderedMap::new(StorageKey::CollectedFees),
+            nonces: NonceManager::new(StorageKey::Nonces, StorageKey::NonceReservations),
+            gas_price_oracle: GasPriceOracle::new(
+                StorageKey::GasPrices,
+                StorageKey::TokenPrices,
+                StorageKey::L1GasPrices,
+                DEFAULT_GAS_PRICE_MAX_AGE_BLOCKS,
+            ),
+            fee_markup_bps: 0,
+            mpc_contract_id: None,
+            mpc_keys: TreeMap::new(StorageKey::MpcKeys),
+            rate_limiter: RateLimiter::new(StorageKey::RateLimits, StorageKey::RateLimitLog),
+            sign_requests: LookupMap::new(StorageKey::SignRequests),
+            sign_request_ids: Vector::new(StorageKey::SignRequestIds),
+            prepaid_fees: LookupMap::new(StorageKey::PrepaidFees),
+            sign_batches: LookupMap::new(StorageKey::SignBatches),
+            pending_sign_requests: LookupMap::new(StorageKey::PendingSignRequests),
+            sign_request_timeout_seconds: DEFAULT_SIGN_REQUEST_TIMEOUT_SECONDS,
+            cancelled_nonce_policy: CancelledNoncePolicy::default(),
+            sign_fees: UnorderedMap::new(StorageKey::SignFees),
+            mpc_sign_gas: DEFAULT_MPC_SIGN_GAS,
+            derivation_prefixes: TreeMap::new(StorageKey::DerivationPrefixes),
+            max_path_len: DEFAULT_MAX_PATH_LEN,
+            treasury: Treasury::default(),
+            fee_slippage_bps: 0,
+            sign_request_memos: LookupMap::new(StorageKey::SignRequestMemos),
+            expire_nonce_reservation_after_blocks: DEFAULT_EXPIRE_NONCE_RESERVATION_AFTER_BLOCKS,
+            mpc_fallback_contract_ids: Vec::new(),
+            sign_request_providers: LookupMap::new(StorageKey::SignRequestProviders),
+            registered_addresses: LookupMap::new(StorageKey::RegisteredAddresses),
+            simulations: LookupMap::new(StorageKey::Simulations),
+            sign_stats: UnorderedMap::new(StorageKey::SignStats),
+            recipient_allowlists: LookupMap::new(StorageKey::RecipientAllowlists),
+            fee_band: None,
         };
 
-        Rbac::add_role(
-            &mut contract,
-            &env::predecessor_account_id(),
-            &Role::Administrator,
-        );
+        contract
+            .derivation_prefixes
+            .insert(&0, &EPSILON_DERIVATION_PREFIX.to_string());
+        Owner::init(&mut contract, &env::predecessor_account_id());
 
         contract
     }
@@ -676,13 +910,65 @@ impl Contract {
 }
 
 impl Contract {
+    /// Like [`Pause::require_unpaused`], for methods that return errors.
+    pub(crate) fn check_unpaused() -> Result<(), ContractError> {
+        if <Self as Pause>::is_paused() {
+            Err(ContractError::Paused)
+        } else {
+            Ok(())
+        }
+    }
+
     #[allow(clippy::unused_self)]
     fn require_unpaused_or_administrator(&self, account_id: &AccountId) {
-        if !<Self as Rbac>::has_role(account_id, &Role::Administrator) {
+        if !Self::is_administrator(account_id) {
             <Self as Pause>::require_unpaused();
         }
     }
 
+    pub(crate) fn refund_deposit(account_id: AccountId, amount: u128) {
+        if amount > 0 {
+            drop(Promise::new(account_id).transfer(NearToken::from_yoctonear(amount)));
+        }
+    }
+
+    /// Keeps the storage cost of `storage_usage` bytes out of the attached
+    /// deposit, and refunds the rest to the predecessor. Returns the storage
+    /// deposit.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ContractError::InsufficientStorageDeposit`] if the attached
+    /// deposit does not cover the storage cost.
+    pub(crate) fn try_charge_storage(storage_usage: u64) -> Result<u128, ContractError> {
+        let deposit = env::attached_deposit().as_yoctonear();
+        let storage_deposit = treasury::storage_cost(storage_usage);
+        if deposit < storage_deposit {
+            return Err(ContractError::InsufficientStorageDeposit {
+                storage_deposit,
+                shortfall: storage_deposit - deposit,
+            });
+        }
+
+        Self::refund_deposit(env::predecessor_account_id(), deposit - storage_deposit);
+        Ok(storage_deposit)
+    }
+
+    /// Refunds the predecessor the storage cost of what was freed since the
+    /// storage usage was `storage_usage_before`.
+    pub(crate) fn refund_freed_storage(storage_usage_before: u64) {
+        Self::refund_deposit(
+            env::predecessor_account_id(),
+            treasury::storage_cost(storage_usage_before.saturating_sub(env::storage_usage())),
+        );
+    }
+
+    /// The most blocks that may be produced within the sign request timeout.
+    fn sign_request_timeout_blocks(&self) -> u64 {
+        self.sign_request_timeout_seconds
+            .saturating_mul(MAX_BLOCKS_PER_SECOND)
+    }
+
     fn with_mut_chain<R>(
         &mut self,
         chain_id: u64,
@@ -706,6 +992,65 @@ impl Contract {
             .ok_or(ChainConfigurationDoesNotExistError { chain_id })
     }
 
+    /// Calculate the NEAR fee for a transaction on a foreign chain, applying
+    /// the chain's markup, or the contract-wide markup if it has none.
+    ///
+    /// # Errors
+    ///
+    /// - If the foreign chain is not configured.
+    /// - If the calculation overflows.
+    pub fn compute_fee(
+        &self,
+        chain_id: u64,
+        gas_limit: u128,
+        gas_price: u128,
+        foreign_token_price_in_near: u128,
+    ) -> Result<NearToken, ComputeFeeError> {
+        Ok(fee::compute_fee(
+            gas_limit,
+            gas_price,
+            foreign_token_price_in_near,
+            self.get_chain(chain_id)?.decimals,
+            self.fee_markup_bps_for_chain(chain_id)?,
+        )?)
+    }
+
+    /// The L1 data fee of a transaction on a foreign chain that is
+    /// `serialized_tx_len` bytes long once signed, at `l1_gas_price`, in the
+    /// smallest unit of the chain's gas token. Zero if the chain is not a
+    /// rollup.
+    ///
+    /// # Errors
+    ///
+    /// - If the foreign chain is not configured.
+    /// - If the calculation overflows.
+    pub fn l1_data_fee(
+        &self,
+        chain_id: u64,
+        serialized_tx_len: u64,
+        l1_gas_price: u128,
+    ) -> Result<u128, ComputeFeeError> {
+        Ok(match self.get_chain(chain_id)?.rollup {
+            Some(rollup) => rollup.l1_data_fee(serialized_tx_len, l1_gas_price)?,
+            None => 0,
+        })
+    }
+
+    /// The chain's fee markup, or the contract-wide markup if it has none.
+    ///
+    /// # Errors
+    ///
+    /// - If the foreign chain is not configured.
+    pub fn fee_markup_bps_for_chain(
+        &self,
+        chain_id: u64,
+    ) -> Result<u32, ChainConfigurationDoesNotExistError> {
+        Ok(self
+            .get_chain(chain_id)?
+            .fee_markup_bps
+            .unwrap_or(self.fee_markup_bps))
+    }
+
     fn generate_unique_id(&mut self) -> u64 {
         let id = self.next_unique_id;
         self.next_unique_id = self
@@ -798,3 +1143,17 @@ impl Contract {
         }
     }
 }
+
+#[cfg(test)]
+fn setup_contract() -> Contract {
+    near_sdk::testing_env!(near_sdk::test_utils::VMContextBuilder::new()
+        .current_account_id("canhazgas.testnet".parse().unwrap())
+        .predecessor_account_id("canhazgas.testnet".parse().unwrap())
+        .build());
+
+    Contract::new(
+        "nft.canhazgas.testnet".parse().unwrap(),
+        "pyth-oracle.testnet".parse().unwrap(),
+        None,
+    )
+}
@@ -0,0 +1,254 @@
+use ethers_core::{k256::AffinePoint, utils::hex};
+use lib::kdf::{
+    near_public_key_to_affine, near_public_key_to_edwards, sec1_public_key_to_near,
+    PublicKeyConversionError,
+};
+use near_sdk::{bs58, near, CurveType, PublicKey};
+
+/// A root public key of the MPC contract, from which the keys that the gas
+/// station signs with are derived.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [borsh, json])]
+#[serde(try_from = "MpcKeyJson")]
+pub struct MpcKey {
+    pub public_key: PublicKey,
+    #[serde(with = "curve_type_json")]
+    pub curve: CurveType,
+    pub version: u32,
+}
+
+impl MpcKey {
+    /// # Errors
+    ///
+    /// Returns an error if `public_key` is not a valid point on its curve.
+    pub fn new(public_key: PublicKey, version: u32) -> Result<Self, PublicKeyConversionError> {
+        let curve = public_key.curve_type();
+        match curve {
+            CurveType::SECP256K1 => {
+                near_public_key_to_affine(public_key.clone())?;
+            }
+            CurveType::ED25519 => {
+                near_public_key_to_edwards(public_key.clone())?;
+            }
+        }
+
+        Ok(Self {
+            public_key,
+            curve,
+            version,
+        })
+    }
+
+    /// The decoded SECP256K1 point of the key, or `None` for ED25519 keys.
+    ///
+    /// Decoded from `public_key` on every call: a point cannot be rebuilt
+    /// from any stored form without the same validation that decoding does,
+    /// so caching one would not save any gas.
+    #[must_use]
+    pub fn affine(&self) -> Option<AffinePoint> {
+        match self.curve {
+            CurveType::SECP256K1 => near_public_key_to_affine(self.public_key.clone()).ok(),
+            CurveType::ED25519 => None,
+        }
+    }
+}
+
+/// The JSON form of [`MpcKey`], which is validated and decoded again when
+/// deserialized.
+#[derive(Clone, Debug)]
+#[near(serializers = [json])]
+struct MpcKeyJson {
+    public_key: PublicKey,
+    #[serde(with = "curve_type_json")]
+    curve: CurveType,
+    version: u32,
+}
+
+impl TryFrom<MpcKeyJson> for MpcKey {
+    type Error = PublicKeyConversionError;
+
+    fn try_from(json: MpcKeyJson) -> Result<Self, Self::Error> {
+        let key = Self::new(json.public_key, json.version)?;
+        if key.curve == json.curve {
+            Ok(key)
+        } else {
+            Err(PublicKeyConversionError::InvalidKeyData)
+        }
+    }
+}
+
+/// A root public key as accepted by `set_mpc_key`: either in the NEAR
+/// format (`secp256k1:<base58>` or `ed25519:<base58>`), or a SECP256K1 key
+/// in SEC1 form, compressed or uncompressed, and hex-encoded (optionally
+/// `0x`-prefixed) or base58-encoded. Normalized to the NEAR format.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [json])]
+#[serde(try_from = "String")]
+pub struct MpcPublicKeyInput(pub PublicKey);
+
+impl From<PublicKey> for MpcPublicKeyInput {
+    fn from(public_key: PublicKey) -> Self {
+        Self(public_key)
+    }
+}
+
+impl TryFrom<String> for MpcPublicKeyInput {
+    type Error = PublicKeyConversionError;
+
+    fn try_from(encoded: String) -> Result<Self, Self::Error> {
+        if encoded.contains(':') {
+            return encoded
+                .parse()
+                .map(Self)
+                .map_err(|_| PublicKeyConversionError::InvalidKeyData);
+        }
+
+        // SEC1 encodings start with `0x02`, `0x03`, or `0x04`, so their hex
+        // encodings are never valid base58.
+        let sec1 = hex::decode(encoded.strip_prefix("0x").unwrap_or(&encoded))
+            .or_else(|_| bs58::decode(&encoded).into_vec())
+            .map_err(|_| PublicKeyConversionError::InvalidKeyData)?;
+
+        sec1_public_key_to_near(&sec1).map(Self)
+    }
+}
+
+/// Serializes curves by the same names that prefix public keys in their
+/// string form.
+mod curve_type_json {
+    use near_sdk::{
+        serde::{de, Deserialize, Deserializer, Serializer},
+        CurveType,
+    };
+
+    #[allow(clippy::trivially_copy_pass_by_ref)]
+    pub fn serialize<S: Serializer>(curve: &CurveType, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(match curve {
+            CurveType::ED25519 => "ed25519",
+            CurveType::SECP256K1 => "secp256k1",
+        })
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<CurveType, D::Error> {
+        match String::deserialize(deserializer)?.as_str() {
+            "ed25519" => Ok(CurveType::ED25519),
+            "secp256k1" => Ok(CurveType::SECP256K1),
+            other => Err(de::Error::unknown_variant(other, &["ed25519", "secp256k1"])),
+        }
+    }
+}
+
+#[test]
+fn test_mpc_key_json() {
+    let key = MpcKey::new(
+        "ed25519:6E8sCci9badyRkXb3JoRpBj5p8C6Tw41ELDZoiihKEtp"
+            .parse()
+            .unwrap(),
+        3,
+    )
+    .unwrap();
+
+    let json = near_sdk::serde_json::to_value(&key).unwrap();
+    assert_eq!(
+        json,
+        near_sdk::serde_json::json!({
+            "public_key": "ed25519:6E8sCci9badyRkXb3JoRpBj5p8C6Tw41ELDZoiihKEtp",
+            "curve": "ed25519",
+            "version": 3,
+        }),
+    );
+    assert_eq!(
+        near_sdk::serde_json::from_value::<MpcKey>(json).unwrap(),
+        key
+    );
+}
+
+#[test]
+fn test_mpc_key_rejects_malformed_secp256k1_key() {
+    // Not a point on the curve.
+    let public_key = PublicKey::from_parts(CurveType::SECP256K1, vec![1; 64]).unwrap();
+
+    assert!(MpcKey::new(public_key, 0).is_err());
+}
+
+#[test]
+fn test_affine_matches_address_derivation() {
+    let public_key: PublicKey =
+        "secp256k1:47xve2ymatpG4x4Gp7pmYwuLJk7eeRegrFuS4VoW5VV4i3GsBiBY87vkH6UZiiY18NeZnkBzcZzipDbJJ5pmjTcc"
+            .parse()
+            .unwrap();
+    let key = MpcKey::new(public_key.clone(), 0).unwrap();
+    let gas_station: near_sdk::AccountId = "canhazgas.testnet".parse().unwrap();
+
+    assert_eq!(
+        key.affine(),
+        Some(near_public_key_to_affine(public_key.clone()).unwrap()),
+    );
+    assert_eq!(
+        lib::kdf::get_mpc_address_from_affine(key.affine().unwrap(), &gas_station, "alice.near",)
+            .unwrap(),
+        lib::kdf::get_mpc_address(public_key, &gas_station, "alice.near").unwrap(),
+    );
+}
+
+#[test]
+fn test_affine_ed25519() {
+    let key = MpcKey::new(
+        "ed25519:6E8sCci9badyRkXb3JoRpBj5p8C6Tw41ELDZoiihKEtp"
+            .parse()
+            .unwrap(),
+        0,
+    )
+    .unwrap();
+
+    assert_eq!(key.affine(), None);
+}
+
+#[test]
+fn test_mpc_public_key_input_sec1() {
+    use ethers_core::k256::ecdsa::SigningKey;
+
+    let verifying_key = *SigningKey::from_bytes(&crate::impl_mpc::TEST_MPC_ROOT_SECRET_KEY.into())
+        .unwrap()
+        .verifying_key();
+    let compressed = verifying_key.to_encoded_point(true);
+    let uncompressed = verifying_key.to_encoded_point(false);
+    let expected =
+        PublicKey::from_parts(CurveType::SECP256K1, uncompressed.as_bytes()[1..].to_vec()).unwrap();
+
+    for encoded in [
+        hex::encode(compressed.as_bytes()),
+        hex::encode_prefixed(compressed.as_bytes()),
+        bs58::encode(compressed.as_bytes()).into_string(),
+        hex::encode(uncompressed.as_bytes()),
+        bs58::encode(uncompressed.as_bytes()).into_string(),
+        String::from(&expected),
+    ] {
+        let input: MpcPublicKeyInput =
+            near_sdk::serde_json::from_value(near_sdk::serde_json::json!(encoded)).unwrap();
+        assert_eq!(input.0, expected, "{encoded}");
+        assert_eq!(
+            MpcKey::new(input.0, 0).unwrap().affine(),
+            Some(*verifying_key.as_affine()),
+        );
+    }
+}
+
+#[test]
+fn test_mpc_public_key_input_rejects_invalid_keys() {
+    for encoded in [
+        "",
+        "0x",
+        "not a key",
+        // Not on the curve.
+        "02ffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff",
+        // Neither SEC1 length.
+        "02ffff",
+        "secp256k1:notbase58",
+    ] {
+        assert!(
+            MpcPublicKeyInput::try_from(encoded.to_string()).is_err(),
+            "{encoded}",
+        );
+    }
+}
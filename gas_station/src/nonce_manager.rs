@@ -0,0 +1,398 @@
+use lib::{foreign_address::ForeignAddress, Rejectable};
+use near_sdk::{env, json_types::U64, near, store::LookupMap, IntoStorageKey};
+
+use crate::{error::NonceOverflowError, request_status::RequestId};
+
+/// The outcome of reconciling the next nonce for a foreign address with the
+/// nonce that the foreign chain expects next from it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[near(serializers = [json])]
+pub enum NonceReconciliation {
+    InSync,
+    /// Nonces were used on the foreign chain without being reserved here, so
+    /// the next nonce was advanced to the chain's.
+    Advanced {
+        previous_nonce: U64,
+    },
+    /// Nonces from `chain_nonce` up to `next_nonce` have been reserved but
+    /// were not yet included on the foreign chain. Either their transactions
+    /// are still to be included, or one of them was never broadcast and is
+    /// blocking the rest. The next nonce was left unchanged.
+    Gap {
+        chain_nonce: U64,
+        next_nonce: U64,
+    },
+    /// The next nonce was forced back to the chain's.
+    Reset {
+        previous_nonce: U64,
+    },
+}
+
+/// A reserved nonce that has been neither used nor released.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[near]
+struct NonceReservation {
+    nonce: u64,
+    block_height: u64,
+    /// The sign request that the nonce was reserved for.
+    request_id: RequestId,
+}
+
+/// Tracks the next nonce to use for each foreign address on each foreign
+/// chain. Addresses that have never been used start at nonce 0.
+#[derive(Debug)]
+#[near]
+pub struct NonceManager {
+    next_nonces: LookupMap<(u64, ForeignAddress), u64>,
+    /// Outstanding reservations of each foreign address, in increasing order
+    /// of nonce.
+    reservations: LookupMap<(u64, ForeignAddress), Vec<NonceReservation>>,
+}
+
+impl NonceManager {
+    pub fn new(prefix: impl IntoStorageKey, reservations_prefix: impl IntoStorageKey) -> Self {
+        Self {
+            next_nonces: LookupMap::new(prefix),
+            reservations: LookupMap::new(reservations_prefix),
+        }
+    }
+
+    /// Writes any cached changes to storage, e.g. to measure storage usage.
+    pub fn flush(&mut self) {
+        self.next_nonces.flush();
+        self.reservations.flush();
+    }
+
+    /// Returns the next nonce for `address` on `chain_id` without reserving
+    /// it.
+    pub fn peek_nonce(&self, chain_id: u64, address: ForeignAddress) -> u64 {
+        self.next_nonces
+            .get(&(chain_id, address))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    /// Returns the next nonce for `address` on `chain_id` and advances the
+    /// stored nonce, so that subsequent calls never return the same nonce.
+    /// The reservation is recorded for `request_id` at the current block
+    /// height until the nonce is confirmed or released.
+    pub fn reserve_nonce(
+        &mut self,
+        chain_id: u64,
+        address: ForeignAddress,
+        request_id: RequestId,
+    ) -> u64 {
+        let next_nonce = self.next_nonces.entry((chain_id, address)).or_insert(0);
+        let nonce = *next_nonce;
+        *next_nonce = nonce.checked_add(1).expect_or_reject(NonceOverflowError);
+
+        self.reservations
+            .entry((chain_id, address))
+            .or_default()
+            .push(NonceReservation {
+                nonce,
+                block_height: env::block_height(),
+                request_id,
+            });
+
+        nonce
+    }
+
+    /// Stops tracking the reservation of `nonce`, because it has been used,
+    /// or is never to be reused, so that it is never reclaimed.
+    pub fn confirm_nonce(&mut self, chain_id: u64, address: ForeignAddress, nonce: u64) {
+        self.remove_reservation(chain_id, address, nonce);
+    }
+
+    /// Returns a reserved nonce that will not be used, e.g. because signing
+    /// failed. This is only possible if it is still reserved, and no later
+    /// nonce has been reserved for the same address since; otherwise the
+    /// nonce is left as is, and `false` is returned.
+    pub fn release_nonce(&mut self, chain_id: u64, address: ForeignAddress, nonce: u64) -> bool {
+        let key = (chain_id, address);
+        let is_reserved = self.reservations.get(&key).map_or(false, |reservations| {
+            reservations
+                .iter()
+                .any(|reservation| reservation.nonce == nonce)
+        });
+        if is_reserved && self.next_nonces.get(&key).copied() == nonce.checked_add(1) {
+            self.next_nonces.insert(key, nonce);
+            self.remove_reservation(chain_id, address, nonce);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Overwrites the next nonce for `address` on `chain_id`. Intended for
+    /// resynchronizing with the foreign chain, e.g. after a reorg.
+    /// Reservations of `nonce` or later nonces are forgotten.
+    pub fn set_nonce(&mut self, chain_id: u64, address: ForeignAddress, nonce: u64) {
+        self.next_nonces.insert((chain_id, address), nonce);
+        if let Some(reservations) = self.reservations.get_mut(&(chain_id, address)) {
+            reservations.retain(|reservation| reservation.nonce < nonce);
+        }
+    }
+
+    /// Reclaims up to `limit` of the latest reservations for `address` on
+    /// `chain_id` that were made more than `expire_after_blocks` blocks ago,
+    /// and returns how many were reclaimed. Reservations for which
+    /// `is_pending` returns `true` are never reclaimed, since their sign
+    /// request may still complete with the nonce. A reservation can only be
+    /// reclaimed while it is the latest one, so sweeping stops at the first
+    /// reservation that cannot be reclaimed.
+    pub fn sweep_expired_reservations(
+        &mut self,
+        chain_id: u64,
+        address: ForeignAddress,
+        expire_after_blocks: u64,
+        limit: u32,
+        is_pending: impl Fn(&RequestId) -> bool,
+    ) -> u32 {
+        let key = (chain_id, address);
+        let mut next_nonce = self.peek_nonce(chain_id, address);
+        let Some(reservations) = self.reservations.get_mut(&key) else {
+            return 0;
+        };
+
+        let mut reclaimed = 0;
+        while reclaimed < limit {
+            match reservations.last() {
+                Some(reservation)
+                    if env::block_height().saturating_sub(reservation.block_height)
+                        > expire_after_blocks
+                        && reservation.nonce.checked_add(1) == Some(next_nonce)
+                        && !is_pending(&reservation.request_id) =>
+                {
+                    next_nonce = reservation.nonce;
+                    reservations.pop();
+                    reclaimed += 1;
+                }
+                _ => break,
+            }
+        }
+
+        if reclaimed > 0 {
+            self.next_nonces.insert(key, next_nonce);
+        }
+        reclaimed
+    }
+
+    fn remove_reservation(&mut self, chain_id: u64, address: ForeignAddress, nonce: u64) {
+        if let Some(reservations) = self.reservations.get_mut(&(chain_id, address)) {
+            reservations.retain(|reservation| reservation.nonce != nonce);
+        }
+    }
+
+    /// Reconciles the next nonce for `address` on `chain_id` with
+    /// `chain_nonce`, the nonce that the foreign chain expects next. The
+    /// next nonce only moves back if `force` is set.
+    pub fn reconcile_nonce(
+        &mut self,
+        chain_id: u64,
+        address: ForeignAddress,
+        chain_nonce: u64,
+        force: bool,
+    ) -> NonceReconciliation {
+        let next_nonce = self.peek_nonce(chain_id, address);
+
+        match chain_nonce.cmp(&next_nonce) {
+            std::cmp::Ordering::Equal => NonceReconciliation::InSync,
+            std::cmp::Ordering::Greater => {
+                self.set_nonce(chain_id, address, chain_nonce);
+                NonceReconciliation::Advanced {
+                    previous_nonce: next_nonce.into(),
+                }
+            }
+            std::cmp::Ordering::Less if force => {
+                self.set_nonce(chain_id, address, chain_nonce);
+                NonceReconciliation::Reset {
+                    previous_nonce: next_nonce.into(),
+                }
+            }
+            std::cmp::Ordering::Less => NonceReconciliation::Gap {
+                chain_nonce: chain_nonce.into(),
+                next_nonce: next_nonce.into(),
+            },
+        }
+    }
+}
+
+#[test]
+fn test_reserve_nonce_sequential() {
+    let mut nonces = NonceManager::new(b"n", b"r");
+    let address = ForeignAddress([1; 20]);
+
+    assert_eq!(nonces.peek_nonce(1, address), 0);
+    assert_eq!(nonces.reserve_nonce(1, address, [0; 32]), 0);
+    assert_eq!(nonces.reserve_nonce(1, address, [0; 32]), 1);
+    assert_eq!(nonces.reserve_nonce(1, address, [0; 32]), 2);
+    assert_eq!(nonces.peek_nonce(1, address), 3);
+}
+
+#[test]
+fn test_reserve_nonce_isolated_per_chain_and_address() {
+    let mut nonces = NonceManager::new(b"n", b"r");
+    let alice = ForeignAddress([1; 20]);
+    let bob = ForeignAddress([2; 20]);
+
+    assert_eq!(nonces.reserve_nonce(1, alice, [0; 32]), 0);
+    assert_eq!(nonces.reserve_nonce(1, alice, [0; 32]), 1);
+
+    assert_eq!(nonces.reserve_nonce(97, alice, [0; 32]), 0);
+    assert_eq!(nonces.reserve_nonce(1, bob, [0; 32]), 0);
+
+    assert_eq!(nonces.peek_nonce(1, alice), 2);
+    assert_eq!(nonces.peek_nonce(97, alice), 1);
+    assert_eq!(nonces.peek_nonce(1, bob), 1);
+}
+
+#[test]
+fn test_release_nonce() {
+    let mut nonces = NonceManager::new(b"n", b"r");
+    let address = ForeignAddress([1; 20]);
+
+    let first = nonces.reserve_nonce(1, address, [0; 32]);
+    let second = nonces.reserve_nonce(1, address, [0; 32]);
+
+    // A later nonce is already reserved.
+    assert!(!nonces.release_nonce(1, address, first));
+    assert_eq!(nonces.peek_nonce(1, address), 2);
+
+    assert!(nonces.release_nonce(1, address, second));
+    assert_eq!(nonces.peek_nonce(1, address), 1);
+    assert!(nonces.release_nonce(1, address, first));
+    assert_eq!(nonces.peek_nonce(1, address), 0);
+
+    // Never reserved.
+    assert!(!nonces.release_nonce(1, address, 0));
+    assert_eq!(nonces.peek_nonce(1, address), 0);
+}
+
+#[test]
+fn test_set_nonce() {
+    let mut nonces = NonceManager::new(b"n", b"r");
+    let address = ForeignAddress([1; 20]);
+
+    assert_eq!(nonces.reserve_nonce(1, address, [0; 32]), 0);
+    assert_eq!(nonces.reserve_nonce(1, address, [0; 32]), 1);
+
+    // Roll back after a reorg dropped the second transaction.
+    nonces.set_nonce(1, address, 1);
+    assert_eq!(nonces.reserve_nonce(1, address, [0; 32]), 1);
+
+    nonces.set_nonce(1, address, 10);
+    assert_eq!(nonces.peek_nonce(1, address), 10);
+    assert_eq!(nonces.peek_nonce(2, address), 0);
+}
+
+#[test]
+fn test_reconcile_nonce() {
+    let mut nonces = NonceManager::new(b"n", b"r");
+    let address = ForeignAddress([1; 20]);
+
+    assert_eq!(
+        nonces.reconcile_nonce(1, address, 0, false),
+        NonceReconciliation::InSync,
+    );
+
+    assert_eq!(
+        nonces.reconcile_nonce(1, address, 5, false),
+        NonceReconciliation::Advanced {
+            previous_nonce: 0.into(),
+        },
+    );
+    assert_eq!(nonces.peek_nonce(1, address), 5);
+
+    nonces.reserve_nonce(1, address, [0; 32]);
+    nonces.reserve_nonce(1, address, [0; 32]);
+    assert_eq!(
+        nonces.reconcile_nonce(1, address, 5, false),
+        NonceReconciliation::Gap {
+            chain_nonce: 5.into(),
+            next_nonce: 7.into(),
+        },
+    );
+    assert_eq!(nonces.peek_nonce(1, address), 7);
+
+    assert_eq!(
+        nonces.reconcile_nonce(1, address, 5, true),
+        NonceReconciliation::Reset {
+            previous_nonce: 7.into(),
+        },
+    );
+    assert_eq!(nonces.peek_nonce(1, address), 5);
+    assert_eq!(nonces.peek_nonce(2, address), 0);
+}
+
+#[test]
+fn test_sweep_expired_reservations() {
+    use near_sdk::{test_utils::VMContextBuilder, testing_env};
+
+    let set_block_height = |block_height| {
+        testing_env!(VMContextBuilder::new().block_height(block_height).build());
+    };
+    let mut nonces = NonceManager::new(b"n", b"r");
+    let address = ForeignAddress([1; 20]);
+
+    set_block_height(100);
+    let first = nonces.reserve_nonce(1, address, [0; 32]);
+    let second = nonces.reserve_nonce(1, address, [0; 32]);
+    set_block_height(150);
+    let third = nonces.reserve_nonce(1, address, [0; 32]);
+    nonces.confirm_nonce(1, address, first);
+
+    // Not expired yet.
+    set_block_height(160);
+    assert_eq!(
+        nonces.sweep_expired_reservations(1, address, 60, 10, |_| false),
+        0
+    );
+    assert_eq!(nonces.peek_nonce(1, address), 3);
+
+    // The third reservation has not expired, so the second cannot be
+    // reclaimed without leaving a gap.
+    set_block_height(200);
+    assert_eq!(
+        nonces.sweep_expired_reservations(1, address, 60, 10, |_| false),
+        0
+    );
+
+    set_block_height(211);
+    assert_eq!(
+        nonces.sweep_expired_reservations(1, address, 60, 1, |_| false),
+        1
+    );
+    assert_eq!(nonces.peek_nonce(1, address), third);
+    assert_eq!(
+        nonces.sweep_expired_reservations(1, address, 60, 10, |_| false),
+        1
+    );
+    assert_eq!(nonces.peek_nonce(1, address), second);
+
+    // The first nonce was used.
+    assert_eq!(
+        nonces.sweep_expired_reservations(1, address, 60, 10, |_| false),
+        0
+    );
+    assert_eq!(nonces.peek_nonce(1, address), 1);
+
+    // Released nonces are no longer tracked.
+    assert_eq!(nonces.reserve_nonce(1, address, [0; 32]), 1);
+    assert!(nonces.release_nonce(1, address, 1));
+    set_block_height(1000);
+    assert_eq!(
+        nonces.sweep_expired_reservations(1, address, 60, 10, |_| false),
+        0
+    );
+    assert_eq!(nonces.peek_nonce(1, address), 1);
+
+    // Nor are the reservations of sign requests that are still pending.
+    assert_eq!(nonces.reserve_nonce(1, address, [7; 32]), 1);
+    set_block_height(2000);
+    assert_eq!(
+        nonces.sweep_expired_reservations(1, address, 60, 10, |request_id| *request_id == [7; 32]),
+        0,
+    );
+    assert_eq!(nonces.peek_nonce(1, address), 2);
+}
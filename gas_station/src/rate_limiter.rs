@@ -0,0 +1,235 @@
+use near_sdk::{json_types::U64, near, store::LookupMap, AccountId, IntoStorageKey};
+
+use crate::error::RateLimitExceededError;
+
+const NANOSECONDS_PER_SECOND: u64 = 1_000_000_000;
+
+/// How many expired entries of the request log each recorded request evicts.
+/// More than one, so that the log shrinks back down after a burst.
+const MAX_EVICTIONS_PER_REQUEST: u32 = 2;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[near(serializers = [borsh, json])]
+pub struct RateLimit {
+    pub max_requests: u32,
+    pub window_seconds: U64,
+}
+
+impl RateLimit {
+    fn window_ns(self) -> u64 {
+        self.window_seconds.0.saturating_mul(NANOSECONDS_PER_SECOND)
+    }
+}
+
+/// Limits how many requests each account may make within a rolling time
+/// window.
+#[derive(Debug)]
+#[near]
+pub struct RateLimiter {
+    /// Timestamps (in nanoseconds) of each account's requests that may still
+    /// be within the window, oldest first.
+    requests: LookupMap<AccountId, Vec<u64>>,
+    /// Every recorded request, oldest first, as a queue indexed from
+    /// `request_log_start` to `request_log_end`. Used to find and evict the
+    /// entries of accounts that have stopped making requests.
+    request_log: LookupMap<u64, (AccountId, u64)>,
+    request_log_start: u64,
+    request_log_end: u64,
+    /// `None` disables rate limiting.
+    pub limit: Option<RateLimit>,
+}
+
+impl RateLimiter {
+    pub fn new(requests_prefix: impl IntoStorageKey, log_prefix: impl IntoStorageKey) -> Self {
+        Self {
+            requests: LookupMap::new(requests_prefix),
+            request_log: LookupMap::new(log_prefix),
+            request_log_start: 0,
+            request_log_end: 0,
+            limit: None,
+        }
+    }
+
+    /// Records a request by `account_id` at `timestamp_ns`, unless the
+    /// account has already made the maximum number of requests within the
+    /// window ending at `timestamp_ns`.
+    ///
+    /// Also evicts the oldest requests that have left the window, removing
+    /// an account's timestamps entirely once none of them are within it.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the request would exceed the rate limit. The
+    /// request is not recorded in that case.
+    pub fn record_request(
+        &mut self,
+        account_id: &AccountId,
+        timestamp_ns: u64,
+    ) -> Result<(), RateLimitExceededError> {
+        let Some(limit) = self.limit else {
+            return Ok(());
+        };
+
+        let window_ns = limit.window_ns();
+        self.evict_expired(timestamp_ns, window_ns);
+
+        let error = RateLimitExceededError {
+            max_requests: limit.max_requests,
+            window_seconds: limit.window_seconds.0,
+        };
+        // Otherwise, a rejected request would leave behind an empty entry
+        // that is never in the log, and so never evicted.
+        if limit.max_requests == 0 {
+            return Err(error);
+        }
+
+        let timestamps = self.requests.entry(account_id.clone()).or_default();
+        timestamps.retain(|&t| timestamp_ns.saturating_sub(t) < window_ns);
+
+        if timestamps.len() >= limit.max_requests as usize {
+            return Err(error);
+        }
+
+        timestamps.push(timestamp_ns);
+        self.request_log
+            .insert(self.request_log_end, (account_id.clone(), timestamp_ns));
+        self.request_log_end += 1;
+        Ok(())
+    }
+
+    fn evict_expired(&mut self, now_ns: u64, window_ns: u64) {
+        for _ in 0..MAX_EVICTIONS_PER_REQUEST {
+            let Some((account_id, timestamp_ns)) = self.request_log.get(&self.request_log_start)
+            else {
+                return;
+            };
+
+            if now_ns.saturating_sub(*timestamp_ns) < window_ns {
+                return;
+            }
+
+            let account_id = account_id.clone();
+            self.request_log.remove(&self.request_log_start);
+            self.request_log_start += 1;
+
+            // The account may have made more recent requests, which are still
+            // in the log and will be evicted in turn.
+            let is_expired = self.requests.get(&account_id).map_or(false, |timestamps| {
+                timestamps
+                    .last()
+                    .map_or(true, |&t| now_ns.saturating_sub(t) >= window_ns)
+            });
+            if is_expired {
+                self.requests.remove(&account_id);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+fn rate_limiter(max_requests: u32, window_seconds: u64) -> RateLimiter {
+    let mut rate_limiter = RateLimiter::new(b"r", b"l");
+    rate_limiter.limit = Some(RateLimit {
+        max_requests,
+        window_seconds: window_seconds.into(),
+    });
+    rate_limiter
+}
+
+#[test]
+fn test_rate_limit_disabled() {
+    let mut rate_limiter = RateLimiter::new(b"r", b"l");
+    let alice: AccountId = "alice.near".parse().unwrap();
+
+    for _ in 0..100 {
+        rate_limiter.record_request(&alice, 0).unwrap();
+    }
+}
+
+#[test]
+fn test_rate_limit_exceeded_within_window() {
+    let mut rate_limiter = rate_limiter(3, 60);
+    let alice: AccountId = "alice.near".parse().unwrap();
+
+    for i in 0..3 {
+        rate_limiter
+            .record_request(&alice, i * NANOSECONDS_PER_SECOND)
+            .unwrap();
+    }
+
+    assert!(rate_limiter
+        .record_request(&alice, 59 * NANOSECONDS_PER_SECOND)
+        .is_err());
+
+    // Other accounts are unaffected.
+    rate_limiter
+        .record_request(&"bob.near".parse().unwrap(), 59 * NANOSECONDS_PER_SECOND)
+        .unwrap();
+}
+
+#[test]
+fn test_rate_limit_rolling_window() {
+    let mut rate_limiter = rate_limiter(2, 60);
+    let alice: AccountId = "alice.near".parse().unwrap();
+
+    rate_limiter.record_request(&alice, 0).unwrap();
+    rate_limiter
+        .record_request(&alice, 30 * NANOSECONDS_PER_SECOND)
+        .unwrap();
+    assert!(rate_limiter
+        .record_request(&alice, 45 * NANOSECONDS_PER_SECOND)
+        .is_err());
+
+    // The first request has left the window, but the second has not.
+    rate_limiter
+        .record_request(&alice, 60 * NANOSECONDS_PER_SECOND)
+        .unwrap();
+    assert!(rate_limiter
+        .record_request(&alice, 61 * NANOSECONDS_PER_SECOND)
+        .is_err());
+
+    // Both have left the window.
+    rate_limiter
+        .record_request(&alice, 150 * NANOSECONDS_PER_SECOND)
+        .unwrap();
+    rate_limiter
+        .record_request(&alice, 150 * NANOSECONDS_PER_SECOND)
+        .unwrap();
+}
+
+#[test]
+fn test_rate_limit_evicts_expired_accounts() {
+    let mut rate_limiter = rate_limiter(2, 60);
+    let alice: AccountId = "alice.near".parse().unwrap();
+    let bob: AccountId = "bob.near".parse().unwrap();
+    let carol: AccountId = "carol.near".parse().unwrap();
+
+    rate_limiter.record_request(&alice, 0).unwrap();
+    rate_limiter
+        .record_request(&bob, 10 * NANOSECONDS_PER_SECOND)
+        .unwrap();
+    rate_limiter
+        .record_request(&bob, 70 * NANOSECONDS_PER_SECOND)
+        .unwrap();
+
+    // Alice's only request left the window and was evicted; Bob's first
+    // request also left the window, but his second has not.
+    assert!(rate_limiter.requests.get(&alice).is_none());
+    assert_eq!(
+        rate_limiter.requests.get(&bob),
+        Some(&vec![70 * NANOSECONDS_PER_SECOND]),
+    );
+    assert_eq!(
+        rate_limiter.request_log_end - rate_limiter.request_log_start,
+        1,
+    );
+
+    rate_limiter
+        .record_request(&carol, 200 * NANOSECONDS_PER_SECOND)
+        .unwrap();
+    assert!(rate_limiter.requests.get(&bob).is_none());
+    assert_eq!(
+        rate_limiter.request_log_end - rate_limiter.request_log_start,
+        1,
+    );
+}
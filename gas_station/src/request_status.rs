@@ -0,0 +1,251 @@
+use ethers_core::utils::hex;
+use lib::{
+    foreign_address::ForeignAddress,
+    hash::{keccak256, to_hex_prefixed},
+    kdf::sha256,
+};
+use near_sdk::{
+    json_types::{U128, U64},
+    near, AccountId,
+};
+
+use crate::{error::ContractError, transaction_input::TransactionInput};
+
+/// Identifies a sign request by its parameters, so that resubmitting the same
+/// request resolves to the same ID.
+pub type RequestId = [u8; 32];
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [borsh, json])]
+pub enum RequestStatus {
+    /// Waiting for the MPC signature.
+    Pending,
+    Completed {
+        /// Hex-encoded raw signed transaction.
+        raw_transaction: String,
+        /// The address that the signature recovers to, which has been
+        /// checked to be the derived foreign address.
+        sender: ForeignAddress,
+    },
+    /// The nonce was released, so the request may be resubmitted.
+    Failed { reason: String },
+    /// Cancelled by its caller before the MPC signature arrived.
+    Cancelled,
+}
+
+impl RequestStatus {
+    /// Bytes of the signature fields (y parity or `v`, `r` and `s`) in the
+    /// RLP encoding of a signed transaction, and of the growth of its list
+    /// prefix, at most.
+    const MAX_SIGNATURE_RLP_LEN: usize = 9 + 33 + 33 + 2;
+
+    /// How many more bytes the `Completed` status of a transaction whose
+    /// unsigned encoding is `unsigned_len` bytes long takes up than
+    /// `Pending`, at most: the length prefix and `0x`-prefixed hex of the
+    /// raw signed transaction, and the sender.
+    pub fn completed_storage_growth(unsigned_len: usize) -> u64 {
+        let raw_transaction_len = 2 + 2 * (unsigned_len + Self::MAX_SIGNATURE_RLP_LEN);
+        u64::try_from(4 + raw_transaction_len + 20).unwrap_or(u64::MAX)
+    }
+}
+
+/// A sign request, as returned by `get_request_status`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [json])]
+pub struct SignRequestRecord {
+    pub status: RequestStatus,
+    /// The reference that the caller attached to the request, if any.
+    pub memo: Option<String>,
+    /// The MPC contract that the signature was last requested from, while
+    /// the request is pending.
+    pub mpc_contract_id: Option<AccountId>,
+}
+
+/// What a completed sign request resolves to: the signed transaction, with
+/// what relayers need to broadcast and track it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [json])]
+pub struct SignResult {
+    /// Hex-encoded raw signed transaction, ready for
+    /// `eth_sendRawTransaction`.
+    pub raw_tx: String,
+    /// Hex-encoded hash of `raw_tx`, by which the foreign chain identifies
+    /// the transaction.
+    pub tx_hash: String,
+    /// The address that the signature recovers to.
+    pub sender: ForeignAddress,
+    pub nonce: U64,
+    pub chain_id: U64,
+}
+
+impl SignResult {
+    #[must_use]
+    pub fn new(raw_tx: &[u8], sender: ForeignAddress, nonce: u64, chain_id: u64) -> Self {
+        Self {
+            raw_tx: to_hex_prefixed(raw_tx),
+            tx_hash: to_hex_prefixed(&keccak256(raw_tx)),
+            sender,
+            nonce: nonce.into(),
+            chain_id: chain_id.into(),
+        }
+    }
+}
+
+/// What a sign request that is still waiting for its MPC signature was
+/// assigned, to undo it if the request is cancelled.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [borsh])]
+pub struct PendingSignRequest {
+    pub caller: AccountId,
+    pub chain_id: u64,
+    pub foreign_address: ForeignAddress,
+    pub nonce: u64,
+    pub created_at_ns: u64,
+    /// To request the signature again from a fallback MPC contract.
+    pub mpc_path: String,
+    pub key_version: u32,
+    /// The storage cost (in yoctoNEAR) charged to `caller` for the request's
+    /// records, including those that it will leave behind once resolved.
+    /// Whatever they do not take up is refunded then.
+    pub storage_deposit: u128,
+    /// The storage (in bytes) that the request's records took up when they
+    /// were written.
+    pub storage_usage: u64,
+}
+
+/// What sign requests have completed on a chain.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[near(serializers = [borsh])]
+pub struct ChainSignStats {
+    /// Sign requests that resolved to a signed transaction.
+    pub signed: u64,
+    /// NEAR fees (in yoctoNEAR) prepaid for them, less refunds on
+    /// settlement.
+    pub fees_collected: u128,
+}
+
+/// What happens to the nonce of a cancelled sign request.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[near(serializers = [borsh, json])]
+pub enum CancelledNoncePolicy {
+    /// Reuse the nonce for the next request, if no later nonce has been
+    /// assigned since. The MPC contract may still produce the signature of
+    /// the cancelled request, in which case only one of the two
+    /// transactions with that nonce can be included.
+    #[default]
+    Release,
+    /// Never reuse the nonce, so that the foreign address needs a
+    /// transaction with it to be broadcast before any later ones can be.
+    Burn,
+}
+
+/// What `sign` would do with a request, as returned by `dry_run_sign`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [json])]
+pub struct SignDryRun {
+    /// Hex-encoded.
+    pub request_id: String,
+    pub foreign_address: ForeignAddress,
+    pub nonce: U64,
+    /// Hex-encoded hash that the MPC contract would be asked to sign.
+    pub signing_hash: String,
+    /// The NEAR fee that would be charged, in yoctoNEAR.
+    pub fee: U128,
+    /// The raw signed transaction if the request has already completed, in
+    /// which case it would be neither signed nor charged again.
+    pub raw_transaction: Option<String>,
+}
+
+/// One of the requests of `sign_batch`, with the same arguments as `sign`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [json])]
+pub struct BatchSignRequest {
+    pub path: String,
+    pub transaction: TransactionInput,
+    pub key_version: Option<u32>,
+    pub memo: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [borsh])]
+pub enum SignBatchItem {
+    Requested(RequestId),
+    /// Rejected before a signature was requested.
+    Rejected {
+        reason: String,
+    },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [json])]
+pub struct SignBatchItemStatus {
+    /// Hex-encoded. `None` if the request was rejected.
+    pub request_id: Option<String>,
+    pub status: RequestStatus,
+}
+
+/// `sha256(caller || chain_id || nonce || key_version || foreign_address ||
+/// payload)`, where `foreign_address` is the address derived for the path of
+/// the request and `payload` is the hash to be signed by the MPC contract.
+/// The integers are big-endian; since every field after `caller` is
+/// fixed-width, the encoding is unambiguous.
+///
+/// The same transaction signed from two paths, or with two key versions, is
+/// thus two distinct requests.
+#[must_use]
+pub fn sign_request_id(
+    caller: &AccountId,
+    chain_id: u64,
+    nonce: u64,
+    key_version: u32,
+    foreign_address: &ForeignAddress,
+    payload: &[u8; 32],
+) -> RequestId {
+    let mut preimage = Vec::with_capacity(caller.len() + 8 + 8 + 4 + 20 + 32);
+    preimage.extend_from_slice(caller.as_bytes());
+    preimage.extend_from_slice(&chain_id.to_be_bytes());
+    preimage.extend_from_slice(&nonce.to_be_bytes());
+    preimage.extend_from_slice(&key_version.to_be_bytes());
+    preimage.extend_from_slice(foreign_address.as_ref());
+    preimage.extend_from_slice(payload);
+
+    sha256(&preimage)
+        .try_into()
+        .unwrap_or_else(|_| unreachable!("SHA-256 digests are 32 bytes"))
+}
+
+/// Parses a hex-encoded request ID, as accepted by view and change methods.
+///
+/// # Errors
+///
+/// Returns an error if `request_id` is not 32 hex-encoded bytes.
+pub fn parse_request_id(request_id: &str) -> Result<RequestId, ContractError> {
+    hex::decode(request_id)
+        .ok()
+        .and_then(|bytes| bytes.try_into().ok())
+        .ok_or_else(|| {
+            ContractError::InvalidInput("Request ID must be 32 hex-encoded bytes".into())
+        })
+}
+
+#[test]
+fn test_sign_request_id() {
+    let alice: AccountId = "alice.near".parse().unwrap();
+    let address = ForeignAddress([5; 20]);
+    let id = sign_request_id(&alice, 1, 0, 0, &address, &[7; 32]);
+
+    assert_eq!(id, sign_request_id(&alice, 1, 0, 0, &address, &[7; 32]));
+
+    assert_ne!(id, sign_request_id(&alice, 1, 0, 0, &address, &[8; 32]));
+    assert_ne!(id, sign_request_id(&alice, 1, 1, 0, &address, &[7; 32]));
+    assert_ne!(id, sign_request_id(&alice, 2, 0, 0, &address, &[7; 32]));
+    assert_ne!(id, sign_request_id(&alice, 1, 0, 1, &address, &[7; 32]));
+    assert_ne!(
+        id,
+        sign_request_id(&alice, 1, 0, 0, &ForeignAddress([6; 20]), &[7; 32]),
+    );
+    assert_ne!(
+        id,
+        sign_request_id(&"bob.near".parse().unwrap(), 1, 0, 0, &address, &[7; 32]),
+    );
+}
@@ -0,0 +1,102 @@
+use lib::foreign_address::ForeignAddress;
+use near_sdk::{
+    json_types::{U128, U64},
+    near,
+    serde_json::{json, Value},
+    AccountId,
+};
+
+/// A read-only call on a foreign chain, requested by `simulate_call` and
+/// fulfilled by a relayer, so that callers can check that a transaction
+/// would succeed before paying to sign it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [borsh, json])]
+pub struct Simulation {
+    pub caller: AccountId,
+    pub chain_id: U64,
+    pub from: Option<ForeignAddress>,
+    pub to: ForeignAddress,
+    /// Hex-encoded calldata.
+    pub data: String,
+    pub requested_at_block: U64,
+    /// `None` until a relayer reports the result.
+    pub result: Option<SimulationResult>,
+    /// Covers the storage of the simulation, including the largest result
+    /// that a relayer may report. Refunded by `take_simulation`.
+    pub storage_deposit: U128,
+}
+
+/// What the foreign chain returned for a simulated call, as reported by a
+/// relayer.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [borsh, json])]
+pub enum SimulationResult {
+    /// The call returned `return_data` (hex-encoded).
+    Success { return_data: String },
+    /// The call reverted, or the RPC node returned an error.
+    Failure { error: String },
+}
+
+impl Simulation {
+    /// The JSON-RPC `eth_call` request that a relayer sends to an RPC node
+    /// of the chain to fulfill the simulation, against the latest block.
+    #[must_use]
+    pub fn eth_call_request(&self, request_id: u64) -> Value {
+        let mut call = json!({
+            "to": self.to,
+            "data": self.data,
+        });
+        if let Some(from) = self.from {
+            call["from"] = json!(from);
+        }
+
+        json!({
+            "jsonrpc": "2.0",
+            "id": request_id,
+            "method": "eth_call",
+            "params": [call, "latest"],
+        })
+    }
+}
+
+#[test]
+fn test_eth_call_request() {
+    let mut simulation = Simulation {
+        caller: "alice.near".parse().unwrap(),
+        chain_id: 97.into(),
+        from: None,
+        to: "0x0505050505050505050505050505050505050505"
+            .parse()
+            .unwrap(),
+        data: "0x70a08231".to_string(),
+        requested_at_block: 0.into(),
+        result: None,
+        storage_deposit: 0.into(),
+    };
+
+    assert_eq!(
+        simulation.eth_call_request(3),
+        json!({
+            "jsonrpc": "2.0",
+            "id": 3,
+            "method": "eth_call",
+            "params": [
+                {
+                    "to": "0x0505050505050505050505050505050505050505",
+                    "data": "0x70a08231",
+                },
+                "latest",
+            ],
+        }),
+    );
+
+    simulation.from = Some(
+        "0x0606060606060606060606060606060606060606"
+            .parse()
+            .unwrap(),
+    );
+    assert_eq!(
+        simulation.eth_call_request(3)["params"][0]["from"],
+        "0x0606060606060606060606060606060606060606",
+    );
+}
@@ -0,0 +1,194 @@
+use ethers_core::{types::U256, utils::hex};
+use lib::{foreign_address::ForeignAddress, transaction::AccessList};
+use near_sdk::{
+    json_types::{U128, U64},
+    near,
+};
+
+use crate::error::ContractError;
+
+/// A foreign transaction to be built, nonced, and signed by the gas station.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [json])]
+pub struct TransactionInput {
+    pub chain_id: U64,
+    /// Defaults to the next nonce for the foreign address. Set this to the
+    /// nonce that a previous request was assigned to resubmit it.
+    pub nonce: Option<U64>,
+    /// `None` for contract creation.
+    pub to: Option<ForeignAddress>,
+    /// Defaults to zero.
+    pub value: Option<Wei>,
+    /// Hex-encoded calldata. Defaults to empty.
+    pub data: Option<String>,
+    /// Defaults to the intrinsic gas of the transaction, which is enough for
+    /// transfers, but not for calls that execute code.
+    pub gas_limit: Option<U128>,
+    /// Defaults to the current quote from the gas price oracle.
+    pub max_fee_per_gas: Option<U128>,
+    /// Defaults to the current quote from the gas price oracle.
+    pub max_priority_fee_per_gas: Option<U128>,
+    /// Defaults to empty. Signed in canonical order, whatever the order in
+    /// which it is given.
+    pub access_list: Option<Vec<AccessListItem>>,
+}
+
+/// An entry of an [EIP-2930](https://eips.ethereum.org/EIPS/eip-2930) access
+/// list, in the JSON form used by Ethereum RPC nodes.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [json])]
+#[serde(rename_all = "camelCase")]
+pub struct AccessListItem {
+    pub address: ForeignAddress,
+    /// Hex-encoded 32-byte storage keys.
+    pub storage_keys: Vec<String>,
+}
+
+/// Decodes the storage keys of `access_list`.
+///
+/// # Errors
+///
+/// Returns an error if a storage key is not 32 hex-encoded bytes.
+pub fn decode_access_list(access_list: Vec<AccessListItem>) -> Result<AccessList, ContractError> {
+    access_list
+        .into_iter()
+        .map(|item| {
+            let storage_keys = item
+                .storage_keys
+                .iter()
+                .map(|storage_key| {
+                    hex::decode(storage_key)
+                        .ok()
+                        .and_then(|bytes| bytes.try_into().ok())
+                        .ok_or_else(|| {
+                            ContractError::InvalidInput(
+                                "Access list storage keys must be 32 hex-encoded bytes".into(),
+                            )
+                        })
+                })
+                .collect::<Result<_, _>>()?;
+            Ok((item.address, storage_keys))
+        })
+        .collect()
+}
+
+/// An amount of the native token of a foreign chain, in its smallest unit,
+/// which may take up all 256 bits of an EVM word. Accepted as a decimal
+/// string or a `0x`-prefixed hex string, and serialized as a decimal string.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[near(serializers = [json])]
+#[serde(try_from = "String", into = "String")]
+pub struct Wei(pub U256);
+
+impl From<U256> for Wei {
+    fn from(value: U256) -> Self {
+        Self(value)
+    }
+}
+
+impl From<u128> for Wei {
+    fn from(value: u128) -> Self {
+        Self(value.into())
+    }
+}
+
+impl From<Wei> for String {
+    fn from(value: Wei) -> Self {
+        value.0.to_string()
+    }
+}
+
+impl TryFrom<String> for Wei {
+    type Error = String;
+
+    fn try_from(encoded: String) -> Result<Self, Self::Error> {
+        let parsed = match encoded.strip_prefix("0x") {
+            _ if encoded.is_empty() => None,
+            Some(hex) if !hex.is_empty() && hex.len() <= 64 => U256::from_str_radix(hex, 16).ok(),
+            Some(_) => None,
+            None => U256::from_dec_str(&encoded).ok(),
+        };
+
+        parsed
+            .map(Self)
+            .ok_or_else(|| format!("Invalid 256-bit value: {encoded}"))
+    }
+}
+
+#[test]
+fn test_wei_json() {
+    use near_sdk::serde_json::{from_value, json, to_value};
+
+    for (encoded, expected) in [
+        ("0", U256::zero()),
+        ("0x0", U256::zero()),
+        (
+            "340282366920938463463374607431768211455",
+            U256::from(u128::MAX),
+        ),
+        ("0xffffffffffffffffffffffffffffffff", U256::from(u128::MAX)),
+        (
+            "115792089237316195423570985008687907853269984665640564039457584007913129639935",
+            U256::MAX,
+        ),
+        (
+            "0xffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffff",
+            U256::MAX,
+        ),
+    ] {
+        let value: Wei = from_value(json!(encoded)).unwrap();
+        assert_eq!(value.0, expected, "{encoded}");
+        assert_eq!(to_value(value).unwrap(), json!(expected.to_string()));
+    }
+}
+
+#[test]
+fn test_access_list_json() {
+    use near_sdk::serde_json::{from_value, json};
+
+    let access_list: Vec<AccessListItem> = from_value(json!([
+        {
+            "address": "0x0505050505050505050505050505050505050505",
+            "storageKeys": [format!("0x{}", "01".repeat(32))],
+        },
+        {
+            "address": "0x0606060606060606060606060606060606060606",
+            "storageKeys": [],
+        },
+    ]))
+    .unwrap();
+
+    assert_eq!(
+        decode_access_list(access_list).unwrap(),
+        vec![
+            (ForeignAddress([5; 20]), vec![[1; 32]]),
+            (ForeignAddress([6; 20]), vec![]),
+        ],
+    );
+
+    for storage_key in ["0x01", "0xzz"] {
+        assert!(matches!(
+            decode_access_list(vec![AccessListItem {
+                address: ForeignAddress([5; 20]),
+                storage_keys: vec![storage_key.to_string()],
+            }]),
+            Err(ContractError::InvalidInput(_)),
+        ));
+    }
+}
+
+#[test]
+fn test_wei_rejects_invalid_values() {
+    for encoded in [
+        "",
+        "0x",
+        "-1",
+        "1.5",
+        "0xg",
+        // 2^256
+        "115792089237316195423570985008687907853269984665640564039457584007913129639936",
+        "0x10000000000000000000000000000000000000000000000000000000000000000",
+    ] {
+        assert!(Wei::try_from(encoded.to_string()).is_err(), "{encoded}");
+    }
+}
@@ -0,0 +1,96 @@
+use near_sdk::{env, near, NearToken};
+
+/// Accounts for the NEAR fees that the contract has collected, separately
+/// from the rest of its balance (most notably the balance that it needs to
+/// stake for storage), which `env::account_balance` does not distinguish.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[near]
+pub struct Treasury {
+    /// Fees (in yoctoNEAR) settled and not withdrawn yet.
+    withdrawable_balance: u128,
+    /// Fees (in yoctoNEAR) prepaid for sign requests and neither settled nor
+    /// refunded yet, which may still be refunded in part or in full.
+    unsettled_balance: u128,
+}
+
+impl Treasury {
+    pub fn withdrawable_balance(&self) -> NearToken {
+        NearToken::from_yoctonear(self.withdrawable_balance)
+    }
+
+    pub fn unsettled_balance(&self) -> NearToken {
+        NearToken::from_yoctonear(self.unsettled_balance)
+    }
+
+    /// Records `amount` of fees prepaid.
+    pub fn prepay(&mut self, amount: u128) {
+        self.unsettled_balance = self.unsettled_balance.saturating_add(amount);
+    }
+
+    /// Records `amount` of prepaid fees refunded.
+    pub fn refund(&mut self, amount: u128) {
+        self.unsettled_balance = self.unsettled_balance.saturating_sub(amount);
+    }
+
+    /// Records `amount` of prepaid fees settled, which makes them
+    /// withdrawable.
+    pub fn settle(&mut self, amount: u128) {
+        let amount = amount.min(self.unsettled_balance);
+        self.unsettled_balance -= amount;
+        self.withdrawable_balance = self.withdrawable_balance.saturating_add(amount);
+    }
+
+    /// Records `amount` of fees withdrawn.
+    pub fn debit(&mut self, amount: u128) {
+        self.withdrawable_balance = self.withdrawable_balance.saturating_sub(amount);
+    }
+}
+
+/// The balance of the contract, as returned by `get_treasury`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [json])]
+pub struct TreasuryInfo {
+    /// Fees that the owner may withdraw.
+    pub withdrawable: NearToken,
+    /// Prepaid fees that are yet to be settled or refunded.
+    pub unsettled: NearToken,
+    /// The balance staked for the storage that the contract uses.
+    pub storage_reserved: NearToken,
+    /// The whole balance of the contract, which also holds e.g. the storage
+    /// deposits of sign requests.
+    pub total: NearToken,
+}
+
+/// The balance that the contract needs to cover its current storage usage.
+pub fn storage_reserve() -> NearToken {
+    NearToken::from_yoctonear(storage_cost(env::storage_usage()))
+}
+
+/// The balance (in yoctoNEAR) needed to cover `bytes` of storage.
+pub fn storage_cost(bytes: u64) -> u128 {
+    env::storage_byte_cost()
+        .saturating_mul(bytes.into())
+        .as_yoctonear()
+}
+
+#[test]
+fn test_treasury() {
+    let mut treasury = Treasury::default();
+
+    treasury.prepay(100);
+    treasury.prepay(50);
+    assert_eq!(treasury.unsettled_balance().as_yoctonear(), 150);
+    assert_eq!(treasury.withdrawable_balance().as_yoctonear(), 0);
+
+    treasury.refund(20);
+    treasury.settle(120);
+    assert_eq!(treasury.unsettled_balance().as_yoctonear(), 10);
+    assert_eq!(treasury.withdrawable_balance().as_yoctonear(), 120);
+
+    treasury.debit(90);
+    assert_eq!(treasury.withdrawable_balance().as_yoctonear(), 30);
+
+    // Never negative.
+    treasury.debit(40);
+    assert_eq!(treasury.withdrawable_balance().as_yoctonear(), 0);
+}
@@ -9,6 +9,8 @@ use ethers_core::{
 };
 use gas_station::{
     chain_configuration::ViewPaymasterConfiguration, contract_event::TransactionSequenceSigned,
+    fee::Quote, gas_price_oracle::GasPriceInput, request_status::SignResult as SignedTransaction,
+    transaction_input::TransactionInput, treasury::TreasuryInfo,
     Nep141ReceiverCreateTransactionArgs, TransactionSequenceCreation,
 };
 use lib::{
@@ -462,11 +464,55 @@ async fn fail_unsupported_chain_id() {
         .unwrap();
 }
 
+#[tokio::test]
+async fn test_foreign_address_for() {
+    let Setup {
+        gas_station, alice, ..
+    } = setup().await;
+
+    let mpc_public_key: near_sdk::PublicKey = "secp256k1:47xve2ymatpG4x4Gp7pmYwuLJk7eeRegrFuS4VoW5VV4i3GsBiBY87vkH6UZiiY18NeZnkBzcZzipDbJJ5pmjTcc"
+        .parse()
+        .unwrap();
+
+    alice
+        .call(gas_station.id(), "set_mpc_key")
+        .args_json(json!({ "key_version": 0, "public_key": mpc_public_key }))
+        .transact()
+        .await
+        .unwrap()
+        .unwrap();
+
+    let foreign_address = gas_station
+        .view("foreign_address_for")
+        .args_json(json!({
+            "account_id": alice.id(),
+            "path": "ethereum-1",
+            "chain_id": "0",
+        }))
+        .await
+        .unwrap()
+        .json::<String>()
+        .unwrap();
+
+    assert_eq!(
+        foreign_address,
+        get_mpc_address(
+            mpc_public_key,
+            &gas_station.id().as_str().parse().unwrap(),
+            &format!("{},ethereum-1", alice.id()),
+        )
+        .unwrap()
+        .to_string(),
+    );
+}
+
 #[tokio::test]
 async fn test_workflow_happy_path() {
     let Setup {
         gas_station,
         oracle,
+        signer,
+        nft_key,
         local_ft,
         alice,
         alice_key,
@@ -625,6 +671,22 @@ async fn test_workflow_happy_path() {
     let (signed_tx, _s) = TypedTransaction::decode_signed(&signed_transaction_rlp).unwrap();
     assert_eq!(alice_foreign_address, signed_tx.from().unwrap().into());
 
+    let signer_public_key = signer
+        .view("public_key")
+        .await
+        .unwrap()
+        .json::<near_sdk::PublicKey>()
+        .unwrap();
+    assert_eq!(
+        alice_foreign_address,
+        get_mpc_address(
+            signer_public_key,
+            &nft_key.id().as_str().parse().unwrap(),
+            &format!("{alice_key},"),
+        )
+        .unwrap(),
+    );
+
     let signed_transaction_sequences = gas_station
         .view("list_signed_transaction_sequences_after")
         .args_json(json!({
@@ -895,3 +957,205 @@ fn test_derive_new_mpc() {
         recovered_signed_transaction.from().unwrap()
     );
 }
+
+struct SignSetup {
+    gas_station: Contract,
+    alice: Account,
+}
+
+/// The gas station with Alice as its owner (and so its relayer), signing
+/// through the mock MPC contract for chain 97, at 1 NEAR per gas token.
+async fn setup_sign() -> SignSetup {
+    let worker = near_workspaces::sandbox().await.unwrap();
+
+    let (gas_station, signer, alice) = tokio::join!(
+        async {
+            let wasm = near_workspaces::compile_project("./").await.unwrap();
+            worker.dev_deploy(&wasm).await.unwrap()
+        },
+        async {
+            let wasm = near_workspaces::compile_project("../mock/signer")
+                .await
+                .unwrap();
+            worker.dev_deploy(&wasm).await.unwrap()
+        },
+        async { worker.dev_create_account().await.unwrap() },
+    );
+
+    let mpc_public_key = signer
+        .view("public_key")
+        .await
+        .unwrap()
+        .json::<near_sdk::PublicKey>()
+        .unwrap();
+
+    alice
+        .batch(gas_station.id())
+        .call(Function::new("new").args_json(json!({
+            "signer_contract_id": signer.id(),
+            "oracle_id": signer.id(),
+        })))
+        .call(Function::new("set_mpc_contract_id").args_json(json!({
+            "account_id": signer.id(),
+        })))
+        .call(Function::new("set_mpc_key").args_json(json!({
+            "key_version": 0,
+            "public_key": mpc_public_key,
+        })))
+        .call(Function::new("add_foreign_chain").args_json(json!({
+            "chain_id": "97",
+            "oracle_asset_id": PYTH_PRICE_ID_ETH_USD,
+            "transfer_gas": "21000",
+            "fee_rate": ["120", "100"],
+            "decimals": 18,
+        })))
+        .call(Function::new("update_gas_price").args_json(json!({
+            "chain_id": "97",
+            "max_fee_per_gas": GasPriceInput::Gwei("20".to_string()),
+            "max_priority_fee_per_gas": GasPriceInput::Gwei("1".to_string()),
+        })))
+        .call(
+            Function::new("update_foreign_token_price").args_json(json!({
+                "chain_id": "97",
+                "price_in_near": U128(NearToken::from_near(1).as_yoctonear()),
+            })),
+        )
+        .transact()
+        .await
+        .unwrap()
+        .unwrap();
+
+    SignSetup { gas_station, alice }
+}
+
+fn sign_transaction_input() -> TransactionInput {
+    TransactionInput {
+        chain_id: 97.into(),
+        nonce: None,
+        to: Some(ForeignAddress([1; 20])),
+        value: Some(U256::from(100).into()),
+        data: None,
+        gas_limit: Some(21_000.into()),
+        max_fee_per_gas: Some(20_000_000_000.into()),
+        max_priority_fee_per_gas: Some(1_000_000_000.into()),
+        access_list: None,
+    }
+}
+
+async fn quote_sign_fee(gas_station: &Contract, alice: &Account) -> u128 {
+    gas_station
+        .view("get_quote")
+        .args_json(json!({
+            "caller": alice.id(),
+            "path": "ethereum-1",
+            "chain_id": "97",
+            "gas_limit": "21000",
+        }))
+        .await
+        .unwrap()
+        .json::<Quote>()
+        .unwrap()
+        .near_fee
+        .0
+}
+
+/// Signs [`sign_transaction_input`] for Alice with `deposit` attached, and
+/// returns how much her balance went down by, gas included.
+async fn sign_with_deposit(
+    gas_station: &Contract,
+    alice: &Account,
+    deposit: u128,
+) -> Result<u128, String> {
+    let balance_before = alice.view_account().await.unwrap().balance;
+
+    let outcome = alice
+        .call(gas_station.id(), "sign")
+        .args_json(json!({
+            "path": "ethereum-1",
+            "transaction": sign_transaction_input(),
+        }))
+        .deposit(NearToken::from_yoctonear(deposit))
+        .max_gas()
+        .transact()
+        .await
+        .unwrap();
+    outcome
+        .into_result()
+        .map_err(|failure| format!("{failure:?}"))?
+        .json::<SignedTransaction>()
+        .unwrap();
+
+    let balance_after = alice.view_account().await.unwrap().balance;
+    Ok(balance_before.as_yoctonear() - balance_after.as_yoctonear())
+}
+
+/// The storage deposit that a sign request for `fee` requires, as reported
+/// when it is not attached.
+async fn sign_storage_deposit(gas_station: &Contract, alice: &Account, fee: u128) -> u128 {
+    let error = sign_with_deposit(gas_station, alice, fee)
+        .await
+        .unwrap_err();
+    let (_, rest) = error
+        .split_once("short of the storage deposit of ")
+        .unwrap_or_else(|| panic!("Unexpected error: {error}"));
+    rest.split_once(' ').unwrap().0.parse().unwrap()
+}
+
+#[tokio::test]
+async fn fail_sign_deposit_short_of_fee() {
+    let SignSetup { gas_station, alice } = setup_sign().await;
+    let fee = quote_sign_fee(&gas_station, &alice).await;
+
+    let error = sign_with_deposit(&gas_station, &alice, fee - 1)
+        .await
+        .unwrap_err();
+
+    assert!(
+        error.contains(&format!(
+            "Attached deposit is 1 yoctoNEAR short of the fee of {fee} yoctoNEAR"
+        )),
+        "{error}",
+    );
+    assert_eq!(
+        gas_station
+            .view("get_sign_fees")
+            .args_json(json!({ "chain_id": "97" }))
+            .await
+            .unwrap()
+            .json::<U128>()
+            .unwrap(),
+        U128(0),
+    );
+}
+
+#[tokio::test]
+async fn test_sign_deposit_exact_and_excess() {
+    let SignSetup { gas_station, alice } = setup_sign().await;
+    let fee = quote_sign_fee(&gas_station, &alice).await;
+    let storage_deposit = sign_storage_deposit(&gas_station, &alice, fee).await;
+
+    let exact_cost = sign_with_deposit(&gas_station, &alice, fee + storage_deposit)
+        .await
+        .unwrap();
+    let excess_cost = sign_with_deposit(
+        &gas_station,
+        &alice,
+        fee + storage_deposit + NearToken::from_near(1).as_yoctonear(),
+    )
+    .await
+    .unwrap();
+
+    // Both requests were charged the fee alone, and the excess was refunded.
+    let treasury = gas_station
+        .view("get_treasury")
+        .await
+        .unwrap()
+        .json::<TreasuryInfo>()
+        .unwrap();
+    assert_eq!(treasury.unsettled.as_yoctonear(), 2 * fee);
+    assert!(exact_cost >= fee);
+    assert!(
+        excess_cost.abs_diff(exact_cost) < NearToken::from_millinear(10).as_yoctonear(),
+        "charged {excess_cost} with an excess deposit, {exact_cost} without",
+    );
+}
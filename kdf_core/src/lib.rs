@@ -0,0 +1,88 @@
+//! The curve math behind `lib::kdf`, without any NEAR types or the NEAR SDK,
+//! so that it can be reused off-chain. Signer IDs are plain strings, which
+//! must be valid NEAR account IDs for the results to match the keys the MPC
+//! network derives.
+//!
+//! Only depends on `k256`, and on `sha2` with the `native-hash` feature, and
+//! does not need `std`.
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::{format, string::String};
+
+use k256::{
+    elliptic_curve::{ops::Reduce, CurveArithmetic},
+    Scalar, Secp256k1, U256,
+};
+
+pub type PublicKey = <Secp256k1 as CurveArithmetic>::AffinePoint;
+
+// Constant prefix that ensures epsilon derivation values are used specifically for
+// near-mpc-recovery with key derivation protocol vX.Y.Z.
+pub const EPSILON_DERIVATION_PREFIX: &str = "near-mpc-recovery v0.1.0 epsilon derivation:";
+
+/// The string that is hashed to derive the epsilon for `signer_id` and
+/// `path` under the derivation prefix `prefix`.
+#[must_use]
+pub fn epsilon_derivation_preimage(prefix: &str, signer_id: &str, path: &str) -> String {
+    format!("{prefix}{signer_id},{path}")
+}
+
+/// Reduces the SHA-256 digest of an epsilon derivation preimage into a
+/// scalar. A digest at or above the curve order is astronomically unlikely,
+/// but it is reduced explicitly rather than constructing an invalid scalar.
+#[must_use]
+pub fn epsilon_from_digest(digest: [u8; 32]) -> Scalar {
+    <Scalar as Reduce<U256>>::reduce(U256::from_le_slice(&digest))
+}
+
+/// Same as `lib::kdf::derive_epsilon`, hashing natively.
+#[cfg(feature = "native-hash")]
+#[must_use]
+pub fn derive_epsilon(signer_id: &str, path: &str) -> Scalar {
+    use sha2::Digest;
+
+    let preimage = epsilon_derivation_preimage(EPSILON_DERIVATION_PREFIX, signer_id, path);
+    epsilon_from_digest(sha2::Sha256::digest(preimage).into())
+}
+
+#[must_use]
+pub fn derive_key(public_key: PublicKey, epsilon: Scalar) -> PublicKey {
+    (<Secp256k1 as CurveArithmetic>::ProjectivePoint::GENERATOR * epsilon + public_key).to_affine()
+}
+
+/// Derives the public key for `signer_id` and `path` from the MPC root
+/// public key.
+#[cfg(feature = "native-hash")]
+#[must_use]
+pub fn derive_key_for_account(mpc_public_key: PublicKey, signer_id: &str, path: &str) -> PublicKey {
+    derive_key(mpc_public_key, derive_epsilon(signer_id, path))
+}
+
+#[cfg(feature = "native-hash")]
+#[test]
+fn test_derive_key_for_account() {
+    use ethers_core::k256::ecdsa::SigningKey;
+
+    // The root secret key of the MPC network in `lib`'s tests, whose helpers
+    // this crate cannot use, since `lib` depends on it.
+    let secret = SigningKey::from_bytes(&[0x17; 32].into()).unwrap();
+    let mpc_public_key = *secret.verifying_key().as_affine();
+
+    let derived = derive_key_for_account(mpc_public_key, "canhazgas.testnet", "alice.near");
+
+    // The derived key is controlled by the root secret plus epsilon.
+    let derived_secret =
+        *secret.as_nonzero_scalar().as_ref() + derive_epsilon("canhazgas.testnet", "alice.near");
+    assert_eq!(
+        derived,
+        (<Secp256k1 as CurveArithmetic>::ProjectivePoint::GENERATOR * derived_secret).to_affine(),
+    );
+
+    assert_ne!(
+        derived,
+        derive_key_for_account(mpc_public_key, "canhazgas.testnet", "bob.near"),
+    );
+}
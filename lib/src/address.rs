@@ -0,0 +1,174 @@
+use near_sdk::{near, AccountId, CurveType};
+use thiserror::Error;
+
+use crate::{
+    bitcoin::{p2wpkh_address, BitcoinNetwork},
+    kdf::{
+        derive_near_implicit_account, get_mpc_address, get_mpc_public_key, PublicKeyConversionError,
+    },
+};
+
+/// The kind of foreign address to derive from an MPC key, which determines
+/// both the curve the key must be on and how the derived key is encoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[near(serializers = [borsh, json])]
+pub enum AddressType {
+    /// Checksummed `0x`-prefixed EVM address. Requires a SECP256K1 key.
+    Evm,
+    /// Bech32 native segwit address. Requires a SECP256K1 key.
+    BitcoinP2wpkh(BitcoinNetwork),
+    /// NEAR implicit account. Supports both ED25519 and SECP256K1 keys.
+    NearImplicit,
+}
+
+impl AddressType {
+    #[must_use]
+    pub fn supports_curve(self, curve_type: CurveType) -> bool {
+        match self {
+            Self::Evm | Self::BitcoinP2wpkh(_) => curve_type == CurveType::SECP256K1,
+            Self::NearImplicit => true,
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum DerivationError {
+    #[error("Address type {address_type:?} does not support {curve_type:?} keys")]
+    UnsupportedCurve {
+        address_type: AddressType,
+        curve_type: CurveType,
+    },
+    #[error("Unknown MPC public key curve")]
+    UnknownCurve,
+    #[error(transparent)]
+    PublicKeyConversion(#[from] PublicKeyConversionError),
+    #[error("Bech32 encoding failed: {0}")]
+    Bech32(#[from] bech32::Error),
+}
+
+/// Derives the address of type `address_type` controlled by the MPC key
+/// derived for `account_id` and `path`, in its canonical string form.
+///
+/// # Errors
+///
+/// Returns an error if the MPC public key is invalid, or is on a curve that
+/// `address_type` does not support.
+pub fn derive_address(
+    mpc_public_key: near_sdk::PublicKey,
+    account_id: &AccountId,
+    path: &str,
+    address_type: AddressType,
+) -> Result<String, DerivationError> {
+    // `PublicKey::curve_type` aborts on unknown tags, which is unavailable off-chain.
+    let curve_type = match mpc_public_key.as_bytes()[0] {
+        tag if tag == CurveType::ED25519 as u8 => CurveType::ED25519,
+        tag if tag == CurveType::SECP256K1 as u8 => CurveType::SECP256K1,
+        _ => return Err(DerivationError::UnknownCurve),
+    };
+
+    if !address_type.supports_curve(curve_type) {
+        return Err(DerivationError::UnsupportedCurve {
+            address_type,
+            curve_type,
+        });
+    }
+
+    Ok(match address_type {
+        AddressType::Evm => get_mpc_address(mpc_public_key, account_id, path)?.to_string(),
+        AddressType::BitcoinP2wpkh(network) => p2wpkh_address(
+            &get_mpc_public_key(mpc_public_key, account_id, path)?,
+            network,
+        )?,
+        AddressType::NearImplicit => {
+            derive_near_implicit_account(mpc_public_key, account_id, path)?.to_string()
+        }
+    })
+}
+
+#[cfg(test)]
+fn secp256k1_key() -> near_sdk::PublicKey {
+    "secp256k1:47xve2ymatpG4x4Gp7pmYwuLJk7eeRegrFuS4VoW5VV4i3GsBiBY87vkH6UZiiY18NeZnkBzcZzipDbJJ5pmjTcc"
+        .parse()
+        .unwrap()
+}
+
+#[cfg(test)]
+fn ed25519_key() -> near_sdk::PublicKey {
+    "ed25519:6E8sCci9badyRkXb3JoRpBj5p8C6Tw41ELDZoiihKEtp"
+        .parse()
+        .unwrap()
+}
+
+#[test]
+fn test_derive_address_evm() {
+    let account_id: AccountId = "canhazgas.testnet".parse().unwrap();
+
+    assert_eq!(
+        derive_address(secp256k1_key(), &account_id, "alice.near", AddressType::Evm).unwrap(),
+        get_mpc_address(secp256k1_key(), &account_id, "alice.near")
+            .unwrap()
+            .to_string(),
+    );
+}
+
+#[test]
+fn test_derive_address_bitcoin() {
+    let account_id: AccountId = "canhazgas.testnet".parse().unwrap();
+
+    for network in [BitcoinNetwork::Mainnet, BitcoinNetwork::Testnet] {
+        assert_eq!(
+            derive_address(
+                secp256k1_key(),
+                &account_id,
+                "alice.near",
+                AddressType::BitcoinP2wpkh(network),
+            )
+            .unwrap(),
+            crate::bitcoin::derive_bitcoin_address(
+                secp256k1_key(),
+                &account_id,
+                "alice.near",
+                network,
+            )
+            .unwrap(),
+        );
+    }
+}
+
+#[test]
+fn test_derive_address_near_implicit() {
+    let account_id: AccountId = "canhazgas.testnet".parse().unwrap();
+
+    for key in [secp256k1_key(), ed25519_key()] {
+        assert_eq!(
+            derive_address(
+                key.clone(),
+                &account_id,
+                "alice.near",
+                AddressType::NearImplicit,
+            )
+            .unwrap(),
+            derive_near_implicit_account(key, &account_id, "alice.near")
+                .unwrap()
+                .to_string(),
+        );
+    }
+}
+
+#[test]
+fn test_derive_address_unsupported_curve() {
+    let account_id: AccountId = "canhazgas.testnet".parse().unwrap();
+
+    for address_type in [
+        AddressType::Evm,
+        AddressType::BitcoinP2wpkh(BitcoinNetwork::Mainnet),
+    ] {
+        assert!(matches!(
+            derive_address(ed25519_key(), &account_id, "alice.near", address_type),
+            Err(DerivationError::UnsupportedCurve {
+                address_type: a,
+                curve_type: CurveType::ED25519,
+            }) if a == address_type,
+        ));
+    }
+}
@@ -0,0 +1,158 @@
+use bech32::{u5, ToBase32, Variant};
+use near_sdk::{near, AccountId};
+use thiserror::Error;
+
+use crate::kdf::{get_mpc_public_key, sha256, PublicKeyConversionError};
+
+#[cfg(any(target_arch = "wasm32", not(feature = "native-crypto")))]
+pub fn ripemd160(bytes: &[u8]) -> [u8; 20] {
+    near_sdk::env::ripemd160_array(bytes)
+}
+
+#[cfg(all(not(target_arch = "wasm32"), feature = "native-crypto"))]
+#[must_use]
+pub fn ripemd160(bytes: &[u8]) -> [u8; 20] {
+    use ripemd::Digest;
+    let mut hasher = ripemd::Ripemd160::new();
+    hasher.update(bytes);
+    hasher.finalize().into()
+}
+
+/// `RIPEMD160(SHA256(bytes))`, as used for Bitcoin public key hashes.
+#[must_use]
+pub fn hash160(bytes: &[u8]) -> [u8; 20] {
+    ripemd160(&sha256(bytes))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[near(serializers = [borsh, json])]
+pub enum BitcoinNetwork {
+    Mainnet,
+    Testnet,
+}
+
+impl BitcoinNetwork {
+    /// Human-readable part of bech32 segwit addresses on this network.
+    pub fn hrp(self) -> &'static str {
+        match self {
+            Self::Mainnet => "bc",
+            Self::Testnet => "tb",
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum BitcoinAddressError {
+    #[error(transparent)]
+    PublicKeyConversion(#[from] PublicKeyConversionError),
+    #[error("Bech32 encoding failed: {0}")]
+    Bech32(#[from] bech32::Error),
+}
+
+/// Encodes the native segwit (P2WPKH, witness version 0) address of a
+/// compressed SEC1 public key.
+///
+/// # Errors
+///
+/// Returns an error if bech32 encoding fails.
+pub fn p2wpkh_address(
+    compressed_public_key: &[u8; 33],
+    network: BitcoinNetwork,
+) -> Result<String, bech32::Error> {
+    let witness_version = u5::try_from_u8(0)?;
+    let mut data = vec![witness_version];
+    data.extend(hash160(compressed_public_key).to_base32());
+
+    bech32::encode(network.hrp(), data, Variant::Bech32)
+}
+
+/// Derives the P2WPKH address controlled by the MPC key derived for
+/// `account_id` and `path`.
+///
+/// # Errors
+///
+/// Returns an error if the MPC public key is not a valid SECP256K1 key.
+pub fn derive_bitcoin_address(
+    mpc_public_key: near_sdk::PublicKey,
+    account_id: &AccountId,
+    path: &str,
+    network: BitcoinNetwork,
+) -> Result<String, BitcoinAddressError> {
+    let public_key = get_mpc_public_key(mpc_public_key, account_id, path)?;
+
+    Ok(p2wpkh_address(&public_key, network)?)
+}
+
+#[test]
+fn test_p2wpkh_address() {
+    // Example from BIP-173
+    let public_key: [u8; 33] = ethers_core::utils::hex::decode(
+        "0279BE667EF9DCBBAC55A06295CE870B07029BFCDB2DCE28D959F2815B16F81798",
+    )
+    .unwrap()
+    .try_into()
+    .unwrap();
+
+    assert_eq!(
+        p2wpkh_address(&public_key, BitcoinNetwork::Mainnet).unwrap(),
+        "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4",
+    );
+    assert_eq!(
+        p2wpkh_address(&public_key, BitcoinNetwork::Testnet).unwrap(),
+        "tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx",
+    );
+}
+
+#[test]
+fn test_derive_bitcoin_address() {
+    let mpc_public_key: near_sdk::PublicKey = "secp256k1:47xve2ymatpG4x4Gp7pmYwuLJk7eeRegrFuS4VoW5VV4i3GsBiBY87vkH6UZiiY18NeZnkBzcZzipDbJJ5pmjTcc"
+        .parse()
+        .unwrap();
+    let account_id: AccountId = "canhazgas.testnet".parse().unwrap();
+
+    let mainnet = derive_bitcoin_address(
+        mpc_public_key.clone(),
+        &account_id,
+        "alice.near,bitcoin-1",
+        BitcoinNetwork::Mainnet,
+    )
+    .unwrap();
+    let testnet = derive_bitcoin_address(
+        mpc_public_key.clone(),
+        &account_id,
+        "alice.near,bitcoin-1",
+        BitcoinNetwork::Testnet,
+    )
+    .unwrap();
+
+    assert!(mainnet.starts_with("bc1q"));
+    assert!(testnet.starts_with("tb1q"));
+    assert_eq!(
+        mainnet,
+        p2wpkh_address(
+            &get_mpc_public_key(mpc_public_key, &account_id, "alice.near,bitcoin-1").unwrap(),
+            BitcoinNetwork::Mainnet,
+        )
+        .unwrap(),
+    );
+    // Same witness program on both networks.
+    assert_eq!(mainnet[3..mainnet.len() - 6], testnet[3..testnet.len() - 6]);
+}
+
+#[test]
+fn test_derive_bitcoin_address_rejects_ed25519() {
+    let mpc_public_key: near_sdk::PublicKey =
+        "ed25519:6E8sCci9badyRkXb3JoRpBj5p8C6Tw41ELDZoiihKEtp"
+            .parse()
+            .unwrap();
+
+    assert!(matches!(
+        derive_bitcoin_address(
+            mpc_public_key,
+            &"canhazgas.testnet".parse().unwrap(),
+            "",
+            BitcoinNetwork::Mainnet,
+        ),
+        Err(BitcoinAddressError::PublicKeyConversion(_)),
+    ));
+}
@@ -0,0 +1,218 @@
+use near_sdk::{json_types::U64, near};
+
+use crate::{foreign_address::ForeignAddress, hash::keccak256};
+
+/// The fields of an [EIP-712](https://eips.ethereum.org/EIPS/eip-712)
+/// `EIP712Domain`. Fields that are `None` are left out of the domain type,
+/// as the standard allows.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[near(serializers = [borsh, json])]
+pub struct Eip712Domain {
+    pub name: Option<String>,
+    pub version: Option<String>,
+    pub chain_id: Option<U64>,
+    pub verifying_contract: Option<ForeignAddress>,
+    pub salt: Option<[u8; 32]>,
+}
+
+/// `hashStruct(domain)`.
+#[must_use]
+pub fn domain_separator(domain: &Eip712Domain) -> [u8; 32] {
+    let mut fields = Vec::with_capacity(5);
+    let mut encoded = Vec::with_capacity(32 * 6);
+
+    if let Some(name) = &domain.name {
+        fields.push("string name");
+        encoded.extend_from_slice(&keccak256(name.as_bytes()));
+    }
+    if let Some(version) = &domain.version {
+        fields.push("string version");
+        encoded.extend_from_slice(&keccak256(version.as_bytes()));
+    }
+    if let Some(chain_id) = domain.chain_id {
+        fields.push("uint256 chainId");
+        encoded.extend_from_slice(&[0; 24]);
+        encoded.extend_from_slice(&chain_id.0.to_be_bytes());
+    }
+    if let Some(verifying_contract) = domain.verifying_contract {
+        fields.push("address verifyingContract");
+        encoded.extend_from_slice(&[0; 12]);
+        encoded.extend_from_slice(&verifying_contract.0);
+    }
+    if let Some(salt) = domain.salt {
+        fields.push("bytes32 salt");
+        encoded.extend_from_slice(&salt);
+    }
+
+    let type_hash = keccak256(format!("EIP712Domain({})", fields.join(",")).as_bytes());
+    let mut preimage = type_hash.to_vec();
+    preimage.extend_from_slice(&encoded);
+    keccak256(&preimage)
+}
+
+/// The hash to sign for typed data in `domain`:
+/// `keccak256(0x19 || 0x01 || domainSeparator || struct_hash)`.
+///
+/// Encoding structs requires their full type schema, which is too costly
+/// to parse on-chain, so `struct_hash` (`hashStruct(message)`) is computed
+/// by the caller.
+#[must_use]
+pub fn typed_data_hash(domain: &Eip712Domain, struct_hash: &[u8; 32]) -> [u8; 32] {
+    let mut preimage = Vec::with_capacity(2 + 32 + 32);
+    preimage.extend_from_slice(&[0x19, 0x01]);
+    preimage.extend_from_slice(&domain_separator(domain));
+    preimage.extend_from_slice(struct_hash);
+    keccak256(&preimage)
+}
+
+#[test]
+fn test_typed_data_hash_mail() {
+    use ethers_core::{abi::Token, utils::hex};
+
+    // The example from EIP-712.
+    let domain = Eip712Domain {
+        name: Some("Ether Mail".to_string()),
+        version: Some("1".to_string()),
+        chain_id: Some(1.into()),
+        verifying_contract: Some(
+            "0xCcCCccccCCCCcCCCCCCcCcCccCcCCCcCcccccccC"
+                .parse()
+                .unwrap(),
+        ),
+        salt: None,
+    };
+    assert_eq!(
+        hex::encode(domain_separator(&domain)),
+        "f2cee375fa42b42143804025fc449deafd50cc031ca257e0b194a650a912090f",
+    );
+
+    let person_type_hash = keccak256(b"Person(string name,address wallet)");
+    let person = |name: &str, wallet: &str| {
+        Token::FixedBytes(
+            keccak256(&ethers_core::abi::encode(&[
+                Token::FixedBytes(person_type_hash.to_vec()),
+                Token::FixedBytes(keccak256(name.as_bytes()).to_vec()),
+                Token::Address(wallet.parse().unwrap()),
+            ]))
+            .to_vec(),
+        )
+    };
+    let struct_hash = keccak256(&ethers_core::abi::encode(&[
+        Token::FixedBytes(
+            keccak256(
+                b"Mail(Person from,Person to,string contents)Person(string name,address wallet)",
+            )
+            .to_vec(),
+        ),
+        person("Cow", "0xCD2a3d9F938E13CD947Ec05AbC7FE734Df8DD826"),
+        person("Bob", "0xbBbBBBBbbBBBbbbBbbBbbbbBBbBbbbbBbBbbBBbB"),
+        Token::FixedBytes(keccak256(b"Hello, Bob!").to_vec()),
+    ]));
+
+    assert_eq!(
+        hex::encode(typed_data_hash(&domain, &struct_hash)),
+        "be609aee343fb3c4b28e1df9e632fca64fcfaede20f02e86244efddf30957bd2",
+    );
+}
+
+#[test]
+fn test_typed_data_hash_permit() {
+    use ethers_core::{
+        abi::Token,
+        types::transaction::eip712::{Eip712, TypedData},
+        utils::hex,
+    };
+
+    let domain = Eip712Domain {
+        name: Some("USD Coin".to_string()),
+        version: Some("2".to_string()),
+        chain_id: Some(1.into()),
+        verifying_contract: Some(
+            "0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48"
+                .parse()
+                .unwrap(),
+        ),
+        salt: None,
+    };
+    let struct_hash = keccak256(&ethers_core::abi::encode(&[
+        Token::FixedBytes(
+            keccak256(
+                b"Permit(address owner,address spender,uint256 value,uint256 nonce,uint256 deadline)",
+            )
+            .to_vec(),
+        ),
+        Token::Address(
+            "0x8840E6C55B9ADA326D211D818C34A994AECED808"
+                .parse()
+                .unwrap(),
+        ),
+        Token::Address(
+            "0xde0b295669a9fd93d5f28d9ec85e40f4cb697bae"
+                .parse()
+                .unwrap(),
+        ),
+        Token::Uint(1_000_000.into()),
+        Token::Uint(0.into()),
+        Token::Uint(1_700_000_000.into()),
+    ]));
+
+    let typed_data: TypedData = near_sdk::serde_json::from_value(near_sdk::serde_json::json!({
+        "types": {
+            "EIP712Domain": [
+                { "name": "name", "type": "string" },
+                { "name": "version", "type": "string" },
+                { "name": "chainId", "type": "uint256" },
+                { "name": "verifyingContract", "type": "address" },
+            ],
+            "Permit": [
+                { "name": "owner", "type": "address" },
+                { "name": "spender", "type": "address" },
+                { "name": "value", "type": "uint256" },
+                { "name": "nonce", "type": "uint256" },
+                { "name": "deadline", "type": "uint256" },
+            ],
+        },
+        "primaryType": "Permit",
+        "domain": {
+            "name": "USD Coin",
+            "version": "2",
+            "chainId": 1,
+            "verifyingContract": "0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48",
+        },
+        "message": {
+            "owner": "0x8840E6C55B9ADA326D211D818C34A994AECED808",
+            "spender": "0xde0b295669a9fd93d5f28d9ec85e40f4cb697bae",
+            "value": 1_000_000,
+            "nonce": 0,
+            "deadline": 1_700_000_000,
+        },
+    }))
+    .unwrap();
+
+    assert_eq!(struct_hash, typed_data.struct_hash().unwrap());
+    assert_eq!(
+        domain_separator(&domain),
+        typed_data.domain_separator().unwrap(),
+    );
+    let digest = typed_data_hash(&domain, &struct_hash);
+    assert_eq!(digest, typed_data.encode_eip712().unwrap());
+    assert_eq!(
+        hex::encode(digest),
+        "8667f391bde0d2370937c8758fd9e4c0ece202d52a1b659bf6ff60fe0eaa78e4",
+    );
+}
+
+#[test]
+fn test_domain_separator_omits_unset_fields() {
+    let domain = Eip712Domain {
+        name: Some("Permit2".to_string()),
+        chain_id: Some(1.into()),
+        ..Default::default()
+    };
+
+    let mut preimage = keccak256(b"EIP712Domain(string name,uint256 chainId)").to_vec();
+    preimage.extend_from_slice(&keccak256(b"Permit2"));
+    preimage.extend_from_slice(&[0; 31]);
+    preimage.push(1);
+    assert_eq!(domain_separator(&domain), keccak256(&preimage));
+}
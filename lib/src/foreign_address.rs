@@ -2,11 +2,13 @@ use std::{fmt::Display, str::FromStr};
 
 use ethers_core::{
     types::{NameOrAddress, H160},
-    utils::to_checksum,
+    utils::{hex, to_checksum},
 };
 use near_sdk::near;
 use schemars::JsonSchema;
 
+use crate::hash::keccak256;
+
 #[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Copy, Clone, Debug)]
 #[near]
 pub struct ForeignAddress(pub [u8; 20]);
@@ -20,6 +22,27 @@ impl ForeignAddress {
     pub fn from_raw_public_key(key_bytes: impl AsRef<[u8]>) -> Self {
         ethers_core::utils::raw_public_key_to_address(&key_bytes.as_ref()[1..]).into()
     }
+
+    /// Renders the address with the [EIP-1191](https://eips.ethereum.org/EIPS/eip-1191)
+    /// chain-specific checksum used by chains like RSK. [`Display`] renders
+    /// the chain-agnostic EIP-55 checksum.
+    #[must_use]
+    pub fn to_checksum_for_chain(&self, chain_id: u64) -> String {
+        let lowercase = hex::encode(self.0);
+        let hash = keccak256(format!("{chain_id}0x{lowercase}").as_bytes());
+
+        let mut checksummed = String::with_capacity(42);
+        checksummed.push_str("0x");
+        for (i, c) in lowercase.chars().enumerate() {
+            let nibble = (hash[i / 2] >> (if i % 2 == 0 { 4 } else { 0 })) & 0xf;
+            checksummed.push(if nibble >= 8 {
+                c.to_ascii_uppercase()
+            } else {
+                c
+            });
+        }
+        checksummed
+    }
 }
 
 impl near_sdk::serde::Serialize for ForeignAddress {
@@ -91,10 +114,204 @@ impl From<ForeignAddress> for NameOrAddress {
     }
 }
 
+#[derive(Debug, thiserror::Error)]
+pub enum AddressParseError {
+    #[error("Expected 40 hex characters, got {0}")]
+    InvalidLength(usize),
+    #[error("Invalid hex: {0}")]
+    InvalidHex(#[from] hex::FromHexError),
+    #[error("Invalid checksum, expected {expected}")]
+    InvalidChecksum { expected: String },
+}
+
+impl ForeignAddress {
+    /// Parses an address and requires it to carry a valid EIP-55 checksum.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the address is malformed or not checksummed.
+    pub fn from_str_checked(s: &str) -> Result<Self, AddressParseError> {
+        let address = Self::from_str(s)?;
+        let expected = address.to_string();
+
+        if expected[2..] == *s.strip_prefix("0x").unwrap_or(s) {
+            Ok(address)
+        } else {
+            Err(AddressParseError::InvalidChecksum { expected })
+        }
+    }
+}
+
 impl FromStr for ForeignAddress {
-    type Err = ethers_core::utils::ConversionError;
+    type Err = AddressParseError;
 
+    /// Parses a hex address with an optional `0x` prefix. Single-case input
+    /// is accepted as-is, while mixed-case input must be a valid EIP-55
+    /// checksum.
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        Ok(Self(ethers_core::utils::parse_checksummed(s, None)?.0))
+        let digits = s.strip_prefix("0x").unwrap_or(s);
+        if digits.len() != 40 {
+            return Err(AddressParseError::InvalidLength(digits.len()));
+        }
+
+        let mut bytes = [0u8; 20];
+        hex::decode_to_slice(digits, &mut bytes)?;
+        let address = Self(bytes);
+
+        let is_mixed_case = digits.chars().any(|c| c.is_ascii_lowercase())
+            && digits.chars().any(|c| c.is_ascii_uppercase());
+        if is_mixed_case {
+            let expected = address.to_string();
+            if expected[2..] != *digits {
+                return Err(AddressParseError::InvalidChecksum { expected });
+            }
+        }
+
+        Ok(address)
+    }
+}
+
+#[test]
+fn test_display_is_checksummed() {
+    let address = ForeignAddress(
+        hex::decode("5aaeb6053f3e94c9b9a09f33669435e7ef1beaed")
+            .unwrap()
+            .try_into()
+            .unwrap(),
+    );
+
+    assert_eq!(
+        address.to_string(),
+        "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed",
+    );
+    assert_eq!(format!("{address}"), address.to_string());
+}
+
+#[test]
+fn test_to_checksum_for_chain() {
+    let address = ForeignAddress(
+        hex::decode("5aaeb6053f3e94c9b9a09f33669435e7ef1beaed")
+            .unwrap()
+            .try_into()
+            .unwrap(),
+    );
+
+    // RSK mainnet and testnet
+    for chain_id in [30, 31] {
+        assert_eq!(
+            address.to_checksum_for_chain(u64::from(chain_id)),
+            to_checksum(&address.into(), Some(chain_id)),
+        );
+    }
+
+    let large_chain_id = address.to_checksum_for_chain(11_297_108_109);
+    assert_ne!(large_chain_id, address.to_string());
+    assert!(large_chain_id.eq_ignore_ascii_case(&address.to_string()));
+}
+
+#[test]
+fn test_from_str() {
+    let expected = ForeignAddress(
+        hex::decode("5aaeb6053f3e94c9b9a09f33669435e7ef1beaed")
+            .unwrap()
+            .try_into()
+            .unwrap(),
+    );
+
+    for valid in [
+        "0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed",
+        "5aaeb6053f3e94c9b9a09f33669435e7ef1beaed",
+        "0x5AAEB6053F3E94C9B9A09F33669435E7EF1BEAED",
+        "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed",
+    ] {
+        assert_eq!(valid.parse::<ForeignAddress>().unwrap(), expected);
+    }
+
+    assert!(matches!(
+        "0x5aaeb6053f3e94c9b9a09f33669435e7ef1bea".parse::<ForeignAddress>(),
+        Err(AddressParseError::InvalidLength(38)),
+    ));
+    assert!(matches!(
+        "0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaedff".parse::<ForeignAddress>(),
+        Err(AddressParseError::InvalidLength(42)),
+    ));
+    assert!(matches!(
+        "0xzaaeb6053f3e94c9b9a09f33669435e7ef1beaed".parse::<ForeignAddress>(),
+        Err(AddressParseError::InvalidHex(_)),
+    ));
+    assert!(matches!(
+        "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAeD".parse::<ForeignAddress>(),
+        Err(AddressParseError::InvalidChecksum { .. }),
+    ));
+}
+
+#[test]
+fn test_from_str_checked() {
+    assert!(ForeignAddress::from_str_checked("0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed").is_ok());
+    assert!(ForeignAddress::from_str_checked("5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed").is_ok());
+    assert!(matches!(
+        ForeignAddress::from_str_checked("0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed"),
+        Err(AddressParseError::InvalidChecksum { expected })
+            if expected == "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed",
+    ));
+}
+
+#[test]
+fn test_borsh_round_trip() {
+    let address = ForeignAddress([0xab; 20]);
+
+    let serialized = near_sdk::borsh::to_vec(&address).unwrap();
+    assert_eq!(serialized, [0xab; 20]);
+    assert_eq!(
+        near_sdk::borsh::from_slice::<ForeignAddress>(&serialized).unwrap(),
+        address,
+    );
+}
+
+#[test]
+fn test_borsh_in_lookup_map() {
+    near_sdk::testing_env!(near_sdk::test_utils::VMContextBuilder::new().build());
+
+    let address = ForeignAddress([0xab; 20]);
+
+    let mut map = near_sdk::store::LookupMap::new(b"a");
+    map.insert("m/44'/60'/0'/0/0".to_string(), address);
+    map.flush();
+
+    let map: near_sdk::store::LookupMap<String, ForeignAddress> =
+        near_sdk::store::LookupMap::new(b"a");
+    assert_eq!(map.get("m/44'/60'/0'/0/0"), Some(&address));
+}
+
+#[test]
+fn test_serde_round_trip() {
+    let address: ForeignAddress = "0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed"
+        .parse()
+        .unwrap();
+
+    let json = near_sdk::serde_json::to_string(&address).unwrap();
+    assert_eq!(json, "\"0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed\"");
+    assert_eq!(
+        near_sdk::serde_json::from_str::<ForeignAddress>(&json).unwrap(),
+        address,
+    );
+    assert_eq!(
+        near_sdk::serde_json::from_str::<ForeignAddress>(
+            "\"5aaeb6053f3e94c9b9a09f33669435e7ef1beaed\"",
+        )
+        .unwrap(),
+        address,
+    );
+}
+
+#[test]
+fn test_serde_rejects_invalid_addresses() {
+    for invalid in [
+        "\"0xnothexnothexnothexnothexnothexnothexnoth\"",
+        "\"0x5aaeb6053f3e94c9b9a09f33669435e7ef1bea\"",
+        "\"0x5aaeb6053f3e94c9b9a09f33669435e7ef1beaed00\"",
+        "1234",
+    ] {
+        assert!(near_sdk::serde_json::from_str::<ForeignAddress>(invalid).is_err());
     }
 }
@@ -0,0 +1,62 @@
+#[cfg(any(target_arch = "wasm32", not(feature = "native-crypto")))]
+pub fn keccak256(bytes: &[u8]) -> [u8; 32] {
+    near_sdk::env::keccak256_array(bytes)
+}
+
+#[cfg(all(not(target_arch = "wasm32"), feature = "native-crypto"))]
+#[must_use]
+pub fn keccak256(bytes: &[u8]) -> [u8; 32] {
+    ethers_core::utils::keccak256(bytes)
+}
+
+/// `0x`-prefixed lowercase hex, the form in which JSON-RPC APIs such as
+/// `eth_sendRawTransaction` accept bytes.
+#[must_use]
+pub fn to_hex_prefixed(bytes: &[u8]) -> String {
+    ethers_core::utils::hex::encode_prefixed(bytes)
+}
+
+/// The hash that `personal_sign` signs, per version `0x45` of
+/// [EIP-191](https://eips.ethereum.org/EIPS/eip-191):
+/// `keccak256("\x19Ethereum Signed Message:\n" || len || message)`, where
+/// `len` is the length of `message` in decimal.
+#[must_use]
+pub fn personal_sign_hash(message: &[u8]) -> [u8; 32] {
+    let mut preimage = format!("\x19Ethereum Signed Message:\n{}", message.len()).into_bytes();
+    preimage.extend_from_slice(message);
+    keccak256(&preimage)
+}
+
+#[test]
+fn test_keccak256() {
+    assert_eq!(
+        ethers_core::utils::hex::encode(keccak256(b"")),
+        "c5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470",
+    );
+    assert_eq!(
+        ethers_core::utils::hex::encode(keccak256(b"abc")),
+        "4e03657aea45a94fc7d47ba826c8d667c0d1e6e33a64a036ec44f58fa12d6c45",
+    );
+}
+
+#[test]
+fn test_to_hex_prefixed() {
+    assert_eq!(to_hex_prefixed(&[]), "0x");
+    assert_eq!(to_hex_prefixed(&[0x02, 0xf8, 0x6c]), "0x02f86c");
+}
+
+#[test]
+fn test_personal_sign_hash() {
+    // `hashMessage("Hello World")`, as signed by MetaMask's `personal_sign`.
+    assert_eq!(
+        ethers_core::utils::hex::encode(personal_sign_hash(b"Hello World")),
+        "a1de988600a42c4b4ab089b619297c17d53cffae5d5120d82d8a92d0bb3b78f2",
+    );
+
+    for message in [&b""[..], b"a", &[0xff; 100]] {
+        assert_eq!(
+            personal_sign_hash(message),
+            ethers_core::utils::hash_message(message).0,
+        );
+    }
+}
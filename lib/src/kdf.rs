@@ -2,24 +2,23 @@
 
 use ethers_core::k256::{
     elliptic_curve::{
-        scalar::FromUintUnchecked,
+        ops::Reduce,
         sec1::{FromEncodedPoint, Tag, ToEncodedPoint},
-        CurveArithmetic,
+        CurveArithmetic, PrimeField,
     },
     AffinePoint, EncodedPoint, Scalar, Secp256k1, U256,
 };
-use near_sdk::AccountId;
+use near_sdk::{AccountId, CurveType};
 
 use crate::foreign_address::ForeignAddress;
+pub use kdf_core::{self as core, derive_key, PublicKey, EPSILON_DERIVATION_PREFIX};
 
-pub type PublicKey = <Secp256k1 as CurveArithmetic>::AffinePoint;
-
-#[cfg(target_arch = "wasm32")]
+#[cfg(any(target_arch = "wasm32", not(feature = "native-crypto")))]
 pub fn sha256(bytes: &[u8]) -> Vec<u8> {
     near_sdk::env::sha256(bytes)
 }
 
-#[cfg(not(target_arch = "wasm32"))]
+#[cfg(all(not(target_arch = "wasm32"), feature = "native-crypto"))]
 #[must_use]
 pub fn sha256(bytes: &[u8]) -> Vec<u8> {
     use sha2::Digest;
@@ -28,19 +27,158 @@ pub fn sha256(bytes: &[u8]) -> Vec<u8> {
     hasher.finalize().to_vec()
 }
 
-// Constant prefix that ensures epsilon derivation values are used specifically for
-// near-mpc-recovery with key derivation protocol vX.Y.Z.
-const EPSILON_DERIVATION_PREFIX: &str = "near-mpc-recovery v0.1.0 epsilon derivation:";
+#[derive(Debug, thiserror::Error)]
+pub enum ScalarError {
+    #[error("Value is not less than the curve order")]
+    OutOfRange,
+}
+
+pub trait ScalarExt: Sized {
+    /// Interprets little-endian bytes as a scalar, reducing modulo the curve
+    /// order.
+    fn from_bytes(bytes: [u8; 32]) -> Self;
+
+    /// Interprets little-endian bytes as a scalar.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the value is not less than the curve order.
+    fn try_from_bytes(bytes: [u8; 32]) -> Result<Self, ScalarError>;
+}
+
+impl ScalarExt for Scalar {
+    fn from_bytes(bytes: [u8; 32]) -> Self {
+        <Self as Reduce<U256>>::reduce(U256::from_le_slice(&bytes))
+    }
+
+    fn try_from_bytes(mut bytes: [u8; 32]) -> Result<Self, ScalarError> {
+        bytes.reverse();
+        Option::from(Self::from_repr(bytes.into())).ok_or(ScalarError::OutOfRange)
+    }
+}
+
+fn epsilon_derivation_hash(prefix: &str, signer_id: &AccountId, path: &str) -> [u8; 32] {
+    let derivation_path = self::core::epsilon_derivation_preimage(prefix, signer_id.as_str(), path);
+    sha256(derivation_path.as_bytes())
+        .try_into()
+        .unwrap_or_else(|_| unreachable!("SHA-256 digests are 32 bytes"))
+}
+
+/// Joins derivation path components with commas, escaping `\` and `,`
+/// within each component with a `\`, so that different components never
+/// join to the same path: `["a", "b,c"]` joins to `a,b\,c`, but
+/// `["a,b", "c"]` joins to `a\,b,c`. Components without either character,
+/// such as account IDs, are joined unchanged.
+#[must_use]
+pub fn join_derivation_path(components: &[&str]) -> String {
+    let mut path = String::new();
+    for (i, component) in components.iter().enumerate() {
+        if i > 0 {
+            path.push(',');
+        }
+        for c in component.chars() {
+            if matches!(c, '\\' | ',') {
+                path.push('\\');
+            }
+            path.push(c);
+        }
+    }
+    path
+}
+
+/// Starts, and separates the segments of, a hierarchical derivation path
+/// (see [`hierarchical_derivation_path`]). Flat paths must not contain it
+/// for hierarchical paths to be guaranteed not to collide with them.
+pub const HIERARCHICAL_PATH_SEPARATOR: char = '\u{1f}';
+
+/// The canonical encoding of a hierarchical derivation path such as
+/// `wallet/3/change/0`, given as its segments: each segment is prefixed with
+/// [`HIERARCHICAL_PATH_SEPARATOR`] (ASCII unit separator, `0x1f`), followed
+/// by its length in bytes in decimal and a `:`. For example, `["a", "bc"]`
+/// is encoded as `"\x1f1:a\x1f2:bc"`.
+///
+/// The length prefixes make segment boundaries unambiguous whatever the
+/// segments contain, and since the encoding always starts with the
+/// separator, it differs from every flat path that does not contain it
+/// (including the empty path, which would otherwise be the encoding of no
+/// segments).
+#[must_use]
+pub fn hierarchical_derivation_path(segments: &[&str]) -> String {
+    let mut path = String::new();
+    for segment in segments {
+        path.push(HIERARCHICAL_PATH_SEPARATOR);
+        path.push_str(&segment.len().to_string());
+        path.push(':');
+        path.push_str(segment);
+    }
+    if path.is_empty() {
+        path.push(HIERARCHICAL_PATH_SEPARATOR);
+    }
+    path
+}
+
+/// Like [`derive_epsilon`], but for a hierarchical path given as its
+/// segments, encoded with [`hierarchical_derivation_path`].
+#[must_use]
+pub fn derive_hierarchical(signer_id: &AccountId, segments: &[&str]) -> Scalar {
+    derive_epsilon(signer_id, &hierarchical_derivation_path(segments))
+}
 
 #[must_use]
 pub fn derive_epsilon(signer_id: &AccountId, path: &str) -> Scalar {
-    let derivation_path = format!("{EPSILON_DERIVATION_PREFIX}{signer_id},{path}");
-    Scalar::from_uint_unchecked(U256::from_le_slice(&sha256(derivation_path.as_bytes())))
+    derive_epsilon_with_prefix(EPSILON_DERIVATION_PREFIX, signer_id, path)
+}
+
+/// Like [`derive_epsilon`], but with a custom derivation prefix, for
+/// matching MPC networks that run a newer version of the derivation
+/// protocol.
+#[must_use]
+pub fn derive_epsilon_with_prefix(prefix: &str, signer_id: &AccountId, path: &str) -> Scalar {
+    self::core::epsilon_from_digest(epsilon_derivation_hash(prefix, signer_id, path))
 }
 
+/// Like [`derive_epsilon`], but isolates the derived key to a single target
+/// chain by appending `,<chain_id>` to the derivation path (see
+/// [`join_derivation_path`]). The resulting
+/// scalar (and therefore key) differs from the one [`derive_epsilon`]
+/// produces for the same `signer_id` and `path`.
 #[must_use]
-pub fn derive_key(public_key: PublicKey, epsilon: Scalar) -> PublicKey {
-    (<Secp256k1 as CurveArithmetic>::ProjectivePoint::GENERATOR * epsilon + public_key).to_affine()
+pub fn derive_epsilon_for_chain(signer_id: &AccountId, path: &str, chain_id: u64) -> Scalar {
+    derive_epsilon(
+        signer_id,
+        &join_derivation_path(&[path, &chain_id.to_string()]),
+    )
+}
+
+/// Same derivation as [`derive_epsilon`], reduced into the Curve25519 scalar field.
+#[must_use]
+pub fn derive_ed25519_epsilon(signer_id: &AccountId, path: &str) -> curve25519_dalek::Scalar {
+    curve25519_dalek::Scalar::from_bytes_mod_order(epsilon_derivation_hash(
+        EPSILON_DERIVATION_PREFIX,
+        signer_id,
+        path,
+    ))
+}
+
+/// Like [`derive_key`], but rejects a derived key at the point at infinity,
+/// which is what [`derive_key`] would produce if `GENERATOR * epsilon` were
+/// equal to `-public_key`.
+///
+/// # Errors
+///
+/// Returns [`PublicKeyConversionError::DegenerateKey`] if the derived key is
+/// the identity point.
+pub fn try_derive_key(
+    public_key: PublicKey,
+    epsilon: Scalar,
+) -> Result<PublicKey, PublicKeyConversionError> {
+    let derived = derive_key(public_key, epsilon);
+
+    if derived == AffinePoint::IDENTITY {
+        Err(PublicKeyConversionError::DegenerateKey)
+    } else {
+        Ok(derived)
+    }
 }
 
 #[must_use]
@@ -56,14 +194,65 @@ pub fn derive_evm_address_for_account(
     ethers_core::utils::raw_public_key_to_address(&encoded_bytes[1..])
 }
 
+/// Derives EVM addresses for many paths of the same account at once. The
+/// results are in the same order as `paths`.
+#[must_use]
+pub fn derive_addresses_for_account(
+    mpc_public_key: PublicKey,
+    account_id: &AccountId,
+    paths: &[&str],
+) -> Vec<ethers_core::types::Address> {
+    let public_key = <Secp256k1 as CurveArithmetic>::ProjectivePoint::from(mpc_public_key);
+
+    paths
+        .iter()
+        .map(|path| {
+            let affine_point = (<Secp256k1 as CurveArithmetic>::ProjectivePoint::GENERATOR
+                * derive_epsilon(account_id, path)
+                + public_key)
+                .to_affine();
+            let encoded = affine_point.to_encoded_point(false);
+            ethers_core::utils::raw_public_key_to_address(&encoded.as_bytes()[1..])
+        })
+        .collect()
+}
+
+#[must_use]
+pub fn derive_ed25519_key(
+    public_key: curve25519_dalek::EdwardsPoint,
+    epsilon: curve25519_dalek::Scalar,
+) -> curve25519_dalek::EdwardsPoint {
+    curve25519_dalek::EdwardsPoint::mul_base(&epsilon) + public_key
+}
+
+/// Derives the ED25519 public key (which doubles as the address on chains
+/// like Solana and NEAR) for the given account ID and derivation path.
+///
+/// # Errors
+///
+/// Returns an error if the MPC public key is not a valid ED25519 key.
+pub fn derive_ed25519_key_for_account(
+    mpc_public_key: near_sdk::PublicKey,
+    account_id: &AccountId,
+    path: &str,
+) -> Result<[u8; 32], PublicKeyConversionError> {
+    let point = near_public_key_to_edwards(mpc_public_key)?;
+    let epsilon = derive_ed25519_epsilon(account_id, path);
+    Ok(derive_ed25519_key(point, epsilon).compress().to_bytes())
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum PublicKeyConversionError {
     #[error("Can only convert from SECP256K1")]
     WrongCurveType(near_sdk::CurveType),
+    #[error("ED25519 derivation requires an ED25519 key, got {0:?}")]
+    ExpectedEd25519(near_sdk::CurveType),
     #[error("Decoding error")]
     DecodingError(#[from] ethers_core::k256::elliptic_curve::Error),
     #[error("Invalid key data")]
     InvalidKeyData,
+    #[error("Derived key is the point at infinity")]
+    DegenerateKey,
 }
 
 /// Converts an SECP256K1-variant [`near_sdk::PublicKey`] to an [`AffinePoint`].
@@ -95,6 +284,59 @@ pub fn near_public_key_to_affine(
     affine.ok_or(PublicKeyConversionError::InvalidKeyData)
 }
 
+/// Converts a SECP256K1 public key in SEC1 form, either compressed (33
+/// bytes) or uncompressed (65 bytes), to the SECP256K1-variant
+/// [`near_sdk::PublicKey`] of the same point.
+///
+/// # Errors
+///
+/// Returns an error if `bytes` is not a SEC1 encoding of a point on the
+/// curve other than the point at infinity.
+pub fn sec1_public_key_to_near(
+    bytes: &[u8],
+) -> Result<near_sdk::PublicKey, PublicKeyConversionError> {
+    let affine: Option<AffinePoint> = AffinePoint::from_encoded_point(
+        &EncodedPoint::from_bytes(bytes)
+            .map_err(|e| PublicKeyConversionError::DecodingError(e.into()))?,
+    )
+    .into();
+    let uncompressed = affine
+        .ok_or(PublicKeyConversionError::InvalidKeyData)?
+        .to_encoded_point(false);
+
+    // The point at infinity has no coordinates to encode.
+    near_sdk::PublicKey::from_parts(CurveType::SECP256K1, uncompressed.as_bytes()[1..].to_vec())
+        .map_err(|_| PublicKeyConversionError::InvalidKeyData)
+}
+
+/// Converts an ED25519-variant [`near_sdk::PublicKey`] to an Edwards point.
+///
+/// # Errors
+///
+/// Returns an error if the public key is not a valid ED25519 key.
+pub fn near_public_key_to_edwards(
+    public_key: near_sdk::PublicKey,
+) -> Result<curve25519_dalek::EdwardsPoint, PublicKeyConversionError> {
+    // `PublicKey::curve_type` aborts on unknown tags, which is unavailable off-chain.
+    match public_key.as_bytes()[0] {
+        tag if tag == CurveType::ED25519 as u8 => {}
+        tag if tag == CurveType::SECP256K1 as u8 => {
+            return Err(PublicKeyConversionError::ExpectedEd25519(
+                CurveType::SECP256K1,
+            ));
+        }
+        _ => return Err(PublicKeyConversionError::InvalidKeyData),
+    }
+
+    let bytes: [u8; 32] = public_key.as_bytes()[1..]
+        .try_into()
+        .map_err(|_| PublicKeyConversionError::InvalidKeyData)?;
+
+    curve25519_dalek::edwards::CompressedEdwardsY(bytes)
+        .decompress()
+        .ok_or(PublicKeyConversionError::InvalidKeyData)
+}
+
 /// Calculates the public key of the MPC signer for the given account ID and derivation path.
 ///
 /// # Errors
@@ -105,8 +347,72 @@ pub fn get_mpc_address(
     gas_station_account_id: &AccountId,
     caller_account_id: &str,
 ) -> Result<ForeignAddress, PublicKeyConversionError> {
+    get_mpc_address_from_affine(
+        near_public_key_to_affine(mpc_public_key)?,
+        gas_station_account_id,
+        caller_account_id,
+    )
+}
+
+/// Like [`get_mpc_address`], for an MPC public key that has already been
+/// decoded.
+///
+/// # Errors
+///
+/// Returns an error if the derived key is degenerate.
+pub fn get_mpc_address_from_affine(
+    mpc_public_key: AffinePoint,
+    gas_station_account_id: &AccountId,
+    caller_account_id: &str,
+) -> Result<ForeignAddress, PublicKeyConversionError> {
+    get_mpc_address_from_affine_with_prefix(
+        mpc_public_key,
+        EPSILON_DERIVATION_PREFIX,
+        gas_station_account_id,
+        caller_account_id,
+    )
+}
+
+/// Like [`get_mpc_address_from_affine`], but with a custom derivation
+/// prefix (see [`derive_epsilon_with_prefix`]).
+///
+/// # Errors
+///
+/// Returns an error if the derived key is degenerate.
+pub fn get_mpc_address_from_affine_with_prefix(
+    mpc_public_key: AffinePoint,
+    prefix: &str,
+    gas_station_account_id: &AccountId,
+    caller_account_id: &str,
+) -> Result<ForeignAddress, PublicKeyConversionError> {
+    let epsilon = derive_epsilon_with_prefix(prefix, gas_station_account_id, caller_account_id);
+    let affine_point = try_derive_key(mpc_public_key, epsilon)?;
+
+    Ok(ForeignAddress::from_raw_public_key(
+        affine_point.to_encoded_point(false).as_bytes(),
+    ))
+}
+
+/// Calculates the compressed SEC1 public key of the MPC signer for the given
+/// account ID and derivation path.
+///
+/// # Errors
+///
+/// Returns an error if the public key is not a valid SECP256K1 key.
+pub fn get_mpc_public_key(
+    mpc_public_key: near_sdk::PublicKey,
+    gas_station_account_id: &AccountId,
+    caller_account_id: &str,
+) -> Result<[u8; 33], PublicKeyConversionError> {
     let affine = near_public_key_to_affine(mpc_public_key)?;
-    Ok(derive_evm_address_for_account(affine, gas_station_account_id, caller_account_id).into())
+    let epsilon = derive_epsilon(gas_station_account_id, caller_account_id);
+    let affine_point = try_derive_key(affine, epsilon)?;
+
+    affine_point
+        .to_encoded_point(true)
+        .as_bytes()
+        .try_into()
+        .map_err(|_| PublicKeyConversionError::InvalidKeyData)
 }
 
 /// Calculates the encoded point for a given MPC public key, predecessor, and key path.
@@ -121,11 +427,79 @@ pub fn derive_public_key_for(
 ) -> Result<EncodedPoint, PublicKeyConversionError> {
     let affine = near_public_key_to_affine(mpc_public_key)?;
     let epsilon = derive_epsilon(predecessor_account_id, path);
-    let affine_point = derive_key(affine, epsilon);
+    let affine_point = try_derive_key(affine, epsilon)?;
 
     Ok(affine_point.to_encoded_point(false))
 }
 
+/// Derives the NEAR implicit account controlled by the derived key: the
+/// 64-character hex encoding of the key for ED25519 MPC keys, or the
+/// `0x`-prefixed ETH-implicit account for SECP256K1 MPC keys.
+///
+/// # Errors
+///
+/// Returns an error if the MPC public key is invalid or the derived key is
+/// degenerate.
+pub fn derive_near_implicit_account(
+    mpc_public_key: near_sdk::PublicKey,
+    account_id: &AccountId,
+    path: &str,
+) -> Result<AccountId, PublicKeyConversionError> {
+    let implicit_account_id = if mpc_public_key.as_bytes()[0] == CurveType::ED25519 as u8 {
+        ethers_core::utils::hex::encode(derive_ed25519_key_for_account(
+            mpc_public_key,
+            account_id,
+            path,
+        )?)
+    } else {
+        let affine = near_public_key_to_affine(mpc_public_key)?;
+        let affine_point = try_derive_key(affine, derive_epsilon(account_id, path))?;
+        // ETH-implicit account IDs are lowercase, unlike checksummed addresses.
+        format!(
+            "{:#x}",
+            ethers_core::utils::raw_public_key_to_address(
+                &affine_point.to_encoded_point(false).as_bytes()[1..],
+            ),
+        )
+    };
+
+    implicit_account_id
+        .parse()
+        .map_err(|_| PublicKeyConversionError::InvalidKeyData)
+}
+
+/// The root secret key of the MPC network in tests.
+#[cfg(test)]
+pub(crate) fn test_mpc_root_key() -> ethers_core::k256::ecdsa::SigningKey {
+    ethers_core::k256::ecdsa::SigningKey::from_bytes(&[0x17; 32].into()).unwrap()
+}
+
+/// The public key of [`test_mpc_root_key`], as the MPC contract reports it.
+#[cfg(test)]
+pub(crate) fn test_mpc_public_key() -> near_sdk::PublicKey {
+    near_sdk::PublicKey::from_parts(
+        CurveType::SECP256K1,
+        test_mpc_root_key()
+            .verifying_key()
+            .to_encoded_point(false)
+            .as_bytes()[1..]
+            .to_vec(),
+    )
+    .unwrap()
+}
+
+/// The key that the MPC network in tests signs with for `path` of
+/// `account_id`: the root secret tweaked by epsilon.
+#[cfg(test)]
+pub(crate) fn test_mpc_derived_key(
+    account_id: &AccountId,
+    path: &str,
+) -> ethers_core::k256::ecdsa::SigningKey {
+    let secret =
+        *test_mpc_root_key().as_nonzero_scalar().as_ref() + derive_epsilon(account_id, path);
+    ethers_core::k256::ecdsa::SigningKey::from_bytes(&secret.to_bytes()).unwrap()
+}
+
 #[test]
 fn test_keys() {
     let public_key: near_sdk::PublicKey = "secp256k1:47xve2ymatpG4x4Gp7pmYwuLJk7eeRegrFuS4VoW5VV4i3GsBiBY87vkH6UZiiY18NeZnkBzcZzipDbJJ5pmjTcc"
@@ -142,6 +516,70 @@ fn test_keys() {
     println!("{}", ethers_core::utils::to_checksum(&mpc_address, None));
 }
 
+#[test]
+fn test_get_mpc_public_key_matches_address() {
+    let public_key: near_sdk::PublicKey = "secp256k1:47xve2ymatpG4x4Gp7pmYwuLJk7eeRegrFuS4VoW5VV4i3GsBiBY87vkH6UZiiY18NeZnkBzcZzipDbJJ5pmjTcc"
+        .parse()
+        .unwrap();
+    let gas_station: AccountId = "canhazgas.testnet".parse().unwrap();
+
+    let compressed = get_mpc_public_key(public_key.clone(), &gas_station, "alice.near").unwrap();
+    assert!(compressed[0] == 0x02 || compressed[0] == 0x03);
+
+    let point =
+        AffinePoint::from_encoded_point(&EncodedPoint::from_bytes(compressed).unwrap()).unwrap();
+    let address = ethers_core::utils::raw_public_key_to_address(
+        &point.to_encoded_point(false).as_bytes()[1..],
+    );
+
+    assert_eq!(
+        ForeignAddress::from(address),
+        get_mpc_address(public_key, &gas_station, "alice.near").unwrap(),
+    );
+}
+
+#[test]
+fn test_derive_addresses_for_account() {
+    let public_key: near_sdk::PublicKey = "secp256k1:47xve2ymatpG4x4Gp7pmYwuLJk7eeRegrFuS4VoW5VV4i3GsBiBY87vkH6UZiiY18NeZnkBzcZzipDbJJ5pmjTcc"
+        .parse()
+        .unwrap();
+    let affine = near_public_key_to_affine(public_key).unwrap();
+    let account_id: AccountId = "canhazgas.testnet".parse().unwrap();
+    let paths = ["", "alice.near", "bob.near", "alice.near"];
+
+    let addresses = derive_addresses_for_account(affine, &account_id, &paths);
+
+    assert_eq!(
+        addresses,
+        paths
+            .iter()
+            .map(|path| derive_evm_address_for_account(affine, &account_id, path))
+            .collect::<Vec<_>>(),
+    );
+    assert!(derive_addresses_for_account(affine, &account_id, &[]).is_empty());
+}
+
+#[test]
+fn test_derived_address_matches_signature_recovery() {
+    let root_key = test_mpc_root_key();
+    let account_id: AccountId = "canhazgas.testnet".parse().unwrap();
+    let path = "alice.near";
+    let derived_key = test_mpc_derived_key(&account_id, path);
+
+    let message_hash = ethers_core::utils::keccak256(b"signature recovery regression");
+    let (signature, recovery_id) = derived_key.sign_prehash_recoverable(&message_hash).unwrap();
+    let signature = ethers_core::types::Signature {
+        r: signature.r().to_bytes().as_slice().into(),
+        s: signature.s().to_bytes().as_slice().into(),
+        v: u64::from(recovery_id.to_byte()) + 27,
+    };
+
+    assert_eq!(
+        signature.recover(message_hash).unwrap(),
+        derive_evm_address_for_account(*root_key.verifying_key().as_affine(), &account_id, path),
+    );
+}
+
 // The below tests confirm parity with https://gist.github.com/esaminu/f8cc37849de754f228c5a67bebce9b0f
 
 #[test]
@@ -154,6 +592,137 @@ fn test_derive_epsilon() {
     );
 }
 
+#[test]
+fn test_scalar_from_bytes_out_of_range() {
+    assert!(matches!(
+        <Scalar as ScalarExt>::try_from_bytes([0xff; 32]),
+        Err(ScalarError::OutOfRange),
+    ));
+
+    // 2^256 - 1 reduced modulo the curve order
+    assert_eq!(
+        ethers_core::utils::hex::encode_prefixed(
+            <Scalar as ScalarExt>::from_bytes([0xff; 32])
+                .to_bytes()
+                .as_slice()
+        ),
+        "0x000000000000000000000000000000014551231950b75fc4402da1732fc9bebe",
+    );
+
+    let mut one = [0; 32];
+    one[0] = 1;
+    assert_eq!(
+        <Scalar as ScalarExt>::try_from_bytes(one).unwrap(),
+        Scalar::ONE
+    );
+    assert_eq!(<Scalar as ScalarExt>::from_bytes(one), Scalar::ONE);
+}
+
+#[test]
+fn test_derive_epsilon_with_prefix() {
+    let signer_id: AccountId = "canhazgas.testnet".parse().unwrap();
+
+    assert_eq!(
+        derive_epsilon_with_prefix(EPSILON_DERIVATION_PREFIX, &signer_id, ""),
+        derive_epsilon(&signer_id, ""),
+    );
+    assert_ne!(
+        derive_epsilon_with_prefix(
+            "near-mpc-recovery v0.2.0 epsilon derivation:",
+            &signer_id,
+            ""
+        ),
+        derive_epsilon(&signer_id, ""),
+    );
+}
+
+#[test]
+fn test_derive_epsilon_for_chain() {
+    let signer_id: AccountId = "canhazgas.testnet".parse().unwrap();
+
+    let mainnet = derive_epsilon_for_chain(&signer_id, "alice.near", 1);
+    let sepolia = derive_epsilon_for_chain(&signer_id, "alice.near", 11_155_111);
+
+    assert_ne!(mainnet, sepolia);
+    assert_ne!(mainnet, derive_epsilon(&signer_id, "alice.near"));
+    assert_eq!(
+        ethers_core::utils::hex::encode_prefixed(mainnet.to_bytes().as_slice()),
+        "0x50a351a5979e946b339b550b6685d0aee5cd97f40a12d960b1f7fc985684eace",
+    );
+}
+
+#[test]
+fn test_join_derivation_path() {
+    assert_eq!(
+        join_derivation_path(&["alice.near", "ethereum-1"]),
+        "alice.near,ethereum-1",
+    );
+    assert_eq!(join_derivation_path(&["a", "b,c"]), "a,b\\,c");
+    assert_eq!(join_derivation_path(&["a,b", "c"]), "a\\,b,c");
+    assert_eq!(join_derivation_path(&["a\\", ",b"]), "a\\\\,\\,b");
+    assert_eq!(join_derivation_path(&["a"]), "a");
+}
+
+#[test]
+fn test_join_derivation_path_prevents_collisions() {
+    let signer_id: AccountId = "canhazgas.testnet".parse().unwrap();
+
+    // Both of these used to derive from `a,b,c`.
+    assert_ne!(
+        derive_epsilon(&signer_id, &join_derivation_path(&["a", "b,c"])),
+        derive_epsilon(&signer_id, &join_derivation_path(&["a,b", "c"])),
+    );
+    // Both of these used to derive from `alice.near,1,2`.
+    assert_ne!(
+        derive_epsilon_for_chain(&signer_id, "alice.near,1", 2),
+        derive_epsilon(&signer_id, &join_derivation_path(&["alice.near", "1", "2"]),),
+    );
+}
+
+#[test]
+fn test_hierarchical_derivation_path() {
+    assert_eq!(
+        hierarchical_derivation_path(&["a", "bc"]),
+        "\x1f1:a\x1f2:bc"
+    );
+    assert_eq!(
+        hierarchical_derivation_path(&["wallet", "3", "change", "0"]),
+        "\x1f6:wallet\x1f1:3\x1f6:change\x1f1:0",
+    );
+    // Segment boundaries are unambiguous.
+    assert_ne!(
+        hierarchical_derivation_path(&["a\x1f1:b"]),
+        hierarchical_derivation_path(&["a", "b"]),
+    );
+    assert_eq!(hierarchical_derivation_path(&[]), "\x1f");
+    assert_ne!(
+        hierarchical_derivation_path(&[]),
+        hierarchical_derivation_path(&[""]),
+    );
+}
+
+#[test]
+fn test_derive_hierarchical() {
+    let signer_id: AccountId = "canhazgas.testnet".parse().unwrap();
+
+    let epsilon = derive_hierarchical(&signer_id, &["a", "b"]);
+    assert_eq!(epsilon, derive_hierarchical(&signer_id, &["a", "b"]));
+    assert_eq!(
+        ethers_core::utils::hex::encode_prefixed(epsilon.to_bytes().as_slice()),
+        "0xdf4e5ae48470c10d72dd469dfd0115b9b05e6ef9006b721df1c5a9ec5702a7f4",
+    );
+
+    for flat in ["a/b", "a,b", "ab", ""] {
+        assert_ne!(epsilon, derive_epsilon(&signer_id, flat));
+    }
+    assert_ne!(epsilon, derive_hierarchical(&signer_id, &["a/b"]));
+    assert_ne!(epsilon, derive_hierarchical(&signer_id, &["b", "a"]));
+    assert_eq!(
+        derive_hierarchical(&signer_id, &[]),
+        derive_epsilon(&signer_id, "\x1f"),
+    );
+}
+
 #[test]
 fn test_derive_key() {
     let parent_public_key_bytes = ethers_core::utils::hex::decode("0x049c0e823c86c14a5810d00c2d584c0b787337bff65a55465febfc15dbaba509f1e46ec19c2b85e8fb6df520df8234127617c94d302abeaed2d2ae1170562e87e9").unwrap();
@@ -168,6 +737,20 @@ fn test_derive_key() {
     );
 }
 
+#[test]
+fn test_try_derive_key_rejects_identity() {
+    let epsilon = derive_epsilon(&"canhazgas.testnet".parse().unwrap(), "");
+    // A public key that cancels out the tweak: -(G * epsilon)
+    let public_key =
+        (<Secp256k1 as CurveArithmetic>::ProjectivePoint::GENERATOR * -epsilon).to_affine();
+
+    assert!(matches!(
+        try_derive_key(public_key, epsilon),
+        Err(PublicKeyConversionError::DegenerateKey),
+    ));
+    assert!(try_derive_key(public_key, epsilon + Scalar::ONE).is_ok());
+}
+
 #[test]
 fn test_derive_evm_address() {
     let public_key_bytes = ethers_core::utils::hex::decode("04762ab28d3efef07ea4df3e61bafb14b9389f67a91fe3db3214132ebceef7a115644a8b87e01cb0c0cb34d78b176c7358f93a73dd7d5d885bbd598dde06e69647").unwrap();
@@ -177,3 +760,125 @@ fn test_derive_evm_address() {
     );
     assert_eq!(evm_address, "0x4a435791735b6295637dbf2a44bd1f9f1a5e3cbc");
 }
+
+#[test]
+fn test_derive_ed25519_key_for_account() {
+    let secret = curve25519_dalek::Scalar::from_bytes_mod_order([7; 32]);
+    let mpc_point = curve25519_dalek::EdwardsPoint::mul_base(&secret);
+    let mpc_public_key = near_sdk::PublicKey::from_parts(
+        CurveType::ED25519,
+        mpc_point.compress().to_bytes().to_vec(),
+    )
+    .unwrap();
+    let account_id: AccountId = "canhazgas.testnet".parse().unwrap();
+
+    let derived = derive_ed25519_key_for_account(mpc_public_key.clone(), &account_id, "").unwrap();
+
+    // The derived key must correspond to the tweaked secret (secret + epsilon).
+    let expected = curve25519_dalek::EdwardsPoint::mul_base(
+        &(secret + derive_ed25519_epsilon(&account_id, "")),
+    );
+    assert_eq!(derived, expected.compress().to_bytes());
+
+    let other_path = derive_ed25519_key_for_account(mpc_public_key, &account_id, "1").unwrap();
+    assert_ne!(derived, other_path);
+}
+
+#[test]
+fn test_derive_near_implicit_account() {
+    let account_id: AccountId = "canhazgas.testnet".parse().unwrap();
+    let ed25519_key = near_sdk::PublicKey::from_parts(
+        CurveType::ED25519,
+        curve25519_dalek::EdwardsPoint::mul_base(&curve25519_dalek::Scalar::from_bytes_mod_order(
+            [7; 32],
+        ))
+        .compress()
+        .to_bytes()
+        .to_vec(),
+    )
+    .unwrap();
+
+    let implicit = derive_near_implicit_account(ed25519_key.clone(), &account_id, "").unwrap();
+    assert_eq!(implicit.len(), 64);
+    assert!(implicit
+        .as_str()
+        .chars()
+        .all(|c| matches!(c, '0'..='9' | 'a'..='f')));
+    assert_eq!(
+        implicit.as_str(),
+        ethers_core::utils::hex::encode(
+            derive_ed25519_key_for_account(ed25519_key, &account_id, "").unwrap()
+        ),
+    );
+
+    let secp256k1_key: near_sdk::PublicKey = "secp256k1:47xve2ymatpG4x4Gp7pmYwuLJk7eeRegrFuS4VoW5VV4i3GsBiBY87vkH6UZiiY18NeZnkBzcZzipDbJJ5pmjTcc"
+        .parse()
+        .unwrap();
+    let eth_implicit =
+        derive_near_implicit_account(secp256k1_key.clone(), &account_id, "").unwrap();
+    assert_eq!(
+        eth_implicit.as_str(),
+        get_mpc_address(secp256k1_key, &account_id, "")
+            .unwrap()
+            .to_string()
+            .to_lowercase(),
+    );
+}
+
+#[test]
+fn test_derive_ed25519_key_rejects_secp256k1() {
+    let public_key: near_sdk::PublicKey = "secp256k1:47xve2ymatpG4x4Gp7pmYwuLJk7eeRegrFuS4VoW5VV4i3GsBiBY87vkH6UZiiY18NeZnkBzcZzipDbJJ5pmjTcc"
+        .parse()
+        .unwrap();
+
+    assert!(matches!(
+        derive_ed25519_key_for_account(public_key, &"canhazgas.testnet".parse().unwrap(), ""),
+        Err(PublicKeyConversionError::ExpectedEd25519(
+            CurveType::SECP256K1
+        )),
+    ));
+}
+
+#[cfg(feature = "native-crypto")]
+#[test]
+fn test_core_matches_account_id_wrappers() {
+    let account_id: AccountId = "canhazgas.testnet".parse().unwrap();
+    let mpc_public_key = near_public_key_to_affine(
+        "secp256k1:47xve2ymatpG4x4Gp7pmYwuLJk7eeRegrFuS4VoW5VV4i3GsBiBY87vkH6UZiiY18NeZnkBzcZzipDbJJ5pmjTcc"
+            .parse()
+            .unwrap(),
+    )
+    .unwrap();
+
+    assert_eq!(
+        derive_epsilon(&account_id, "alice.near"),
+        self::core::derive_epsilon(account_id.as_str(), "alice.near"),
+    );
+    assert_eq!(
+        derive_key(mpc_public_key, derive_epsilon(&account_id, "alice.near")),
+        self::core::derive_key_for_account(mpc_public_key, account_id.as_str(), "alice.near"),
+    );
+}
+
+#[test]
+fn test_sec1_public_key_to_near() {
+    let point = AffinePoint::GENERATOR;
+    let compressed = point.to_encoded_point(true);
+    let uncompressed = point.to_encoded_point(false);
+    assert_eq!(compressed.len(), 33);
+    assert_eq!(uncompressed.len(), 65);
+
+    let from_compressed = sec1_public_key_to_near(compressed.as_bytes()).unwrap();
+    let from_uncompressed = sec1_public_key_to_near(uncompressed.as_bytes()).unwrap();
+    assert_eq!(from_compressed, from_uncompressed);
+    assert_eq!(from_compressed.curve_type(), CurveType::SECP256K1);
+    assert_eq!(near_public_key_to_affine(from_compressed).unwrap(), point);
+
+    // Not on the curve.
+    let mut invalid = compressed.as_bytes().to_vec();
+    invalid[1..].fill(0xff);
+    assert!(sec1_public_key_to_near(&invalid).is_err());
+    // The point at infinity.
+    assert!(sec1_public_key_to_near(&[0]).is_err());
+    assert!(sec1_public_key_to_near(&[0x02; 10]).is_err());
+}
@@ -1,12 +1,20 @@
 use std::fmt::Display;
 
+pub mod address;
 pub mod asset;
+pub mod bitcoin;
 pub mod chain_key;
+pub mod eip712;
 pub mod foreign_address;
+pub mod hash;
 pub mod kdf;
 pub mod oracle;
 pub mod pyth;
+pub mod signature;
 pub mod signer;
+pub mod solana;
+pub mod transaction;
+pub mod tron;
 
 pub trait Rejectable<T> {
     fn unwrap_or_reject(self) -> T;
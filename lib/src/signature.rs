@@ -0,0 +1,488 @@
+use ethers_core::k256::{
+    ecdsa::{RecoveryId, Signature as EcdsaSignature, VerifyingKey},
+    elliptic_curve::{ops::Reduce, scalar::IsHigh},
+    Scalar,
+};
+use near_sdk::AccountId;
+use thiserror::Error;
+
+use crate::{foreign_address::ForeignAddress, kdf::get_mpc_address};
+
+#[derive(Debug, Error)]
+pub enum SignatureError {
+    #[error("Invalid recovery ID: {0}")]
+    InvalidRecoveryId(u8),
+    #[error("Invalid signature: {0}")]
+    InvalidSignature(#[from] ethers_core::k256::ecdsa::Error),
+    #[error("Invalid EIP-155 v value {v} for chain ID {chain_id}")]
+    InvalidV { v: u64, chain_id: u64 },
+}
+
+/// The largest chain ID for which EIP-155 `v` values fit in 64 bits, as
+/// recommended by [EIP-2294](https://eips.ethereum.org/EIPS/eip-2294).
+pub const MAX_EIP155_CHAIN_ID: u64 = (u64::MAX - 36) / 2;
+
+/// The [EIP-155](https://eips.ethereum.org/EIPS/eip-155) `v` value of a
+/// legacy transaction signature: `chain_id * 2 + 35 + y_parity`, where the
+/// y-parity is bit 0 of `recovery_id`.
+///
+/// # Panics
+///
+/// Panics if `chain_id` exceeds [`MAX_EIP155_CHAIN_ID`].
+#[must_use]
+pub fn eip155_v(recovery_id: u8, chain_id: u64) -> u64 {
+    chain_id
+        .checked_mul(2)
+        .and_then(|v| v.checked_add(35 + u64::from(recovery_id & 1)))
+        .unwrap_or_else(|| panic!("Chain ID {chain_id} is too large for EIP-155"))
+}
+
+/// Inverse of [`eip155_v`]: the y-parity of the signature.
+///
+/// # Errors
+///
+/// Returns an error if `v` is not one of the two values that are valid for
+/// `chain_id`.
+pub fn recovery_id_from_v(v: u64, chain_id: u64) -> Result<u8, SignatureError> {
+    let invalid = || SignatureError::InvalidV { v, chain_id };
+
+    let base = chain_id
+        .checked_mul(2)
+        .and_then(|base| base.checked_add(35))
+        .ok_or_else(invalid)?;
+
+    match v.checked_sub(base) {
+        Some(0) => Ok(0),
+        Some(1) => Ok(1),
+        _ => Err(invalid()),
+    }
+}
+
+/// Canonicalizes `s` to the low-S form required by
+/// [EIP-2](https://eips.ethereum.org/EIPS/eip-2): if `s > n/2`, returns
+/// `n - s`, which is an equally valid signature for the negated `R`. The
+/// flag is `true` if `s` was negated, in which case the parity bit of the
+/// recovery ID must be flipped.
+///
+/// Values of `s` at or above the curve order are reduced first.
+#[must_use]
+pub fn normalize_s(s: [u8; 32]) -> ([u8; 32], bool) {
+    let s = <Scalar as Reduce<ethers_core::k256::U256>>::reduce_bytes(&s.into());
+
+    if bool::from(s.is_high()) {
+        ((-s).to_bytes().into(), true)
+    } else {
+        (s.to_bytes().into(), false)
+    }
+}
+
+/// A secp256k1 ECDSA signature and its recovery ID, as produced by the MPC
+/// signer. Always in low-S form: constructing one normalizes `s` (see
+/// [`normalize_s`]) and flips the parity bit of the recovery ID to match, so
+/// every encoding of it is accepted by Ethereum nodes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Signature {
+    r: [u8; 32],
+    s: [u8; 32],
+    recovery_id: u8,
+}
+
+impl Signature {
+    /// # Errors
+    ///
+    /// Returns an error if `recovery_id` is not in `0..=3`.
+    pub fn from_rsv(r: [u8; 32], s: [u8; 32], recovery_id: u8) -> Result<Self, SignatureError> {
+        if recovery_id > 3 {
+            return Err(SignatureError::InvalidRecoveryId(recovery_id));
+        }
+
+        let (s, flipped) = normalize_s(s);
+        Ok(Self {
+            r,
+            s,
+            recovery_id: recovery_id ^ u8::from(flipped),
+        })
+    }
+
+    #[must_use]
+    pub fn r(&self) -> [u8; 32] {
+        self.r
+    }
+
+    /// Low-S.
+    #[must_use]
+    pub fn s(&self) -> [u8; 32] {
+        self.s
+    }
+
+    #[must_use]
+    pub fn recovery_id(&self) -> u8 {
+        self.recovery_id
+    }
+
+    /// The parity of R's y-coordinate (bit 0 of the recovery ID), which is
+    /// all that Ethereum encodes of the recovery ID.
+    #[must_use]
+    pub fn y_parity(&self) -> u8 {
+        self.recovery_id & 1
+    }
+
+    /// `r || s`, as accepted by `env::ecrecover`.
+    #[must_use]
+    pub fn to_rs_bytes(&self) -> [u8; 64] {
+        let mut bytes = [0; 64];
+        bytes[..32].copy_from_slice(&self.r);
+        bytes[32..].copy_from_slice(&self.s);
+        bytes
+    }
+
+    /// The 65-byte `r || s || v` form that `personal_sign` returns, where
+    /// `v` is 27 plus the y-parity.
+    #[must_use]
+    pub fn to_rsv_bytes(&self) -> [u8; 65] {
+        let mut bytes = [0; 65];
+        bytes[..64].copy_from_slice(&self.to_rs_bytes());
+        bytes[64] = 27 + self.y_parity();
+        bytes
+    }
+
+    /// The 65-byte `v || r || s` form, with the same `v` as
+    /// [`Signature::to_rsv_bytes`].
+    #[must_use]
+    pub fn to_vrs_bytes(&self) -> [u8; 65] {
+        let mut bytes = [0; 65];
+        bytes[0] = 27 + self.y_parity();
+        bytes[1..].copy_from_slice(&self.to_rs_bytes());
+        bytes
+    }
+
+    /// See [`eip155_v`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `chain_id` exceeds [`MAX_EIP155_CHAIN_ID`].
+    #[must_use]
+    pub fn eip155_v(&self, chain_id: u64) -> u64 {
+        eip155_v(self.y_parity(), chain_id)
+    }
+
+    /// `r || s || v`, where `v` is the [`eip155_v`] for `chain_id` in
+    /// big-endian, without leading zero bytes, so that it is 65 bytes long
+    /// for chain IDs up to 110 and longer beyond.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `chain_id` exceeds [`MAX_EIP155_CHAIN_ID`].
+    #[must_use]
+    pub fn to_eip155_bytes(&self, chain_id: u64) -> Vec<u8> {
+        let v = self.eip155_v(chain_id).to_be_bytes();
+        // `v` is at least 35, so it has a nonzero byte.
+        let leading_zeros = v.iter().take_while(|&&byte| byte == 0).count();
+
+        let mut bytes = self.to_rs_bytes().to_vec();
+        bytes.extend_from_slice(&v[leading_zeros..]);
+        bytes
+    }
+}
+
+/// Recovers the address of the key that produced the signature `(r, s)`
+/// over `message_hash`.
+///
+/// # Errors
+///
+/// Returns an error if the recovery ID is not in `0..=3`, or if the
+/// signature is malformed or does not recover to a valid key.
+pub fn recover_address(
+    message_hash: &[u8; 32],
+    r: &[u8; 32],
+    s: &[u8; 32],
+    recovery_id: u8,
+) -> Result<ForeignAddress, SignatureError> {
+    let signature = EcdsaSignature::from_scalars(*r, *s)?;
+    let recovery_id =
+        RecoveryId::from_byte(recovery_id).ok_or(SignatureError::InvalidRecoveryId(recovery_id))?;
+    let verifying_key = VerifyingKey::recover_from_prehash(message_hash, &signature, recovery_id)?;
+
+    Ok(ForeignAddress::from_raw_public_key(
+        verifying_key.to_encoded_point(false).as_bytes(),
+    ))
+}
+
+/// Checks that a signature produced by the MPC signer was made by the key
+/// derived for `caller_account_id`, i.e. that it recovers to the address
+/// returned by [`get_mpc_address`].
+///
+/// Returns `false` for malformed signatures and invalid MPC public keys.
+#[must_use]
+pub fn verify_derived_signature(
+    mpc_public_key: near_sdk::PublicKey,
+    gas_station_account_id: &AccountId,
+    caller_account_id: &str,
+    message_hash: &[u8; 32],
+    r: &[u8; 32],
+    s: &[u8; 32],
+    recovery_id: u8,
+) -> bool {
+    let Ok(expected) = get_mpc_address(mpc_public_key, gas_station_account_id, caller_account_id)
+    else {
+        return false;
+    };
+
+    recover_address(message_hash, r, s, recovery_id).is_ok_and(|address| address == expected)
+}
+
+#[test]
+fn test_eip155_v_mainnet() {
+    assert_eq!(eip155_v(0, 1), 37);
+    assert_eq!(eip155_v(1, 1), 38);
+    assert_eq!(recovery_id_from_v(37, 1).unwrap(), 0);
+    assert_eq!(recovery_id_from_v(38, 1).unwrap(), 1);
+
+    // Pre-EIP-155 values and values for other chains are rejected.
+    for v in [0, 1, 27, 28, 36, 39, 41] {
+        assert!(matches!(
+            recovery_id_from_v(v, 1),
+            Err(SignatureError::InvalidV { v: invalid, chain_id: 1 }) if invalid == v,
+        ));
+    }
+}
+
+#[test]
+fn test_eip155_v_high_chain_id() {
+    for chain_id in [11_297_108_109, u64::from(u32::MAX), MAX_EIP155_CHAIN_ID] {
+        for recovery_id in [0, 1] {
+            let v = eip155_v(recovery_id, chain_id);
+            assert_eq!(
+                u128::from(v),
+                u128::from(chain_id) * 2 + 35 + u128::from(recovery_id),
+            );
+            assert_eq!(recovery_id_from_v(v, chain_id).unwrap(), recovery_id);
+        }
+    }
+
+    assert_eq!(eip155_v(1, MAX_EIP155_CHAIN_ID), u64::MAX - 1);
+    assert!(recovery_id_from_v(u64::MAX, MAX_EIP155_CHAIN_ID).is_err());
+    assert!(recovery_id_from_v(u64::MAX, u64::MAX).is_err());
+}
+
+#[test]
+#[should_panic = "too large for EIP-155"]
+fn test_eip155_v_chain_id_too_large() {
+    let _ = eip155_v(1, MAX_EIP155_CHAIN_ID + 1);
+}
+
+#[test]
+fn test_normalize_s() {
+    use ethers_core::k256::elliptic_curve::{bigint::Encoding, Curve};
+    use ethers_core::k256::{ecdsa::SigningKey, Secp256k1};
+
+    let signing_key = SigningKey::from_bytes(&[0x46; 32].into()).unwrap();
+    let message_hash = ethers_core::utils::keccak256(b"normalize me");
+    let (signature, recovery_id) = signing_key.sign_prehash_recoverable(&message_hash).unwrap();
+    let r: [u8; 32] = signature.r().to_bytes().into();
+    let low_s: [u8; 32] = signature.s().to_bytes().into();
+
+    // k256 already produces low-S signatures.
+    assert_eq!(normalize_s(low_s), (low_s, false));
+
+    let high_s: [u8; 32] = (-*signature.s().as_ref()).to_bytes().into();
+    let (normalized, flipped) = normalize_s(high_s);
+    assert!(flipped);
+    assert_eq!(normalized, low_s);
+
+    // n - s
+    let order = ethers_core::types::U256::from_big_endian(&Secp256k1::ORDER.to_be_bytes());
+    assert_eq!(
+        ethers_core::types::U256::from_big_endian(&normalized),
+        order - ethers_core::types::U256::from_big_endian(&high_s),
+    );
+
+    // k256 rejects high-S signatures outright, so only the normalized form
+    // can be checked by recovery.
+    assert!(recover_address(&message_hash, &r, &high_s, recovery_id.to_byte() ^ 1).is_err());
+    assert_eq!(
+        recover_address(&message_hash, &r, &normalized, recovery_id.to_byte()).unwrap(),
+        recover_address(&message_hash, &r, &low_s, recovery_id.to_byte()).unwrap(),
+    );
+}
+
+#[test]
+fn test_recover_address() {
+    let signing_key = ethers_core::k256::ecdsa::SigningKey::from_bytes(&[0x46; 32].into()).unwrap();
+    let message_hash = ethers_core::utils::keccak256(b"recover me");
+    let (signature, recovery_id) = signing_key.sign_prehash_recoverable(&message_hash).unwrap();
+
+    let address = recover_address(
+        &message_hash,
+        &signature.r().to_bytes().into(),
+        &signature.s().to_bytes().into(),
+        recovery_id.to_byte(),
+    )
+    .unwrap();
+
+    // Well-known address of the 0x46..46 private key.
+    assert_eq!(
+        address.to_string(),
+        "0x9d8A62f656a8d1615C1294fd71e9CFb3E4855A4F",
+    );
+    assert!(matches!(
+        recover_address(&message_hash, &[0; 32], &[0; 32], 0),
+        Err(SignatureError::InvalidSignature(_)),
+    ));
+    assert!(matches!(
+        recover_address(&message_hash, &[1; 32], &[1; 32], 4),
+        Err(SignatureError::InvalidRecoveryId(4)),
+    ));
+}
+
+#[test]
+fn test_verify_derived_signature() {
+    let mpc_public_key = crate::kdf::test_mpc_public_key();
+    let gas_station: AccountId = "canhazgas.testnet".parse().unwrap();
+    let message_hash = ethers_core::utils::keccak256(b"verify me");
+
+    let (signature, recovery_id) = crate::kdf::test_mpc_derived_key(&gas_station, "alice.near")
+        .sign_prehash_recoverable(&message_hash)
+        .unwrap();
+    let (r, s, v) = (
+        signature.r().to_bytes().into(),
+        signature.s().to_bytes().into(),
+        recovery_id.to_byte(),
+    );
+
+    assert!(verify_derived_signature(
+        mpc_public_key.clone(),
+        &gas_station,
+        "alice.near",
+        &message_hash,
+        &r,
+        &s,
+        v,
+    ));
+    assert!(!verify_derived_signature(
+        mpc_public_key,
+        &gas_station,
+        "bob.near",
+        &message_hash,
+        &r,
+        &s,
+        v,
+    ));
+}
+
+#[test]
+fn test_signature_normalizes_s() {
+    use ethers_core::k256::ecdsa::SigningKey;
+
+    let signing_key = SigningKey::from_bytes(&[0x46; 32].into()).unwrap();
+    let message_hash = ethers_core::utils::keccak256(b"normalize me");
+    let (signature, recovery_id) = signing_key.sign_prehash_recoverable(&message_hash).unwrap();
+    let r: [u8; 32] = signature.r().to_bytes().into();
+    let low_s: [u8; 32] = signature.s().to_bytes().into();
+    let high_s: [u8; 32] = (-*signature.s().as_ref()).to_bytes().into();
+
+    let low = Signature::from_rsv(r, low_s, recovery_id.to_byte()).unwrap();
+    assert_eq!(low.s(), low_s);
+    assert_eq!(low.recovery_id(), recovery_id.to_byte());
+
+    // A high-S signature is the same signature once normalized.
+    let high = Signature::from_rsv(r, high_s, recovery_id.to_byte() ^ 1).unwrap();
+    assert_eq!(high, low);
+    assert_eq!(
+        recover_address(&message_hash, &high.r(), &high.s(), high.recovery_id()).unwrap(),
+        recover_address(&message_hash, &r, &low_s, recovery_id.to_byte()).unwrap(),
+    );
+
+    assert!(matches!(
+        Signature::from_rsv(r, low_s, 4),
+        Err(SignatureError::InvalidRecoveryId(4)),
+    ));
+}
+
+#[test]
+fn test_signature_to_bytes() {
+    let signature = Signature::from_rsv([0x11; 32], [0x22; 32], 1).unwrap();
+
+    let rsv = signature.to_rsv_bytes();
+    assert_eq!(rsv[..32], [0x11; 32]);
+    assert_eq!(rsv[32..64], [0x22; 32]);
+    assert_eq!(rsv[64], 28);
+
+    let vrs = signature.to_vrs_bytes();
+    assert_eq!(vrs[0], 28);
+    assert_eq!(vrs[1..], rsv[..64]);
+
+    // Round-trips through both forms.
+    assert_eq!(
+        Signature::from_rsv(
+            rsv[..32].try_into().unwrap(),
+            rsv[32..64].try_into().unwrap(),
+            rsv[64] - 27,
+        )
+        .unwrap(),
+        signature,
+    );
+    assert_eq!(
+        Signature::from_rsv(
+            vrs[1..33].try_into().unwrap(),
+            vrs[33..].try_into().unwrap(),
+            vrs[0] - 27,
+        )
+        .unwrap(),
+        signature,
+    );
+}
+
+#[test]
+fn test_signature_to_eip155_bytes() {
+    let signature = Signature::from_rsv([0x11; 32], [0x22; 32], 1).unwrap();
+
+    let mainnet = signature.to_eip155_bytes(1);
+    assert_eq!(mainnet.len(), 65);
+    assert_eq!(mainnet[..64], signature.to_rs_bytes());
+    assert_eq!(mainnet[64], 38);
+    assert_eq!(recovery_id_from_v(38, 1).unwrap(), signature.y_parity());
+
+    // 110 * 2 + 35 + 1 = 256
+    assert_eq!(signature.to_eip155_bytes(110)[64..], [0x01, 0x00]);
+    assert_eq!(signature.to_eip155_bytes(109)[64..], [0xfe]);
+    assert_eq!(
+        signature.to_eip155_bytes(MAX_EIP155_CHAIN_ID)[64..],
+        (u64::MAX - 1).to_be_bytes(),
+    );
+}
+
+#[test]
+fn test_signature_to_rsv_bytes_recovers() {
+    use ethers_core::k256::ecdsa::SigningKey;
+
+    let signing_key = SigningKey::from_bytes(&[0x46; 32].into()).unwrap();
+    let message_hash = crate::hash::personal_sign_hash(b"Hello World");
+    let (signature, recovery_id) = signing_key.sign_prehash_recoverable(&message_hash).unwrap();
+    let r: [u8; 32] = signature.r().to_bytes().into();
+    let s: [u8; 32] = signature.s().to_bytes().into();
+    let high_s: [u8; 32] = (-*signature.s().as_ref()).to_bytes().into();
+
+    let encoded = Signature::from_rsv(r, s, recovery_id.to_byte())
+        .unwrap()
+        .to_rsv_bytes();
+    // A high-S signature encodes to the same low-S signature.
+    assert_eq!(
+        Signature::from_rsv(r, high_s, recovery_id.to_byte() ^ 1)
+            .unwrap()
+            .to_rsv_bytes(),
+        encoded,
+    );
+
+    let expected = ForeignAddress::from_raw_public_key(
+        signing_key
+            .verifying_key()
+            .to_encoded_point(false)
+            .as_bytes(),
+    );
+    let ethers_signature = ethers_core::types::Signature::try_from(&encoded[..]).unwrap();
+    assert_eq!(
+        ethers_signature.recover(message_hash).unwrap().0,
+        expected.0,
+    );
+}
@@ -0,0 +1,54 @@
+use near_sdk::AccountId;
+
+use crate::kdf::{derive_ed25519_key_for_account, PublicKeyConversionError};
+
+/// Derives the Solana address controlled by the MPC key derived for
+/// `account_id` and `path`: the base58-encoded 32-byte ED25519 public key.
+///
+/// # Errors
+///
+/// Returns an error if the MPC public key is not a valid ED25519 key.
+pub fn derive_solana_address(
+    mpc_ed25519_key: near_sdk::PublicKey,
+    account_id: &AccountId,
+    path: &str,
+) -> Result<String, PublicKeyConversionError> {
+    let public_key = derive_ed25519_key_for_account(mpc_ed25519_key, account_id, path)?;
+    Ok(near_sdk::bs58::encode(public_key).into_string())
+}
+
+#[test]
+fn test_derive_solana_address() {
+    let mpc_ed25519_key: near_sdk::PublicKey =
+        "ed25519:6E8sCci9badyRkXb3JoRpBj5p8C6Tw41ELDZoiihKEtp"
+            .parse()
+            .unwrap();
+    let account_id: AccountId = "canhazgas.testnet".parse().unwrap();
+
+    let address =
+        derive_solana_address(mpc_ed25519_key.clone(), &account_id, "alice.near").unwrap();
+
+    assert!((32..=44).contains(&address.len()));
+    let decoded = near_sdk::bs58::decode(&address).into_vec().unwrap();
+    assert_eq!(
+        decoded,
+        derive_ed25519_key_for_account(mpc_ed25519_key.clone(), &account_id, "alice.near").unwrap(),
+    );
+
+    assert_ne!(
+        address,
+        derive_solana_address(mpc_ed25519_key, &account_id, "bob.near").unwrap(),
+    );
+}
+
+#[test]
+fn test_derive_solana_address_rejects_secp256k1() {
+    let public_key: near_sdk::PublicKey = "secp256k1:47xve2ymatpG4x4Gp7pmYwuLJk7eeRegrFuS4VoW5VV4i3GsBiBY87vkH6UZiiY18NeZnkBzcZzipDbJJ5pmjTcc"
+        .parse()
+        .unwrap();
+
+    assert!(matches!(
+        derive_solana_address(public_key, &"canhazgas.testnet".parse().unwrap(), ""),
+        Err(PublicKeyConversionError::ExpectedEd25519(_)),
+    ));
+}
@@ -0,0 +1,1921 @@
+use ethers_core::{
+    types::U256,
+    utils::rlp::{DecoderError, Rlp, RlpStream},
+};
+
+use near_sdk::{
+    borsh::{self, BorshDeserialize, BorshSerialize},
+    near,
+};
+use thiserror::Error;
+
+use crate::{
+    foreign_address::ForeignAddress,
+    hash::keccak256,
+    signature::{recovery_id_from_v, Signature},
+};
+
+/// Reported as the type of legacy transactions, which predate EIP-2718 and
+/// have no type byte.
+pub const LEGACY_TRANSACTION_TYPE: u8 = 0x00;
+
+/// EIP-2718 type byte of EIP-2930 transactions.
+pub const EIP2930_TRANSACTION_TYPE: u8 = 0x01;
+
+/// EIP-2718 type byte of EIP-1559 transactions.
+pub const EIP1559_TRANSACTION_TYPE: u8 = 0x02;
+
+/// EIP-2718 type byte of EIP-4844 transactions.
+pub const EIP4844_TRANSACTION_TYPE: u8 = 0x03;
+
+fn borsh_serialize_u256<W: borsh::io::Write>(
+    value: &U256,
+    writer: &mut W,
+) -> borsh::io::Result<()> {
+    value.0.serialize(writer)
+}
+
+fn borsh_deserialize_u256<R: borsh::io::Read>(reader: &mut R) -> borsh::io::Result<U256> {
+    <[u64; 4]>::deserialize_reader(reader).map(U256)
+}
+
+/// EIP-2930 access list: the addresses and storage keys a transaction plans
+/// to access, which are then charged at the warm rate.
+pub type AccessList = Vec<(ForeignAddress, Vec<[u8; 32]>)>;
+
+/// Puts `access_list` in canonical form, so that logically equal access
+/// lists encode (and hash) identically: entries for the same address are
+/// merged, and both addresses and their storage keys are sorted (by their
+/// bytes) and deduplicated.
+///
+/// Merging entries lowers the intrinsic gas, which charges per address
+/// entry, but never changes which slots are warm.
+#[must_use]
+pub fn canonicalize_access_list(access_list: AccessList) -> AccessList {
+    let mut merged = std::collections::BTreeMap::<ForeignAddress, Vec<[u8; 32]>>::new();
+    for (address, storage_keys) in access_list {
+        merged.entry(address).or_default().extend(storage_keys);
+    }
+
+    merged
+        .into_iter()
+        .map(|(address, mut storage_keys)| {
+            storage_keys.sort_unstable();
+            storage_keys.dedup();
+            (address, storage_keys)
+        })
+        .collect()
+}
+
+fn append_access_list(s: &mut RlpStream, access_list: &AccessList) {
+    // An empty access list is still encoded, as an empty list.
+    s.begin_list(access_list.len());
+    for (address, storage_keys) in access_list {
+        s.begin_list(2);
+        s.append(&address.0.as_slice());
+        s.begin_list(storage_keys.len());
+        for key in storage_keys {
+            s.append(&key.as_slice());
+        }
+    }
+}
+
+/// Gas charged for every transaction, before any execution.
+pub const TX_BASE_GAS: u64 = 21_000;
+
+/// Additional gas charged for transactions that create a contract.
+pub const TX_CREATE_GAS: u64 = 32_000;
+
+const TX_DATA_ZERO_BYTE_GAS: u64 = 4;
+const TX_DATA_NON_ZERO_BYTE_GAS: u64 = 16;
+const TX_ACCESS_LIST_ADDRESS_GAS: u64 = 2_400;
+const TX_ACCESS_LIST_STORAGE_KEY_GAS: u64 = 1_900;
+/// EIP-3860: contract creation is also charged per 32-byte word of initcode.
+const INITCODE_WORD_GAS: u64 = 2;
+
+/// The intrinsic gas of a transaction: the gas that the EVM charges before
+/// executing it, and so the least `gas_limit` that the transaction can be
+/// included with. Calls may need more to execute successfully.
+#[must_use]
+pub fn intrinsic_gas(
+    data: &[u8],
+    access_list: &[(ForeignAddress, Vec<[u8; 32]>)],
+    is_contract_creation: bool,
+) -> u64 {
+    let data_gas = data.iter().fold(0u64, |gas, &byte| {
+        gas.saturating_add(if byte == 0 {
+            TX_DATA_ZERO_BYTE_GAS
+        } else {
+            TX_DATA_NON_ZERO_BYTE_GAS
+        })
+    });
+
+    let access_list_gas = access_list.iter().fold(0u64, |gas, (_, storage_keys)| {
+        gas.saturating_add(TX_ACCESS_LIST_ADDRESS_GAS)
+            .saturating_add(
+                TX_ACCESS_LIST_STORAGE_KEY_GAS.saturating_mul(storage_keys.len() as u64),
+            )
+    });
+
+    let creation_gas = if is_contract_creation {
+        let words = (data.len() as u64).div_ceil(32);
+        TX_CREATE_GAS.saturating_add(INITCODE_WORD_GAS.saturating_mul(words))
+    } else {
+        0
+    };
+
+    TX_BASE_GAS
+        .saturating_add(data_gas)
+        .saturating_add(access_list_gas)
+        .saturating_add(creation_gas)
+}
+
+/// An unsigned EIP-1559 (type 2) transaction.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [borsh])]
+pub struct Eip1559Transaction {
+    pub chain_id: u64,
+    pub nonce: u64,
+    pub max_priority_fee_per_gas: u128,
+    pub max_fee_per_gas: u128,
+    pub gas_limit: u128,
+    /// `None` for contract creation.
+    pub to: Option<ForeignAddress>,
+    #[borsh(
+        serialize_with = "borsh_serialize_u256",
+        deserialize_with = "borsh_deserialize_u256"
+    )]
+    pub value: U256,
+    pub data: Vec<u8>,
+    pub access_list: AccessList,
+}
+
+impl Eip1559Transaction {
+    const UNSIGNED_FIELD_COUNT: usize = 9;
+
+    fn append_unsigned_fields(&self, s: &mut RlpStream) {
+        s.append(&self.chain_id);
+        s.append(&self.nonce);
+        s.append(&self.max_priority_fee_per_gas);
+        s.append(&self.max_fee_per_gas);
+        s.append(&self.gas_limit);
+        match self.to {
+            Some(to) => s.append(&to.0.as_slice()),
+            None => s.append_empty_data(),
+        };
+        s.append(&self.value);
+        s.append(&self.data);
+        append_access_list(s, &self.access_list);
+    }
+
+    /// The EIP-2718 envelope of the unsigned transaction: the type byte
+    /// followed by the RLP list of its fields.
+    #[must_use]
+    pub fn encode_unsigned(&self) -> Vec<u8> {
+        let mut s = RlpStream::new();
+        s.append_raw(&[EIP1559_TRANSACTION_TYPE], 0);
+        s.begin_list(Self::UNSIGNED_FIELD_COUNT);
+        self.append_unsigned_fields(&mut s);
+        s.out().to_vec()
+    }
+
+    /// The hash that the sender signs, i.e. the payload for the MPC signer.
+    #[must_use]
+    pub fn signing_hash(&self) -> [u8; 32] {
+        keccak256(&self.encode_unsigned())
+    }
+
+    /// See [`intrinsic_gas`].
+    #[must_use]
+    pub fn intrinsic_gas(&self) -> u64 {
+        intrinsic_gas(&self.data, &self.access_list, self.to.is_none())
+    }
+
+    /// Attaches the MPC signature and returns the raw, broadcastable
+    /// transaction (the `0x02`-prefixed envelope).
+    #[must_use]
+    pub fn into_signed(self, signature: Signature) -> Vec<u8> {
+        let mut stream = RlpStream::new();
+        stream.append_raw(&[EIP1559_TRANSACTION_TYPE], 0);
+        stream.begin_list(Self::UNSIGNED_FIELD_COUNT + 3);
+        self.append_unsigned_fields(&mut stream);
+        stream.append(&signature.y_parity());
+        stream.append(&U256::from_big_endian(&signature.r()));
+        stream.append(&U256::from_big_endian(&signature.s()));
+        stream.out().to_vec()
+    }
+}
+
+/// An unsigned [EIP-2930](https://eips.ethereum.org/EIPS/eip-2930) (type 1)
+/// transaction: a legacy-priced transaction with an access list.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [borsh])]
+pub struct Eip2930Transaction {
+    pub chain_id: u64,
+    pub nonce: u64,
+    pub gas_price: u128,
+    pub gas_limit: u128,
+    /// `None` for contract creation.
+    pub to: Option<ForeignAddress>,
+    #[borsh(
+        serialize_with = "borsh_serialize_u256",
+        deserialize_with = "borsh_deserialize_u256"
+    )]
+    pub value: U256,
+    pub data: Vec<u8>,
+    pub access_list: AccessList,
+}
+
+impl Eip2930Transaction {
+    const UNSIGNED_FIELD_COUNT: usize = 8;
+
+    fn append_unsigned_fields(&self, s: &mut RlpStream) {
+        s.append(&self.chain_id);
+        s.append(&self.nonce);
+        s.append(&self.gas_price);
+        s.append(&self.gas_limit);
+        match self.to {
+            Some(to) => s.append(&to.0.as_slice()),
+            None => s.append_empty_data(),
+        };
+        s.append(&self.value);
+        s.append(&self.data);
+        append_access_list(s, &self.access_list);
+    }
+
+    /// The EIP-2718 envelope of the unsigned transaction: the type byte
+    /// followed by the RLP list of its fields.
+    #[must_use]
+    pub fn encode_unsigned(&self) -> Vec<u8> {
+        let mut s = RlpStream::new();
+        s.append_raw(&[EIP2930_TRANSACTION_TYPE], 0);
+        s.begin_list(Self::UNSIGNED_FIELD_COUNT);
+        self.append_unsigned_fields(&mut s);
+        s.out().to_vec()
+    }
+
+    /// The hash that the sender signs, i.e. the payload for the MPC signer.
+    #[must_use]
+    pub fn signing_hash(&self) -> [u8; 32] {
+        keccak256(&self.encode_unsigned())
+    }
+
+    /// See [`intrinsic_gas`].
+    #[must_use]
+    pub fn intrinsic_gas(&self) -> u64 {
+        intrinsic_gas(&self.data, &self.access_list, self.to.is_none())
+    }
+
+    /// Attaches the MPC signature and returns the raw, broadcastable
+    /// transaction (the `0x01`-prefixed envelope).
+    #[must_use]
+    pub fn into_signed(self, signature: Signature) -> Vec<u8> {
+        let mut stream = RlpStream::new();
+        stream.append_raw(&[EIP2930_TRANSACTION_TYPE], 0);
+        stream.begin_list(Self::UNSIGNED_FIELD_COUNT + 3);
+        self.append_unsigned_fields(&mut stream);
+        stream.append(&signature.y_parity());
+        stream.append(&U256::from_big_endian(&signature.r()));
+        stream.append(&U256::from_big_endian(&signature.s()));
+        stream.out().to_vec()
+    }
+}
+
+/// An unsigned [EIP-4844](https://eips.ethereum.org/EIPS/eip-4844) (type 3)
+/// blob-carrying transaction. Only the transaction envelope is modeled: the
+/// blobs, commitments, and proofs of the network-form sidecar are not part
+/// of the signing hash, and are attached by whoever broadcasts it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [borsh])]
+pub struct Eip4844Transaction {
+    pub chain_id: u64,
+    pub nonce: u64,
+    pub max_priority_fee_per_gas: u128,
+    pub max_fee_per_gas: u128,
+    pub gas_limit: u128,
+    /// Blob transactions cannot create contracts.
+    pub to: ForeignAddress,
+    #[borsh(
+        serialize_with = "borsh_serialize_u256",
+        deserialize_with = "borsh_deserialize_u256"
+    )]
+    pub value: U256,
+    pub data: Vec<u8>,
+    pub access_list: AccessList,
+    pub max_fee_per_blob_gas: u128,
+    pub blob_versioned_hashes: Vec<[u8; 32]>,
+}
+
+impl Eip4844Transaction {
+    const UNSIGNED_FIELD_COUNT: usize = 11;
+
+    fn append_unsigned_fields(&self, s: &mut RlpStream) {
+        s.append(&self.chain_id);
+        s.append(&self.nonce);
+        s.append(&self.max_priority_fee_per_gas);
+        s.append(&self.max_fee_per_gas);
+        s.append(&self.gas_limit);
+        s.append(&self.to.0.as_slice());
+        s.append(&self.value);
+        s.append(&self.data);
+        append_access_list(s, &self.access_list);
+        s.append(&self.max_fee_per_blob_gas);
+        s.begin_list(self.blob_versioned_hashes.len());
+        for hash in &self.blob_versioned_hashes {
+            s.append(&hash.as_slice());
+        }
+    }
+
+    /// The EIP-2718 envelope of the unsigned transaction: the type byte
+    /// followed by the RLP list of its fields.
+    #[must_use]
+    pub fn encode_unsigned(&self) -> Vec<u8> {
+        let mut s = RlpStream::new();
+        s.append_raw(&[EIP4844_TRANSACTION_TYPE], 0);
+        s.begin_list(Self::UNSIGNED_FIELD_COUNT);
+        self.append_unsigned_fields(&mut s);
+        s.out().to_vec()
+    }
+
+    /// The hash that the sender signs, i.e. the payload for the MPC signer.
+    #[must_use]
+    pub fn signing_hash(&self) -> [u8; 32] {
+        keccak256(&self.encode_unsigned())
+    }
+
+    /// See [`intrinsic_gas`]. Blob transactions cannot create contracts.
+    #[must_use]
+    pub fn intrinsic_gas(&self) -> u64 {
+        intrinsic_gas(&self.data, &self.access_list, false)
+    }
+
+    /// Attaches the MPC signature and returns the `0x03`-prefixed signed
+    /// envelope, without the sidecar.
+    #[must_use]
+    pub fn into_signed(self, signature: Signature) -> Vec<u8> {
+        let mut stream = RlpStream::new();
+        stream.append_raw(&[EIP4844_TRANSACTION_TYPE], 0);
+        stream.begin_list(Self::UNSIGNED_FIELD_COUNT + 3);
+        self.append_unsigned_fields(&mut stream);
+        stream.append(&signature.y_parity());
+        stream.append(&U256::from_big_endian(&signature.r()));
+        stream.append(&U256::from_big_endian(&signature.s()));
+        stream.out().to_vec()
+    }
+}
+
+/// An unsigned legacy transaction with [EIP-155](https://eips.ethereum.org/EIPS/eip-155)
+/// replay protection.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [borsh])]
+pub struct LegacyTransaction {
+    pub nonce: u64,
+    pub gas_price: u128,
+    pub gas_limit: u128,
+    /// `None` for contract creation.
+    pub to: Option<ForeignAddress>,
+    #[borsh(
+        serialize_with = "borsh_serialize_u256",
+        deserialize_with = "borsh_deserialize_u256"
+    )]
+    pub value: U256,
+    pub data: Vec<u8>,
+    pub chain_id: u64,
+}
+
+impl LegacyTransaction {
+    fn begin_fields(&self, s: &mut RlpStream) {
+        s.begin_list(9);
+        s.append(&self.nonce);
+        s.append(&self.gas_price);
+        s.append(&self.gas_limit);
+        match self.to {
+            Some(to) => s.append(&to.0.as_slice()),
+            None => s.append_empty_data(),
+        };
+        s.append(&self.value);
+        s.append(&self.data);
+    }
+
+    /// RLP of the transaction fields followed by the EIP-155
+    /// `(chain_id, 0, 0)` trailer.
+    #[must_use]
+    pub fn encode_unsigned(&self) -> Vec<u8> {
+        let mut s = RlpStream::new();
+        self.begin_fields(&mut s);
+        s.append(&self.chain_id);
+        s.append(&0u8);
+        s.append(&0u8);
+        s.out().to_vec()
+    }
+
+    /// The hash that the sender signs, i.e. the payload for the MPC signer.
+    #[must_use]
+    pub fn signing_hash(&self) -> [u8; 32] {
+        keccak256(&self.encode_unsigned())
+    }
+
+    /// See [`intrinsic_gas`].
+    #[must_use]
+    pub fn intrinsic_gas(&self) -> u64 {
+        intrinsic_gas(&self.data, &[], self.to.is_none())
+    }
+
+    /// The raw, broadcastable transaction, with the
+    /// [`eip155_v`](crate::signature::eip155_v) of the signature.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the chain ID is too large for EIP-155.
+    #[must_use]
+    pub fn encode_signed(&self, signature: Signature) -> Vec<u8> {
+        let mut stream = RlpStream::new();
+        self.begin_fields(&mut stream);
+        stream.append(&signature.eip155_v(self.chain_id));
+        stream.append(&U256::from_big_endian(&signature.r()));
+        stream.append(&U256::from_big_endian(&signature.s()));
+        stream.out().to_vec()
+    }
+}
+
+/// An unsigned transaction in whichever format the foreign chain supports.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[near(serializers = [borsh])]
+pub enum ForeignTransaction {
+    Eip1559(Eip1559Transaction),
+    Legacy(LegacyTransaction),
+    Eip2930(Eip2930Transaction),
+    Eip4844(Eip4844Transaction),
+}
+
+impl ForeignTransaction {
+    pub fn chain_id(&self) -> u64 {
+        match self {
+            Self::Eip1559(transaction) => transaction.chain_id,
+            Self::Legacy(transaction) => transaction.chain_id,
+            Self::Eip2930(transaction) => transaction.chain_id,
+            Self::Eip4844(transaction) => transaction.chain_id,
+        }
+    }
+
+    pub fn nonce(&self) -> u64 {
+        match self {
+            Self::Eip1559(transaction) => transaction.nonce,
+            Self::Legacy(transaction) => transaction.nonce,
+            Self::Eip2930(transaction) => transaction.nonce,
+            Self::Eip4844(transaction) => transaction.nonce,
+        }
+    }
+
+    pub fn gas_limit(&self) -> u128 {
+        match self {
+            Self::Eip1559(transaction) => transaction.gas_limit,
+            Self::Legacy(transaction) => transaction.gas_limit,
+            Self::Eip2930(transaction) => transaction.gas_limit,
+            Self::Eip4844(transaction) => transaction.gas_limit,
+        }
+    }
+
+    /// The most that the sender can be charged per unit of gas, i.e. the
+    /// gas price of transactions without EIP-1559 fees. Excludes blob gas.
+    pub fn max_fee_per_gas(&self) -> u128 {
+        match self {
+            Self::Eip1559(transaction) => transaction.max_fee_per_gas,
+            Self::Legacy(transaction) => transaction.gas_price,
+            Self::Eip2930(transaction) => transaction.gas_price,
+            Self::Eip4844(transaction) => transaction.max_fee_per_gas,
+        }
+    }
+
+    /// The EIP-2718 type of the transaction, or
+    /// [`LEGACY_TRANSACTION_TYPE`] for legacy transactions.
+    pub fn transaction_type(&self) -> u8 {
+        match self {
+            Self::Eip1559(_) => EIP1559_TRANSACTION_TYPE,
+            Self::Legacy(_) => LEGACY_TRANSACTION_TYPE,
+            Self::Eip2930(_) => EIP2930_TRANSACTION_TYPE,
+            Self::Eip4844(_) => EIP4844_TRANSACTION_TYPE,
+        }
+    }
+
+    /// `None` for contract creation.
+    pub fn to(&self) -> Option<ForeignAddress> {
+        match self {
+            Self::Eip1559(transaction) => transaction.to,
+            Self::Legacy(transaction) => transaction.to,
+            Self::Eip2930(transaction) => transaction.to,
+            Self::Eip4844(transaction) => Some(transaction.to),
+        }
+    }
+
+    /// The most of the fee per unit of gas that goes to the block producer,
+    /// i.e. the gas price of transactions without EIP-1559 fees.
+    pub fn max_priority_fee_per_gas(&self) -> u128 {
+        match self {
+            Self::Eip1559(transaction) => transaction.max_priority_fee_per_gas,
+            Self::Legacy(transaction) => transaction.gas_price,
+            Self::Eip2930(transaction) => transaction.gas_price,
+            Self::Eip4844(transaction) => transaction.max_priority_fee_per_gas,
+        }
+    }
+
+    /// See [`Eip1559Transaction::encode_unsigned`].
+    #[must_use]
+    pub fn encode_unsigned(&self) -> Vec<u8> {
+        match self {
+            Self::Eip1559(transaction) => transaction.encode_unsigned(),
+            Self::Legacy(transaction) => transaction.encode_unsigned(),
+            Self::Eip2930(transaction) => transaction.encode_unsigned(),
+            Self::Eip4844(transaction) => transaction.encode_unsigned(),
+        }
+    }
+
+    /// The hash that the sender signs, i.e. the payload for the MPC signer.
+    #[must_use]
+    pub fn signing_hash(&self) -> [u8; 32] {
+        match self {
+            Self::Eip1559(transaction) => transaction.signing_hash(),
+            Self::Legacy(transaction) => transaction.signing_hash(),
+            Self::Eip2930(transaction) => transaction.signing_hash(),
+            Self::Eip4844(transaction) => transaction.signing_hash(),
+        }
+    }
+
+    /// The least gas limit that the transaction can be included with. See
+    /// [`intrinsic_gas`].
+    #[must_use]
+    pub fn intrinsic_gas(&self) -> u64 {
+        match self {
+            Self::Eip1559(transaction) => transaction.intrinsic_gas(),
+            Self::Legacy(transaction) => transaction.intrinsic_gas(),
+            Self::Eip2930(transaction) => transaction.intrinsic_gas(),
+            Self::Eip4844(transaction) => transaction.intrinsic_gas(),
+        }
+    }
+
+    /// The raw, broadcastable transaction.
+    #[must_use]
+    pub fn into_signed(self, signature: Signature) -> Vec<u8> {
+        match self {
+            Self::Eip1559(transaction) => transaction.into_signed(signature),
+            Self::Legacy(transaction) => transaction.encode_signed(signature),
+            Self::Eip2930(transaction) => transaction.into_signed(signature),
+            Self::Eip4844(transaction) => transaction.into_signed(signature),
+        }
+    }
+
+    /// The length of the raw transaction once signed. Exact unless `r` or
+    /// `s` of the signature has a leading zero byte, in which case it is
+    /// shorter.
+    #[must_use]
+    pub fn signed_len(&self) -> usize {
+        // `s` is below half the curve order, so it is not normalized.
+        let signature = Signature::from_rsv([0xff; 32], [0x7f; 32], 1)
+            .unwrap_or_else(|_| unreachable!("1 is a valid recovery ID"));
+        self.clone().into_signed(signature).len()
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum DecodeError {
+    #[error("Expected transaction type {expected:#04x}, got {actual:?}")]
+    WrongType { expected: u8, actual: Option<u8> },
+    #[error("Malformed RLP: {0}")]
+    Rlp(#[from] DecoderError),
+    #[error("Expected {expected} fields, got {actual}")]
+    FieldCount { expected: usize, actual: usize },
+    #[error("Invalid address length: {0}")]
+    InvalidAddress(usize),
+    #[error("Invalid y-parity: {0}")]
+    InvalidYParity(u8),
+    #[error("Unsupported transaction type {0:?}")]
+    UnsupportedType(Option<u8>),
+    #[error("Not the canonical encoding of the transaction")]
+    NonCanonical,
+    #[error("Invalid EIP-155 v value: {0}")]
+    InvalidV(u64),
+}
+
+fn decode_address(bytes: &[u8]) -> Result<ForeignAddress, DecodeError> {
+    bytes
+        .try_into()
+        .map(ForeignAddress)
+        .map_err(|_| DecodeError::InvalidAddress(bytes.len()))
+}
+
+fn decode_access_list(rlp: &Rlp) -> Result<AccessList, DecodeError> {
+    rlp.iter()
+        .map(|entry| {
+            let address = decode_address(entry.at(0)?.data()?)?;
+            let storage_keys = entry
+                .at(1)?
+                .iter()
+                .map(|key| {
+                    key.data()?
+                        .try_into()
+                        .map_err(|_| DecoderError::RlpInvalidLength.into())
+                })
+                .collect::<Result<_, DecodeError>>()?;
+            Ok((address, storage_keys))
+        })
+        .collect()
+}
+
+fn decode_to(rlp: &Rlp) -> Result<Option<ForeignAddress>, DecodeError> {
+    if rlp.is_empty() {
+        Ok(None)
+    } else {
+        decode_address(rlp.data()?).map(Some)
+    }
+}
+
+/// Decodes `payload` as exactly one RLP list of `field_count` items.
+fn decode_list(payload: &[u8], field_count: usize) -> Result<Rlp, DecodeError> {
+    let rlp = Rlp::new(payload);
+    if !rlp.is_list() {
+        return Err(DecoderError::RlpExpectedToBeList.into());
+    }
+    // Truncated lists are not otherwise detected, and trailing bytes would
+    // not be covered by the signature.
+    if rlp.payload_info()?.total() != payload.len() {
+        return Err(DecoderError::RlpInconsistentLengthAndData.into());
+    }
+    let actual = rlp.item_count()?;
+    if actual != field_count {
+        return Err(DecodeError::FieldCount {
+            expected: field_count,
+            actual,
+        });
+    }
+    Ok(rlp)
+}
+
+fn decode_u256_bytes(rlp: &Rlp, index: usize) -> Result<[u8; 32], DecodeError> {
+    let mut bytes = [0; 32];
+    rlp.val_at::<U256>(index)?.to_big_endian(&mut bytes);
+    Ok(bytes)
+}
+
+/// Decodes a raw signed EIP-1559 transaction, as returned by
+/// [`Eip1559Transaction::into_signed`], back into its fields and signature,
+/// e.g. to verify what was signed. The signature is normalized to low-S
+/// form.
+///
+/// # Errors
+///
+/// Returns an error if `raw` is not a `0x02`-prefixed envelope of exactly
+/// one well-formed RLP list of the fields of a signed EIP-1559 transaction.
+pub fn decode_eip1559(raw: &[u8]) -> Result<(Eip1559Transaction, Signature), DecodeError> {
+    let payload = match raw.split_first() {
+        Some((&EIP1559_TRANSACTION_TYPE, payload)) => payload,
+        first => {
+            return Err(DecodeError::WrongType {
+                expected: EIP1559_TRANSACTION_TYPE,
+                actual: first.map(|(&tx_type, _)| tx_type),
+            })
+        }
+    };
+
+    let rlp = decode_list(payload, Eip1559Transaction::UNSIGNED_FIELD_COUNT + 3)?;
+    let transaction = decode_eip1559_fields(&rlp)?;
+
+    let signature = decode_typed_signature(&rlp, Eip1559Transaction::UNSIGNED_FIELD_COUNT)?;
+
+    Ok((transaction, signature))
+}
+
+/// The `y_parity`, `r`, and `s` fields of a signed EIP-2718 transaction,
+/// from `index` on.
+fn decode_typed_signature(rlp: &Rlp, index: usize) -> Result<Signature, DecodeError> {
+    let y_parity: u8 = rlp.val_at(index)?;
+    if y_parity > 1 {
+        return Err(DecodeError::InvalidYParity(y_parity));
+    }
+    decode_signature(rlp, index + 1, y_parity)
+}
+
+/// The `r` and `s` fields from `index` on, with `y_parity`.
+fn decode_signature(rlp: &Rlp, index: usize, y_parity: u8) -> Result<Signature, DecodeError> {
+    Ok(Signature::from_rsv(
+        decode_u256_bytes(rlp, index)?,
+        decode_u256_bytes(rlp, index + 1)?,
+        y_parity,
+    )
+    .unwrap_or_else(|_| unreachable!("y-parity is a valid recovery ID")))
+}
+
+fn decode_eip1559_fields(rlp: &Rlp) -> Result<Eip1559Transaction, DecodeError> {
+    Ok(Eip1559Transaction {
+        chain_id: rlp.val_at(0)?,
+        nonce: rlp.val_at(1)?,
+        max_priority_fee_per_gas: rlp.val_at(2)?,
+        max_fee_per_gas: rlp.val_at(3)?,
+        gas_limit: rlp.val_at(4)?,
+        to: decode_to(&rlp.at(5)?)?,
+        value: rlp.val_at(6)?,
+        data: rlp.val_at(7)?,
+        access_list: decode_access_list(&rlp.at(8)?)?,
+    })
+}
+
+fn decode_eip2930_fields(rlp: &Rlp) -> Result<Eip2930Transaction, DecodeError> {
+    Ok(Eip2930Transaction {
+        chain_id: rlp.val_at(0)?,
+        nonce: rlp.val_at(1)?,
+        gas_price: rlp.val_at(2)?,
+        gas_limit: rlp.val_at(3)?,
+        to: decode_to(&rlp.at(4)?)?,
+        value: rlp.val_at(5)?,
+        data: rlp.val_at(6)?,
+        access_list: decode_access_list(&rlp.at(7)?)?,
+    })
+}
+
+fn decode_eip4844_fields(rlp: &Rlp) -> Result<Eip4844Transaction, DecodeError> {
+    Ok(Eip4844Transaction {
+        chain_id: rlp.val_at(0)?,
+        nonce: rlp.val_at(1)?,
+        max_priority_fee_per_gas: rlp.val_at(2)?,
+        max_fee_per_gas: rlp.val_at(3)?,
+        gas_limit: rlp.val_at(4)?,
+        to: decode_address(rlp.at(5)?.data()?)?,
+        value: rlp.val_at(6)?,
+        data: rlp.val_at(7)?,
+        access_list: decode_access_list(&rlp.at(8)?)?,
+        max_fee_per_blob_gas: rlp.val_at(9)?,
+        blob_versioned_hashes: rlp
+            .at(10)?
+            .iter()
+            .map(|hash| {
+                hash.data()?
+                    .try_into()
+                    .map_err(|_| DecoderError::RlpInvalidLength.into())
+            })
+            .collect::<Result<_, DecodeError>>()?,
+    })
+}
+
+/// The fields of a legacy transaction before its EIP-155 trailer or
+/// signature, which encode `chain_id` in different ways.
+fn decode_legacy_fields(rlp: &Rlp, chain_id: u64) -> Result<LegacyTransaction, DecodeError> {
+    Ok(LegacyTransaction {
+        nonce: rlp.val_at(0)?,
+        gas_price: rlp.val_at(1)?,
+        gas_limit: rlp.val_at(2)?,
+        to: decode_to(&rlp.at(3)?)?,
+        value: rlp.val_at(4)?,
+        data: rlp.val_at(5)?,
+        chain_id,
+    })
+}
+
+/// Per EIP-2718, the first byte of a legacy transaction is the prefix of
+/// its RLP list, at least `0xc0`, while typed transactions start with their
+/// type byte, at most `0x7f`.
+const LEGACY_LIST_PREFIX: u8 = 0xc0;
+
+/// Decodes a raw signed transaction of any supported type, which is
+/// detected from its first byte as in EIP-2718, back into its fields and
+/// signature. The signature is normalized to low-S form.
+///
+/// Legacy transactions must be signed with EIP-155 replay protection, from
+/// whose `v` their chain ID is recovered.
+///
+/// # Errors
+///
+/// Returns an error if `raw` is not exactly one well-formed RLP list of the
+/// fields of a signed transaction, after the type byte of typed ones, or if
+/// its type is not supported.
+pub fn decode_signed(raw: &[u8]) -> Result<(ForeignTransaction, Signature), DecodeError> {
+    match raw.split_first() {
+        Some((&EIP1559_TRANSACTION_TYPE, _)) => decode_eip1559(raw)
+            .map(|(transaction, signature)| (ForeignTransaction::Eip1559(transaction), signature)),
+        Some((&EIP2930_TRANSACTION_TYPE, payload)) => {
+            let rlp = decode_list(payload, Eip2930Transaction::UNSIGNED_FIELD_COUNT + 3)?;
+            Ok((
+                ForeignTransaction::Eip2930(decode_eip2930_fields(&rlp)?),
+                decode_typed_signature(&rlp, Eip2930Transaction::UNSIGNED_FIELD_COUNT)?,
+            ))
+        }
+        Some((&EIP4844_TRANSACTION_TYPE, payload)) => {
+            let rlp = decode_list(payload, Eip4844Transaction::UNSIGNED_FIELD_COUNT + 3)?;
+            Ok((
+                ForeignTransaction::Eip4844(decode_eip4844_fields(&rlp)?),
+                decode_typed_signature(&rlp, Eip4844Transaction::UNSIGNED_FIELD_COUNT)?,
+            ))
+        }
+        Some((&first, _)) if first >= LEGACY_LIST_PREFIX => {
+            let rlp = decode_list(raw, 9)?;
+            let v: u64 = rlp.val_at(6)?;
+            // Pre-EIP-155 signatures have a `v` of 27 or 28.
+            let chain_id = v
+                .checked_sub(35)
+                .map(|chain_id| chain_id / 2)
+                .ok_or(DecodeError::InvalidV(v))?;
+            let y_parity = recovery_id_from_v(v, chain_id)
+                .unwrap_or_else(|_| unreachable!("`v` is valid for the chain ID derived from it"));
+            Ok((
+                ForeignTransaction::Legacy(decode_legacy_fields(&rlp, chain_id)?),
+                decode_signature(&rlp, 7, y_parity)?,
+            ))
+        }
+        first => Err(DecodeError::UnsupportedType(
+            first.map(|(&tx_type, _)| tx_type),
+        )),
+    }
+}
+
+/// Decodes an unsigned transaction, as returned by `encode_unsigned` of an
+/// EIP-1559, EIP-2930, or legacy transaction, e.g. to sign a transaction
+/// that was built elsewhere.
+///
+/// Only canonical encodings are accepted, so the signing hash of the
+/// decoded transaction is the hash of `raw`, and its signed encoding is
+/// `raw` with the signature appended to the list of fields.
+///
+/// # Errors
+///
+/// Returns an error if `raw` is not the canonical encoding of an unsigned
+/// transaction of one of the supported types.
+pub fn decode_unsigned(raw: &[u8]) -> Result<ForeignTransaction, DecodeError> {
+    let transaction = match raw.split_first() {
+        Some((&EIP1559_TRANSACTION_TYPE, payload)) => {
+            let rlp = decode_list(payload, Eip1559Transaction::UNSIGNED_FIELD_COUNT)?;
+            ForeignTransaction::Eip1559(decode_eip1559_fields(&rlp)?)
+        }
+        Some((&EIP2930_TRANSACTION_TYPE, payload)) => {
+            let rlp = decode_list(payload, Eip2930Transaction::UNSIGNED_FIELD_COUNT)?;
+            ForeignTransaction::Eip2930(decode_eip2930_fields(&rlp)?)
+        }
+        // Legacy transactions are RLP lists, which start at `0xc0`. The
+        // EIP-155 trailer is checked by the canonical encoding below.
+        Some((&first, _)) if first >= LEGACY_LIST_PREFIX => {
+            let rlp = decode_list(raw, 9)?;
+            ForeignTransaction::Legacy(decode_legacy_fields(&rlp, rlp.val_at(6)?)?)
+        }
+        first => {
+            return Err(DecodeError::UnsupportedType(
+                first.map(|(&tx_type, _)| tx_type),
+            ))
+        }
+    };
+
+    if transaction.encode_unsigned() != raw {
+        return Err(DecodeError::NonCanonical);
+    }
+    Ok(transaction)
+}
+
+/// Fields of a mainnet transfer (also used by the gas station's RLP decoding test).
+#[cfg(test)]
+fn sample_eip1559_transaction() -> Eip1559Transaction {
+    Eip1559Transaction {
+        chain_id: 1,
+        nonce: 26,
+        max_priority_fee_per_gas: 50_000_000,
+        max_fee_per_gas: 15_000_000_000,
+        gas_limit: 21_000,
+        to: Some(
+            "0xb9a07c631d10fdce87d37eb6f18c11cbe75f1eeb"
+                .parse()
+                .unwrap(),
+        ),
+        value: U256::from(40_000_000_000_000_000_u128),
+        data: vec![],
+        access_list: vec![],
+    }
+}
+
+#[cfg(test)]
+fn ethers_access_list(
+    access_list: &AccessList,
+) -> ethers_core::types::transaction::eip2930::AccessList {
+    use ethers_core::types::transaction::eip2930::AccessListItem;
+
+    access_list
+        .iter()
+        .map(|(address, storage_keys)| AccessListItem {
+            address: (*address).into(),
+            storage_keys: storage_keys.iter().copied().map(Into::into).collect(),
+        })
+        .collect::<Vec<_>>()
+        .into()
+}
+
+#[cfg(test)]
+fn sample_access_list() -> AccessList {
+    vec![
+        (
+            "0xde0b295669a9fd93d5f28d9ec85e40f4cb697bae"
+                .parse()
+                .unwrap(),
+            vec![[0; 32], {
+                let mut key = [0; 32];
+                key[31] = 7;
+                key
+            }],
+        ),
+        (
+            "0xbb9bc244d798123fde783fcc1c72d3bb8c189413"
+                .parse()
+                .unwrap(),
+            vec![],
+        ),
+    ]
+}
+
+#[test]
+fn test_eip1559_signing_hash() {
+    use ethers_core::types::{transaction::eip2718::TypedTransaction, Eip1559TransactionRequest};
+
+    let transaction = sample_eip1559_transaction();
+
+    let reference: TypedTransaction = Eip1559TransactionRequest {
+        from: None,
+        to: transaction.to.map(Into::into),
+        gas: Some(transaction.gas_limit.into()),
+        value: Some(transaction.value),
+        data: Some(transaction.data.clone().into()),
+        nonce: Some(transaction.nonce.into()),
+        access_list: ethers_access_list(&transaction.access_list),
+        max_priority_fee_per_gas: Some(transaction.max_priority_fee_per_gas.into()),
+        max_fee_per_gas: Some(transaction.max_fee_per_gas.into()),
+        chain_id: Some(transaction.chain_id.into()),
+    }
+    .into();
+
+    assert_eq!(transaction.encode_unsigned(), reference.rlp().to_vec());
+    assert_eq!(transaction.signing_hash(), reference.sighash().0);
+    assert_eq!(
+        ethers_core::utils::hex::encode_prefixed(transaction.signing_hash()),
+        "0xe769a0cde218b31c175ad1627df51cb13bb2d85c38e82aa75f512eba23b16653",
+    );
+}
+
+#[test]
+fn test_eip1559_contract_creation() {
+    use ethers_core::{
+        types::{transaction::eip2718::TypedTransaction, Eip1559TransactionRequest},
+        utils::{hex, rlp::Rlp},
+    };
+
+    let mut transaction = sample_eip1559_transaction();
+    transaction.to = None;
+    transaction.value = U256::zero();
+    transaction.gas_limit = 100_000;
+    // Initcode that deploys an empty contract.
+    transaction.data =
+        hex::decode("6080604052348015600f57600080fd5b50603f80601d6000396000f3fe").unwrap();
+
+    let reference: TypedTransaction = Eip1559TransactionRequest {
+        from: None,
+        to: None,
+        gas: Some(transaction.gas_limit.into()),
+        value: Some(transaction.value),
+        data: Some(transaction.data.clone().into()),
+        nonce: Some(transaction.nonce.into()),
+        access_list: ethers_access_list(&transaction.access_list),
+        max_priority_fee_per_gas: Some(transaction.max_priority_fee_per_gas.into()),
+        max_fee_per_gas: Some(transaction.max_fee_per_gas.into()),
+        chain_id: Some(transaction.chain_id.into()),
+    }
+    .into();
+
+    let encoded = transaction.encode_unsigned();
+    assert_eq!(encoded, reference.rlp().to_vec());
+    // `to` is encoded as the empty byte string.
+    assert!(Rlp::new(&encoded[1..]).at(5).unwrap().is_empty());
+    assert_eq!(transaction.signing_hash(), reference.sighash().0);
+    assert_eq!(
+        hex::encode_prefixed(transaction.signing_hash()),
+        "0xf0036bcbb76355c8ce889231aef990723b9a6a4660da4cc2cbb1b648a8765cad",
+    );
+
+    let data_gas: u64 = transaction
+        .data
+        .iter()
+        .map(|&byte| if byte == 0 { 4 } else { 16 })
+        .sum();
+    assert_eq!(
+        ForeignTransaction::Eip1559(transaction).intrinsic_gas(),
+        // 29 bytes of initcode is a single word.
+        21_000 + 32_000 + data_gas + 2,
+    );
+}
+
+#[test]
+fn test_eip1559_value_encoding() {
+    use ethers_core::utils::rlp::Rlp;
+
+    let mut transaction = sample_eip1559_transaction();
+
+    for (value, expected) in [
+        (U256::zero(), vec![]),
+        (U256::from(u128::MAX), vec![0xff; 16]),
+        (U256::MAX, vec![0xff; 32]),
+        // Big-endian, without leading zeros.
+        (U256::from(0x0100), vec![0x01, 0x00]),
+    ] {
+        transaction.value = value;
+        let encoded = transaction.encode_unsigned();
+
+        assert_eq!(
+            Rlp::new(&encoded[1..]).at(6).unwrap().data().unwrap(),
+            expected,
+            "{value}",
+        );
+        assert_eq!(
+            decode_eip1559(
+                &transaction
+                    .clone()
+                    .into_signed(Signature::from_rsv([0x11; 32], [0x22; 32], 0).unwrap())
+            )
+            .unwrap()
+            .0
+            .value,
+            value,
+        );
+    }
+}
+
+#[test]
+fn test_eip1559_long_calldata() {
+    use ethers_core::{
+        types::{transaction::eip2718::TypedTransaction, Eip1559TransactionRequest},
+        utils::rlp::Rlp,
+    };
+
+    // Byte strings of up to 55 bytes have a single-byte header; longer ones
+    // are prefixed with the big-endian length of their length.
+    for (len, data_header) in [
+        (55, vec![0xb7]),
+        (56, vec![0xb8, 56]),
+        (256, vec![0xb9, 0x01, 0x00]),
+        (70_000, vec![0xba, 0x01, 0x11, 0x70]),
+    ] {
+        let mut transaction = sample_eip1559_transaction();
+        transaction.gas_limit = 2_000_000;
+        transaction.data = (1..=u8::MAX).cycle().take(len).collect();
+
+        let reference: TypedTransaction = Eip1559TransactionRequest {
+            from: None,
+            to: transaction.to.map(Into::into),
+            gas: Some(transaction.gas_limit.into()),
+            value: Some(transaction.value),
+            data: Some(transaction.data.clone().into()),
+            nonce: Some(transaction.nonce.into()),
+            access_list: ethers_access_list(&transaction.access_list),
+            max_priority_fee_per_gas: Some(transaction.max_priority_fee_per_gas.into()),
+            max_fee_per_gas: Some(transaction.max_fee_per_gas.into()),
+            chain_id: Some(transaction.chain_id.into()),
+        }
+        .into();
+
+        let encoded = transaction.encode_unsigned();
+        assert_eq!(encoded, reference.rlp().to_vec(), "{len} bytes");
+
+        let rlp = Rlp::new(&encoded[1..]);
+        let payload_info = rlp.payload_info().unwrap();
+        assert_eq!(
+            payload_info.header_len + payload_info.value_len,
+            encoded.len() - 1,
+        );
+        let data = rlp.at(7).unwrap();
+        assert_eq!(data.as_raw()[..data_header.len()], data_header);
+        assert_eq!(data.data().unwrap(), transaction.data);
+    }
+}
+
+#[test]
+fn test_eip1559_into_signed() {
+    use ethers_core::utils::hex;
+
+    let transaction = sample_eip1559_transaction();
+    let expected = "0x02f872011a8402faf08085037e11d60082520894b9a07c631d10fdce87d37eb6f18c11cbe75f1eeb878e1bc9bf04000080c001a05861ee93132033ed723d5bceb606c68f2107fc4f5ad1c36edbbf64b026381b0aa02e4398767b401a3faec153b95e639695077248b88991b57a1954a3505d998f15";
+
+    let r: [u8; 32] =
+        hex::decode("5861ee93132033ed723d5bceb606c68f2107fc4f5ad1c36edbbf64b026381b0a")
+            .unwrap()
+            .try_into()
+            .unwrap();
+    let s: [u8; 32] =
+        hex::decode("2e4398767b401a3faec153b95e639695077248b88991b57a1954a3505d998f15")
+            .unwrap()
+            .try_into()
+            .unwrap();
+
+    assert_eq!(
+        hex::encode_prefixed(
+            transaction
+                .clone()
+                .into_signed(Signature::from_rsv(r, s, 1).unwrap())
+        ),
+        expected,
+    );
+
+    // The high-S twin of the same signature, with the opposite parity
+    let high_s = -<ethers_core::k256::Scalar as ethers_core::k256::elliptic_curve::ops::Reduce<
+        ethers_core::k256::U256,
+    >>::reduce_bytes(&s.into());
+    assert_eq!(
+        hex::encode_prefixed(
+            transaction.into_signed(Signature::from_rsv(r, high_s.to_bytes().into(), 0).unwrap())
+        ),
+        expected,
+    );
+}
+
+#[test]
+fn test_eip1559_into_signed_recovers_mpc_address() {
+    use ethers_core::{types::transaction::eip2718::TypedTransaction, utils::rlp::Rlp};
+    use near_sdk::AccountId;
+
+    let gas_station: AccountId = "canhazgas.testnet".parse().unwrap();
+
+    let transaction = sample_eip1559_transaction();
+
+    let (signature, recovery_id) = crate::kdf::test_mpc_derived_key(&gas_station, "alice.near")
+        .sign_prehash_recoverable(&transaction.signing_hash())
+        .unwrap();
+
+    let raw = transaction.into_signed(
+        Signature::from_rsv(
+            signature.r().to_bytes().into(),
+            signature.s().to_bytes().into(),
+            recovery_id.to_byte(),
+        )
+        .unwrap(),
+    );
+    let (decoded, decoded_signature) = TypedTransaction::decode_signed(&Rlp::new(&raw)).unwrap();
+
+    assert_eq!(
+        ForeignAddress::from(decoded_signature.recover(decoded.sighash()).unwrap()),
+        crate::kdf::get_mpc_address(
+            crate::kdf::test_mpc_public_key(),
+            &gas_station,
+            "alice.near"
+        )
+        .unwrap(),
+    );
+}
+
+#[test]
+fn test_eip1559_signing_hash_with_access_list() {
+    use ethers_core::types::{transaction::eip2718::TypedTransaction, Eip1559TransactionRequest};
+
+    let mut transaction = sample_eip1559_transaction();
+    transaction.access_list = sample_access_list();
+
+    let reference: TypedTransaction = Eip1559TransactionRequest {
+        from: None,
+        to: transaction.to.map(Into::into),
+        gas: Some(transaction.gas_limit.into()),
+        value: Some(transaction.value),
+        data: Some(transaction.data.clone().into()),
+        nonce: Some(transaction.nonce.into()),
+        access_list: ethers_access_list(&transaction.access_list),
+        max_priority_fee_per_gas: Some(transaction.max_priority_fee_per_gas.into()),
+        max_fee_per_gas: Some(transaction.max_fee_per_gas.into()),
+        chain_id: Some(transaction.chain_id.into()),
+    }
+    .into();
+
+    assert_eq!(transaction.encode_unsigned(), reference.rlp().to_vec());
+    assert_ne!(
+        transaction.signing_hash(),
+        sample_eip1559_transaction().signing_hash(),
+    );
+}
+
+#[cfg(test)]
+fn sample_eip2930_transaction() -> Eip2930Transaction {
+    Eip2930Transaction {
+        chain_id: 1,
+        nonce: 26,
+        gas_price: 15_000_000_000,
+        gas_limit: 50_000,
+        to: Some(
+            "0xb9a07c631d10fdce87d37eb6f18c11cbe75f1eeb"
+                .parse()
+                .unwrap(),
+        ),
+        value: U256::from(40_000_000_000_000_000_u128),
+        data: vec![],
+        access_list: sample_access_list(),
+    }
+}
+
+#[test]
+fn test_eip2930_signing_hash() {
+    use ethers_core::types::{
+        transaction::{eip2718::TypedTransaction, eip2930::Eip2930TransactionRequest},
+        TransactionRequest,
+    };
+
+    let transaction = sample_eip2930_transaction();
+
+    let reference: TypedTransaction = Eip2930TransactionRequest::new(
+        TransactionRequest {
+            from: None,
+            to: transaction.to.map(Into::into),
+            gas: Some(transaction.gas_limit.into()),
+            gas_price: Some(transaction.gas_price.into()),
+            value: Some(transaction.value),
+            data: Some(transaction.data.clone().into()),
+            nonce: Some(transaction.nonce.into()),
+            chain_id: Some(transaction.chain_id.into()),
+        },
+        ethers_access_list(&transaction.access_list),
+    )
+    .into();
+
+    assert_eq!(transaction.encode_unsigned()[0], EIP2930_TRANSACTION_TYPE);
+    assert_eq!(transaction.encode_unsigned(), reference.rlp().to_vec());
+    assert_eq!(transaction.signing_hash(), reference.sighash().0);
+    assert_eq!(
+        ethers_core::utils::hex::encode_prefixed(transaction.signing_hash()),
+        "0xcab8497fd74f69ea482e24ee429796456987ddd2142f3e45c9dfeecfe6aaf832",
+    );
+}
+
+#[test]
+fn test_eip2930_empty_access_list() {
+    let mut transaction = sample_eip2930_transaction();
+    transaction.access_list = vec![];
+
+    // The access list is the last field, and is encoded as an empty list
+    // (0xc0) rather than omitted.
+    assert_eq!(transaction.encode_unsigned().last(), Some(&0xc0));
+}
+
+#[test]
+fn test_eip2930_into_signed_recovers_signer() {
+    use ethers_core::{
+        k256::ecdsa::SigningKey, types::transaction::eip2718::TypedTransaction, utils::rlp::Rlp,
+    };
+
+    let signing_key = SigningKey::from_bytes(&[0x46; 32].into()).unwrap();
+    let transaction = sample_eip2930_transaction();
+
+    let (signature, recovery_id) = signing_key
+        .sign_prehash_recoverable(&transaction.signing_hash())
+        .unwrap();
+
+    let raw = transaction.into_signed(
+        Signature::from_rsv(
+            signature.r().to_bytes().into(),
+            signature.s().to_bytes().into(),
+            recovery_id.to_byte(),
+        )
+        .unwrap(),
+    );
+    let (decoded, decoded_signature) = TypedTransaction::decode_signed(&Rlp::new(&raw)).unwrap();
+
+    assert!(matches!(decoded, TypedTransaction::Eip2930(_)));
+    assert_eq!(
+        ForeignAddress::from(decoded_signature.recover(decoded.sighash()).unwrap()),
+        ForeignAddress::from_raw_public_key(
+            signing_key
+                .verifying_key()
+                .to_encoded_point(false)
+                .as_bytes(),
+        ),
+    );
+}
+
+/// Example from EIP-155.
+#[cfg(test)]
+fn sample_legacy_transaction() -> LegacyTransaction {
+    LegacyTransaction {
+        nonce: 9,
+        gas_price: 20_000_000_000,
+        gas_limit: 21_000,
+        to: Some(
+            "0x3535353535353535353535353535353535353535"
+                .parse()
+                .unwrap(),
+        ),
+        value: U256::exp10(18),
+        data: vec![],
+        chain_id: 1,
+    }
+}
+
+#[test]
+fn test_legacy_transaction() {
+    use ethers_core::utils::hex;
+
+    let transaction = sample_legacy_transaction();
+
+    assert_eq!(
+        hex::encode_prefixed(transaction.encode_unsigned()),
+        "0xec098504a817c800825208943535353535353535353535353535353535353535880de0b6b3a764000080018080",
+    );
+    assert_eq!(
+        hex::encode_prefixed(transaction.signing_hash()),
+        "0xdaf5a779ae972f972197303d7b574746c7ef83eadac0f2791ad23db92e4c8e53",
+    );
+
+    let r = hex::decode("28ef61340bd939bc2195fe537567866003e1a15d3c71ff63e1590620aa636276")
+        .unwrap()
+        .try_into()
+        .unwrap();
+    let s = hex::decode("67cbe9d8997f761aecb703304b3800ccf555c9f3dc64214b297fb1966a3b6d83")
+        .unwrap()
+        .try_into()
+        .unwrap();
+
+    assert_eq!(
+        hex::encode_prefixed(transaction.encode_signed(Signature::from_rsv(r, s, 0).unwrap())),
+        "0xf86c098504a817c800825208943535353535353535353535353535353535353535880de0b6b3a76400008025a028ef61340bd939bc2195fe537567866003e1a15d3c71ff63e1590620aa636276a067cbe9d8997f761aecb703304b3800ccf555c9f3dc64214b297fb1966a3b6d83",
+    );
+
+    // The high-S twin of the same signature, with the opposite parity
+    let high_s = -<ethers_core::k256::Scalar as ethers_core::k256::elliptic_curve::ops::Reduce<
+        ethers_core::k256::U256,
+    >>::reduce_bytes(&s.into());
+    assert_eq!(
+        transaction.encode_signed(Signature::from_rsv(r, high_s.to_bytes().into(), 1).unwrap()),
+        transaction.encode_signed(Signature::from_rsv(r, s, 0).unwrap()),
+    );
+}
+
+#[test]
+fn test_legacy_transaction_large_chain_ids() {
+    use ethers_core::{
+        k256::ecdsa::SigningKey,
+        types::{transaction::eip2718::TypedTransaction, Transaction, TransactionRequest},
+        utils::rlp::{Decodable, Rlp},
+    };
+
+    use crate::signature::{eip155_v, recovery_id_from_v};
+
+    let signing_key = SigningKey::from_bytes(&[0x46; 32].into()).unwrap();
+    let sender = ForeignAddress::from_raw_public_key(
+        signing_key
+            .verifying_key()
+            .to_encoded_point(false)
+            .as_bytes(),
+    );
+
+    // Avalanche C-Chain, Palm, and the first chain ID beyond `u32`.
+    for chain_id in [43_114, 11_297_108_109, u64::from(u32::MAX) + 1] {
+        let transaction = LegacyTransaction {
+            nonce: 9,
+            gas_price: 20_000_000_000,
+            gas_limit: 21_000,
+            to: Some(ForeignAddress([0x35; 20])),
+            value: U256::exp10(18),
+            data: vec![],
+            chain_id,
+        };
+        let (signature, recovery_id) = signing_key
+            .sign_prehash_recoverable(&transaction.signing_hash())
+            .unwrap();
+        let r: [u8; 32] = signature.r().to_bytes().into();
+        let s: [u8; 32] = signature.s().to_bytes().into();
+
+        let raw_transaction =
+            transaction.encode_signed(Signature::from_rsv(r, s, recovery_id.to_byte()).unwrap());
+
+        let v = eip155_v(recovery_id.to_byte(), chain_id);
+        assert_eq!(
+            u128::from(v),
+            u128::from(chain_id) * 2 + 35 + u128::from(recovery_id.to_byte() & 1),
+        );
+        assert_eq!(
+            recovery_id_from_v(v, chain_id).unwrap(),
+            recovery_id.to_byte() & 1,
+        );
+
+        let reference: TypedTransaction = TransactionRequest {
+            from: None,
+            to: transaction.to.map(Into::into),
+            gas: Some(transaction.gas_limit.into()),
+            gas_price: Some(transaction.gas_price.into()),
+            value: Some(transaction.value),
+            data: Some(vec![].into()),
+            nonce: Some(transaction.nonce.into()),
+            chain_id: Some(chain_id.into()),
+        }
+        .into();
+        assert_eq!(
+            raw_transaction,
+            reference
+                .rlp_signed(&ethers_core::types::Signature {
+                    r: U256::from_big_endian(&r),
+                    s: U256::from_big_endian(&s),
+                    v,
+                })
+                .to_vec(),
+        );
+
+        let decoded = Transaction::decode(&Rlp::new(&raw_transaction)).unwrap();
+        assert_eq!(decoded.v.as_u64(), v);
+        assert_eq!(decoded.chain_id, Some(chain_id.into()));
+        assert_eq!(
+            ForeignAddress::from(decoded.recover_from().unwrap()),
+            sender
+        );
+    }
+}
+
+#[test]
+fn test_transaction_borsh_roundtrip() {
+    let mut transaction = sample_eip1559_transaction();
+    transaction.access_list = vec![(ForeignAddress([3; 20]), vec![[4; 32], [5; 32]])];
+
+    let transactions = [
+        ForeignTransaction::Eip1559(transaction.clone()),
+        ForeignTransaction::Legacy(LegacyTransaction {
+            nonce: transaction.nonce,
+            gas_price: transaction.max_fee_per_gas,
+            gas_limit: transaction.gas_limit,
+            to: None,
+            value: U256::MAX,
+            data: vec![1, 2, 3],
+            chain_id: transaction.chain_id,
+        }),
+        ForeignTransaction::Eip2930(sample_eip2930_transaction()),
+    ];
+
+    for transaction in transactions {
+        let bytes = borsh::to_vec(&transaction).unwrap();
+        assert_eq!(
+            ForeignTransaction::try_from_slice(&bytes).unwrap(),
+            transaction,
+        );
+    }
+}
+
+#[cfg(test)]
+fn sample_eip4844_transaction() -> Eip4844Transaction {
+    let mut blob_hash = [0x11; 32];
+    blob_hash[0] = 0x01; // KZG versioned hash
+
+    Eip4844Transaction {
+        chain_id: 1,
+        nonce: 3,
+        max_priority_fee_per_gas: 1_000_000_000,
+        max_fee_per_gas: 30_000_000_000,
+        gas_limit: 21_000,
+        to: "0xb9a07c631d10fdce87d37eb6f18c11cbe75f1eeb"
+            .parse()
+            .unwrap(),
+        value: U256::zero(),
+        data: vec![],
+        access_list: sample_access_list(),
+        max_fee_per_blob_gas: 2_000_000_000,
+        blob_versioned_hashes: vec![blob_hash, [0x01; 32]],
+    }
+}
+
+#[test]
+fn test_eip4844_encode_unsigned() {
+    use ethers_core::utils::rlp::Rlp;
+
+    let transaction = sample_eip4844_transaction();
+    let encoded = transaction.encode_unsigned();
+
+    assert_eq!(encoded[0], EIP4844_TRANSACTION_TYPE);
+    assert_eq!(transaction.signing_hash(), keccak256(&encoded));
+
+    let rlp = Rlp::new(&encoded[1..]);
+    assert_eq!(rlp.item_count().unwrap(), 11);
+    assert_eq!(rlp.val_at::<u64>(0).unwrap(), 1);
+    assert_eq!(rlp.val_at::<u64>(1).unwrap(), 3);
+    assert_eq!(rlp.val_at::<u128>(2).unwrap(), 1_000_000_000);
+    assert_eq!(rlp.val_at::<u128>(3).unwrap(), 30_000_000_000);
+    assert_eq!(rlp.val_at::<u128>(4).unwrap(), 21_000);
+    assert_eq!(rlp.val_at::<Vec<u8>>(5).unwrap(), transaction.to.0);
+    assert_eq!(rlp.at(8).unwrap().item_count().unwrap(), 2);
+    assert_eq!(rlp.val_at::<u128>(9).unwrap(), 2_000_000_000);
+    assert_eq!(
+        rlp.list_at::<Vec<u8>>(10).unwrap(),
+        transaction
+            .blob_versioned_hashes
+            .iter()
+            .map(|hash| hash.to_vec())
+            .collect::<Vec<_>>(),
+    );
+
+    // The access list is encoded the same way as in other typed transactions.
+    let mut eip1559 = sample_eip1559_transaction();
+    eip1559.access_list = sample_access_list();
+    let eip1559_encoded = eip1559.encode_unsigned();
+    assert_eq!(
+        rlp.at(8).unwrap().as_raw(),
+        Rlp::new(&eip1559_encoded[1..]).at(8).unwrap().as_raw(),
+    );
+}
+
+#[test]
+fn test_eip4844_into_signed() {
+    use ethers_core::{k256::ecdsa::SigningKey, utils::rlp::Rlp};
+
+    let signing_key = SigningKey::from_bytes(&[0x46; 32].into()).unwrap();
+    let transaction = sample_eip4844_transaction();
+    let signing_hash = transaction.signing_hash();
+    let unsigned = transaction.encode_unsigned();
+
+    let (signature, recovery_id) = signing_key.sign_prehash_recoverable(&signing_hash).unwrap();
+    let raw = transaction.into_signed(
+        Signature::from_rsv(
+            signature.r().to_bytes().into(),
+            signature.s().to_bytes().into(),
+            recovery_id.to_byte(),
+        )
+        .unwrap(),
+    );
+
+    assert_eq!(raw[0], EIP4844_TRANSACTION_TYPE);
+    let signed = Rlp::new(&raw[1..]);
+    let fields = Rlp::new(&unsigned[1..]);
+    assert_eq!(signed.item_count().unwrap(), 14);
+    for i in 0..11 {
+        assert_eq!(
+            signed.at(i).unwrap().as_raw(),
+            fields.at(i).unwrap().as_raw()
+        );
+    }
+
+    let y_parity: u8 = signed.val_at(11).unwrap();
+    let mut r = [0; 32];
+    signed.val_at::<U256>(12).unwrap().to_big_endian(&mut r);
+    let mut s = [0; 32];
+    signed.val_at::<U256>(13).unwrap().to_big_endian(&mut s);
+
+    assert_eq!(
+        crate::signature::recover_address(&signing_hash, &r, &s, y_parity).unwrap(),
+        ForeignAddress::from_raw_public_key(
+            signing_key
+                .verifying_key()
+                .to_encoded_point(false)
+                .as_bytes()
+        ),
+    );
+}
+
+#[test]
+fn test_eip4844_sepolia_transaction() {
+    use ethers_core::utils::hex;
+
+    // Blob transaction 0x9a22ccb0029bc8b0ddd073be1a1d923b7ae2b2ea52100bae0db4424f9107e9c0
+    // on Sepolia, sent by 0xa83c816d4f9b2783761a22ba6fadb0eb0606d7b2.
+    let raw = hex::decode(
+        "03f9011d83aa36a7820fa28477359400852e90edd0008252089411e9ca82a3a762b4b5bd264d4173a242e7a770648080c08504a817c800f8a5a0012ec3d6f66766bedb002a190126b3549fce0047de0d4c25cffce0dc1c57921aa00152d8e24762ff22b1cfd9f8c0683786a7ca63ba49973818b3d1e9512cd2cec4a0013b98c6c83e066d5b14af2b85199e3d4fc7d1e778dd53130d180f5077e2d1c7a001148b495d6e859114e670ca54fb6e2657f0cbae5b08063605093a4b3dc9f8f1a0011ac212f13c5dff2b2c6b600a79635103d6f580a4221079951181b25c7e654901a0c8de4cced43169f9aa3d36506363b2d2c44f6c49fc1fd91ea114c86f3757077ea01e11fdd0d1934eda0492606ee0bb80a7bf8f35cc5f86ec60fe5031ba48bfd544",
+    )
+    .unwrap();
+    assert_eq!(
+        hex::encode_prefixed(keccak256(&raw)),
+        "0x9a22ccb0029bc8b0ddd073be1a1d923b7ae2b2ea52100bae0db4424f9107e9c0",
+    );
+
+    let transaction = Eip4844Transaction {
+        chain_id: 11_155_111,
+        nonce: 4002,
+        max_priority_fee_per_gas: 2_000_000_000,
+        max_fee_per_gas: 200_000_000_000,
+        gas_limit: 21_000,
+        to: "0x11e9ca82a3a762b4b5bd264d4173a242e7a77064"
+            .parse()
+            .unwrap(),
+        value: U256::zero(),
+        data: vec![],
+        access_list: vec![],
+        max_fee_per_blob_gas: 20_000_000_000,
+        blob_versioned_hashes: [
+            "012ec3d6f66766bedb002a190126b3549fce0047de0d4c25cffce0dc1c57921a",
+            "0152d8e24762ff22b1cfd9f8c0683786a7ca63ba49973818b3d1e9512cd2cec4",
+            "013b98c6c83e066d5b14af2b85199e3d4fc7d1e778dd53130d180f5077e2d1c7",
+            "01148b495d6e859114e670ca54fb6e2657f0cbae5b08063605093a4b3dc9f8f1",
+            "011ac212f13c5dff2b2c6b600a79635103d6f580a4221079951181b25c7e6549",
+        ]
+        .into_iter()
+        .map(|hash| hex::decode(hash).unwrap().try_into().unwrap())
+        .collect(),
+    };
+    let signature = Signature::from_rsv(
+        hex::decode("c8de4cced43169f9aa3d36506363b2d2c44f6c49fc1fd91ea114c86f3757077e")
+            .unwrap()
+            .try_into()
+            .unwrap(),
+        hex::decode("1e11fdd0d1934eda0492606ee0bb80a7bf8f35cc5f86ec60fe5031ba48bfd544")
+            .unwrap()
+            .try_into()
+            .unwrap(),
+        1,
+    )
+    .unwrap();
+
+    let signing_hash = transaction.signing_hash();
+    assert_eq!(
+        hex::encode_prefixed(signing_hash),
+        "0x1acf60247481a7d9b8bea78b62af1db72d9034c50b9f3bf99daec97a98075e87",
+    );
+    assert_eq!(
+        crate::signature::recover_address(
+            &signing_hash,
+            &signature.r(),
+            &signature.s(),
+            signature.y_parity(),
+        )
+        .unwrap(),
+        "0xa83c816d4f9b2783761a22ba6fadb0eb0606d7b2"
+            .parse()
+            .unwrap(),
+    );
+    assert_eq!(transaction.clone().into_signed(signature), raw);
+
+    let (decoded, decoded_signature) = decode_signed(&raw).unwrap();
+    assert_eq!(decoded, ForeignTransaction::Eip4844(transaction));
+    assert_eq!(decoded_signature, signature);
+}
+
+#[test]
+fn test_intrinsic_gas_empty_data() {
+    assert_eq!(intrinsic_gas(&[], &[], false), 21_000);
+}
+
+#[test]
+fn test_intrinsic_gas_data() {
+    // 3 zero bytes and 2 non-zero bytes.
+    assert_eq!(
+        intrinsic_gas(&[0, 1, 0, 0xff, 0], &[], false),
+        21_000 + 3 * 4 + 2 * 16,
+    );
+}
+
+#[test]
+fn test_intrinsic_gas_access_list() {
+    let access_list = vec![
+        (ForeignAddress([1; 20]), vec![[0; 32], [1; 32]]),
+        (ForeignAddress([2; 20]), vec![]),
+    ];
+
+    assert_eq!(
+        intrinsic_gas(&[], &access_list, false),
+        21_000 + 2 * 2_400 + 2 * 1_900,
+    );
+}
+
+#[test]
+fn test_intrinsic_gas_contract_creation() {
+    // 33 bytes of initcode are 2 words.
+    assert_eq!(
+        intrinsic_gas(&[0x60; 33], &[], true),
+        21_000 + 32_000 + 33 * 16 + 2 * 2,
+    );
+    assert_eq!(intrinsic_gas(&[], &[], true), 53_000);
+}
+
+#[test]
+fn test_canonicalize_access_list() {
+    let access_list = vec![
+        (ForeignAddress([2; 20]), vec![[9; 32], [1; 32]]),
+        (ForeignAddress([1; 20]), vec![]),
+        (ForeignAddress([2; 20]), vec![[1; 32], [5; 32]]),
+    ];
+
+    assert_eq!(
+        canonicalize_access_list(access_list),
+        vec![
+            (ForeignAddress([1; 20]), vec![]),
+            (ForeignAddress([2; 20]), vec![[1; 32], [5; 32], [9; 32]]),
+        ],
+    );
+    assert_eq!(canonicalize_access_list(vec![]), vec![]);
+}
+
+#[test]
+fn test_signed_len() {
+    let transaction = ForeignTransaction::Eip1559(sample_eip1559_transaction());
+    let signed = transaction
+        .clone()
+        .into_signed(Signature::from_rsv([0x11; 32], [0x22; 32], 0).unwrap());
+
+    assert_eq!(transaction.signed_len(), signed.len());
+}
+
+#[test]
+fn test_decode_eip1559_round_trip() {
+    let mut with_access_list = sample_eip1559_transaction();
+    with_access_list.data = vec![0xa9, 0x05, 0x9c, 0xbb, 0, 0, 7];
+    with_access_list.access_list = sample_access_list();
+    let mut contract_creation = sample_eip1559_transaction();
+    contract_creation.to = None;
+
+    for transaction in [
+        sample_eip1559_transaction(),
+        with_access_list,
+        contract_creation,
+    ] {
+        let signature = Signature::from_rsv([0x11; 32], [0x22; 32], 1).unwrap();
+        let raw = transaction.clone().into_signed(signature);
+
+        let (decoded, decoded_signature) = decode_eip1559(&raw).unwrap();
+        assert_eq!(decoded, transaction);
+        assert_eq!(decoded_signature, signature);
+        assert_eq!(decoded_signature.y_parity(), 1);
+        assert_eq!(decoded.into_signed(decoded_signature), raw);
+    }
+}
+
+#[test]
+fn test_decode_eip1559_rejects_malformed_transactions() {
+    let raw = sample_eip1559_transaction()
+        .into_signed(Signature::from_rsv([0x11; 32], [0x22; 32], 0).unwrap());
+
+    assert!(matches!(
+        decode_eip1559(&[]),
+        Err(DecodeError::WrongType { actual: None, .. }),
+    ));
+    let mut wrong_type = raw.clone();
+    wrong_type[0] = EIP2930_TRANSACTION_TYPE;
+    assert!(matches!(
+        decode_eip1559(&wrong_type),
+        Err(DecodeError::WrongType {
+            actual: Some(EIP2930_TRANSACTION_TYPE),
+            ..
+        }),
+    ));
+    // Legacy transactions are RLP lists, which start at `0xc0`.
+    assert!(matches!(
+        decode_eip1559(&raw[1..]),
+        Err(DecodeError::WrongType { .. }),
+    ));
+
+    assert!(matches!(
+        decode_eip1559(&raw[..raw.len() - 1]),
+        Err(DecodeError::Rlp(_)),
+    ));
+    let mut trailing = raw.clone();
+    trailing.push(0);
+    assert!(matches!(
+        decode_eip1559(&trailing),
+        Err(DecodeError::Rlp(_)),
+    ));
+
+    // The unsigned envelope lacks the signature fields.
+    assert!(matches!(
+        decode_eip1559(&sample_eip1559_transaction().encode_unsigned()),
+        Err(DecodeError::FieldCount {
+            expected: 12,
+            actual: 9,
+        }),
+    ));
+}
+
+#[test]
+fn test_decode_unsigned_round_trip() {
+    let mut contract_creation = sample_eip1559_transaction();
+    contract_creation.to = None;
+    let mut eip2930 = sample_eip2930_transaction();
+    eip2930.access_list = sample_access_list();
+
+    for transaction in [
+        ForeignTransaction::Eip1559(sample_eip1559_transaction()),
+        ForeignTransaction::Eip1559(contract_creation),
+        ForeignTransaction::Eip2930(eip2930),
+        ForeignTransaction::Legacy(sample_legacy_transaction()),
+    ] {
+        let raw = transaction.encode_unsigned();
+
+        let decoded = decode_unsigned(&raw).unwrap();
+        assert_eq!(decoded, transaction);
+        assert_eq!(decoded.signing_hash(), keccak256(&raw));
+    }
+}
+
+#[test]
+fn test_decode_unsigned_rejects_malformed_transactions() {
+    let raw = sample_eip1559_transaction().encode_unsigned();
+
+    assert!(matches!(
+        decode_unsigned(&[]),
+        Err(DecodeError::UnsupportedType(None)),
+    ));
+    let mut eip4844 = raw.clone();
+    eip4844[0] = EIP4844_TRANSACTION_TYPE;
+    assert!(matches!(
+        decode_unsigned(&eip4844),
+        Err(DecodeError::UnsupportedType(Some(EIP4844_TRANSACTION_TYPE))),
+    ));
+    // Without its type byte, the fields of an EIP-1559 transaction are read
+    // as those of a legacy one, with its max fee per gas as the recipient.
+    assert!(matches!(
+        decode_unsigned(&raw[1..]),
+        Err(DecodeError::InvalidAddress(5)),
+    ));
+
+    let mut trailing = raw.clone();
+    trailing.push(0);
+    assert!(matches!(
+        decode_unsigned(&trailing),
+        Err(DecodeError::Rlp(_)),
+    ));
+
+    // Signed transactions have more fields.
+    let signed = sample_eip1559_transaction()
+        .into_signed(Signature::from_rsv([0x11; 32], [0x22; 32], 0).unwrap());
+    assert!(matches!(
+        decode_unsigned(&signed),
+        Err(DecodeError::FieldCount {
+            expected: 9,
+            actual: 12,
+        }),
+    ));
+
+    // A legacy transaction with a nonzero EIP-155 trailer.
+    let mut s = RlpStream::new();
+    let legacy = sample_legacy_transaction();
+    legacy.begin_fields(&mut s);
+    s.append(&legacy.chain_id);
+    s.append(&1u8);
+    s.append(&0u8);
+    assert!(matches!(
+        decode_unsigned(&s.out()),
+        Err(DecodeError::NonCanonical),
+    ));
+}
+
+#[test]
+fn test_decode_signed_round_trip() {
+    let signature = Signature::from_rsv([0x11; 32], [0x22; 32], 1).unwrap();
+
+    for transaction in [
+        ForeignTransaction::Eip1559(sample_eip1559_transaction()),
+        ForeignTransaction::Eip2930(sample_eip2930_transaction()),
+        ForeignTransaction::Eip4844(sample_eip4844_transaction()),
+        ForeignTransaction::Legacy(sample_legacy_transaction()),
+    ] {
+        let raw = transaction.clone().into_signed(signature);
+
+        let (decoded, decoded_signature) = decode_signed(&raw).unwrap();
+        assert_eq!(decoded, transaction);
+        assert_eq!(decoded_signature.y_parity(), 1);
+        assert_eq!(decoded.into_signed(decoded_signature), raw);
+    }
+}
+
+#[test]
+fn test_decode_signed_legacy_eip155_example() {
+    use ethers_core::utils::hex;
+
+    // Signed example from EIP-155.
+    let raw = hex::decode(
+        "f86c098504a817c800825208943535353535353535353535353535353535353535880de0b6b3a76400008025a028ef61340bd939bc2195fe537567866003e1a15d3c71ff63e1590620aa636276a067cbe9d8997f761aecb703304b3800ccf555c9f3dc64214b297fb1966a3b6d83",
+    )
+    .unwrap();
+
+    let (transaction, signature) = decode_signed(&raw).unwrap();
+    assert_eq!(
+        transaction,
+        ForeignTransaction::Legacy(sample_legacy_transaction()),
+    );
+    assert_eq!(signature.y_parity(), 0);
+    assert_eq!(transaction.into_signed(signature), raw);
+}
+
+#[test]
+fn test_decode_signed_rejects_unknown_types() {
+    let raw = sample_eip1559_transaction()
+        .into_signed(Signature::from_rsv([0x11; 32], [0x22; 32], 0).unwrap());
+
+    assert!(matches!(
+        decode_signed(&[]),
+        Err(DecodeError::UnsupportedType(None)),
+    ));
+    let mut unknown_type = raw.clone();
+    unknown_type[0] = 0x04;
+    assert!(matches!(
+        decode_signed(&unknown_type),
+        Err(DecodeError::UnsupportedType(Some(0x04))),
+    ));
+    // RLP strings are neither typed nor legacy transactions.
+    assert!(matches!(
+        decode_signed(&[0x80]),
+        Err(DecodeError::UnsupportedType(Some(0x80))),
+    ));
+
+    // The unsigned envelope lacks the signature fields.
+    assert!(matches!(
+        decode_signed(&sample_eip2930_transaction().encode_unsigned()),
+        Err(DecodeError::FieldCount {
+            expected: 11,
+            actual: 8,
+        }),
+    ));
+
+    // Legacy signatures without replay protection.
+    let legacy = sample_legacy_transaction();
+    let mut s = RlpStream::new();
+    legacy.begin_fields(&mut s);
+    s.append(&27u8);
+    s.append(&U256::from(1));
+    s.append(&U256::from(1));
+    assert!(matches!(
+        decode_signed(&s.out()),
+        Err(DecodeError::InvalidV(27)),
+    ));
+}
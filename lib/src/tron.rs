@@ -0,0 +1,68 @@
+use near_sdk::AccountId;
+
+use crate::{
+    foreign_address::ForeignAddress,
+    kdf::{get_mpc_address, sha256, PublicKeyConversionError},
+};
+
+/// Version byte of Tron mainnet addresses, which makes them start with `T`.
+pub const TRON_ADDRESS_PREFIX: u8 = 0x41;
+
+/// Encodes the Tron address with the same 20 bytes as an EVM address:
+/// base58check of [`TRON_ADDRESS_PREFIX`] followed by the address.
+#[must_use]
+pub fn tron_address(address: &ForeignAddress) -> String {
+    let mut payload = Vec::with_capacity(1 + 20 + 4);
+    payload.push(TRON_ADDRESS_PREFIX);
+    payload.extend_from_slice(&address.0);
+
+    let checksum = sha256(&sha256(&payload));
+    payload.extend_from_slice(&checksum[..4]);
+
+    near_sdk::bs58::encode(payload).into_string()
+}
+
+/// Derives the Tron address controlled by the MPC key derived for
+/// `account_id` and `path`.
+///
+/// # Errors
+///
+/// Returns an error if the MPC public key is not a valid SECP256K1 key.
+pub fn derive_tron_address(
+    mpc_public_key: near_sdk::PublicKey,
+    account_id: &AccountId,
+    path: &str,
+) -> Result<String, PublicKeyConversionError> {
+    Ok(tron_address(&get_mpc_address(
+        mpc_public_key,
+        account_id,
+        path,
+    )?))
+}
+
+#[test]
+fn test_tron_address() {
+    // Example from the Tron developer documentation.
+    let address: ForeignAddress = "0x8840E6C55B9ADA326D211D818C34A994AECED808"
+        .parse()
+        .unwrap();
+
+    assert_eq!(tron_address(&address), "TNPeeaaFB7K9cmo4uQpcU32zGK8G1NYqeL");
+}
+
+#[test]
+fn test_derive_tron_address() {
+    let mpc_public_key: near_sdk::PublicKey = "secp256k1:47xve2ymatpG4x4Gp7pmYwuLJk7eeRegrFuS4VoW5VV4i3GsBiBY87vkH6UZiiY18NeZnkBzcZzipDbJJ5pmjTcc"
+        .parse()
+        .unwrap();
+    let account_id: AccountId = "canhazgas.testnet".parse().unwrap();
+
+    let address = derive_tron_address(mpc_public_key.clone(), &account_id, "alice.near").unwrap();
+
+    assert!(address.starts_with('T'));
+    assert_eq!(address.len(), 34);
+    assert_eq!(
+        address,
+        tron_address(&get_mpc_address(mpc_public_key, &account_id, "alice.near").unwrap()),
+    );
+}
@@ -1,17 +1,36 @@
+use ethers_core::k256::ecdsa::SigningKey;
 use lib::{
-    kdf::sha256,
-    signer::{SignRequest, SignResult, SignerInterface},
+    kdf::derive_epsilon,
+    signer::{AffnPnt, Sclr, SignRequest, SignResult, SignatureResponse, SignerInterface},
     Rejectable,
 };
 use near_sdk::{env, near, require, AccountId, PromiseOrValue, PublicKey};
 
+/// Stands in for the secret that the MPC network's key shares combine to.
+const ROOT_SECRET_KEY: [u8; 32] = [0x5a; 32];
+
+fn root_signing_key() -> SigningKey {
+    SigningKey::from_bytes(&ROOT_SECRET_KEY.into()).unwrap()
+}
+
+/// Tweaks the root secret by the derivation epsilon, exactly like the MPC
+/// network does, so that signatures recover to the addresses produced by
+/// [`lib::kdf`].
 #[must_use]
-pub fn construct_spoof_key(
-    predecessor: &[u8],
-    path: &[u8],
-) -> ethers_core::k256::ecdsa::SigningKey {
-    let predecessor_hash = sha256([predecessor, b",", path].concat().as_slice());
-    ethers_core::k256::ecdsa::SigningKey::from_bytes(predecessor_hash.as_slice().into()).unwrap()
+pub fn derive_signing_key(predecessor: &AccountId, path: &str) -> SigningKey {
+    let secret =
+        *root_signing_key().as_nonzero_scalar().as_ref() + derive_epsilon(predecessor, path);
+    SigningKey::from_bytes(&secret.to_bytes()).unwrap()
+}
+
+fn to_near_public_key(signing_key: &SigningKey) -> PublicKey {
+    let encoded = signing_key.verifying_key().to_encoded_point(false);
+
+    PublicKey::from_parts(
+        near_sdk::CurveType::SECP256K1,
+        encoded.to_bytes()[1..].to_vec(),
+    )
+    .unwrap_or_reject()
 }
 
 const KEY_VERSION: u32 = 0;
@@ -23,38 +42,36 @@ pub struct MockSignerContract {}
 #[near]
 impl SignerInterface for MockSignerContract {
     #[payable]
-    fn sign(&mut self, request: SignRequest) -> PromiseOrValue<SignResult> {
+    fn sign(&mut self, request: SignRequest) -> PromiseOrValue<SignatureResponse> {
         require!(
             request.key_version == KEY_VERSION,
             "Key version not supported",
         );
 
         let predecessor = env::predecessor_account_id();
-        // This is unused, but needs to be in the sign signature.
-        let signing_key = construct_spoof_key(predecessor.as_bytes(), request.path.as_bytes());
+        let signing_key = derive_signing_key(&predecessor, &request.path);
         let (sig, recid) = signing_key
             .sign_prehash_recoverable(&request.payload)
             .unwrap();
-        PromiseOrValue::Value(SignResult::from_ecdsa_signature(sig, recid).unwrap())
+        let result = SignResult::from_ecdsa_signature(sig, recid).unwrap();
+        PromiseOrValue::Value(SignatureResponse {
+            big_r: AffnPnt {
+                affine_point: result.big_r_hex,
+            },
+            s: Sclr {
+                scalar: result.s_hex,
+            },
+            recovery_id: recid.to_byte(),
+        })
     }
 
     fn public_key(&self) -> PublicKey {
-        "secp256k1:37aFybhUHCxRdDkuCcB3yHzxqK7N8EQ745MujyAQohXSsYymVeHzhLxKvZ2qYeRHf3pGFiAsxqFJZjpF9gP2JV5u"
-            .parse()
-            .unwrap()
+        to_near_public_key(&root_signing_key())
     }
 
     fn derived_public_key(&self, path: String, predecessor: Option<AccountId>) -> PublicKey {
         let predecessor = predecessor.unwrap_or_else(env::predecessor_account_id);
-        let signing_key = construct_spoof_key(predecessor.as_bytes(), path.as_bytes());
-        let verifying_key = signing_key.verifying_key();
-        let encoded = verifying_key.to_encoded_point(false);
-
-        PublicKey::from_parts(
-            near_sdk::CurveType::SECP256K1,
-            encoded.to_bytes()[1..].to_vec(),
-        )
-        .unwrap_or_reject()
+        to_near_public_key(&derive_signing_key(&predecessor, &path))
     }
 
     fn latest_key_version(&self) -> u32 {